@@ -1,5 +1,6 @@
 //! This module defines the command-line arguments for the application.
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
 /// Defines the command-line arguments for the application.
 ///
@@ -12,31 +13,262 @@ pub struct AppArgs {
     #[arg(long, help = "Run in mailbox node mode")]
     pub mailbox: bool,
 
-    /// The port to listen on.
+    /// If set, the client runs without the terminal UI and is controlled
+    /// through a JSON-RPC socket in the data directory.
+    #[arg(
+        long,
+        conflicts_with = "mailbox",
+        help = "Run the client without the TUI, controlled through a local socket"
+    )]
+    pub headless: bool,
+
+    /// The TCP port to listen on.
     /// If not specified, a random free port will be used.
-    #[arg(long, help = "Port to listen on (random free port if not specified)")]
+    #[arg(
+        long,
+        help = "TCP port to listen on (random free port if not specified)"
+    )]
     pub port: Option<u16>,
 
+    /// The UDP port to listen on for QUIC.
+    /// If not specified, the same port number as for TCP will be used.
+    #[arg(
+        long,
+        help = "UDP port to listen on for QUIC (defaults to the TCP port)"
+    )]
+    pub quic_port: Option<u16>,
+
     /// The directory where data will be stored.
-    #[arg(long, default_value = "data", help = "Data directory")]
+    #[arg(long, global = true, default_value = "data", help = "Data directory")]
     pub data_dir: String,
 
     /// If set, storage encryption will be enabled.
-    #[arg(long, help = "Enable storage encryption")]
+    #[arg(long, global = true, help = "Enable storage encryption")]
     pub encrypt: bool,
 
     /// The password to use for storage encryption.
     /// This can also be set using the `P2P_MESSENGER_PASSWORD` environment variable.
     #[arg(
         long = "encryption-password",
+        global = true,
         help = "Password used for storage encryption (or set P2P_MESSENGER_PASSWORD)"
     )]
     pub encryption_password: Option<String>,
 
+    /// The passphrase sealing the identity file.
+    /// This can also be set using the `P2P_MESSENGER_IDENTITY_PASSPHRASE`
+    /// environment variable, and defaults to the encryption password.
+    #[arg(
+        long,
+        global = true,
+        help = "Passphrase sealing the identity file (or set P2P_MESSENGER_IDENTITY_PASSPHRASE; defaults to the encryption password)"
+    )]
+    pub identity_passphrase: Option<String>,
+
+    /// The display name advertised to peers in friend requests.
+    #[arg(long, help = "Display name shown to peers in friend requests")]
+    pub name: Option<String>,
+
     /// The port for the Web UI.
     /// If not specified, a random free port will be used.
     #[arg(long, help = "Web UI port (random free port if not specified)")]
     pub web_port: Option<u16>,
+
+    /// The bootstrap nodes used to join the DHT. Can be given multiple times.
+    /// Overrides `network.bootstrap` in `config.toml`.
+    #[arg(
+        long = "bootstrap",
+        value_name = "MULTIADDR",
+        help = "Bootstrap node multiaddr including /p2p/<peer id> (repeatable)"
+    )]
+    pub bootstrap: Vec<String>,
+
+    /// How often, in seconds, the outbox is retried and mailboxes are polled.
+    #[arg(long, value_name = "SECS", help = "Sync interval in seconds")]
+    pub sync_interval: Option<u64>,
+
+    /// The maximum number of messages a mailbox node stores per user.
+    #[arg(long, help = "Mailbox mode: max messages stored per user")]
+    pub max_storage_per_user: Option<usize>,
+
+    /// How long, in hours, a mailbox node keeps undelivered messages.
+    #[arg(
+        long,
+        value_name = "HOURS",
+        help = "Mailbox mode: hours to keep undelivered messages"
+    )]
+    pub mailbox_retention_hours: Option<u64>,
+
+    /// The address the Web UI binds to.
+    #[arg(
+        long,
+        value_name = "IP",
+        help = "Web UI bind address (default 127.0.0.1)"
+    )]
+    pub web_bind: Option<String>,
+
+    /// The `tracing` filter directives, e.g. `info,p2p_chat=debug`.
+    #[arg(long, value_name = "FILTER", help = "Log filter directives")]
+    pub log_filter: Option<String>,
+
+    /// The subcommand to run instead of starting a node, if any.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The subcommands of the application.
+///
+/// Except for `ctl`, they work on the data directory directly, or through
+/// the control socket when a headless client is running.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Controls a client running in headless mode.
+    Ctl(CtlArgs),
+    /// Shows, exports or imports the identity.
+    Identity {
+        /// The identity operation.
+        #[command(subcommand)]
+        command: IdentityCommand,
+    },
+    /// Adds, lists or removes friends.
+    Friend {
+        /// The friend operation.
+        #[command(subcommand)]
+        command: FriendCommand,
+    },
+    /// Shows the latest messages exchanged with a peer.
+    History {
+        /// The Peer ID or nickname of the peer.
+        peer: String,
+        /// The maximum number of messages to show.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Sends a message to a friend, or queues it until the client next runs.
+    Send {
+        /// The Peer ID or nickname of the friend.
+        peer: String,
+        /// The message text.
+        #[arg(required = true, num_args = 1..)]
+        text: Vec<String>,
+    },
+    /// Re-encrypts the storage under a new encryption password, or finishes
+    /// an interrupted re-key. Requires `--encrypt` and the current password.
+    Rekey {
+        /// The new encryption password.
+        /// This can also be set using the `P2P_MESSENGER_NEW_PASSWORD`
+        /// environment variable.
+        #[arg(long)]
+        new_password: Option<String>,
+    },
+    /// Replaces the E2E key and queues a signed key update for every friend.
+    /// Messages to the previous key are still decrypted for a grace period.
+    RotateKeys,
+    /// Measures how lookups and pages of the message history scale with its
+    /// size, in a temporary database.
+    #[command(hide = true)]
+    BenchHistory {
+        /// The number of messages to fill the history with.
+        #[arg(long, default_value_t = 1_000_000)]
+        messages: usize,
+    },
+}
+
+/// The operations of the `identity` subcommand.
+#[derive(Subcommand, Debug, Clone)]
+pub enum IdentityCommand {
+    /// Prints the Peer ID and E2E public key.
+    Show,
+    /// Writes the identity, including its private keys, to a file or stdout.
+    Export {
+        /// The file to write to instead of stdout.
+        #[arg(long, value_name = "PATH")]
+        output: Option<String>,
+    },
+    /// Changes the passphrase sealing the identity file.
+    ChangePassphrase {
+        /// The new passphrase.
+        /// This can also be set using the `P2P_MESSENGER_NEW_IDENTITY_PASSPHRASE`
+        /// environment variable.
+        #[arg(long, conflicts_with = "remove")]
+        new_passphrase: Option<String>,
+        /// Store the identity file in plaintext instead. With storage
+        /// encryption enabled, it is sealed with the encryption password again
+        /// at the next start.
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Replaces the identity with one from an exported file.
+    Import {
+        /// The exported identity file.
+        path: String,
+        /// Replace an existing identity.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// The operations of the `friend` subcommand.
+#[derive(Subcommand, Debug, Clone)]
+pub enum FriendCommand {
+    /// Adds a friend.
+    Add {
+        /// The Peer ID of the friend.
+        peer_id: String,
+        /// The base64-encoded E2E public key of the friend.
+        e2e_key: String,
+        /// An optional nickname.
+        nickname: Option<String>,
+    },
+    /// Lists the friends.
+    List,
+    /// Removes a friend.
+    Remove {
+        /// The Peer ID or nickname of the friend.
+        peer: String,
+    },
+}
+
+/// The arguments of the `ctl` subcommand.
+#[derive(Args, Debug, Clone)]
+pub struct CtlArgs {
+    /// The control socket to connect to.
+    /// Defaults to `control.sock` in the data directory.
+    #[arg(long, value_name = "PATH", help = "Control socket path")]
+    pub socket: Option<PathBuf>,
+
+    /// The request to send.
+    #[command(subcommand)]
+    pub command: CtlCommand,
+}
+
+/// The requests `ctl` can send to a headless client.
+#[derive(Subcommand, Debug, Clone)]
+pub enum CtlCommand {
+    /// Sends a message to a friend.
+    Send {
+        /// The Peer ID or nickname of the friend.
+        peer: String,
+        /// The message text.
+        #[arg(required = true, num_args = 1..)]
+        text: Vec<String>,
+    },
+    /// Shows the latest messages exchanged with a peer.
+    History {
+        /// The Peer ID or nickname of the peer.
+        peer: String,
+        /// The maximum number of messages to show.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Lists the friends and whether they are online.
+    Friends,
+    /// Lists the connected peers.
+    Peers,
+    /// Shows the messages waiting in the outbox.
+    Outbox,
+    /// Prints events, one JSON object per line, until interrupted.
+    Events,
 }
 
 impl AppArgs {
@@ -0,0 +1,155 @@
+//! This module implements the hidden `bench-history` subcommand, which
+//! measures how lookups and pages of the message history scale with its size.
+//!
+//! The history is filled in steps up to the requested number of messages, in
+//! a temporary database, spread over several conversations. After each step
+//! the lookups are timed on random messages of one conversation. The whole
+//! run is done twice, once with a plaintext history and once with storage
+//! encryption enabled, as the application stores it with a password.
+use crate::crypto::StorageEncryption;
+use crate::storage::{MessageCursor, MessageHistory, MessageStore};
+use crate::types::{DeliveryStatus, Message};
+use anyhow::Result;
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use rand::seq::SliceRandom;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// The history sizes measured, up to the requested number of messages.
+const STEPS: [usize; 4] = [10_000, 100_000, 1_000_000, 10_000_000];
+
+/// The number of conversations the messages are spread over.
+const CONVERSATIONS: usize = 10;
+
+/// The number of messages stored per batch while filling the history.
+const BATCH_SIZE: usize = 10_000;
+
+/// The number of timed runs of each lookup per step.
+const SAMPLES: usize = 200;
+
+/// The number of messages per page.
+const PAGE_SIZE: usize = 50;
+
+/// Runs the benchmark on a plaintext and on an encrypted history.
+///
+/// # Arguments
+///
+/// * `messages` - The number of messages to fill the history with.
+///
+/// # Errors
+///
+/// This function will return an error if the temporary histories cannot be
+/// created or queried.
+pub async fn run(messages: usize) -> Result<()> {
+    println!("Plaintext history");
+    let db = sled::Config::new().temporary(true).open()?;
+    measure(&MessageHistory::new(db, None)?, messages).await?;
+
+    println!();
+    println!("Encrypted history");
+    let db = sled::Config::new().temporary(true).open()?;
+    let encryption = StorageEncryption::new("bench-history", &StorageEncryption::generate_salt())?;
+    measure(&MessageHistory::new(db, Some(encryption))?, messages).await
+}
+
+/// Fills the history in steps and prints the timings after each step.
+async fn measure(history: &MessageHistory, messages: usize) -> Result<()> {
+    let own_id = random_peer_id();
+    let peers: Vec<PeerId> = (0..CONVERSATIONS).map(|_| random_peer_id()).collect();
+    let peer = &peers[0];
+    let mut steps: Vec<usize> = STEPS.into_iter().filter(|&step| step < messages).collect();
+    steps.push(messages);
+
+    println!(
+        "{:>10}  {:>12}  {:>12}  {:>12}  {:>12}  {:>12}",
+        "messages", "fill", "by id", "before", "after", "status"
+    );
+
+    // The messages of the first conversation, which the lookups run on.
+    let mut sampled = Vec::new();
+    let mut stored = 0;
+    for step in steps {
+        let started = Instant::now();
+        while stored < step {
+            let count = BATCH_SIZE.min(step - stored);
+            let batch: Vec<Message> = (stored..stored + count)
+                .map(|index| bench_message(own_id, peers[index % CONVERSATIONS], index))
+                .collect();
+            sampled.extend(batch.iter().filter(|msg| msg.recipient == *peer).cloned());
+            history.store_messages(batch).await?;
+            stored += count;
+        }
+        let fill = started.elapsed();
+
+        let mut rng = rand::thread_rng();
+        let samples: Vec<&Message> = sampled.choose_multiple(&mut rng, SAMPLES).collect();
+
+        let by_id = time(&samples, |msg| async move {
+            history.get_message_by_id(&msg.id).await.map(|_| ())
+        })
+        .await?;
+        let before = time(&samples, |msg| async move {
+            history
+                .get_messages_before(&own_id, peer, &MessageCursor::at(msg), PAGE_SIZE)
+                .await
+                .map(|_| ())
+        })
+        .await?;
+        let after = time(&samples, |msg| async move {
+            history
+                .get_messages_after(&own_id, peer, &MessageCursor::at(msg), PAGE_SIZE)
+                .await
+                .map(|_| ())
+        })
+        .await?;
+        let status = time(&samples, |msg| async move {
+            history
+                .update_delivery_status(&msg.id, DeliveryStatus::Delivered)
+                .await
+        })
+        .await?;
+
+        println!(
+            "{:>10}  {:>12.2?}  {:>12.2?}  {:>12.2?}  {:>12.2?}  {:>12.2?}",
+            stored, fill, by_id, before, after, status
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the mean time of a lookup over the sampled messages.
+async fn time<'a, F, Fut>(samples: &[&'a Message], lookup: F) -> Result<Duration>
+where
+    F: Fn(&'a Message) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    let started = Instant::now();
+    for msg in samples {
+        lookup(msg).await?;
+    }
+    Ok(started.elapsed() / samples.len().max(1) as u32)
+}
+
+/// Creates the message with the given index, one millisecond after the previous one.
+fn bench_message(sender: PeerId, recipient: PeerId, index: usize) -> Message {
+    Message {
+        id: Uuid::new_v4(),
+        sender,
+        recipient,
+        timestamp: 1_700_000_000_000 + index as i64,
+        content: format!("Benchmark message {}", index).into_bytes(),
+        nonce: rand::random(),
+        delivery_status: DeliveryStatus::Sent,
+        signature: Vec::new(),
+        group_id: None,
+        attachment: None,
+        sender_pub_key: Vec::new(),
+    }
+}
+
+/// Returns a random Peer ID.
+fn random_peer_id() -> PeerId {
+    Keypair::generate_ed25519().public().to_peer_id()
+}
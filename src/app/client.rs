@@ -1,21 +1,25 @@
 //! This module contains the primary entry point for running the application in client mode.
+use super::args::AppArgs;
+use super::config::Config;
 use crate::cli::commands::{Node, UiNotification};
+use crate::cli::{fetch_attachment_from, open_direct_message, resolve_origin, Origin};
 use crate::crypto::{Identity, StorageEncryption};
 use crate::network::NetworkLayer;
 use crate::storage::{
-    MessageHistory, SeenTracker, SledFriendsStore, SledKnownMailboxesStore, SledOutboxStore,
+    AttachmentStore, MessageHistory, SeenTracker, SledBlockListStore, SledContactRequestsStore,
+    SledDeviceStore, SledFriendsStore, SledGroupsStore, SledKnownMailboxesStore,
+    SledMessageRequestsStore, SledOutboxStore, SledPeerAddressStore, SledRecordStore,
     SledSeenTracker,
 };
 use crate::sync::{SyncEngine, SyncStores};
-use crate::types::Message;
+use crate::types::{ContactRequest, Message};
 use crate::ui::run_tui;
 use anyhow::Result;
 use libp2p::Multiaddr;
-use std::str::FromStr;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::sync::{mpsc, Mutex};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 /// Runs the application in client mode.
 ///
@@ -28,8 +32,10 @@ use tracing::{debug, error, info};
 /// * `identity` - The user's identity.
 /// * `db` - The database instance.
 /// * `encryption` - The encryption key for the storage, if enabled.
-/// * `port` - The port to listen on for P2P connections.
+/// * `listen_addrs` - The addresses to listen on for P2P connections.
 /// * `web_port` - The port for the Web UI.
+/// * `args` - The command-line arguments, for the display name and the data directory.
+/// * `config` - The validated application configuration.
 ///
 /// # Errors
 ///
@@ -39,46 +45,71 @@ pub async fn run(
     identity: Arc<Identity>,
     db: sled::Db,
     encryption: Option<StorageEncryption>,
-    port: u16,
+    listen_addrs: Vec<Multiaddr>,
     web_port: u16,
+    args: &AppArgs,
+    config: Config,
 ) -> Result<()> {
     println!("💬 Starting client mode");
 
     // Initialize storage components.
     let friends = Arc::new(SledFriendsStore::new(db.clone(), encryption.clone())?);
     let history = Arc::new(MessageHistory::new(db.clone(), encryption.clone())?);
+    history.migrate(&identity, friends.as_ref()).await?;
     let outbox = Arc::new(SledOutboxStore::new(db.clone(), encryption.clone())?);
     let seen = Arc::new(SledSeenTracker::new(db.clone())?);
+    let contacts = Arc::new(SledContactRequestsStore::new(
+        db.clone(),
+        encryption.clone(),
+    )?);
     let known_mailboxes = Arc::new(SledKnownMailboxesStore::new(
         db.clone(),
         encryption.clone(),
     )?);
-
-    let listen_addr = Multiaddr::from_str(&format!("/ip4/0.0.0.0/tcp/{}", port))?;
-
-    let bootstrap_nodes = vec![];
+    let groups = Arc::new(SledGroupsStore::new(db.clone(), encryption.clone())?);
+    let attachments = Arc::new(AttachmentStore::new(&args.data_dir, encryption.clone())?);
+    let blocks = Arc::new(SledBlockListStore::new(db.clone(), encryption.clone())?);
+    let requests = Arc::new(SledMessageRequestsStore::new(
+        db.clone(),
+        encryption.clone(),
+    )?);
+    let devices = Arc::new(SledDeviceStore::new(db.clone(), encryption.clone())?);
+    let record_store = SledRecordStore::new(identity.peer_id, db.clone(), encryption.clone())?;
+    let address_book = Arc::new(SledPeerAddressStore::new(db.clone(), encryption.clone())?);
 
     // Initialize the network layer.
-    let (mut network_layer, network_handle) =
-        NetworkLayer::new(identity.clone(), listen_addr, false, bootstrap_nodes)?;
+    let (mut network_layer, network_handle) = NetworkLayer::new(
+        identity.clone(),
+        listen_addrs,
+        false,
+        config.bootstrap_nodes,
+        record_store,
+        address_book,
+    )?;
 
     // Create channels for communication between components.
     let (incoming_tx, mut incoming_rx) = mpsc::unbounded_channel::<Message>();
+    let (contact_tx, mut contact_rx) = mpsc::unbounded_channel::<ContactRequest>();
     let (ui_notify_tx, ui_notify_rx) = mpsc::unbounded_channel::<UiNotification>();
     let (web_notify_tx, web_notify_rx) = mpsc::unbounded_channel::<UiNotification>();
     let (network_notify_tx, mut network_notify_rx) = mpsc::unbounded_channel::<UiNotification>();
 
-    let sync_stores = SyncStores::new(
-        friends.clone(),
-        outbox.clone(),
-        history.clone(),
-        seen.clone(),
-        known_mailboxes.clone(),
-    );
+    let sync_stores = SyncStores {
+        friends: friends.clone(),
+        outbox: outbox.clone(),
+        history: history.clone(),
+        seen: seen.clone(),
+        known_mailboxes: known_mailboxes.clone(),
+        groups: groups.clone(),
+        attachments: attachments.clone(),
+        blocks: blocks.clone(),
+        requests: requests.clone(),
+        devices: devices.clone(),
+    };
 
     // Initialize the synchronization engine.
     let (sync_engine_instance, sync_event_tx, mut sync_event_rx) = SyncEngine::new_with_network(
-        Duration::from_secs(30),
+        config.sync_interval,
         identity.clone(),
         sync_stores,
         network_handle.clone(),
@@ -87,7 +118,11 @@ pub async fn run(
     )?;
     let sync_engine = Arc::new(Mutex::new(sync_engine_instance));
 
+    network_layer.set_rate_limits(config.limits);
     network_layer.set_sync_event_sender(sync_event_tx.clone());
+    network_layer.set_contact_event_sender(contact_tx);
+    network_layer.set_attachment_store(attachments.clone());
+    network_layer.set_block_list(blocks.clone());
 
     // Create the main application node context.
     let node = Arc::new(Node {
@@ -95,8 +130,17 @@ pub async fn run(
         friends: friends.clone(),
         history: history.clone(),
         outbox: outbox.clone(),
+        seen: seen.clone(),
+        contacts,
+        groups,
+        attachments,
+        blocks,
+        requests: requests.clone(),
+        devices,
+        display_name: args.name.clone(),
         network: network_handle,
         ui_notify_tx,
+        web_notify_tx: web_notify_tx.clone(),
         sync_engine: sync_engine.clone(),
     });
 
@@ -161,8 +205,14 @@ pub async fn run(
     // Handle network notifications (e.g., delivery confirmations).
     let node_for_network = node.clone();
     let web_notify_tx_for_network = web_notify_tx.clone();
+    let headless = args.headless;
     tokio::spawn(async move {
         while let Some(notification) = network_notify_rx.recv().await {
+            // Control socket subscribers get network events too.
+            if headless {
+                let _ = node_for_network.ui_notify_tx.send(notification.clone());
+            }
+
             match notification {
                 UiNotification::DeliveryStatusUpdate { message_id, new_status } => {
                     // Update the delivery status in the database.
@@ -176,6 +226,11 @@ pub async fn run(
                         new_status,
                     });
                 }
+                UiNotification::PeerConnected(peer_id) => {
+                    // Resume attachment downloads from the peer that came online.
+                    node_for_network.resume_attachments_from(peer_id);
+                    let _ = web_notify_tx_for_network.send(UiNotification::PeerConnected(peer_id));
+                }
                 // Forward other notifications as-is.
                 other => {
                     let _ = web_notify_tx_for_network.send(other);
@@ -203,18 +258,106 @@ pub async fn run(
                 continue;
             }
 
-            if let Err(e) = node_clone.history.store_message(message.clone()).await {
-                error!("Failed to store incoming message {}: {}", message.id, e);
-                continue;
+            let wire_id = message.id;
+            let sender = message.sender;
+            let mut source = sender;
+            let mut notification = None;
+            let (stored, group_update) = if message.group_id.is_some() {
+                match node_clone.receive_group_message(message).await {
+                    Ok(incoming) => (incoming.message, incoming.group),
+                    Err(e) => {
+                        warn!("Dropping group message {}: {}", wire_id, e);
+                        continue;
+                    }
+                }
+            } else {
+                let origin = match resolve_origin(
+                    &node_clone.identity,
+                    node_clone.friends.as_ref(),
+                    node_clone.devices.as_ref(),
+                    &sender,
+                )
+                .await
+                {
+                    Ok(Origin::Unknown) => {
+                        // Messages from strangers wait in the message requests, unconfirmed.
+                        match node_clone.receive_message_request(message).await {
+                            Ok(true) => {
+                                let _ = node_clone
+                                    .ui_notify_tx
+                                    .send(UiNotification::MessageRequestReceived(sender));
+                                let _ = web_notify_tx_clone
+                                    .send(UiNotification::MessageRequestReceived(sender));
+                            }
+                            Ok(false) => {
+                                debug!(
+                                    "Dropping message {} from {}: the message request is full or has another key",
+                                    wire_id, sender
+                                );
+                            }
+                            Err(e) => {
+                                warn!(
+                                    "Dropping message {} from unknown peer {}: {}",
+                                    wire_id, sender, e
+                                );
+                                continue;
+                            }
+                        }
+                        if let Err(e) = seen_clone.mark_seen(wire_id).await {
+                            error!("Failed to mark message {} as seen: {}", wire_id, e);
+                        }
+                        continue;
+                    }
+                    Ok(origin) => origin,
+                    Err(e) => {
+                        error!("Failed to look up sender of message {}: {}", wire_id, e);
+                        continue;
+                    }
+                };
+
+                // The network layer has verified the signature; it does not cover the plaintext.
+                match open_direct_message(
+                    &node_clone.identity,
+                    node_clone.friends.as_ref(),
+                    node_clone.devices.as_ref(),
+                    node_clone.history.as_ref(),
+                    origin,
+                    message,
+                )
+                .await
+                {
+                    Ok(opened) => {
+                        source = opened.source;
+                        notification = opened.notification;
+                        (opened.message, None)
+                    }
+                    Err(e) => {
+                        warn!("Failed to decrypt message {} from {}: {}", wire_id, sender, e);
+                        continue;
+                    }
+                }
+            };
+
+            if let Some(ref message) = stored {
+                if let Err(e) = node_clone.history.store_message(message.clone()).await {
+                    error!("Failed to store incoming message {}: {}", message.id, e);
+                    continue;
+                }
+                fetch_attachment_from(
+                    &node_clone.network,
+                    &node_clone.attachments,
+                    message,
+                    source,
+                );
             }
 
-            if let Err(e) = seen_clone.mark_seen(message.id).await {
-                error!("Failed to mark message {} as seen: {}", message.id, e);
+            if let Err(e) = seen_clone.mark_seen(wire_id).await {
+                error!("Failed to mark message {} as seen: {}", wire_id, e);
             }
 
             // Send a delivery confirmation back to the sender.
             let confirmation = crate::types::DeliveryConfirmation {
-                original_message_id: message.id,
+                original_message_id: wire_id,
                 timestamp: chrono::Utc::now().timestamp_millis(),
             };
 
@@ -223,7 +366,6 @@ pub async fn run(
             };
 
             let network_clone = node_clone.network.clone();
-            let sender = message.sender;
             tokio::spawn(async move {
                 if let Err(e) = network_clone.send_chat_request(sender, confirmation_request).await
                 {
@@ -231,22 +373,61 @@ pub async fn run(
                 }
             });
 
-            // Notify the UI and web server of the new message.
-            let _ = node_clone
-                .ui_notify_tx
-                .send(UiNotification::NewMessage(message.clone()));
-            let _ = web_notify_tx_clone.send(UiNotification::NewMessage(message));
+            // Notify the UI and web server of group changes and the new message.
+            if let Some(group) = group_update {
+                let _ = node_clone
+                    .ui_notify_tx
+                    .send(UiNotification::GroupUpdated(group.clone()));
+                let _ = web_notify_tx_clone.send(UiNotification::GroupUpdated(group));
+            }
+
+            if let Some(notification) = notification {
+                let _ = node_clone.ui_notify_tx.send(notification.clone());
+                let _ = web_notify_tx_clone.send(notification);
+            }
+
+            if let Some(message) = stored {
+                let _ = node_clone
+                    .ui_notify_tx
+                    .send(UiNotification::NewMessage(message.clone()));
+                let _ = web_notify_tx_clone.send(UiNotification::NewMessage(message));
+            }
+        }
+    });
+
+    // Handle incoming friend requests and answers.
+    let node_for_contacts = node.clone();
+    let web_notify_tx_for_contacts = web_notify_tx.clone();
+    tokio::spawn(async move {
+        while let Some(request) = contact_rx.recv().await {
+            match node_for_contacts.handle_contact_request(request).await {
+                Ok(Some(notification)) => {
+                    let _ = node_for_contacts.ui_notify_tx.send(notification.clone());
+                    let _ = web_notify_tx_for_contacts.send(notification);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to handle contact request: {}", e);
+                }
+            }
         }
     });
 
     // Start the web server.
+    let web_addr = SocketAddr::new(config.web_bind_address, web_port);
     let node_for_web = node.clone();
     tokio::spawn(async move {
-        if let Err(e) = crate::web::start_server(node_for_web, web_port, web_notify_rx).await {
+        if let Err(e) = crate::web::start_server(node_for_web, web_addr, web_notify_rx).await {
             error!("Web server error: {}", e);
         }
     });
 
+    if args.headless {
+        // Serve the control socket instead of the terminal UI.
+        let socket_path = crate::control::socket_path(&args.data_dir);
+        return crate::control::run_server(node, &socket_path, ui_notify_rx).await;
+    }
+
     // Run the terminal UI.
-    run_tui(node, ui_notify_rx, web_port).await
+    run_tui(node, ui_notify_rx, web_addr, config.log_filter).await
 }
@@ -0,0 +1,347 @@
+//! This module loads the application configuration.
+//!
+//! Settings are read from `config.toml` in the data directory, if it exists,
+//! and command-line flags override the values from the file. The result is
+//! validated once at startup, so the rest of the application can rely on it.
+//!
+//! An example `config.toml`:
+//!
+//! ```toml
+//! [network]
+//! bootstrap = ["/ip4/203.0.113.7/tcp/4001/p2p/12D3KooW..."]
+//!
+//! [sync]
+//! interval_secs = 30
+//!
+//! [mailbox]
+//! max_storage_per_user = 1000
+//! retention_hours = 168
+//!
+//! [limits]
+//! chat_requests_per_minute = 120
+//! mailbox_requests_per_minute = 60
+//! dht_writes_per_minute = 60
+//! max_connections = 256
+//! max_connections_per_peer = 4
+//! max_connections_per_ip = 16
+//! auto_block_after = 20
+//! block_duration_secs = 600
+//!
+//! [web]
+//! bind_address = "127.0.0.1"
+//!
+//! [log]
+//! filter = "info,p2p_chat=debug"
+//! ```
+use super::args::AppArgs;
+use crate::network::RateLimits;
+use anyhow::{anyhow, bail, Context, Result};
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+/// The name of the configuration file in the data directory.
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The shortest sync interval accepted, to keep mailboxes from being flooded.
+const MIN_SYNC_INTERVAL_SECS: u64 = 5;
+/// The longest sync interval accepted.
+const MAX_SYNC_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// The contents of `config.toml`. Every setting is optional.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    network: NetworkSection,
+    sync: SyncSection,
+    mailbox: MailboxSection,
+    limits: LimitsSection,
+    web: WebSection,
+    log: LogSection,
+}
+
+/// The `[network]` section of `config.toml`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct NetworkSection {
+    bootstrap: Vec<String>,
+}
+
+/// The `[sync]` section of `config.toml`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct SyncSection {
+    interval_secs: Option<u64>,
+}
+
+/// The `[mailbox]` section of `config.toml`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct MailboxSection {
+    max_storage_per_user: Option<usize>,
+    retention_hours: Option<u64>,
+}
+
+/// The `[limits]` section of `config.toml`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct LimitsSection {
+    chat_requests_per_minute: Option<u32>,
+    mailbox_requests_per_minute: Option<u32>,
+    dht_writes_per_minute: Option<u32>,
+    max_connections: Option<u32>,
+    max_connections_per_peer: Option<u32>,
+    max_connections_per_ip: Option<u32>,
+    auto_block_after: Option<u32>,
+    block_duration_secs: Option<u64>,
+}
+
+/// The `[web]` section of `config.toml`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct WebSection {
+    bind_address: Option<String>,
+}
+
+/// The `[log]` section of `config.toml`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct LogSection {
+    filter: Option<String>,
+}
+
+/// The validated application configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The bootstrap nodes used to join the DHT, each including a `/p2p/` peer ID.
+    pub bootstrap_nodes: Vec<Multiaddr>,
+    /// How often the sync engine retries the outbox and polls mailboxes.
+    pub sync_interval: Duration,
+    /// The maximum number of messages a mailbox node stores per user.
+    pub max_storage_per_user: usize,
+    /// How long a mailbox node keeps undelivered messages.
+    pub mailbox_retention: Duration,
+    /// The request rate and connection limits.
+    pub limits: RateLimits,
+    /// The address the web UI binds to.
+    pub web_bind_address: IpAddr,
+    /// The `tracing` filter directives, if logging is filtered.
+    pub log_filter: Option<String>,
+}
+
+impl Config {
+    /// Loads the configuration from the data directory and applies the command-line overrides.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command-line arguments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the configuration file cannot be
+    /// parsed or any setting is invalid.
+    pub fn load(args: &AppArgs) -> Result<Self> {
+        let path = Path::new(&args.data_dir).join(CONFIG_FILE_NAME);
+        let file = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Invalid configuration in {}", path.display()))?
+        } else {
+            FileConfig::default()
+        };
+
+        Self::resolve(file, args)
+    }
+
+    /// Merges the file settings with the command-line overrides and validates the result.
+    fn resolve(file: FileConfig, args: &AppArgs) -> Result<Self> {
+        let bootstrap = if args.bootstrap.is_empty() {
+            file.network.bootstrap
+        } else {
+            args.bootstrap.clone()
+        };
+        let bootstrap_nodes = bootstrap
+            .iter()
+            .map(|address| parse_bootstrap_address(address))
+            .collect::<Result<Vec<_>>>()?;
+
+        let sync_interval_secs = args.sync_interval.or(file.sync.interval_secs).unwrap_or(30);
+        if !(MIN_SYNC_INTERVAL_SECS..=MAX_SYNC_INTERVAL_SECS).contains(&sync_interval_secs) {
+            bail!(
+                "Sync interval must be between {} and {} seconds, got {}",
+                MIN_SYNC_INTERVAL_SECS,
+                MAX_SYNC_INTERVAL_SECS,
+                sync_interval_secs
+            );
+        }
+
+        let max_storage_per_user = args
+            .max_storage_per_user
+            .or(file.mailbox.max_storage_per_user)
+            .unwrap_or(1000);
+        if max_storage_per_user == 0 {
+            bail!("Mailbox max_storage_per_user must be at least 1");
+        }
+
+        let retention_hours = args
+            .mailbox_retention_hours
+            .or(file.mailbox.retention_hours)
+            .unwrap_or(7 * 24);
+        if retention_hours == 0 {
+            bail!("Mailbox retention must be at least 1 hour");
+        }
+
+        let limits = resolve_limits(file.limits)?;
+
+        let bind_address = args
+            .web_bind
+            .clone()
+            .or(file.web.bind_address)
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let web_bind_address = IpAddr::from_str(&bind_address)
+            .map_err(|e| anyhow!("Invalid web bind address '{}': {}", bind_address, e))?;
+
+        let log_filter = args.log_filter.clone().or(file.log.filter);
+        if let Some(ref filter) = log_filter {
+            EnvFilter::try_new(filter)
+                .map_err(|e| anyhow!("Invalid log filter '{}': {}", filter, e))?;
+        }
+
+        Ok(Self {
+            bootstrap_nodes,
+            sync_interval: Duration::from_secs(sync_interval_secs),
+            max_storage_per_user,
+            mailbox_retention: Duration::from_secs(retention_hours * 60 * 60),
+            limits,
+            web_bind_address,
+            log_filter,
+        })
+    }
+}
+
+/// Fills in the default limits and checks that none of them is zero.
+///
+/// `auto_block_after` may be zero, which turns automatic blocking off.
+fn resolve_limits(section: LimitsSection) -> Result<RateLimits> {
+    let defaults = RateLimits::default();
+    let limits = RateLimits {
+        chat_requests_per_minute: section
+            .chat_requests_per_minute
+            .unwrap_or(defaults.chat_requests_per_minute),
+        mailbox_requests_per_minute: section
+            .mailbox_requests_per_minute
+            .unwrap_or(defaults.mailbox_requests_per_minute),
+        dht_writes_per_minute: section
+            .dht_writes_per_minute
+            .unwrap_or(defaults.dht_writes_per_minute),
+        max_connections: section.max_connections.unwrap_or(defaults.max_connections),
+        max_connections_per_peer: section
+            .max_connections_per_peer
+            .unwrap_or(defaults.max_connections_per_peer),
+        max_connections_per_ip: section
+            .max_connections_per_ip
+            .unwrap_or(defaults.max_connections_per_ip),
+        auto_block_after: section
+            .auto_block_after
+            .unwrap_or(defaults.auto_block_after),
+        block_duration: section
+            .block_duration_secs
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.block_duration),
+    };
+
+    let required = [
+        ("chat_requests_per_minute", limits.chat_requests_per_minute),
+        (
+            "mailbox_requests_per_minute",
+            limits.mailbox_requests_per_minute,
+        ),
+        ("dht_writes_per_minute", limits.dht_writes_per_minute),
+        ("max_connections", limits.max_connections),
+        ("max_connections_per_peer", limits.max_connections_per_peer),
+        ("max_connections_per_ip", limits.max_connections_per_ip),
+    ];
+    for (name, value) in required {
+        if value == 0 {
+            bail!("Limit {} must be at least 1", name);
+        }
+    }
+    if limits.block_duration.is_zero() {
+        bail!("Limit block_duration_secs must be at least 1");
+    }
+    Ok(limits)
+}
+
+/// Parses a bootstrap address, which must name the peer it belongs to.
+fn parse_bootstrap_address(address: &str) -> Result<Multiaddr> {
+    let multiaddr = Multiaddr::from_str(address)
+        .map_err(|e| anyhow!("Invalid bootstrap address '{}': {}", address, e))?;
+
+    if !multiaddr.iter().any(|p| matches!(p, Protocol::P2p(_))) {
+        bail!(
+            "Bootstrap address '{}' must end with /p2p/<peer id>",
+            address
+        );
+    }
+    Ok(multiaddr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const BOOTSTRAP: &str =
+        "/ip4/203.0.113.7/tcp/4001/p2p/12D3KooWD3eckifWpRn9wQpMG9R9hX3sD158z7EqHWmweQAJU5SA";
+
+    fn args(flags: &[&str]) -> AppArgs {
+        AppArgs::parse_from(std::iter::once("p2p-chat").chain(flags.iter().copied()))
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let file: FileConfig = toml::from_str(
+            r#"
+            [sync]
+            interval_secs = 60
+
+            [mailbox]
+            retention_hours = 12
+
+            [web]
+            bind_address = "0.0.0.0"
+            "#,
+        )
+        .unwrap();
+        let config = Config::resolve(
+            file,
+            &args(&["--sync-interval", "10", "--bootstrap", BOOTSTRAP]),
+        )
+        .unwrap();
+        assert_eq!(config.sync_interval, Duration::from_secs(10));
+        assert_eq!(config.mailbox_retention, Duration::from_secs(12 * 60 * 60));
+        assert_eq!(config.web_bind_address, IpAddr::from([0, 0, 0, 0]));
+        assert_eq!(config.bootstrap_nodes, [BOOTSTRAP.parse().unwrap()]);
+        assert_eq!(config.max_storage_per_user, 1000);
+    }
+
+    #[test]
+    fn invalid_settings_are_refused() {
+        let resolve = |flags: &[&str]| Config::resolve(FileConfig::default(), &args(flags));
+        assert!(resolve(&["--sync-interval", "1"]).is_err());
+        assert!(resolve(&["--bootstrap", "/ip4/203.0.113.7/tcp/4001"]).is_err());
+        assert!(resolve(&["--max-storage-per-user", "0"]).is_err());
+        assert!(resolve(&["--web-bind", "localhost"]).is_err());
+        assert!(resolve(&[]).is_ok());
+
+        let file: FileConfig = toml::from_str("[limits]\nmax_connections = 0").unwrap();
+        assert!(Config::resolve(file, &args(&[])).is_err());
+        assert!(toml::from_str::<FileConfig>("[network]\nbootstrap_nodes = []").is_err());
+    }
+}
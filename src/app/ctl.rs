@@ -0,0 +1,56 @@
+//! This module implements the `ctl` subcommand, which sends a request to a
+//! client running in headless mode and prints the result as JSON.
+use super::args::{CtlArgs, CtlCommand};
+use crate::control::client::ControlClient;
+use crate::control::protocol::{HistoryParams, SendParams};
+use anyhow::Result;
+use serde_json::Value;
+
+/// Runs the `ctl` subcommand.
+///
+/// # Arguments
+///
+/// * `data_dir` - The data directory of the headless client.
+/// * `args` - The arguments of the subcommand.
+///
+/// # Errors
+///
+/// This function will return an error if the client cannot be reached or
+/// the request fails.
+pub async fn run(data_dir: &str, args: &CtlArgs) -> Result<()> {
+    let socket = args
+        .socket
+        .clone()
+        .unwrap_or_else(|| crate::control::socket_path(data_dir));
+    let mut client = ControlClient::connect(&socket).await?;
+
+    let result = match &args.command {
+        CtlCommand::Send { peer, text } => {
+            let params = SendParams {
+                peer: peer.clone(),
+                text: text.join(" "),
+            };
+            client.call("send", params).await?
+        }
+        CtlCommand::History { peer, limit } => {
+            let params = HistoryParams {
+                peer: peer.clone(),
+                limit: *limit,
+            };
+            client.call("history", params).await?
+        }
+        CtlCommand::Friends => client.call("friends", Value::Null).await?,
+        CtlCommand::Peers => client.call("peers", Value::Null).await?,
+        CtlCommand::Outbox => client.call("outbox", Value::Null).await?,
+        CtlCommand::Events => {
+            client.call("subscribe", Value::Null).await?;
+            while let Some(event) = client.next_event().await? {
+                println!("{}", serde_json::to_string(&event)?);
+            }
+            return Ok(());
+        }
+    };
+
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
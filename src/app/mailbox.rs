@@ -1,12 +1,12 @@
 //! This module contains the primary entry point for running a mailbox node.
+use super::config::Config;
 use crate::crypto::{Identity, StorageEncryption};
 use crate::mailbox::MailboxNode;
 use crate::network::NetworkLayer;
+use crate::storage::{SledPeerAddressStore, SledRecordStore};
 use anyhow::Result;
 use libp2p::Multiaddr;
-use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
 
 /// Runs a mailbox node.
 ///
@@ -18,7 +18,8 @@ use std::time::Duration;
 /// * `identity` - The identity of the node.
 /// * `db` - The database instance for storing mailbox data.
 /// * `encryption` - The encryption key for the storage.
-/// * `port` - The port to listen on for incoming connections.
+/// * `listen_addrs` - The addresses to listen on for incoming connections.
+/// * `config` - The validated application configuration.
 ///
 /// # Errors
 ///
@@ -27,16 +28,20 @@ pub async fn run(
     identity: Arc<Identity>,
     db: sled::Db,
     encryption: Option<StorageEncryption>,
-    port: u16,
+    listen_addrs: Vec<Multiaddr>,
+    config: Config,
 ) -> Result<()> {
     println!("📬 Starting mailbox node");
 
+    let record_store = SledRecordStore::new(identity.peer_id, db.clone(), encryption.clone())?;
+    let address_book = Arc::new(SledPeerAddressStore::new(db.clone(), encryption.clone())?);
+
     let mut mailbox_node = MailboxNode::new(
         identity.clone(),
         db,
         encryption,
-        1000,
-        Duration::from_secs(7 * 24 * 60 * 60),
+        config.max_storage_per_user,
+        config.mailbox_retention,
     )?;
 
     let stats = mailbox_node.get_stats();
@@ -48,18 +53,21 @@ pub async fn run(
     println!("  Retention period: {:?}", stats.retention_period);
     println!();
 
-    let listen_addr = Multiaddr::from_str(&format!("/ip4/0.0.0.0/tcp/{}", port))?;
-
     let mailbox_storage = mailbox_node.storage.clone();
-    let (mut network_layer, _network_handle) = NetworkLayer::new_with_mailbox_storage(
+    let (mut network_layer, network_handle) = NetworkLayer::new_with_mailbox_storage(
         identity,
-        listen_addr,
+        listen_addrs,
         true,
         Some(mailbox_storage),
-        vec![],
+        config.bootstrap_nodes,
+        record_store,
+        address_book,
     )?;
+    network_layer.set_rate_limits(config.limits);
 
     network_layer.bootstrap_dht()?;
 
-    mailbox_node.run_with_network(network_layer).await
+    mailbox_node
+        .run_with_network(network_layer, network_handle)
+        .await
 }
@@ -3,11 +3,15 @@
 //! It is responsible for parsing command-line arguments, setting up the
 //! application environment, and launching either a client or a mailbox node.
 pub mod args;
+mod bench;
 mod client;
+pub mod config;
+mod ctl;
 mod mailbox;
+mod oneshot;
 mod setup;
 
-pub use args::AppArgs;
+pub use args::{AppArgs, Command};
 
 use anyhow::Result;
 
@@ -25,7 +29,8 @@ pub async fn launch() -> Result<()> {
 /// Launches the application with the given arguments.
 ///
 /// This function prepares the application environment and then runs either a
-/// client or a mailbox node, depending on the provided arguments.
+/// client or a mailbox node, depending on the provided arguments. Subcommands
+/// run without starting a node.
 ///
 /// # Arguments
 ///
@@ -35,18 +40,35 @@ pub async fn launch() -> Result<()> {
 ///
 /// This function will return an error if the application fails to launch.
 pub async fn launch_with_args(args: AppArgs) -> Result<()> {
+    match args.command {
+        Some(Command::Ctl(ref ctl_args)) => return ctl::run(&args.data_dir, ctl_args).await,
+        Some(Command::BenchHistory { messages }) => return bench::run(messages).await,
+        Some(ref command) => return oneshot::run(&args, command).await,
+        None => {}
+    }
+
     let setup::PreparedApp {
         args,
-        port,
+        listen_addrs,
         web_port,
         identity,
         db,
         encryption,
+        config,
     } = setup::prepare(args)?;
 
     if args.mailbox {
-        mailbox::run(identity, db, encryption, port).await
+        mailbox::run(identity, db, encryption, listen_addrs, config).await
     } else {
-        client::run(identity, db, encryption, port, web_port).await
+        client::run(
+            identity,
+            db,
+            encryption,
+            listen_addrs,
+            web_port,
+            &args,
+            config,
+        )
+        .await
     }
 }
@@ -0,0 +1,514 @@
+//! This module implements the one-shot subcommands for the identity, friends,
+//! the history, sending, re-keying the storage and rotating the E2E key.
+//!
+//! When a headless client is running on the data directory, the commands go
+//! through its control socket, since it holds the database open. Otherwise
+//! they open the data directory themselves; messages sent that way are queued
+//! in the outbox and delivered when the client next runs.
+use super::args::{AppArgs, Command, FriendCommand, IdentityCommand};
+use super::setup::{
+    attach_client_stores, identity_path, open_storage, resolve_encryption_password,
+    resolve_identity_passphrase,
+};
+use crate::control::client::ControlClient;
+use crate::control::methods::{friend_entries, friend_from_params, history_entries};
+use crate::control::protocol::{FriendAddParams, FriendRemoveParams, HistoryParams, SendParams};
+use crate::crypto::{HpkeContext, Identity, StorageEncryption};
+use crate::storage::{
+    encryption, DeviceStore, FriendsStore, MessageHistory, MessageStore, OutboxStore,
+    SledDeviceStore, SledFriendsStore, SledOutboxStore,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::prelude::*;
+use serde_json::Value;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+/// Runs a one-shot subcommand.
+///
+/// # Arguments
+///
+/// * `args` - The command-line arguments.
+/// * `command` - The subcommand to run.
+///
+/// # Errors
+///
+/// This function will return an error if the subcommand fails.
+pub async fn run(args: &AppArgs, command: &Command) -> Result<()> {
+    let result = match command {
+        Command::Identity { command } => return run_identity(args, command).await,
+        Command::Rekey { new_password } => return run_rekey(args, new_password.as_deref()).await,
+        Command::RotateKeys => return run_rotate_keys(args).await,
+        Command::Ctl(_) | Command::BenchHistory { .. } => {
+            bail!("{:?} is not a one-shot subcommand", command)
+        }
+        _ => match connect_daemon(&args.data_dir).await {
+            Some(client) => run_on_daemon(client, command).await?,
+            None => run_offline(args, command).await?,
+        },
+    };
+
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+/// Connects to the control socket of a headless client running on the data
+/// directory, if there is one.
+async fn connect_daemon(data_dir: &str) -> Option<ControlClient> {
+    let socket = crate::control::socket_path(data_dir);
+    if !socket.exists() {
+        return None;
+    }
+    ControlClient::connect(&socket).await.ok()
+}
+
+/// Runs a subcommand through the control socket of a headless client.
+async fn run_on_daemon(mut client: ControlClient, command: &Command) -> Result<Value> {
+    match command {
+        Command::Friend { command } => match command {
+            FriendCommand::Add {
+                peer_id,
+                e2e_key,
+                nickname,
+            } => {
+                let params = FriendAddParams {
+                    peer_id: peer_id.clone(),
+                    e2e_public_key: e2e_key.clone(),
+                    nickname: nickname.clone(),
+                };
+                client.call("friend_add", params).await
+            }
+            FriendCommand::List => client.call("friends", Value::Null).await,
+            FriendCommand::Remove { peer } => {
+                let params = FriendRemoveParams { peer: peer.clone() };
+                client.call("friend_remove", params).await
+            }
+        },
+        Command::History { peer, limit } => {
+            let params = HistoryParams {
+                peer: peer.clone(),
+                limit: *limit,
+            };
+            client.call("history", params).await
+        }
+        Command::Send { peer, text } => {
+            let params = SendParams {
+                peer: peer.clone(),
+                text: text.join(" "),
+            };
+            client.call("send", params).await
+        }
+        Command::Ctl(_)
+        | Command::Identity { .. }
+        | Command::Rekey { .. }
+        | Command::RotateKeys
+        | Command::BenchHistory { .. } => {
+            bail!("This subcommand does not use the control socket")
+        }
+    }
+}
+
+/// Runs a subcommand on the data directory.
+async fn run_offline(args: &AppArgs, command: &Command) -> Result<Value> {
+    let storage = OfflineStorage::open(args)?;
+
+    match command {
+        Command::Friend { command } => match command {
+            FriendCommand::Add {
+                peer_id,
+                e2e_key,
+                nickname,
+            } => {
+                let friend = friend_from_params(FriendAddParams {
+                    peer_id: peer_id.clone(),
+                    e2e_public_key: e2e_key.clone(),
+                    nickname: nickname.clone(),
+                })?;
+                let previous = storage.friends.get_friend(&friend.peer_id).await?;
+                storage.friends.add_friend(friend.clone()).await?;
+                if crate::cli::verified_key_changed(previous.as_ref(), &friend) {
+                    eprintln!(
+                        "⚠️  WARNING: the key of verified friend {} changed. It is no longer verified; compare safety numbers again",
+                        friend.peer_id
+                    );
+                }
+                Ok(serde_json::to_value(
+                    friend_entries(vec![friend], &[]).remove(0),
+                )?)
+            }
+            FriendCommand::List => {
+                let friends = storage.friends.list_friends().await?;
+                Ok(serde_json::to_value(friend_entries(friends, &[]))?)
+            }
+            FriendCommand::Remove { peer } => {
+                let peer_id = crate::cli::resolve_peer(&storage.friends, peer).await?;
+                let removed = storage.friends.remove_friend(&peer_id).await?;
+                Ok(serde_json::json!({ "removed": removed }))
+            }
+        },
+        Command::History { peer, limit } => {
+            let identity = load_identity(args)?;
+            let peer_id = crate::cli::resolve_peer(&storage.friends, peer).await?;
+            let history = MessageHistory::new(storage.db.clone(), storage.encryption.clone())?;
+            history.migrate(&identity, &storage.friends).await?;
+            let messages = history
+                .get_history(&identity.peer_id, &peer_id, *limit)
+                .await?;
+
+            Ok(serde_json::to_value(history_entries(&messages))?)
+        }
+        Command::Send { peer, text } => {
+            let identity =
+                attach_client_stores(load_identity(args)?, &storage.db, &storage.encryption)?;
+            let peer_id = crate::cli::resolve_peer(&storage.friends, peer).await?;
+            let friend = storage
+                .friends
+                .get_friend(&peer_id)
+                .await?
+                .ok_or_else(|| anyhow!("{} is not a friend", peer_id))?;
+
+            let (message, sealed) = crate::cli::compose_text(&identity, &friend, text.join(" "))?;
+            let devices = SledDeviceStore::new(storage.db.clone(), storage.encryption.clone())?;
+            let own_devices = crate::cli::own_devices(&identity, &devices).await?;
+            let copies =
+                crate::cli::compose_device_copies(&identity, &friend, &own_devices, &message)?;
+            let history = MessageHistory::new(storage.db.clone(), storage.encryption.clone())?;
+            let outbox = SledOutboxStore::new(storage.db.clone(), storage.encryption.clone())?;
+            history.store_message(message).await?;
+            outbox.add_pending(sealed.clone()).await?;
+            for copy in copies {
+                outbox.add_pending(copy).await?;
+            }
+
+            eprintln!("Queued; the message is delivered when the client next runs.");
+            Ok(serde_json::json!({ "id": sealed.id }))
+        }
+        Command::Ctl(_)
+        | Command::Identity { .. }
+        | Command::Rekey { .. }
+        | Command::RotateKeys
+        | Command::BenchHistory { .. } => {
+            bail!("This subcommand does not use the data directory")
+        }
+    }
+}
+
+/// Runs an `identity` subcommand.
+async fn run_identity(args: &AppArgs, command: &IdentityCommand) -> Result<()> {
+    match command {
+        IdentityCommand::Show => {
+            let identity = load_identity(args)?;
+            println!("Peer ID: {}", identity.peer_id);
+            println!(
+                "E2E Public Key: {}",
+                BASE64_STANDARD.encode(identity.hpke_public_key())
+            );
+        }
+        IdentityCommand::Export { output } => {
+            let exported = load_identity(args)?.export()?;
+            match output {
+                Some(path) => {
+                    // The export contains the private keys, so the file is
+                    // made owner-only before anything is written to it.
+                    let mut file = std::fs::OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .mode(0o600)
+                        .open(path)?;
+                    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+                    file.write_all(exported.as_bytes())?;
+                    file.sync_all()?;
+                    println!("Identity exported to {}", path);
+                }
+                None => println!("{}", exported),
+            }
+        }
+        IdentityCommand::Import { path, force } => {
+            if connect_daemon(&args.data_dir).await.is_some() {
+                bail!("Stop the running client before importing an identity");
+            }
+
+            let target = identity_path(&args.data_dir);
+            if Path::new(&target).exists() && !force {
+                bail!(
+                    "{} already has an identity. Use --force to replace it",
+                    args.data_dir
+                );
+            }
+
+            let passphrase = resolve_identity_passphrase(args)?;
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path))?;
+            let identity = Identity::import(&content, passphrase.as_deref())
+                .with_context(|| format!("Failed to import the identity from {}", path))?;
+            identity.save(&target, passphrase.as_deref())?;
+            println!("Imported identity {}", identity.peer_id);
+        }
+        IdentityCommand::ChangePassphrase {
+            new_passphrase,
+            remove,
+        } => {
+            let new_passphrase = if *remove {
+                None
+            } else {
+                let new_passphrase = new_passphrase
+                    .clone()
+                    .or_else(|| std::env::var("P2P_MESSENGER_NEW_IDENTITY_PASSPHRASE").ok())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Supply --new-passphrase or set P2P_MESSENGER_NEW_IDENTITY_PASSPHRASE, or use --remove"
+                        )
+                    })?;
+                Some(new_passphrase)
+            };
+
+            Identity::change_passphrase(
+                &existing_identity_path(args)?,
+                resolve_identity_passphrase(args)?.as_deref(),
+                new_passphrase.as_deref(),
+            )?;
+
+            if new_passphrase.is_some() {
+                println!("Identity passphrase changed. Supply it with --identity-passphrase or P2P_MESSENGER_IDENTITY_PASSPHRASE from now on.");
+            } else {
+                println!("The identity file is no longer sealed.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the `rekey` subcommand.
+///
+/// Unless the identity file has a passphrase of its own, it is sealed with
+/// the encryption password, so it is resealed with the new one.
+async fn run_rekey(args: &AppArgs, new_password: Option<&str>) -> Result<()> {
+    if !args.encrypt {
+        bail!("Storage encryption is not enabled. Use --encrypt with the current password");
+    }
+    if connect_daemon(&args.data_dir).await.is_some() {
+        bail!("Stop the running client before re-keying the storage");
+    }
+
+    let old_password = resolve_encryption_password(args)?;
+    let new_password = new_password
+        .map(str::to_string)
+        .or_else(|| std::env::var("P2P_MESSENGER_NEW_PASSWORD").ok())
+        .ok_or_else(|| anyhow!("Supply --new-password or set P2P_MESSENGER_NEW_PASSWORD"))?;
+
+    let report = encryption::rekey(Path::new(&args.data_dir), &old_password, &new_password)?;
+    println!(
+        "Re-encrypted {} values in {} trees and {} attachments ({} unencrypted values copied)",
+        report.reencrypted, report.trees, report.attachments, report.copied
+    );
+
+    let own_passphrase = args.identity_passphrase.is_some()
+        || std::env::var("P2P_MESSENGER_IDENTITY_PASSPHRASE").is_ok();
+    let path = identity_path(&args.data_dir);
+    if !own_passphrase && Path::new(&path).exists() {
+        match Identity::change_passphrase(&path, Some(&old_password), Some(&new_password)) {
+            Ok(()) => println!("The identity file is now sealed with the new password."),
+            Err(e) => eprintln!(
+                "The identity file was not resealed ({}). Use 'identity change-passphrase' to seal it with the new password",
+                e
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the `rotate-keys` subcommand.
+///
+/// The key updates are queued in the outbox and delivered when the client
+/// next runs, directly or through mailboxes.
+async fn run_rotate_keys(args: &AppArgs) -> Result<()> {
+    if connect_daemon(&args.data_dir).await.is_some() {
+        bail!("Stop the running client before rotating the E2E key");
+    }
+
+    let storage = OfflineStorage::open(args)?;
+    let devices = SledDeviceStore::new(storage.db.clone(), storage.encryption.clone())?;
+    if let Some(primary) = devices.get_primary().await? {
+        bail!(
+            "This device joined {}; rotate the key on the primary device",
+            primary.peer_id
+        );
+    }
+
+    let path = existing_identity_path(args)?;
+    let passphrase = resolve_identity_passphrase(args)?;
+    let mut identity = attach_client_stores(
+        Identity::load(&path, passphrase.as_deref())?,
+        &storage.db,
+        &storage.encryption,
+    )?;
+
+    // Friends only hold sessions with the previous key, so the updates are
+    // encrypted before it is replaced.
+    let new_key = HpkeContext::new()?;
+    let update = identity.sign_key_update(&new_key.public_key_bytes())?;
+    let friends = storage.friends.list_friends().await?;
+    let own_devices = crate::cli::own_devices(&identity, &devices).await?;
+    let announcements =
+        crate::cli::compose_key_updates(&identity, &friends, &own_devices, &update)?;
+
+    identity.rotate_hpke_key(new_key)?;
+    identity.save(&path, passphrase.as_deref())?;
+
+    let outbox = SledOutboxStore::new(storage.db.clone(), storage.encryption.clone())?;
+    for announcement in announcements {
+        outbox.add_pending(announcement).await?;
+    }
+
+    println!(
+        "New E2E Public Key: {}",
+        BASE64_STANDARD.encode(identity.hpke_public_key())
+    );
+    println!(
+        "Queued key updates for {} friends and {} linked devices; they are delivered when the client next runs.",
+        friends.len(),
+        own_devices.len()
+    );
+    Ok(())
+}
+
+/// Loads the identity of the data directory.
+fn load_identity(args: &AppArgs) -> Result<Identity> {
+    let path = existing_identity_path(args)?;
+    Identity::load(&path, resolve_identity_passphrase(args)?.as_deref())
+}
+
+/// Returns the path of the identity file of the data directory, which must exist.
+fn existing_identity_path(args: &AppArgs) -> Result<String> {
+    let path = identity_path(&args.data_dir);
+    if !Path::new(&path).exists() {
+        bail!(
+            "No identity in {}. Start the client once or use 'identity import'",
+            args.data_dir
+        );
+    }
+    Ok(path)
+}
+
+/// The storage of a data directory opened by a one-shot subcommand.
+struct OfflineStorage {
+    db: sled::Db,
+    encryption: Option<StorageEncryption>,
+    friends: SledFriendsStore,
+}
+
+impl OfflineStorage {
+    /// Opens the storage of the data directory.
+    fn open(args: &AppArgs) -> Result<Self> {
+        let (db, encryption) = open_storage(args).map_err(|e| {
+            // The database is locked while a client runs on it.
+            if e.downcast_ref::<sled::Error>().is_some() {
+                e.context(
+                    "Is the client running? Stop it, or run it with --headless to use these commands",
+                )
+            } else {
+                e
+            }
+        })?;
+        let friends = SledFriendsStore::new(db.clone(), encryption.clone())?;
+
+        Ok(Self {
+            db,
+            encryption,
+            friends,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// A temporary data directory, removed when dropped.
+    struct TestDir(std::path::PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("p2p-chat-test-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn args(&self) -> AppArgs {
+            AppArgs::parse_from(["p2p-chat", "--data-dir", self.0.to_str().unwrap()])
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn friend_command(command: FriendCommand) -> Command {
+        Command::Friend { command }
+    }
+
+    #[tokio::test]
+    async fn friends_are_managed_on_the_data_directory() {
+        let dir = TestDir::new();
+        let args = dir.args();
+        let friend = Identity::generate().unwrap();
+        let add = friend_command(FriendCommand::Add {
+            peer_id: friend.peer_id.to_string(),
+            e2e_key: BASE64_STANDARD.encode(friend.hpke_public_key()),
+            nickname: Some("bob".to_string()),
+        });
+        run_offline(&args, &add).await.unwrap();
+
+        let listed = run_offline(&args, &friend_command(FriendCommand::List))
+            .await
+            .unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+
+        let remove = friend_command(FriendCommand::Remove {
+            peer: "bob".to_string(),
+        });
+        let removed = run_offline(&args, &remove).await.unwrap();
+        assert_eq!(removed["removed"], true);
+        let listed = run_offline(&args, &friend_command(FriendCommand::List))
+            .await
+            .unwrap();
+        assert!(listed.as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn identities_are_exported_privately_and_imported_once() {
+        let source = TestDir::new();
+        let identity = Identity::generate().unwrap();
+        identity
+            .save(&identity_path(source.0.to_str().unwrap()), None)
+            .unwrap();
+        let exported = source.0.join("exported.json");
+        let export = IdentityCommand::Export {
+            output: Some(exported.to_str().unwrap().to_string()),
+        };
+        run_identity(&source.args(), &export).await.unwrap();
+        let mode = std::fs::metadata(&exported).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let target = TestDir::new();
+        let import = IdentityCommand::Import {
+            path: exported.to_str().unwrap().to_string(),
+            force: false,
+        };
+        run_identity(&target.args(), &import).await.unwrap();
+        assert_eq!(
+            load_identity(&target.args()).unwrap().peer_id,
+            identity.peer_id
+        );
+
+        // An existing identity is only replaced with --force.
+        assert!(run_identity(&target.args(), &import).await.is_err());
+    }
+}
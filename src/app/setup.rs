@@ -1,10 +1,14 @@
 //! This module handles the initial setup of the application.
 use super::args::AppArgs;
+use super::config::Config;
 use crate::crypto::{Identity, StorageEncryption};
-use anyhow::{anyhow, Result};
+use crate::storage::{encryption, SledSenderKeyStore, SledSessionStore};
+use anyhow::{anyhow, bail, Context, Result};
 use base64::prelude::*;
-use std::net::TcpListener;
+use libp2p::Multiaddr;
+use std::net::{SocketAddr, TcpListener};
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
 use tracing_subscriber::EnvFilter;
 
@@ -15,8 +19,8 @@ use tracing_subscriber::EnvFilter;
 pub struct PreparedApp {
     /// The command-line arguments.
     pub args: AppArgs,
-    /// The port to listen on for P2P connections.
-    pub port: u16,
+    /// The addresses to listen on for P2P connections, over QUIC and TCP.
+    pub listen_addrs: Vec<Multiaddr>,
     /// The port for the Web UI.
     pub web_port: u16,
     /// The user's identity.
@@ -25,19 +29,22 @@ pub struct PreparedApp {
     pub db: sled::Db,
     /// The encryption key for the storage, if enabled.
     pub encryption: Option<StorageEncryption>,
+    /// The validated application configuration.
+    pub config: Config,
 }
 
 /// Prepares the application for running.
 ///
 /// This function performs the following steps:
-/// 1. Finds free ports if not specified.
-/// 2. Configures logging.
-/// 3. Prints a start banner.
-/// 4. Creates the data directory.
-/// 5. Loads or generates the user's identity.
-/// 6. Prints identity information.
-/// 7. Opens the database.
-/// 8. Sets up storage encryption if enabled.
+/// 1. Loads and validates the configuration.
+/// 2. Finds free ports if not specified.
+/// 3. Configures logging.
+/// 4. Prints a start banner.
+/// 5. Creates the data directory.
+/// 6. Loads or generates the user's identity, sealing it if a passphrase is set.
+/// 7. Prints identity information.
+/// 8. Opens the database and sets up storage encryption if enabled.
+/// 9. Attaches the ratchet session and sender key stores to the identity (client mode only).
 ///
 /// # Arguments
 ///
@@ -47,76 +54,159 @@ pub struct PreparedApp {
 ///
 /// This function will return an error if any of the setup steps fail.
 pub fn prepare(args: AppArgs) -> Result<PreparedApp> {
+    let config = Config::load(&args)?;
+
     let port = args.port.unwrap_or(find_free_port()?);
+    let quic_port = args.quic_port.unwrap_or(port);
     let web_port = args.web_port.unwrap_or(find_free_port()?);
 
-    configure_logging(args.mailbox);
-    print_start_banner(&args, port, web_port);
+    configure_logging(args.mailbox || args.headless, config.log_filter.as_deref());
+    print_start_banner(&args, &config, port, quic_port, web_port);
 
     std::fs::create_dir_all(&args.data_dir)?;
 
-    let identity_path = format!("{}/identity.json", args.data_dir);
-    let identity = Arc::new(Identity::load_or_generate(&identity_path)?);
+    let passphrase = resolve_identity_passphrase(&args)?;
+    if passphrase.is_some() {
+        println!("🔐 Identity file sealed with a passphrase");
+    }
+    let identity =
+        Identity::load_or_generate(&identity_path(&args.data_dir), passphrase.as_deref())?;
 
     print_identity_info(&identity);
 
-    let db_path = format!("{}/db", args.data_dir);
-    let db = sled::open(&db_path)?;
-
-    let encryption = if args.encrypt {
+    if args.encrypt {
         println!("🔐 Storage encryption enabled");
+    }
+    let (db, encryption) = open_storage(&args)?;
 
-        let password = resolve_encryption_password(&args)?;
-        let salt_path = format!("{}/encryption_salt.bin", args.data_dir);
-        let salt = load_or_create_salt(&salt_path)?;
-
-        Some(StorageEncryption::new(&password, &salt)?)
+    let identity = if args.mailbox {
+        Arc::new(identity)
     } else {
-        None
+        Arc::new(attach_client_stores(identity, &db, &encryption)?)
     };
 
+    let listen_addrs = vec![
+        Multiaddr::from_str(&format!("/ip4/0.0.0.0/udp/{}/quic-v1", quic_port))?,
+        Multiaddr::from_str(&format!("/ip4/0.0.0.0/tcp/{}", port))?,
+    ];
+
     Ok(PreparedApp {
         args,
-        port,
+        listen_addrs,
         web_port,
         identity,
         db,
         encryption,
+        config,
     })
 }
 
+/// Returns the path of the identity file in a data directory.
+pub fn identity_path(data_dir: &str) -> String {
+    format!("{}/identity.json", data_dir)
+}
+
+/// Opens the database in the data directory and sets up storage encryption
+/// if enabled.
+///
+/// # Arguments
+///
+/// * `args` - The command-line arguments.
+///
+/// # Errors
+///
+/// This function will return an error if the database is in use by another
+/// process or cannot be opened, a re-key is unfinished, or the encryption
+/// password is missing or wrong.
+pub fn open_storage(args: &AppArgs) -> Result<(sled::Db, Option<StorageEncryption>)> {
+    let data_dir = Path::new(&args.data_dir);
+    if encryption::rekey_pending(data_dir) {
+        bail!(
+            "A re-key of {} was interrupted. Run 'rekey' again with the same passwords to finish it",
+            args.data_dir
+        );
+    }
+
+    let db_path = format!("{}/db", args.data_dir);
+    let db = sled::open(&db_path)
+        .with_context(|| format!("Failed to open the database at {}", db_path))?;
+
+    let encryption = if args.encrypt {
+        let password = resolve_encryption_password(args)?;
+        let salt = encryption::load_or_create_salt(data_dir)?;
+        let encryption = StorageEncryption::new(&password, &salt)?;
+        encryption::verify_key(data_dir, &db, &encryption)?;
+
+        Some(encryption)
+    } else {
+        None
+    };
+
+    Ok((db, encryption))
+}
+
+/// Attaches the ratchet session and sender key stores a client needs to an
+/// identity.
+///
+/// # Arguments
+///
+/// * `identity` - The identity to attach the stores to.
+/// * `db` - The database holding the stores.
+/// * `encryption` - The storage encryption, if enabled.
+///
+/// # Errors
+///
+/// This function will return an error if the stores cannot be opened.
+pub fn attach_client_stores(
+    identity: Identity,
+    db: &sled::Db,
+    encryption: &Option<StorageEncryption>,
+) -> Result<Identity> {
+    let sessions = SledSessionStore::new(db.clone(), encryption.clone())?;
+    let sender_keys = SledSenderKeyStore::new(db.clone(), encryption.clone())?;
+    Ok(identity
+        .with_session_store(Arc::new(sessions))
+        .with_sender_key_store(Arc::new(sender_keys)))
+}
+
 /// Configures logging for the application.
 ///
-/// If running in mailbox mode, it sets a more verbose logging level.
-fn configure_logging(mailbox_mode: bool) {
-    if mailbox_mode {
+/// Without a TUI to show logs (mailbox and headless mode), it logs to stdout
+/// with a more verbose level unless a filter is configured.
+fn configure_logging(log_to_stdout: bool, filter: Option<&str>) {
+    if log_to_stdout {
         let _ = tracing_subscriber::fmt()
-            .with_env_filter(EnvFilter::new("info,p2p_chat=debug"))
+            .with_env_filter(EnvFilter::new(filter.unwrap_or("info,p2p_chat=debug")))
             .try_init();
     }
 }
 
 /// Prints a banner with startup information.
-fn print_start_banner(args: &AppArgs, port: u16, web_port: u16) {
+fn print_start_banner(args: &AppArgs, config: &Config, port: u16, quic_port: u16, web_port: u16) {
     println!("🚀 Starting P2P E2E Messenger");
     println!(
         "Mode: {}",
         if args.mailbox {
             "Mailbox Node"
+        } else if args.headless {
+            "Client (headless)"
         } else {
             "Client"
         }
     );
-    println!("Port: {}", port);
+    println!("Port: {} (TCP), {} (QUIC)", port, quic_port);
     if !args.mailbox {
-        println!("Web UI: http://127.0.0.1:{}", web_port);
+        println!(
+            "Web UI: http://{}",
+            SocketAddr::new(config.web_bind_address, web_port)
+        );
     }
     println!("Data directory: {}", args.data_dir);
     println!();
 }
 
 /// Prints information about the user's identity.
-fn print_identity_info(identity: &Arc<Identity>) {
+pub fn print_identity_info(identity: &Identity) {
     println!("Identity loaded:");
     println!("  Peer ID: {}", identity.peer_id);
     println!(
@@ -129,7 +219,11 @@ fn print_identity_info(identity: &Arc<Identity>) {
 /// Resolves the encryption password.
 ///
 /// The password can be provided via a command-line argument or an environment variable.
-fn resolve_encryption_password(args: &AppArgs) -> Result<String> {
+///
+/// # Errors
+///
+/// This function will return an error if no password is provided.
+pub fn resolve_encryption_password(args: &AppArgs) -> Result<String> {
     args
         .encryption_password
         .clone()
@@ -141,24 +235,30 @@ fn resolve_encryption_password(args: &AppArgs) -> Result<String> {
         })
 }
 
-/// Loads an encryption salt from a file, or creates a new one if it doesn't exist.
-fn load_or_create_salt(path: &str) -> Result<[u8; 16]> {
-    if Path::new(path).exists() {
-        let bytes = std::fs::read(path)?;
-        if bytes.len() != 16 {
-            anyhow::bail!(
-                "Encryption salt at '{}' has unexpected length {} (expected 16)",
-                path,
-                bytes.len()
-            );
-        }
-        let mut salt = [0u8; 16];
-        salt.copy_from_slice(&bytes);
-        Ok(salt)
+/// Resolves the passphrase sealing the identity file.
+///
+/// The passphrase can be provided via a command-line argument or an
+/// environment variable. Otherwise, the encryption password is used when
+/// storage encryption is enabled, and the identity file is not sealed when
+/// it is not.
+///
+/// # Errors
+///
+/// This function will return an error if storage encryption is enabled but
+/// no password is provided.
+pub fn resolve_identity_passphrase(args: &AppArgs) -> Result<Option<String>> {
+    if let Some(passphrase) = args
+        .identity_passphrase
+        .clone()
+        .or_else(|| std::env::var("P2P_MESSENGER_IDENTITY_PASSPHRASE").ok())
+    {
+        return Ok(Some(passphrase));
+    }
+
+    if args.encrypt {
+        resolve_encryption_password(args).map(Some)
     } else {
-        let generated = StorageEncryption::generate_salt();
-        std::fs::write(path, generated)?;
-        Ok(generated)
+        Ok(None)
     }
 }
 
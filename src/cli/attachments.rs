@@ -0,0 +1,166 @@
+//! This module implements sending and fetching attachments.
+//!
+//! A sent file is split into encrypted chunks in the attachment store and its
+//! manifest travels inside the message. The recipient fetches the chunks from
+//! the sender over the attachment protocol; chunks that have arrived are kept,
+//! so a transfer interrupted by a disconnect resumes when the sender is back.
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use libp2p::PeerId;
+use tracing::{debug, info, warn};
+
+use crate::network::NetworkHandle;
+use crate::storage::AttachmentStore;
+use crate::types::{AttachmentManifest, Message};
+
+use super::commands::Node;
+
+impl Node {
+    /// Imports a local file into the attachment store, ready to be sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The manifest to attach to the message.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read, is
+    /// empty or too large, or cannot be stored.
+    pub async fn import_attachment(&self, path: PathBuf) -> Result<AttachmentManifest> {
+        let store = self.attachments.clone();
+        tokio::task::spawn_blocking(move || store.import_file(&path)).await?
+    }
+
+    /// Starts fetching the attachment of a received message, if it has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The received message.
+    pub fn fetch_attachment_of(&self, message: &Message) {
+        fetch_attachment_of(&self.network, &self.attachments, message);
+    }
+
+    /// Resumes the downloads of attachments sent by a peer that has just connected.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the connected peer.
+    pub fn resume_attachments_from(&self, peer_id: PeerId) {
+        let pending = match self.attachments.pending_downloads() {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!("Failed to list pending attachment downloads: {}", e);
+                return;
+            }
+        };
+
+        for (manifest, source) in pending {
+            if source == peer_id {
+                spawn_download(
+                    self.network.clone(),
+                    self.attachments.clone(),
+                    manifest,
+                    source,
+                );
+            }
+        }
+    }
+}
+
+/// Records the attachment of a received message and starts fetching it from the sender.
+///
+/// # Arguments
+///
+/// * `network` - The network handle used to fetch chunks.
+/// * `store` - The attachment store.
+/// * `message` - The received message.
+pub fn fetch_attachment_of(
+    network: &NetworkHandle,
+    store: &Arc<AttachmentStore>,
+    message: &Message,
+) {
+    fetch_attachment_from(network, store, message, message.sender);
+}
+
+/// Records the attachment of a received message and starts fetching it from
+/// the device that sent it, which may be a linked device of the sender.
+///
+/// # Arguments
+///
+/// * `network` - The network handle used to fetch chunks.
+/// * `store` - The attachment store.
+/// * `message` - The received message.
+/// * `source` - The `PeerId` of the device that holds the attachment.
+pub fn fetch_attachment_from(
+    network: &NetworkHandle,
+    store: &Arc<AttachmentStore>,
+    message: &Message,
+    source: PeerId,
+) {
+    let Some(ref manifest) = message.attachment else {
+        return;
+    };
+
+    if let Err(e) = store.register_download(manifest, source) {
+        warn!(
+            "Ignoring attachment of message {} from {}: {}",
+            message.id, source, e
+        );
+        return;
+    }
+
+    spawn_download(network.clone(), store.clone(), (**manifest).clone(), source);
+}
+
+/// Fetches the missing chunks of an attachment in the background.
+fn spawn_download(
+    network: NetworkHandle,
+    store: Arc<AttachmentStore>,
+    manifest: AttachmentManifest,
+    source: PeerId,
+) {
+    tokio::spawn(async move {
+        if !store.begin_download(&manifest.hash) {
+            return;
+        }
+
+        match download(&network, &store, &manifest, source).await {
+            Ok(()) => info!(
+                "Received attachment {} ({}) from {}",
+                manifest.file_name, manifest.hash, source
+            ),
+            Err(e) => debug!(
+                "Attachment {} from {} is incomplete, will resume later: {}",
+                manifest.hash, source, e
+            ),
+        }
+
+        store.end_download(&manifest.hash);
+    });
+}
+
+/// Fetches each missing chunk from the source and completes the attachment.
+async fn download(
+    network: &NetworkHandle,
+    store: &AttachmentStore,
+    manifest: &AttachmentManifest,
+    source: PeerId,
+) -> Result<()> {
+    for index in store.missing_chunks(manifest) {
+        let Some(data) = network
+            .fetch_attachment_chunk(source, manifest.hash.clone(), index)
+            .await?
+        else {
+            bail!("{} no longer has chunk {}", source, index);
+        };
+        store.write_chunk(manifest, index, &data)?;
+    }
+
+    store.complete_download(&manifest.hash)
+}
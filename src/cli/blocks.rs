@@ -0,0 +1,80 @@
+//! This module implements blocking and unblocking peers.
+//!
+//! Blocked peers are dropped by the network layer and the sync engine, and the
+//! block list is sent to our mailboxes so they stop storing messages from them.
+use anyhow::{bail, Result};
+use libp2p::PeerId;
+use tracing::error;
+
+use crate::storage::ContactDirection;
+
+use super::commands::Node;
+
+impl Node {
+    /// Adds a peer to the block list.
+    ///
+    /// Pending friend and message requests from the peer are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer to block.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the peer was already blocked.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the peer is ourselves or the
+    /// block list cannot be updated.
+    pub async fn block_peer(&self, peer_id: PeerId) -> Result<bool> {
+        if peer_id == self.identity.peer_id {
+            bail!("Cannot block yourself");
+        }
+
+        if !self.blocks.block(&peer_id).await? {
+            return Ok(false);
+        }
+
+        self.contacts
+            .remove_pending(ContactDirection::Incoming, &peer_id)
+            .await?;
+        self.requests.remove_request(&peer_id).await?;
+        self.sync_blocks();
+        Ok(true)
+    }
+
+    /// Removes a peer from the block list.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer to unblock.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the peer was not blocked.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block list cannot be updated.
+    pub async fn unblock_peer(&self, peer_id: PeerId) -> Result<bool> {
+        if !self.blocks.unblock(&peer_id).await? {
+            return Ok(false);
+        }
+
+        self.sync_blocks();
+        Ok(true)
+    }
+
+    /// Sends the changed block list to our mailboxes in the background.
+    fn sync_blocks(&self) {
+        let sync_engine = self.sync_engine.clone();
+        tokio::spawn(async move {
+            let mut engine = sync_engine.lock().await;
+            engine.mark_blocks_changed();
+            if let Err(e) = engine.sync_blocks_to_mailboxes().await {
+                error!("Failed to send the block list to mailboxes: {}", e);
+            }
+        });
+    }
+}
@@ -2,9 +2,12 @@
 //! application, particularly for the CLI and TUI.
 use crate::crypto::Identity;
 use crate::network::NetworkHandle;
-use crate::storage::{FriendsStore, MessageStore, OutboxStore};
+use crate::storage::{
+    AttachmentStore, BlockListStore, ContactRequestsStore, DeviceStore, FriendsStore, GroupsStore,
+    MessageRequestsStore, MessageStore, OutboxStore, SeenTracker,
+};
 use crate::sync::SyncEngine;
-use crate::types::{EncryptedMessage, Friend, Message};
+use crate::types::{EncryptedMessage, Group, Message};
 use anyhow::Result;
 use libp2p::PeerId;
 use std::collections::HashSet;
@@ -33,10 +36,28 @@ pub struct Node {
     pub history: Arc<dyn MessageStore + Send + Sync>,
     /// The store for managing outgoing messages.
     pub outbox: Arc<dyn OutboxStore + Send + Sync>,
+    /// The tracker of messages that have already been received.
+    pub seen: Arc<dyn SeenTracker + Send + Sync>,
+    /// The store for managing pending friend requests.
+    pub contacts: Arc<dyn ContactRequestsStore + Send + Sync>,
+    /// The store for managing groups.
+    pub groups: Arc<dyn GroupsStore + Send + Sync>,
+    /// The store for attachments.
+    pub attachments: Arc<AttachmentStore>,
+    /// The block list.
+    pub blocks: Arc<dyn BlockListStore + Send + Sync>,
+    /// The store for messages from peers that are not friends.
+    pub requests: Arc<dyn MessageRequestsStore + Send + Sync>,
+    /// The devices linked to our identity.
+    pub devices: Arc<dyn DeviceStore + Send + Sync>,
+    /// The display name advertised in our contact card, if any.
+    pub display_name: Option<String>,
     /// The handle for interacting with the network layer.
     pub network: NetworkHandle,
     /// The sender for sending notifications to the TUI.
     pub ui_notify_tx: mpsc::UnboundedSender<UiNotification>,
+    /// The sender for sending notifications to the web UI.
+    pub web_notify_tx: mpsc::UnboundedSender<UiNotification>,
     /// The synchronization engine.
     pub sync_engine: Arc<Mutex<SyncEngine>>,
 }
@@ -57,19 +78,38 @@ pub enum UiNotification {
         /// The new delivery status.
         new_status: crate::types::DeliveryStatus,
     },
+    /// A friend request has been received.
+    ContactRequestReceived {
+        /// The ID of the requesting peer.
+        peer_id: PeerId,
+        /// The display name from the requester's contact card.
+        display_name: Option<String>,
+    },
+    /// A friend request has been accepted and the peer is now a friend.
+    ContactAccepted(PeerId),
+    /// A friend request we sent has been rejected.
+    ContactRejected(PeerId),
+    /// A peer that is not a friend sent us a message, which awaits acceptance.
+    MessageRequestReceived(PeerId),
+    /// We joined a group, or the members of a group changed.
+    GroupUpdated(Group),
+    /// The key of a verified friend changed, so the friend is no longer verified.
+    VerifiedKeyChanged(PeerId),
+    /// Messages were deleted by their sender.
+    MessagesDeleted(Vec<uuid::Uuid>),
 }
 
 impl Node {
     /// Forwards a message to a set of mailboxes.
     ///
     /// This function attempts to deliver a message to a set of mailboxes for a
-    /// given friend. It will try to deliver the message to at least two mailboxes
-    /// for redundancy.
+    /// given recipient. It will try to deliver the message to at least two
+    /// mailboxes for redundancy.
     ///
     /// # Arguments
     ///
     /// * `message` - The message to forward.
-    /// * `friend` - The friend to whom the message is being sent.
+    /// * `recipient_public_key` - The E2E public key of the recipient, which addresses its mailbox.
     /// * `providers` - The set of mailboxes to try.
     ///
     /// # Returns
@@ -78,7 +118,7 @@ impl Node {
     pub async fn forward_to_mailboxes(
         &self,
         message: &Message,
-        friend: &Friend,
+        recipient_public_key: &[u8],
         providers: &HashSet<PeerId>,
     ) -> Result<MailboxDeliveryResult> {
         if providers.is_empty() {
@@ -91,8 +131,8 @@ impl Node {
         );
 
         let recipient_hash =
-            crate::crypto::StorageEncryption::derive_recipient_hash(&friend.e2e_public_key);
-        let encrypted_msg = EncryptedMessage {
+            crate::crypto::StorageEncryption::derive_recipient_hash(recipient_public_key);
+        let mut encrypted_msg = EncryptedMessage {
             id: message.id,
             sender: self.identity.peer_id,
             recipient_hash,
@@ -100,7 +140,10 @@ impl Node {
             timestamp: message.timestamp,
             nonce: message.nonce,
             sender_pub_key: self.identity.hpke_public_key(),
+            signature: Vec::new(),
+            group_id: message.group_id,
         };
+        encrypted_msg.signature = self.identity.sign_mailbox_message(&encrypted_msg)?;
 
         // Try to send to at least 2 mailboxes for redundancy
         let min_replicas = 2;
@@ -0,0 +1,268 @@
+//! This module implements the friend-request handshake on top of the contact protocol.
+//!
+//! A request carries the requester's signed contact card. Accepting it stores
+//! the requester as a friend and answers with our own card, which makes the
+//! requester store us as a friend in turn.
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use libp2p::PeerId;
+use tracing::{info, warn};
+
+use crate::storage::{ContactDirection, PendingContact};
+use crate::types::{ContactCard, ContactRequest, Friend};
+
+use super::commands::{Node, UiNotification};
+
+impl Node {
+    /// Sends a friend request to a peer.
+    ///
+    /// If the peer has already sent us a request, it is accepted instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer to befriend.
+    ///
+    /// # Returns
+    ///
+    /// `Some(Friend)` if a pending request from the peer was accepted, or
+    /// `None` if a new request was sent.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the peer is already a friend, is
+    /// not connected, or the request cannot be stored.
+    pub async fn send_contact_request(&self, peer_id: PeerId) -> Result<Option<Friend>> {
+        if peer_id == self.identity.peer_id {
+            bail!("Cannot send a friend request to yourself");
+        }
+
+        if self.friends.get_friend(&peer_id).await?.is_some() {
+            bail!("{} is already a friend", peer_id);
+        }
+
+        if self
+            .contacts
+            .get_pending(ContactDirection::Incoming, &peer_id)
+            .await?
+            .is_some()
+        {
+            return self.accept_contact_request(peer_id).await.map(Some);
+        }
+
+        let card = self.identity.contact_card(self.display_name.clone())?;
+        self.network
+            .send_contact_request(peer_id, ContactRequest::Request { card })
+            .await?;
+
+        self.contacts
+            .add_pending(PendingContact {
+                peer_id,
+                direction: ContactDirection::Outgoing,
+                card: None,
+                created_at: Utc::now().timestamp_millis(),
+            })
+            .await?;
+
+        Ok(None)
+    }
+
+    /// Accepts a pending friend request.
+    ///
+    /// The requester is told first, so that a peer that cannot be reached
+    /// keeps its pending request and can be accepted again later.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the requester.
+    ///
+    /// # Returns
+    ///
+    /// The newly added `Friend`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no pending request from
+    /// the peer, the peer is not connected, or the friend cannot be stored.
+    pub async fn accept_contact_request(&self, peer_id: PeerId) -> Result<Friend> {
+        let card = self
+            .contacts
+            .get_pending(ContactDirection::Incoming, &peer_id)
+            .await?
+            .and_then(|pending| pending.card)
+            .ok_or_else(|| anyhow!("No pending friend request from {}", peer_id))?;
+
+        let our_card = self.identity.contact_card(self.display_name.clone())?;
+        self.network
+            .send_contact_request(peer_id, ContactRequest::Accept { card: our_card })
+            .await?;
+
+        let friend = friend_from_card(card);
+        self.save_friend(friend.clone()).await?;
+        self.contacts
+            .remove_pending(ContactDirection::Incoming, &peer_id)
+            .await?;
+
+        info!("Accepted friend request from {}", peer_id);
+        Ok(friend)
+    }
+
+    /// Rejects a pending friend request.
+    ///
+    /// The requester is notified if it can be reached; the request is removed
+    /// either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the requester.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no pending request from
+    /// the peer or it cannot be removed.
+    pub async fn reject_contact_request(&self, peer_id: PeerId) -> Result<()> {
+        if self
+            .contacts
+            .get_pending(ContactDirection::Incoming, &peer_id)
+            .await?
+            .is_none()
+        {
+            bail!("No pending friend request from {}", peer_id);
+        }
+
+        let our_card = self.identity.contact_card(self.display_name.clone())?;
+        if let Err(e) = self
+            .network
+            .send_contact_request(peer_id, ContactRequest::Reject { card: our_card })
+            .await
+        {
+            warn!(
+                "Failed to notify {} of rejected friend request: {}",
+                peer_id, e
+            );
+        }
+
+        self.contacts
+            .remove_pending(ContactDirection::Incoming, &peer_id)
+            .await
+    }
+
+    /// Handles a verified contact request received from the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `ContactRequest` to handle.
+    ///
+    /// # Returns
+    ///
+    /// A `UiNotification` describing the outcome, if the UI should be told.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if updating storage fails.
+    pub async fn handle_contact_request(
+        &self,
+        request: ContactRequest,
+    ) -> Result<Option<UiNotification>> {
+        match request {
+            ContactRequest::Request { card } => {
+                let peer_id = card.peer_id;
+
+                if self.blocks.is_blocked(&peer_id).await? {
+                    info!("Ignoring friend request from blocked peer {}", peer_id);
+                    return Ok(None);
+                }
+
+                // Both sides asked at the same time; treat it as mutual consent.
+                if self
+                    .contacts
+                    .get_pending(ContactDirection::Outgoing, &peer_id)
+                    .await?
+                    .is_some()
+                {
+                    self.store_pending_incoming(card).await?;
+                    let friend = self.accept_contact_request(peer_id).await?;
+                    self.contacts
+                        .remove_pending(ContactDirection::Outgoing, &peer_id)
+                        .await?;
+                    return Ok(Some(UiNotification::ContactAccepted(friend.peer_id)));
+                }
+
+                if self.friends.get_friend(&peer_id).await?.is_some() {
+                    info!("Ignoring friend request from existing friend {}", peer_id);
+                    return Ok(None);
+                }
+
+                let display_name = card.display_name.clone();
+                self.store_pending_incoming(card).await?;
+                Ok(Some(UiNotification::ContactRequestReceived {
+                    peer_id,
+                    display_name,
+                }))
+            }
+            ContactRequest::Accept { card } => {
+                let peer_id = card.peer_id;
+
+                if self
+                    .contacts
+                    .get_pending(ContactDirection::Outgoing, &peer_id)
+                    .await?
+                    .is_none()
+                {
+                    warn!("Ignoring unsolicited friend acceptance from {}", peer_id);
+                    return Ok(None);
+                }
+
+                self.save_friend(friend_from_card(card)).await?;
+                self.contacts
+                    .remove_pending(ContactDirection::Outgoing, &peer_id)
+                    .await?;
+
+                info!("{} accepted our friend request", peer_id);
+                Ok(Some(UiNotification::ContactAccepted(peer_id)))
+            }
+            ContactRequest::Reject { card } => {
+                let peer_id = card.peer_id;
+
+                if self
+                    .contacts
+                    .get_pending(ContactDirection::Outgoing, &peer_id)
+                    .await?
+                    .is_none()
+                {
+                    return Ok(None);
+                }
+
+                self.contacts
+                    .remove_pending(ContactDirection::Outgoing, &peer_id)
+                    .await?;
+
+                info!("{} rejected our friend request", peer_id);
+                Ok(Some(UiNotification::ContactRejected(peer_id)))
+            }
+        }
+    }
+
+    /// Stores an incoming friend request.
+    async fn store_pending_incoming(&self, card: ContactCard) -> Result<()> {
+        self.contacts
+            .add_pending(PendingContact {
+                peer_id: card.peer_id,
+                direction: ContactDirection::Incoming,
+                card: Some(card),
+                created_at: Utc::now().timestamp_millis(),
+            })
+            .await
+    }
+}
+
+/// Builds a `Friend` record from a verified contact card.
+fn friend_from_card(card: ContactCard) -> Friend {
+    Friend {
+        peer_id: card.peer_id,
+        e2e_public_key: card.e2e_public_key,
+        nickname: card.display_name,
+        verified: false,
+        devices: Vec::new(),
+        devices_timestamp: 0,
+    }
+}
@@ -0,0 +1,301 @@
+//! This module implements deleting messages and conversations.
+//!
+//! Deleting locally removes the messages from the history and its search
+//! index, forgets that they were seen and drops any copies still waiting in
+//! the outbox. The history keeps their IDs, so copies that arrive later from
+//! mailboxes or outbox retries are not stored again. Deleting for everyone
+//! also sends a `DeleteRequest`, signed by this device, to every device of
+//! the friend and to our other devices, like a `KeyUpdate`. Only messages we sent can be deleted for everyone, and the
+//! recipients check that the messages they delete were sent by the author of
+//! the request.
+use anyhow::{bail, Result};
+use libp2p::PeerId;
+use std::collections::HashSet;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::crypto::signing;
+use crate::storage::MessageStore;
+use crate::types::{DeleteRequest, Friend, Message};
+
+use super::commands::Node;
+use super::devices::{device_copy_id, own_devices};
+use super::send::compose_body;
+
+/// The maximum number of message IDs in one delete request.
+const DELETE_REQUEST_CHUNK: usize = 500;
+
+impl Node {
+    /// Deletes a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg_id` - The `Uuid` of the message.
+    /// * `everyone` - Whether to also delete it on the friend's devices and
+    ///   our other devices.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the message was not found.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message is deleted for
+    /// everyone but was not sent by us to a friend, or storage fails.
+    pub async fn delete_message(&self, msg_id: &Uuid, everyone: bool) -> Result<bool> {
+        let Some(message) = self.history.get_message_by_id(msg_id).await? else {
+            return Ok(false);
+        };
+
+        let friend = if everyone {
+            if message.sender != self.identity.peer_id || message.group_id.is_some() {
+                bail!("Only direct messages we sent can be deleted for everyone");
+            }
+            Some(self.deletion_recipient(&message.recipient).await?)
+        } else {
+            None
+        };
+
+        let Some(deleted) = self.history.delete_message(msg_id).await? else {
+            return Ok(false);
+        };
+        if let Some(friend) = friend {
+            self.request_deletion(&friend, &[deleted.id]).await?;
+        }
+        self.forget_deleted(&[deleted]).await;
+        Ok(true)
+    }
+
+    /// Deletes the whole conversation with a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer.
+    /// * `everyone` - Whether to also delete the messages we sent on the
+    ///   friend's devices and our other devices.
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted messages.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the conversation is deleted for
+    /// everyone but the peer is not a friend, or storage fails.
+    pub async fn delete_conversation(&self, peer_id: &PeerId, everyone: bool) -> Result<usize> {
+        let friend = if everyone {
+            Some(self.deletion_recipient(peer_id).await?)
+        } else {
+            None
+        };
+
+        let deleted = self
+            .history
+            .delete_conversation(&self.identity.peer_id, peer_id)
+            .await?;
+        if let Some(friend) = friend {
+            let sent: Vec<Uuid> = deleted
+                .iter()
+                .filter(|msg| msg.sender == self.identity.peer_id)
+                .map(|msg| msg.id)
+                .collect();
+            self.request_deletion(&friend, &sent).await?;
+        }
+        self.forget_deleted(&deleted).await;
+
+        info!(
+            "Deleted {} messages of the conversation with {}",
+            deleted.len(),
+            peer_id
+        );
+        Ok(deleted.len())
+    }
+
+    /// Returns the friend to send a delete request to.
+    async fn deletion_recipient(&self, peer_id: &PeerId) -> Result<Friend> {
+        match self.friends.get_friend(peer_id).await? {
+            Some(friend) => Ok(friend),
+            None => bail!("{} is not a friend; delete the messages locally", peer_id),
+        }
+    }
+
+    /// Sends delete requests for messages we sent to all devices of a friend
+    /// and to our other devices.
+    ///
+    /// The friend's linked devices and our other devices store copies, so
+    /// each request lists the IDs of the copies on its device.
+    async fn request_deletion(&self, friend: &Friend, message_ids: &[Uuid]) -> Result<()> {
+        let own = own_devices(&self.identity, self.devices.as_ref()).await?;
+
+        let mut sealed = Vec::new();
+        for chunk in message_ids.chunks(DELETE_REQUEST_CHUNK) {
+            for device in friend.all_devices() {
+                let ids = if device.peer_id == friend.peer_id {
+                    chunk.to_vec()
+                } else {
+                    chunk
+                        .iter()
+                        .map(|id| device_copy_id(id, &device.peer_id))
+                        .collect()
+                };
+                let request = self.identity.sign_delete_request(ids)?;
+                sealed.push(compose_body(&self.identity, &device, request.to_body()?)?.1);
+            }
+
+            // Our devices store sent copies under the original IDs.
+            if !own.is_empty() {
+                let body = self
+                    .identity
+                    .sign_delete_request(chunk.to_vec())?
+                    .to_body()?;
+                for device in &own {
+                    sealed.push(compose_body(&self.identity, device, body.clone())?.1);
+                }
+            }
+        }
+
+        info!(
+            "Asking {} devices to delete {} messages",
+            friend.all_devices().len() + own.len(),
+            message_ids.len()
+        );
+        self.deliver(sealed).await;
+        Ok(())
+    }
+
+    /// Forgets that deleted messages were seen, and drops the copies still
+    /// waiting in the outbox. Failures are logged.
+    async fn forget_deleted(&self, deleted: &[Message]) {
+        for msg in deleted {
+            if let Err(e) = self.seen.unmark_seen(&msg.id).await {
+                warn!("Failed to forget that message {} was seen: {}", msg.id, e);
+            }
+        }
+
+        let pending = match self.outbox.get_pending().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!("Failed to read the outbox: {}", e);
+                return;
+            }
+        };
+        let recipients: HashSet<PeerId> = pending.iter().map(|msg| msg.recipient).collect();
+        let mut ids: HashSet<Uuid> = HashSet::new();
+        for msg in deleted {
+            ids.insert(msg.id);
+            ids.extend(
+                recipients
+                    .iter()
+                    .map(|device| device_copy_id(&msg.id, device)),
+            );
+        }
+
+        for msg in pending.iter().filter(|msg| ids.contains(&msg.id)) {
+            if let Err(e) = self.outbox.remove_pending(&msg.id).await {
+                warn!("Failed to remove message {} from the outbox: {}", msg.id, e);
+            }
+        }
+    }
+}
+
+/// Applies a delete request received from a friend's device or one of our
+/// devices.
+///
+/// Only the listed direct messages sent by `author` are deleted; the others
+/// are logged and kept. The history keeps the IDs of the deleted messages,
+/// so that copies still held by mailboxes are not stored again.
+///
+/// # Arguments
+///
+/// * `history` - The message history.
+/// * `request` - The delete request.
+/// * `sender` - The `PeerId` of the device that sent the request.
+/// * `author` - The `PeerId` the deleted messages are stored as sent by:
+///   the friend, or ourselves for requests from our own devices.
+///
+/// # Returns
+///
+/// The IDs of the deleted messages.
+///
+/// # Errors
+///
+/// This function will return an error if the request is not signed by the
+/// device that sent it, or storage fails.
+pub(super) async fn apply_delete_request(
+    history: &(dyn MessageStore + Send + Sync),
+    request: &DeleteRequest,
+    sender: &PeerId,
+    author: &PeerId,
+) -> Result<Vec<Uuid>> {
+    if request.peer_id != *sender {
+        bail!(
+            "Delete request of {} was sent by {}",
+            request.peer_id,
+            sender
+        );
+    }
+    signing::verify_delete_request(request)?;
+
+    let mut deleted = Vec::new();
+    for msg_id in &request.message_ids {
+        match history.get_message_by_id(msg_id).await? {
+            Some(msg) if msg.sender == *author && msg.group_id.is_none() => {
+                history.delete_message(msg_id).await?;
+                deleted.push(*msg_id);
+            }
+            Some(_) => warn!(
+                "Ignoring request of {} to delete message {}, which {} did not send",
+                sender, msg_id, author
+            ),
+            None => {}
+        }
+    }
+
+    if !deleted.is_empty() {
+        info!("{} deleted {} messages", sender, deleted.len());
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Identity;
+    use crate::storage::MessageHistory;
+    use crate::types::DeliveryStatus;
+
+    #[tokio::test]
+    async fn deleted_messages_do_not_come_back() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let history = MessageHistory::new(db, None).unwrap();
+        let friend = Identity::generate().unwrap();
+        let message = Message {
+            id: Uuid::new_v4(),
+            sender: friend.peer_id,
+            recipient: PeerId::random(),
+            timestamp: 1,
+            content: b"regretted".to_vec(),
+            nonce: 0,
+            delivery_status: DeliveryStatus::Delivered,
+            signature: Vec::new(),
+            group_id: None,
+            attachment: None,
+            sender_pub_key: Vec::new(),
+        };
+        history.store_message(message.clone()).await.unwrap();
+
+        let request = friend.sign_delete_request(vec![message.id]).unwrap();
+        let deleted = apply_delete_request(&history, &request, &friend.peer_id, &friend.peer_id)
+            .await
+            .unwrap();
+        assert_eq!(deleted, [message.id]);
+
+        // A copy from another mailbox or an outbox retry arrives afterwards.
+        history.store_message(message.clone()).await.unwrap();
+        assert!(history
+            .get_message_by_id(&message.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,757 @@
+//! This module implements linked devices, which share one identity.
+//!
+//! Each device has its own libp2p keypair and E2E key. The first device is the
+//! primary device, whose Peer ID is the identity friends know. A new device
+//! joins the primary, the primary links it and sends a signed `DeviceList` to
+//! its friends and its devices. Friends then encrypt each message for every
+//! device, and each device sends a `SentCopy` of what it sends to the others,
+//! so that all of them show the whole conversation.
+//!
+//! The copies of a message have IDs derived from the message ID and the
+//! device, so that they can be found again to delete them.
+//!
+//! Each device fetches its mail with its own E2E key, so mailboxes keep a
+//! separate queue per device and one device's Ack only removes its own copies.
+//!
+//! Friends are added on each device separately, and group messages only reach
+//! the device they are sent to.
+use anyhow::{bail, Result};
+use libp2p::PeerId;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::crypto::{signing, Identity};
+use crate::storage::{DeviceStore, FriendsStore, MessageStore};
+use crate::types::{
+    DeleteRequest, DeliveryStatus, Device, DeviceList, Friend, KeyUpdate, Message, SentCopy,
+};
+
+use super::commands::{Node, UiNotification};
+use super::delete::apply_delete_request;
+use super::keys::apply_key_update;
+use super::send::{compose_body, compose_body_with_id};
+
+/// The devices linked to our identity, as seen from this device.
+#[derive(Clone, Debug)]
+pub struct LinkedDevices {
+    /// The primary device this device joined, or `None` on the primary device.
+    pub primary: Option<Device>,
+    /// The devices in the primary's latest list.
+    pub devices: Vec<Device>,
+    /// Whether this device joined a primary that has not linked it yet.
+    pub pending: bool,
+}
+
+/// The device a direct message came from.
+#[derive(Clone, Debug)]
+pub enum Origin {
+    /// One of a friend's devices.
+    Friend {
+        /// The friend.
+        friend: Friend,
+        /// The device that sent the message.
+        device: Device,
+    },
+    /// One of our own devices.
+    OwnDevice(Device),
+    /// A peer that is neither a friend nor one of our devices.
+    Unknown,
+}
+
+/// A direct message opened by `open_direct_message`.
+pub struct DirectMessage {
+    /// The message to store in the history, if any.
+    pub message: Option<Message>,
+    /// The device that sent the message, which holds its attachment.
+    pub source: PeerId,
+    /// The notification to show, if any.
+    pub notification: Option<UiNotification>,
+}
+
+impl Node {
+    /// Lists the devices linked to our identity.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if storage cannot be read.
+    pub async fn linked_devices(&self) -> Result<LinkedDevices> {
+        let primary = self.devices.get_primary().await?;
+        let list = self.devices.get_device_list().await?;
+        let pending = primary.is_some()
+            && !list
+                .as_ref()
+                .is_some_and(|list| list.contains(&self.identity.peer_id));
+
+        Ok(LinkedDevices {
+            primary,
+            devices: list.map(|list| list.devices).unwrap_or_default(),
+            pending,
+        })
+    }
+
+    /// Links a device to our identity, which must be the primary device.
+    ///
+    /// The device must have joined us first. The new device list is sent to
+    /// our friends and our devices.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The device to link.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if this device joined a primary
+    /// device, or the list cannot be signed or stored.
+    pub async fn link_device(&self, device: Device) -> Result<()> {
+        if let Some(primary) = self.devices.get_primary().await? {
+            bail!(
+                "This device joined {}; link devices from the primary device",
+                primary.peer_id
+            );
+        }
+        if device.peer_id == self.identity.peer_id {
+            bail!("Cannot link this device to itself");
+        }
+
+        let mut devices = self.current_devices().await?;
+        devices.retain(|d| d.peer_id != device.peer_id);
+        devices.push(device);
+
+        let list = self.identity.sign_device_list(devices)?;
+        self.devices.set_device_list(Some(&list)).await?;
+        self.announce_device_list(&list, &[]).await
+    }
+
+    /// Unlinks a device from our identity, which must be the primary device.
+    ///
+    /// The new device list is sent to our friends, our remaining devices and
+    /// the unlinked device, which then leaves.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the device.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the device was not linked.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the list cannot be signed or stored.
+    pub async fn unlink_device(&self, peer_id: &PeerId) -> Result<bool> {
+        let mut devices = self.current_devices().await?;
+        let Some(index) = devices.iter().position(|d| d.peer_id == *peer_id) else {
+            return Ok(false);
+        };
+        let removed = devices.remove(index);
+
+        let list = self.identity.sign_device_list(devices)?;
+        self.devices.set_device_list(Some(&list)).await?;
+        self.announce_device_list(&list, &[removed]).await?;
+        Ok(true)
+    }
+
+    /// Sends the current device list to our friends and our devices again,
+    /// for friends added since the last device was linked.
+    ///
+    /// # Returns
+    ///
+    /// The number of devices the list was sent to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if this device is not a primary
+    /// device with linked devices.
+    pub async fn announce_devices(&self) -> Result<usize> {
+        if self.devices.get_primary().await?.is_some() {
+            bail!("Only the primary device announces its devices");
+        }
+        let Some(list) = self.devices.get_device_list().await? else {
+            bail!("No devices are linked");
+        };
+
+        let recipients = self.friends.list_friends().await?.len() + list.devices.len();
+        self.announce_device_list(&list, &[]).await?;
+        Ok(recipients)
+    }
+
+    /// Joins the primary device of our identity.
+    ///
+    /// The device is linked once the primary sends a device list that contains it.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The primary device.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if this device has linked devices
+    /// of its own, or storage fails.
+    pub async fn join_primary(&self, primary: Device) -> Result<()> {
+        if primary.peer_id == self.identity.peer_id {
+            bail!("Cannot join this device");
+        }
+        if self.devices.get_primary().await?.is_none() && !self.current_devices().await?.is_empty()
+        {
+            bail!("This device is the primary device of its own linked devices");
+        }
+
+        self.devices.set_primary(Some(&primary)).await?;
+        self.devices.set_device_list(None).await
+    }
+
+    /// Leaves the primary device this device joined.
+    ///
+    /// # Returns
+    ///
+    /// `false` if this device had not joined a primary device.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if storage fails.
+    pub async fn leave_primary(&self) -> Result<bool> {
+        if self.devices.get_primary().await?.is_none() {
+            return Ok(false);
+        }
+
+        self.devices.set_primary(None).await?;
+        self.devices.set_device_list(None).await?;
+        Ok(true)
+    }
+
+    /// Sends copies of a message to a friend's linked devices and to our other devices.
+    ///
+    /// The friend's primary device gets the message itself. Copies that cannot
+    /// be built are logged and skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend` - The recipient.
+    /// * `message` - The message, before it was sealed.
+    pub async fn queue_device_copies(&self, friend: &Friend, message: &Message) {
+        let copies = match own_devices(&self.identity, self.devices.as_ref()).await {
+            Ok(own) => compose_device_copies(&self.identity, friend, &own, message),
+            Err(e) => Err(e),
+        };
+
+        match copies {
+            Ok(copies) => self.deliver(copies).await,
+            Err(e) => warn!(
+                "Failed to copy message {} to the other devices: {}",
+                message.id, e
+            ),
+        }
+    }
+
+    /// Returns the devices in our own device list.
+    async fn current_devices(&self) -> Result<Vec<Device>> {
+        Ok(self
+            .devices
+            .get_device_list()
+            .await?
+            .map(|list| list.devices)
+            .unwrap_or_default())
+    }
+
+    /// Sends a device list to all devices of our friends, our linked devices
+    /// and any extra devices.
+    async fn announce_device_list(&self, list: &DeviceList, extra: &[Device]) -> Result<()> {
+        let body = list.to_body()?;
+        let friends = self.friends.list_friends().await?;
+        let sealed = friends
+            .iter()
+            .flat_map(Friend::all_devices)
+            .chain(list.devices.iter().cloned())
+            .chain(extra.iter().cloned())
+            .map(|device| Ok(compose_body(&self.identity, &device, body.clone())?.1))
+            .collect::<Result<Vec<_>>>()?;
+
+        info!(
+            "Announcing {} linked devices to {} friends",
+            list.devices.len(),
+            friends.len()
+        );
+        self.deliver(sealed).await;
+        Ok(())
+    }
+
+    /// Queues sealed messages in the outbox and attempts their direct delivery
+    /// in the background.
+    pub(super) async fn deliver(&self, sealed: Vec<Message>) {
+        for message in sealed {
+            if let Err(e) = self.outbox.add_pending(message.clone()).await {
+                warn!("Failed to queue message {}: {}", message.id, e);
+                continue;
+            }
+
+            let network = self.network.clone();
+            let outbox = self.outbox.clone();
+            tokio::spawn(async move {
+                let message_id = message.id;
+                match network.send_message(message.recipient, message).await {
+                    Ok(()) => {
+                        if let Err(e) = outbox.remove_pending(&message_id).await {
+                            debug!("Failed to remove delivered message from outbox: {}", e);
+                        }
+                    }
+                    Err(e) => debug!("Direct send failed, will retry via sync: {}", e),
+                }
+            });
+        }
+    }
+}
+
+/// Returns our other devices, which get copies of the messages we send.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity.
+/// * `devices` - The linked devices store.
+///
+/// # Returns
+///
+/// On the primary device, its linked devices. On a linked device, the primary
+/// device and the other linked devices, once the primary has linked it.
+///
+/// # Errors
+///
+/// This function will return an error if storage cannot be read.
+pub async fn own_devices(
+    identity: &Identity,
+    devices: &(dyn DeviceStore + Send + Sync),
+) -> Result<Vec<Device>> {
+    let list = devices.get_device_list().await?;
+    let Some(primary) = devices.get_primary().await? else {
+        return Ok(list.map(|list| list.devices).unwrap_or_default());
+    };
+
+    match list {
+        Some(list) if list.contains(&identity.peer_id) => Ok(std::iter::once(primary)
+            .chain(
+                list.devices
+                    .into_iter()
+                    .filter(|device| device.peer_id != identity.peer_id),
+            )
+            .collect()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Builds the copies of a direct message for a friend's linked devices and
+/// our other devices.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity, used to encrypt and sign the copies.
+/// * `friend` - The recipient.
+/// * `own_devices` - Our other devices.
+/// * `message` - The message, before it was sealed.
+///
+/// # Returns
+///
+/// The sealed copies to deliver, one per device.
+///
+/// # Errors
+///
+/// This function will return an error if a copy cannot be encrypted or signed.
+pub fn compose_device_copies(
+    identity: &Identity,
+    friend: &Friend,
+    own_devices: &[Device],
+    message: &Message,
+) -> Result<Vec<Message>> {
+    let body = message.body()?;
+    let mut copies = friend
+        .devices
+        .iter()
+        .map(|device| {
+            let id = device_copy_id(&message.id, &device.peer_id);
+            Ok(compose_body_with_id(identity, device, id, body.clone())?.1)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if !own_devices.is_empty() {
+        let copy = SentCopy {
+            id: message.id,
+            recipient: friend.peer_id,
+            timestamp: message.timestamp,
+            nonce: message.nonce,
+            body,
+        }
+        .to_body()?;
+        for device in own_devices {
+            let id = device_copy_id(&message.id, &device.peer_id);
+            copies.push(compose_body_with_id(identity, device, id, copy.clone())?.1);
+        }
+    }
+
+    Ok(copies)
+}
+
+/// Returns the ID of the copy of a message sent to another device.
+///
+/// # Arguments
+///
+/// * `message_id` - The ID of the message.
+/// * `device` - The `PeerId` of the device the copy is sent to.
+pub(super) fn device_copy_id(message_id: &Uuid, device: &PeerId) -> Uuid {
+    let mut hasher = Sha256::new();
+    hasher.update(b"p2p-chat/device-copy/v1");
+    hasher.update(message_id.as_bytes());
+    hasher.update(device.to_bytes());
+    let digest = hasher.finalize();
+
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_random_bytes(bytes).into_uuid()
+}
+
+/// Finds the device a direct message came from.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity.
+/// * `friends` - The friends store.
+/// * `devices` - The linked devices store.
+/// * `sender` - The `PeerId` of the sender.
+///
+/// # Errors
+///
+/// This function will return an error if storage cannot be read.
+pub async fn resolve_origin(
+    identity: &Identity,
+    friends: &(dyn FriendsStore + Send + Sync),
+    devices: &(dyn DeviceStore + Send + Sync),
+    sender: &PeerId,
+) -> Result<Origin> {
+    // The primary is known before it links us, so its first list can be read.
+    if let Some(primary) = devices.get_primary().await? {
+        if primary.peer_id == *sender {
+            return Ok(Origin::OwnDevice(primary));
+        }
+    }
+    if let Some(device) = own_devices(identity, devices)
+        .await?
+        .into_iter()
+        .find(|device| device.peer_id == *sender)
+    {
+        return Ok(Origin::OwnDevice(device));
+    }
+
+    if let Some(friend) = friends.get_friend(sender).await? {
+        let device = friend.primary_device();
+        return Ok(Origin::Friend { friend, device });
+    }
+    if let Some((friend, device)) = friends.find_by_device(sender).await? {
+        return Ok(Origin::Friend { friend, device });
+    }
+
+    Ok(Origin::Unknown)
+}
+
+/// Decrypts a direct message and applies the key updates, device lists, sent
+/// copies and delete requests it may carry.
+///
+/// Messages from a friend's linked device are shown as coming from the friend.
+/// Updates the sender may not make are logged and dropped.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity.
+/// * `friends` - The friends store.
+/// * `devices` - The linked devices store.
+/// * `history` - The message history, for delete requests.
+/// * `origin` - The device the message came from, from `resolve_origin`.
+/// * `message` - The authenticated message, with its content still encrypted.
+///
+/// # Errors
+///
+/// This function will return an error if the sender is unknown or the
+/// message cannot be decrypted.
+pub async fn open_direct_message(
+    identity: &Identity,
+    friends: &(dyn FriendsStore + Send + Sync),
+    devices: &(dyn DeviceStore + Send + Sync),
+    history: &(dyn MessageStore + Send + Sync),
+    origin: Origin,
+    mut message: Message,
+) -> Result<DirectMessage> {
+    let source = message.sender;
+    let sender_key = match origin {
+        Origin::Friend { ref device, .. } | Origin::OwnDevice(ref device) => {
+            device.e2e_public_key.clone()
+        }
+        Origin::Unknown => bail!("{} is neither a friend nor one of our devices", source),
+    };
+
+    let body = identity.decrypt_from(&sender_key, &message.content)?;
+    message.signature = Vec::new();
+    message.sender_pub_key = Vec::new();
+    message.set_body(body);
+
+    let message_id = message.id;
+    let mut opened = DirectMessage {
+        message: None,
+        source,
+        notification: None,
+    };
+    match apply_body(identity, friends, devices, history, origin, message).await {
+        Ok((message, notification)) => {
+            opened.message = message;
+            opened.notification = notification;
+        }
+        Err(e) => warn!("Ignoring message {} from {}: {}", message_id, source, e),
+    }
+    Ok(opened)
+}
+
+/// Applies the body of a decrypted direct message.
+///
+/// # Returns
+///
+/// The message to store in the history, if any, and the notification to show, if any.
+async fn apply_body(
+    identity: &Identity,
+    friends: &(dyn FriendsStore + Send + Sync),
+    devices: &(dyn DeviceStore + Send + Sync),
+    history: &(dyn MessageStore + Send + Sync),
+    origin: Origin,
+    mut message: Message,
+) -> Result<(Option<Message>, Option<UiNotification>)> {
+    let source = message.sender;
+    match origin {
+        Origin::Friend { friend, device } => {
+            if let Some(update) = KeyUpdate::from_body(&message.content) {
+                if device.peer_id != friend.peer_id {
+                    bail!(
+                        "Linked device {} cannot rotate the key of {}",
+                        source,
+                        friend.peer_id
+                    );
+                }
+                if apply_key_update(identity, friends, &friend.peer_id, &update).await? {
+                    return Ok((
+                        None,
+                        Some(UiNotification::VerifiedKeyChanged(friend.peer_id)),
+                    ));
+                }
+            } else if let Some(list) = DeviceList::from_body(&message.content) {
+                if list.account != friend.peer_id || device.peer_id != friend.peer_id {
+                    bail!("Device list of {} was sent by {}", list.account, source);
+                }
+                signing::verify_device_list(&list)?;
+                if list.timestamp <= friend.devices_timestamp {
+                    debug!("Ignoring an outdated device list from {}", friend.peer_id);
+                    return Ok((None, None));
+                }
+                info!(
+                    "Friend {} has {} linked devices",
+                    friend.peer_id,
+                    list.devices.len()
+                );
+                friends
+                    .set_devices(&friend.peer_id, list.devices, list.timestamp)
+                    .await?;
+            } else if let Some(request) = DeleteRequest::from_body(&message.content) {
+                let deleted =
+                    apply_delete_request(history, &request, &source, &friend.peer_id).await?;
+                return Ok((None, deleted_notification(deleted)));
+            } else if SentCopy::from_body(&message.content).is_some() {
+                bail!("{} is not one of our devices", source);
+            } else {
+                // All devices of a friend share one conversation.
+                message.sender = friend.peer_id;
+                return Ok((Some(message), None));
+            }
+        }
+        Origin::OwnDevice(device) => {
+            if let Some(list) = DeviceList::from_body(&message.content) {
+                accept_device_list(identity, devices, &device, list).await?;
+            } else if let Some(update) = KeyUpdate::from_body(&message.content) {
+                apply_primary_key_update(identity, devices, &device, &update).await?;
+            } else if let Some(copy) = SentCopy::from_body(&message.content) {
+                let mut sent = Message {
+                    id: copy.id,
+                    sender: identity.peer_id,
+                    recipient: copy.recipient,
+                    timestamp: copy.timestamp,
+                    content: Vec::new(),
+                    nonce: copy.nonce,
+                    delivery_status: DeliveryStatus::Sent,
+                    signature: Vec::new(),
+                    group_id: None,
+                    attachment: None,
+                    sender_pub_key: Vec::new(),
+                };
+                sent.set_body(copy.body);
+                return Ok((Some(sent), None));
+            } else if let Some(request) = DeleteRequest::from_body(&message.content) {
+                let deleted =
+                    apply_delete_request(history, &request, &source, &identity.peer_id).await?;
+                return Ok((None, deleted_notification(deleted)));
+            } else {
+                bail!(
+                    "Our devices only exchange device lists, key updates, sent copies and \
+                     delete requests"
+                );
+            }
+        }
+        Origin::Unknown => bail!("{} is neither a friend nor one of our devices", source),
+    }
+
+    Ok((None, None))
+}
+
+/// Returns the notification of messages deleted by their sender, if any.
+fn deleted_notification(deleted: Vec<Uuid>) -> Option<UiNotification> {
+    (!deleted.is_empty()).then_some(UiNotification::MessagesDeleted(deleted))
+}
+
+/// Applies a device list sent by the primary device this device joined.
+///
+/// A list that no longer contains this device means it was unlinked, so it
+/// leaves the primary.
+async fn accept_device_list(
+    identity: &Identity,
+    devices: &(dyn DeviceStore + Send + Sync),
+    sender: &Device,
+    list: DeviceList,
+) -> Result<()> {
+    let primary = devices.get_primary().await?;
+    if primary.as_ref().map(|p| p.peer_id) != Some(sender.peer_id) || list.account != sender.peer_id
+    {
+        bail!(
+            "Device list of {} was sent by {}",
+            list.account,
+            sender.peer_id
+        );
+    }
+    signing::verify_device_list(&list)?;
+
+    if let Some(current) = devices.get_device_list().await? {
+        if current.timestamp >= list.timestamp {
+            debug!("Ignoring an outdated device list from {}", sender.peer_id);
+            return Ok(());
+        }
+    }
+
+    if list.contains(&identity.peer_id) {
+        info!(
+            "Linked to {} with {} devices",
+            list.account,
+            list.devices.len()
+        );
+        devices.set_device_list(Some(&list)).await
+    } else {
+        warn!("Unlinked by {}", list.account);
+        devices.set_primary(None).await?;
+        devices.set_device_list(None).await
+    }
+}
+
+/// Applies a key update sent by the primary device this device joined.
+async fn apply_primary_key_update(
+    identity: &Identity,
+    devices: &(dyn DeviceStore + Send + Sync),
+    sender: &Device,
+    update: &KeyUpdate,
+) -> Result<()> {
+    let is_primary = devices
+        .get_primary()
+        .await?
+        .is_some_and(|primary| primary.peer_id == sender.peer_id);
+    if !is_primary || update.peer_id != sender.peer_id {
+        bail!(
+            "Key update for {} was sent by {}",
+            update.peer_id,
+            sender.peer_id
+        );
+    }
+    signing::verify_key_update(update)?;
+
+    if sender.e2e_public_key == update.new_key {
+        // A repeated update.
+        return Ok(());
+    }
+    if sender.e2e_public_key != update.previous_key {
+        bail!(
+            "Key update does not replace the key we hold for {}",
+            sender.peer_id
+        );
+    }
+
+    identity.carry_over_sessions(&update.previous_key, &update.new_key)?;
+    let primary = Device {
+        peer_id: sender.peer_id,
+        e2e_public_key: update.new_key.clone(),
+    };
+    info!("Primary device {} rotated its key", sender.peer_id);
+    devices.set_primary(Some(&primary)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MessageHistory, SledDeviceStore, SledFriendsStore};
+
+    fn device() -> Device {
+        Device {
+            peer_id: PeerId::random(),
+            e2e_public_key: vec![1; 32],
+        }
+    }
+
+    #[tokio::test]
+    async fn outdated_device_lists_of_friends_are_ignored() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let friends = SledFriendsStore::new(db.clone(), None).unwrap();
+        let devices = SledDeviceStore::new(db.clone(), None).unwrap();
+        let history = MessageHistory::new(db, None).unwrap();
+        let identity = Identity::generate().unwrap();
+        let peer = Identity::generate().unwrap();
+        friends
+            .add_friend(Friend {
+                peer_id: peer.peer_id,
+                e2e_public_key: peer.hpke_public_key(),
+                nickname: None,
+                verified: false,
+                devices: Vec::new(),
+                devices_timestamp: 0,
+            })
+            .await
+            .unwrap();
+
+        let older = peer.sign_device_list(vec![device()]).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let newer = peer.sign_device_list(vec![device(), device()]).unwrap();
+
+        for list in [&older, &newer, &older] {
+            let friend = friends.get_friend(&peer.peer_id).await.unwrap().unwrap();
+            let message = Message {
+                id: Uuid::new_v4(),
+                sender: peer.peer_id,
+                recipient: identity.peer_id,
+                timestamp: list.timestamp,
+                content: list.to_body().unwrap(),
+                nonce: 0,
+                delivery_status: DeliveryStatus::Delivered,
+                signature: Vec::new(),
+                group_id: None,
+                attachment: None,
+                sender_pub_key: Vec::new(),
+            };
+            let origin = Origin::Friend {
+                device: friend.primary_device(),
+                friend,
+            };
+            apply_body(&identity, &friends, &devices, &history, origin, message)
+                .await
+                .unwrap();
+        }
+
+        let friend = friends.get_friend(&peer.peer_id).await.unwrap().unwrap();
+        assert_eq!(friend.devices, newer.devices);
+        assert_eq!(friend.devices_timestamp, newer.timestamp);
+    }
+}
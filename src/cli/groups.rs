@@ -0,0 +1,634 @@
+//! This module implements group conversations on top of pairwise sessions and sender keys.
+//!
+//! A group message is encrypted once with the sender's sender key and then
+//! delivered to every other member as a separate signed copy, directly or
+//! through the mailboxes. Membership changes and sender keys travel inside
+//! those copies, sealed with the pairwise session held with each member.
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use libp2p::PeerId;
+use rand::random;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::crypto::sender_key::{SenderKeyDistribution, SenderKeyMessage};
+use crate::crypto::Identity;
+use crate::storage::{FriendsStore, GroupsStore};
+use crate::types::{DeliveryStatus, Group, GroupControl, GroupEnvelope, GroupMember, Message};
+
+use super::commands::{MailboxDeliveryResult, Node};
+
+/// Counts how the copies of a group message were delivered.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GroupDelivery {
+    /// Copies delivered directly to online members.
+    pub direct: usize,
+    /// Copies stored in mailboxes for offline members.
+    pub mailbox: usize,
+    /// Copies left in the outbox for a later retry.
+    pub queued: usize,
+}
+
+/// The result of opening a group message received from another member.
+pub struct GroupIncoming {
+    /// The group, if we joined it or its members changed.
+    pub group: Option<Group>,
+    /// The decrypted message, ready to be stored, if the copy carried one.
+    pub message: Option<Message>,
+}
+
+/// A group message about to be delivered to the other members.
+struct GroupOutgoing<'a> {
+    /// The group state to announce to the members.
+    group: &'a Group,
+    /// The ID of the group message, shared by all copies.
+    message_id: Uuid,
+    /// The timestamp of the group message.
+    timestamp: i64,
+    /// Our sender key, and the members that do not hold it yet.
+    sender_key: Option<(SenderKeyDistribution, Vec<PeerId>)>,
+    /// The message text encrypted with our sender key.
+    body: Option<SenderKeyMessage>,
+    /// Whether every member should be sent the group state.
+    announce: bool,
+}
+
+impl Node {
+    /// Creates a group with some of our friends and invites them.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the group.
+    /// * `members` - The `PeerId`s of the friends to invite.
+    ///
+    /// # Returns
+    ///
+    /// The new `Group` and how the invitations were delivered.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a member is not a friend or the
+    /// group cannot be stored.
+    pub async fn create_group(
+        &self,
+        name: String,
+        members: Vec<PeerId>,
+    ) -> Result<(Group, GroupDelivery)> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            bail!("Group name cannot be empty");
+        }
+
+        let mut group = Group {
+            id: Uuid::new_v4(),
+            name,
+            members: vec![GroupMember {
+                peer_id: self.identity.peer_id,
+                e2e_public_key: self.identity.hpke_public_key(),
+                name: self.display_name.clone(),
+            }],
+            created_by: self.identity.peer_id,
+            created_at: Utc::now().timestamp_millis(),
+        };
+        for member in self.members_from_friends(&group, members).await? {
+            group.members.push(member);
+        }
+
+        self.groups.store_group(group.clone()).await?;
+        info!("Created group {} ({})", group.name, group.id);
+
+        let delivery = self.announce_group(&group).await?;
+        Ok((group, delivery))
+    }
+
+    /// Adds some of our friends to a group and announces them to all members.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `members` - The `PeerId`s of the friends to add.
+    ///
+    /// # Returns
+    ///
+    /// The updated `Group` and how the announcements were delivered.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if we are not in the group, a
+    /// member is not a friend or already in the group, or storage fails.
+    pub async fn add_group_members(
+        &self,
+        group_id: &Uuid,
+        members: Vec<PeerId>,
+    ) -> Result<(Group, GroupDelivery)> {
+        let mut group = self.get_group(group_id).await?;
+        let added = self.members_from_friends(&group, members).await?;
+        if added.is_empty() {
+            bail!("No members to add");
+        }
+        group.members.extend(added);
+
+        self.groups.store_group(group.clone()).await?;
+        info!(
+            "Group {} now has {} members",
+            group.name,
+            group.members.len()
+        );
+
+        let delivery = self.announce_group(&group).await?;
+        Ok((group, delivery))
+    }
+
+    /// Leaves a group, telling the other members.
+    ///
+    /// The group and its sender keys are removed; its history is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    ///
+    /// # Returns
+    ///
+    /// How the announcements were delivered.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if we are not in the group or
+    /// storage fails.
+    pub async fn leave_group(&self, group_id: &Uuid) -> Result<GroupDelivery> {
+        let group = self.get_group(group_id).await?;
+
+        let mut remaining = group.clone();
+        remaining
+            .members
+            .retain(|member| member.peer_id != self.identity.peer_id);
+
+        let delivery = self
+            .send_to_members(
+                GroupOutgoing {
+                    group: &remaining,
+                    message_id: Uuid::new_v4(),
+                    timestamp: Utc::now().timestamp_millis(),
+                    sender_key: None,
+                    body: None,
+                    announce: true,
+                },
+                &remaining.members,
+            )
+            .await?;
+
+        self.groups.remove_group(group_id).await?;
+        self.identity.forget_group_keys(group_id)?;
+        info!("Left group {} ({})", group.name, group.id);
+
+        Ok(delivery)
+    }
+
+    /// Sends a text message to a group.
+    ///
+    /// The text is encrypted once with our sender key and a copy is delivered
+    /// to every other member. Members that have not been sent our sender key
+    /// yet receive it with their copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `text` - The message text.
+    ///
+    /// # Returns
+    ///
+    /// The message as stored in our history, and how the copies were delivered.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if we are not in the group,
+    /// encryption fails, or storage fails.
+    pub async fn send_group_message(
+        &self,
+        group_id: &Uuid,
+        text: String,
+    ) -> Result<(Message, GroupDelivery)> {
+        let group = self.get_group(group_id).await?;
+        let recipients = self.other_members(&group);
+
+        let message = Message {
+            id: Uuid::new_v4(),
+            sender: self.identity.peer_id,
+            recipient: self.identity.peer_id,
+            timestamp: Utc::now().timestamp_millis(),
+            content: text.into_bytes(),
+            nonce: random(),
+            delivery_status: DeliveryStatus::Sent,
+            signature: Vec::new(),
+            group_id: Some(group.id),
+            attachment: None,
+            sender_pub_key: Vec::new(),
+        };
+
+        // The key must be taken before encrypting, so that it can decrypt this message.
+        let peer_ids: Vec<PeerId> = recipients.iter().map(|member| member.peer_id).collect();
+        let sender_key = self.identity.distribute_sender_key(&group.id, &peer_ids)?;
+        let body = self
+            .identity
+            .group_encrypt(&group.id, &message.id, &message.body()?)?;
+
+        self.history.store_message(message.clone()).await?;
+
+        let delivery = self
+            .send_to_members(
+                GroupOutgoing {
+                    group: &group,
+                    message_id: message.id,
+                    timestamp: message.timestamp,
+                    sender_key: Some(sender_key),
+                    body: Some(body),
+                    announce: false,
+                },
+                &recipients,
+            )
+            .await?;
+
+        Ok((message, delivery))
+    }
+
+    /// Opens a group message received directly from another member.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The received copy, with its signature already verified.
+    ///
+    /// # Errors
+    ///
+    /// See `open_group_message`.
+    pub async fn receive_group_message(&self, message: Message) -> Result<GroupIncoming> {
+        open_group_message(
+            &self.identity,
+            self.friends.as_ref(),
+            self.groups.as_ref(),
+            message,
+        )
+        .await
+    }
+
+    /// Finds a group by its ID or name.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The group ID or name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no group matches or storage fails.
+    pub async fn find_group(&self, target: &str) -> Result<Group> {
+        if let Ok(group_id) = Uuid::from_str(target) {
+            return self.get_group(&group_id).await;
+        }
+
+        let mut matches: Vec<Group> = self
+            .groups
+            .list_groups()
+            .await?
+            .into_iter()
+            .filter(|group| group.name == target)
+            .collect();
+
+        match matches.len() {
+            0 => bail!("Group not found by ID or name: '{}'", target),
+            1 => Ok(matches.remove(0)),
+            _ => bail!("Several groups are named '{}'; use the group ID", target),
+        }
+    }
+
+    /// Retrieves a group we are a member of.
+    async fn get_group(&self, group_id: &Uuid) -> Result<Group> {
+        self.groups
+            .get_group(group_id)
+            .await?
+            .ok_or_else(|| anyhow!("Group {} not found", group_id))
+    }
+
+    /// Returns the members of a group other than ourselves.
+    fn other_members(&self, group: &Group) -> Vec<GroupMember> {
+        group
+            .members
+            .iter()
+            .filter(|member| member.peer_id != self.identity.peer_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Builds group members from friends that are not in the group yet.
+    async fn members_from_friends(
+        &self,
+        group: &Group,
+        peer_ids: Vec<PeerId>,
+    ) -> Result<Vec<GroupMember>> {
+        let mut members: Vec<GroupMember> = Vec::new();
+
+        for peer_id in peer_ids {
+            if group.is_member(&peer_id) || members.iter().any(|m| m.peer_id == peer_id) {
+                bail!("{} is already a member of the group", peer_id);
+            }
+
+            let friend = self
+                .friends
+                .get_friend(&peer_id)
+                .await?
+                .ok_or_else(|| anyhow!("{} is not a friend", peer_id))?;
+
+            members.push(GroupMember {
+                peer_id,
+                e2e_public_key: friend.e2e_public_key,
+                name: friend.nickname,
+            });
+        }
+
+        Ok(members)
+    }
+
+    /// Sends the group state, and our sender key where needed, to all other members.
+    async fn announce_group(&self, group: &Group) -> Result<GroupDelivery> {
+        let recipients = self.other_members(group);
+        let peer_ids: Vec<PeerId> = recipients.iter().map(|member| member.peer_id).collect();
+        let sender_key = self.identity.distribute_sender_key(&group.id, &peer_ids)?;
+
+        self.send_to_members(
+            GroupOutgoing {
+                group,
+                message_id: Uuid::new_v4(),
+                timestamp: Utc::now().timestamp_millis(),
+                sender_key: Some(sender_key),
+                body: None,
+                announce: true,
+            },
+            &recipients,
+        )
+        .await
+    }
+
+    /// Builds, signs, and delivers a copy of a group message for each recipient.
+    ///
+    /// Copies are queued in the outbox first, so that copies that cannot be
+    /// delivered right away are retried by the sync engine.
+    async fn send_to_members(
+        &self,
+        outgoing: GroupOutgoing<'_>,
+        recipients: &[GroupMember],
+    ) -> Result<GroupDelivery> {
+        let mut delivery = GroupDelivery::default();
+
+        for member in recipients {
+            let sender_key = outgoing
+                .sender_key
+                .as_ref()
+                .filter(|(_, missing)| missing.contains(&member.peer_id))
+                .map(|(distribution, _)| distribution.clone());
+
+            let control = if outgoing.announce || sender_key.is_some() {
+                let control = GroupControl {
+                    group: outgoing.group.clone(),
+                    sender_key,
+                };
+                Some(
+                    self.identity
+                        .encrypt_for(&member.e2e_public_key, &serde_json::to_vec(&control)?)?,
+                )
+            } else {
+                None
+            };
+
+            let envelope = GroupEnvelope {
+                message_id: outgoing.message_id,
+                control,
+                body: outgoing.body.clone(),
+            };
+
+            let mut copy = Message {
+                id: Uuid::new_v4(),
+                sender: self.identity.peer_id,
+                recipient: member.peer_id,
+                timestamp: outgoing.timestamp,
+                content: serde_json::to_vec(&envelope)?,
+                nonce: random(),
+                delivery_status: DeliveryStatus::Sending,
+                signature: Vec::new(),
+                group_id: Some(outgoing.group.id),
+                attachment: None,
+                sender_pub_key: Vec::new(),
+            };
+            copy.signature = self.identity.sign_message(&copy)?;
+
+            self.outbox.add_pending(copy.clone()).await?;
+            self.deliver_group_copy(&copy, member, &mut delivery)
+                .await?;
+        }
+
+        Ok(delivery)
+    }
+
+    /// Attempts to deliver a queued group message copy directly, then via mailboxes.
+    async fn deliver_group_copy(
+        &self,
+        copy: &Message,
+        member: &GroupMember,
+        delivery: &mut GroupDelivery,
+    ) -> Result<()> {
+        if self
+            .network
+            .send_message(member.peer_id, copy.clone())
+            .await
+            .is_ok()
+        {
+            self.outbox.remove_pending(&copy.id).await?;
+            delivery.direct += 1;
+            return Ok(());
+        }
+
+        let providers = {
+            let sync_engine = self.sync_engine.lock().await;
+            sync_engine.get_mailbox_providers().clone()
+        };
+
+        match self
+            .forward_to_mailboxes(copy, &member.e2e_public_key, &providers)
+            .await
+        {
+            Ok(MailboxDeliveryResult::Success(_)) => {
+                self.outbox.remove_pending(&copy.id).await?;
+                delivery.mailbox += 1;
+            }
+            Ok(MailboxDeliveryResult::Failure) => {
+                delivery.queued += 1;
+            }
+            Err(e) => {
+                debug!(
+                    "Mailbox delivery of group message {} to {} failed: {}",
+                    copy.id, member.peer_id, e
+                );
+                delivery.queued += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens a group message copy received from another member.
+///
+/// Any group state in the copy is applied first, followed by any sender key,
+/// so that the first message from a member can be read right away.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity, holding the pairwise sessions and sender keys.
+/// * `friends` - The friends store, used to authenticate invitations.
+/// * `groups` - The groups store.
+/// * `message` - The received copy, with its signature already verified.
+///
+/// # Returns
+///
+/// A `GroupIncoming` with the changed group and the decrypted message, if any.
+///
+/// # Errors
+///
+/// This function will return an error if the sender is neither a friend nor
+/// a member of the group, is not allowed to make the requested change, or the
+/// copy cannot be decrypted.
+pub async fn open_group_message(
+    identity: &Identity,
+    friends: &(dyn FriendsStore + Send + Sync),
+    groups: &(dyn GroupsStore + Send + Sync),
+    message: Message,
+) -> Result<GroupIncoming> {
+    let group_id = message
+        .group_id
+        .ok_or_else(|| anyhow!("Message {} is not a group message", message.id))?;
+    let envelope: GroupEnvelope = serde_json::from_slice(&message.content)?;
+    let sender = message.sender;
+
+    let existing = groups.get_group(&group_id).await?;
+    let sender_key = match friends.get_friend(&sender).await? {
+        Some(friend) => friend.e2e_public_key,
+        None => existing
+            .as_ref()
+            .and_then(|group| group.member(&sender))
+            .map(|member| member.e2e_public_key.clone())
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} is neither a friend nor a member of group {}",
+                    sender,
+                    group_id
+                )
+            })?,
+    };
+
+    let mut updated = None;
+    if let Some(sealed) = envelope.control {
+        let control: GroupControl =
+            serde_json::from_slice(&identity.decrypt_from(&sender_key, &sealed)?)?;
+        if control.group.id != group_id {
+            bail!("Group state does not match group {}", group_id);
+        }
+
+        updated = apply_group_state(identity, groups, existing, &sender, control.group).await?;
+
+        if let Some(distribution) = control.sender_key {
+            identity.process_sender_key(&group_id, &sender, &distribution)?;
+        }
+    }
+
+    let Some(body) = envelope.body else {
+        return Ok(GroupIncoming {
+            group: updated,
+            message: None,
+        });
+    };
+
+    let group = groups
+        .get_group(&group_id)
+        .await?
+        .ok_or_else(|| anyhow!("Not a member of group {}", group_id))?;
+    if !group.is_member(&sender) {
+        bail!("{} is not a member of group {}", sender, group_id);
+    }
+
+    let body = identity.group_decrypt(&group_id, &sender, &envelope.message_id, &body)?;
+
+    let mut opened = Message {
+        id: envelope.message_id,
+        sender,
+        recipient: identity.peer_id,
+        timestamp: message.timestamp,
+        content: Vec::new(),
+        nonce: message.nonce,
+        delivery_status: DeliveryStatus::Delivered,
+        signature: Vec::new(),
+        group_id: Some(group_id),
+        attachment: None,
+        sender_pub_key: Vec::new(),
+    };
+    opened.set_body(body);
+
+    Ok(GroupIncoming {
+        group: updated,
+        message: Some(opened),
+    })
+}
+
+/// Applies the group state sent by a member.
+///
+/// A friend may invite us to a new group. Within an existing group, any
+/// member may add new members, and a member that is missing from its own
+/// state has left. Members are never removed on someone else's behalf.
+///
+/// # Returns
+///
+/// The stored group if it changed, otherwise `None`.
+async fn apply_group_state(
+    identity: &Identity,
+    groups: &(dyn GroupsStore + Send + Sync),
+    existing: Option<Group>,
+    sender: &PeerId,
+    proposed: Group,
+) -> Result<Option<Group>> {
+    let Some(mut group) = existing else {
+        if !proposed.is_member(&identity.peer_id) || !proposed.is_member(sender) {
+            bail!("Invalid invitation to group {}", proposed.id);
+        }
+
+        info!("Joined group {} ({})", proposed.name, proposed.id);
+        groups.store_group(proposed.clone()).await?;
+        return Ok(Some(proposed));
+    };
+
+    if !group.is_member(sender) {
+        bail!("{} is not a member of group {}", sender, group.id);
+    }
+
+    if !proposed.is_member(sender) {
+        group.members.retain(|member| member.peer_id != *sender);
+        identity.forget_sender_key(&group.id, sender)?;
+        // The member that left knows our sender key, so start a new one.
+        identity.forget_sender_key(&group.id, &identity.peer_id)?;
+
+        info!("{} left group {}", sender, group.name);
+        groups.store_group(group.clone()).await?;
+        return Ok(Some(group));
+    }
+
+    let added: Vec<GroupMember> = proposed
+        .members
+        .into_iter()
+        .filter(|member| !group.is_member(&member.peer_id))
+        .collect();
+    if added.is_empty() {
+        return Ok(None);
+    }
+    group.members.extend(added);
+
+    info!("{} added members to group {}", sender, group.name);
+    groups.store_group(group.clone()).await?;
+    Ok(Some(group))
+}
@@ -0,0 +1,171 @@
+//! This module implements E2E key rotation.
+//!
+//! A peer that rotates its E2E key sends each friend a `KeyUpdate` signed
+//! with its libp2p identity, encrypted with the previous key. Friends check
+//! the signature and that the update replaces the key they hold, then store
+//! the new key. The rotating peer keeps decrypting with the previous key for
+//! a grace period, until its friends have learned the new one.
+use anyhow::{bail, Result};
+use libp2p::PeerId;
+use tracing::{info, warn};
+
+use crate::crypto::{signing, Identity};
+use crate::storage::FriendsStore;
+use crate::types::{Device, Friend, KeyUpdate, Message};
+
+use super::send::compose_body;
+use super::verify::verified_key_changed;
+
+/// Applies a key update received from a friend, replacing the friend's E2E key.
+///
+/// The ratchet sessions held with the previous key are carried over, so that
+/// messages the friend sent before rotating can still be decrypted.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity.
+/// * `friends` - The friends store.
+/// * `sender` - The `PeerId` of the friend that sent the update.
+/// * `update` - The key update.
+///
+/// # Returns
+///
+/// `true` if the key of a verified friend changed, which the UIs should warn about.
+///
+/// # Errors
+///
+/// This function will return an error if the sender is not a friend, the
+/// update is not signed by the sender, or it does not replace the key we
+/// hold for the sender.
+pub async fn apply_key_update(
+    identity: &Identity,
+    friends: &(dyn FriendsStore + Send + Sync),
+    sender: &PeerId,
+    update: &KeyUpdate,
+) -> Result<bool> {
+    if update.peer_id != *sender {
+        bail!("Key update for {} was sent by {}", update.peer_id, sender);
+    }
+    signing::verify_key_update(update)?;
+
+    let Some(previous) = friends.get_friend(sender).await? else {
+        bail!("{} is not a friend", sender);
+    };
+    if previous.e2e_public_key == update.new_key {
+        // A repeated update.
+        return Ok(false);
+    }
+    if previous.e2e_public_key != update.previous_key {
+        bail!("Key update does not replace the key we hold for {}", sender);
+    }
+
+    identity.carry_over_sessions(&update.previous_key, &update.new_key)?;
+    let friend = Friend {
+        e2e_public_key: update.new_key.clone(),
+        // The safety number covers the E2E key, so it must be compared again.
+        verified: false,
+        ..previous.clone()
+    };
+    friends.add_friend(friend.clone()).await?;
+
+    let verified_changed = verified_key_changed(Some(&previous), &friend);
+    if verified_changed {
+        warn!(
+            "Verified friend {} rotated its key; compare safety numbers again",
+            sender
+        );
+    } else {
+        info!("Friend {} rotated its key", sender);
+    }
+    Ok(verified_changed)
+}
+
+/// Builds the key update announcements for our friends and our other devices.
+///
+/// They must be built before the key is replaced, as they are encrypted with
+/// the sessions held with the previous key.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity, still holding the previous key.
+/// * `friends` - The friends to notify, on all their devices.
+/// * `own_devices` - Our other devices.
+/// * `update` - The signed key update.
+///
+/// # Returns
+///
+/// The sealed messages to deliver, one per device.
+///
+/// # Errors
+///
+/// This function will return an error if a message cannot be encrypted or signed.
+pub fn compose_key_updates(
+    identity: &Identity,
+    friends: &[Friend],
+    own_devices: &[Device],
+    update: &KeyUpdate,
+) -> Result<Vec<Message>> {
+    let body = update.to_body()?;
+    friends
+        .iter()
+        .flat_map(Friend::all_devices)
+        .chain(own_devices.iter().cloned())
+        .map(|device| Ok(compose_body(identity, &device, body.clone())?.1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::HpkeContext;
+    use crate::storage::SledFriendsStore;
+
+    #[tokio::test]
+    async fn friends_learn_keys_from_signed_updates_only() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let friends = SledFriendsStore::new(db, None).unwrap();
+        let us = Identity::generate().unwrap();
+        let friend = Identity::generate().unwrap();
+        friends
+            .add_friend(Friend {
+                peer_id: friend.peer_id,
+                e2e_public_key: friend.hpke_public_key(),
+                nickname: None,
+                verified: true,
+                devices: Vec::new(),
+                devices_timestamp: 0,
+            })
+            .await
+            .unwrap();
+
+        let new_key = HpkeContext::new().unwrap().public_key_bytes();
+        let update = friend.sign_key_update(&new_key).unwrap();
+
+        // Relayed by someone else, tampered with, or from a stranger.
+        let relayed = PeerId::random();
+        assert!(apply_key_update(&us, &friends, &relayed, &update)
+            .await
+            .is_err());
+        let mut tampered = update.clone();
+        tampered.new_key = HpkeContext::new().unwrap().public_key_bytes();
+        assert!(apply_key_update(&us, &friends, &friend.peer_id, &tampered)
+            .await
+            .is_err());
+        let stranger = Identity::generate().unwrap();
+        let foreign = stranger.sign_key_update(&new_key).unwrap();
+        assert!(apply_key_update(&us, &friends, &stranger.peer_id, &foreign)
+            .await
+            .is_err());
+
+        // The genuine update replaces the key and needs a new verification.
+        assert!(apply_key_update(&us, &friends, &friend.peer_id, &update)
+            .await
+            .unwrap());
+        let stored = friends.get_friend(&friend.peer_id).await.unwrap().unwrap();
+        assert_eq!(stored.e2e_public_key, new_key);
+        assert!(!stored.verified);
+        assert!(!apply_key_update(&us, &friends, &friend.peer_id, &update)
+            .await
+            .unwrap());
+    }
+}
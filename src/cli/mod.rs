@@ -1,5 +1,23 @@
 //! This module defines the commands and data structures used by the command-line
 //! interface (CLI) and the terminal UI (TUI).
+mod attachments;
+mod blocks;
 pub mod commands;
+mod contacts;
+mod delete;
+mod devices;
+pub mod groups;
+mod keys;
+mod requests;
+mod send;
+mod verify;
 
+pub use attachments::fetch_attachment_from;
 pub use commands::UiNotification;
+pub use devices::{
+    compose_device_copies, open_direct_message, own_devices, resolve_origin, Origin,
+};
+pub use groups::{open_group_message, GroupDelivery};
+pub use keys::compose_key_updates;
+pub use send::{compose_text, resolve_peer};
+pub use verify::verified_key_changed;
@@ -0,0 +1,130 @@
+//! This module implements message requests: direct messages from peers that
+//! are not friends.
+//!
+//! They are decrypted with the public key the sender claims and held apart
+//! from the history. No delivery confirmation is sent for them. Accepting a
+//! request adds the sender as a friend with that key and moves the messages
+//! into the history; deleting it discards them.
+use anyhow::{anyhow, bail, Result};
+use libp2p::PeerId;
+use tracing::info;
+
+use crate::types::{Friend, Message};
+
+use super::commands::Node;
+
+impl Node {
+    /// Decrypts a direct message from a peer that is not a friend and stores
+    /// it as a message request.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The authenticated message, as received.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the message was dropped because the sender has too many
+    /// pending messages or claimed a different key before.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message carries no public
+    /// key, cannot be decrypted with it, or cannot be stored.
+    pub async fn receive_message_request(&self, mut message: Message) -> Result<bool> {
+        let sender_key = std::mem::take(&mut message.sender_pub_key);
+        if sender_key.is_empty() {
+            bail!("the sender did not include its public key");
+        }
+
+        let body = self.identity.decrypt_from(&sender_key, &message.content)?;
+        message.set_body(body);
+        message.signature = Vec::new();
+
+        self.requests.add_message(&message, &sender_key).await
+    }
+
+    /// Accepts a message request.
+    ///
+    /// The sender is added as a friend with the public key its messages were
+    /// encrypted with, and the messages are moved into the history.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the sender.
+    /// * `nickname` - An optional nickname for the new friend.
+    ///
+    /// # Returns
+    ///
+    /// The `Friend` and the number of messages moved into the history.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no request from the
+    /// peer, the peer is a friend with a different key, or storage fails.
+    pub async fn accept_message_request(
+        &self,
+        peer_id: PeerId,
+        nickname: Option<String>,
+    ) -> Result<(Friend, usize)> {
+        let request = self
+            .requests
+            .get_request(&peer_id)
+            .await?
+            .ok_or_else(|| anyhow!("No message request from {}", peer_id))?;
+
+        let friend = match self.friends.get_friend(&peer_id).await? {
+            Some(friend) if friend.e2e_public_key != request.e2e_public_key => {
+                bail!("{} is already a friend with a different key", peer_id);
+            }
+            Some(friend) => friend,
+            None => {
+                let friend = Friend {
+                    peer_id,
+                    e2e_public_key: request.e2e_public_key,
+                    nickname,
+                    verified: false,
+                    devices: Vec::new(),
+                    devices_timestamp: 0,
+                };
+                self.friends.add_friend(friend.clone()).await?;
+                friend
+            }
+        };
+
+        let messages = self.requests.get_messages(&peer_id).await?;
+        let moved = messages.len();
+        self.history.store_messages(messages.clone()).await?;
+        for message in &messages {
+            self.fetch_attachment_of(message);
+        }
+        self.requests.remove_request(&peer_id).await?;
+
+        info!(
+            "Accepted message request from {} with {} messages",
+            peer_id, moved
+        );
+        Ok((friend, moved))
+    }
+
+    /// Deletes a message request with all its messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the sender.
+    ///
+    /// # Returns
+    ///
+    /// The number of messages deleted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no request from the
+    /// peer or it cannot be removed.
+    pub async fn delete_message_request(&self, peer_id: PeerId) -> Result<usize> {
+        if self.requests.get_request(&peer_id).await?.is_none() {
+            bail!("No message request from {}", peer_id);
+        }
+
+        self.requests.remove_request(&peer_id).await
+    }
+}
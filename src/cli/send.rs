@@ -0,0 +1,205 @@
+//! This module implements sending text messages to friends outside the TUI.
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use libp2p::PeerId;
+use rand::random;
+use std::str::FromStr;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::crypto::Identity;
+use crate::storage::FriendsStore;
+use crate::types::{DeliveryStatus, Device, Friend, Message};
+
+use super::commands::Node;
+
+impl Node {
+    /// Resolves a `PeerId` from a string, which can be either a `PeerId` or
+    /// a friend's nickname.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - The Peer ID or nickname to resolve.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the string is neither a Peer ID
+    /// nor the nickname of a friend.
+    pub async fn resolve_peer(&self, destination: &str) -> Result<PeerId> {
+        resolve_peer(self.friends.as_ref(), destination).await
+    }
+
+    /// Sends a text message to a friend.
+    ///
+    /// The message is stored in the history and the outbox, and a direct
+    /// delivery is attempted in the background. If it fails, the sync engine
+    /// retries it and falls back to mailboxes. Copies go to the friend's
+    /// linked devices and to our other devices.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the friend.
+    /// * `text` - The message text.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the message.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the peer is not a friend, or
+    /// the message cannot be encrypted or stored.
+    pub async fn send_text(&self, peer_id: PeerId, text: String) -> Result<Uuid> {
+        let friend = self
+            .friends
+            .get_friend(&peer_id)
+            .await?
+            .ok_or_else(|| anyhow!("{} is not a friend", peer_id))?;
+
+        let (message, sealed) = compose_text(&self.identity, &friend, text)?;
+
+        let message_id = message.id;
+        self.history.store_message(message.clone()).await?;
+        self.outbox.add_pending(sealed.clone()).await?;
+        self.queue_device_copies(&friend, &message).await;
+
+        let network = self.network.clone();
+        let outbox = self.outbox.clone();
+        tokio::spawn(async move {
+            match network.send_message(peer_id, sealed).await {
+                Ok(()) => {
+                    if let Err(e) = outbox.remove_pending(&message_id).await {
+                        debug!("Failed to remove delivered message from outbox: {}", e);
+                    }
+                }
+                Err(e) => debug!("Direct send failed, will retry via sync: {}", e),
+            }
+        });
+
+        Ok(message_id)
+    }
+}
+
+/// Resolves a `PeerId` from a string, which can be either a `PeerId` or a
+/// nickname from a friends store.
+///
+/// # Arguments
+///
+/// * `friends` - The friends store to look nicknames up in.
+/// * `destination` - The Peer ID or nickname to resolve.
+///
+/// # Errors
+///
+/// This function will return an error if the string is neither a Peer ID
+/// nor the nickname of a friend.
+pub async fn resolve_peer(
+    friends: &(dyn FriendsStore + Send + Sync),
+    destination: &str,
+) -> Result<PeerId> {
+    if let Ok(peer_id) = PeerId::from_str(destination) {
+        return Ok(peer_id);
+    }
+
+    friends
+        .list_friends()
+        .await?
+        .into_iter()
+        .find(|f| f.nickname.as_deref() == Some(destination))
+        .map(|f| f.peer_id)
+        .ok_or_else(|| anyhow!("Peer not found by ID or nickname: '{}'", destination))
+}
+
+/// Builds a text message to a friend.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity, used to encrypt and sign the message.
+/// * `friend` - The recipient.
+/// * `text` - The message text.
+///
+/// # Returns
+///
+/// The message to store in the history, and the sealed copy to deliver.
+///
+/// # Errors
+///
+/// This function will return an error if the message cannot be encrypted
+/// or signed.
+pub fn compose_text(
+    identity: &Identity,
+    friend: &Friend,
+    text: String,
+) -> Result<(Message, Message)> {
+    compose_body(identity, &friend.primary_device(), text.into_bytes())
+}
+
+/// Builds a message to a device from a message body.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity, used to encrypt and sign the message.
+/// * `device` - The recipient device.
+/// * `body` - The message body.
+///
+/// # Returns
+///
+/// The message to store in the history, and the sealed copy to deliver.
+///
+/// # Errors
+///
+/// This function will return an error if the message cannot be encrypted
+/// or signed.
+pub(super) fn compose_body(
+    identity: &Identity,
+    device: &Device,
+    body: Vec<u8>,
+) -> Result<(Message, Message)> {
+    compose_body_with_id(identity, device, Uuid::new_v4(), body)
+}
+
+/// Builds a message with a given ID to a device from a message body.
+///
+/// # Arguments
+///
+/// * `identity` - Our identity, used to encrypt and sign the message.
+/// * `device` - The recipient device.
+/// * `id` - The ID of the message.
+/// * `body` - The message body.
+///
+/// # Returns
+///
+/// The message to store in the history, and the sealed copy to deliver.
+///
+/// # Errors
+///
+/// This function will return an error if the message cannot be encrypted
+/// or signed.
+pub(super) fn compose_body_with_id(
+    identity: &Identity,
+    device: &Device,
+    id: Uuid,
+    body: Vec<u8>,
+) -> Result<(Message, Message)> {
+    let message = Message {
+        id,
+        sender: identity.peer_id,
+        recipient: device.peer_id,
+        timestamp: Utc::now().timestamp_millis(),
+        content: body,
+        nonce: random(),
+        delivery_status: DeliveryStatus::Sending,
+        signature: Vec::new(),
+        group_id: None,
+        attachment: None,
+        sender_pub_key: Vec::new(),
+    };
+
+    let mut sealed = Message {
+        content: identity.encrypt_for(&device.e2e_public_key, &message.content)?,
+        sender_pub_key: identity.hpke_public_key(),
+        ..message.clone()
+    };
+    sealed.signature = identity.sign_message(&sealed)?;
+
+    Ok((message, sealed))
+}
@@ -0,0 +1,90 @@
+//! This module implements verifying friends' keys with safety numbers.
+//!
+//! Two friends compare their safety number out of band, in person or over
+//! another channel, and mark each other as verified when it matches. A
+//! verified friend whose key changes loses the flag and raises a warning,
+//! since the change may be a man in the middle.
+use anyhow::{bail, Result};
+use libp2p::PeerId;
+use tracing::warn;
+
+use crate::crypto::safety_number::safety_number;
+use crate::types::Friend;
+
+use super::commands::{Node, UiNotification};
+
+impl Node {
+    /// Computes the safety number shared with a friend.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend` - The friend.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the friend's Peer ID does not
+    /// embed an Ed25519 key.
+    pub fn safety_number(&self, friend: &Friend) -> Result<String> {
+        safety_number(
+            &self.identity.peer_id,
+            &self.identity.hpke_public_key(),
+            &friend.peer_id,
+            &friend.e2e_public_key,
+        )
+    }
+
+    /// Marks a friend's keys as verified or unverified.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the friend.
+    /// * `verified` - Whether the safety number matched.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the peer is not a friend or
+    /// storage fails.
+    pub async fn set_friend_verified(&self, peer_id: PeerId, verified: bool) -> Result<()> {
+        if !self.friends.set_verified(&peer_id, verified).await? {
+            bail!("{} is not a friend", peer_id);
+        }
+        Ok(())
+    }
+
+    /// Adds a friend, or replaces an existing one.
+    ///
+    /// If this changes the key of a verified friend, the friend is no longer
+    /// verified and the UIs are warned.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend` - The friend to store.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the friend cannot be stored.
+    pub async fn save_friend(&self, friend: Friend) -> Result<()> {
+        let previous = self.friends.get_friend(&friend.peer_id).await?;
+        self.friends.add_friend(friend.clone()).await?;
+
+        if verified_key_changed(previous.as_ref(), &friend) {
+            warn!("The key of verified friend {} changed", friend.peer_id);
+            let notification = UiNotification::VerifiedKeyChanged(friend.peer_id);
+            let _ = self.ui_notify_tx.send(notification.clone());
+            let _ = self.web_notify_tx.send(notification);
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether replacing a friend changes the key of a verified friend.
+///
+/// # Arguments
+///
+/// * `previous` - The stored friend, if any.
+/// * `friend` - The friend replacing it.
+pub fn verified_key_changed(previous: Option<&Friend>, friend: &Friend) -> bool {
+    previous.is_some_and(|previous| {
+        previous.verified && previous.e2e_public_key != friend.e2e_public_key
+    })
+}
@@ -0,0 +1,111 @@
+//! This module implements a client for the control socket of a headless
+//! client.
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+
+use super::protocol::{Event, Notification, Request, Response, EVENT_METHOD};
+
+/// A connection to the control socket.
+pub struct ControlClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl ControlClient {
+    /// Connects to a control socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the socket.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no client is listening on the socket.
+    pub async fn connect(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path).await.with_context(|| {
+            format!(
+                "Failed to connect to {}. Is a client running with --headless?",
+                path.display()
+            )
+        })?;
+        let (reader, writer) = stream.into_split();
+
+        Ok(Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            next_id: 1,
+        })
+    }
+
+    /// Calls a method and waits for its result.
+    ///
+    /// Events that arrive in the meantime are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The name of the method.
+    /// * `params` - The parameters of the method.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the connection fails or the
+    /// method returns an error.
+    pub async fn call<P: Serialize>(&mut self, method: &str, params: P) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = Request::new(id, method, serde_json::to_value(params)?);
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+
+        loop {
+            let line = self
+                .lines
+                .next_line()
+                .await?
+                .ok_or_else(|| anyhow!("The client closed the control connection"))?;
+            let value: Value = serde_json::from_str(&line)?;
+            if value.get("method").is_some() {
+                continue;
+            }
+
+            let response: Response = serde_json::from_value(value)?;
+            if response.id != id {
+                continue;
+            }
+            if let Some(error) = response.error {
+                bail!("{} (code {})", error.message, error.code);
+            }
+            return Ok(response.result.unwrap_or(Value::Null));
+        }
+    }
+
+    /// Waits for the next event after subscribing with the `subscribe` method.
+    ///
+    /// # Returns
+    ///
+    /// `None` once the client closes the connection.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the connection fails or a
+    /// message cannot be parsed.
+    pub async fn next_event(&mut self) -> Result<Option<Event>> {
+        while let Some(line) = self.lines.next_line().await? {
+            let value: Value = serde_json::from_str(&line)?;
+            if value.get("method").and_then(Value::as_str) == Some(EVENT_METHOD) {
+                let notification: Notification = serde_json::from_value(value)?;
+                return Ok(Some(notification.params));
+            }
+        }
+
+        Ok(None)
+    }
+}
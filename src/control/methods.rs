@@ -0,0 +1,254 @@
+//! This module implements the methods of the control socket on top of the
+//! application's `Node`, and the conversions the one-shot subcommands share
+//! with them.
+use anyhow::{anyhow, Result};
+use base64::prelude::*;
+use libp2p::PeerId;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::cli::commands::{Node, UiNotification};
+use crate::types::{Friend, Message};
+
+use super::protocol::{
+    Event, FriendAddParams, FriendEntry, FriendRemoveParams, HistoryEntry, HistoryParams,
+    OutboxEntry, OutboxStatus, RpcError, SendParams, INVALID_PARAMS, METHOD_FAILED,
+    METHOD_NOT_FOUND,
+};
+
+/// Calls a method.
+///
+/// `subscribe` is handled by the connection itself, since it changes what
+/// is written to it.
+///
+/// # Arguments
+///
+/// * `node` - The application's core `Node`.
+/// * `method` - The name of the method.
+/// * `params` - The parameters of the method.
+///
+/// # Returns
+///
+/// The result of the method, or the error to send back.
+pub async fn dispatch(node: &Node, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "send" => {
+            let params = parse_params(params)?;
+            respond(send(node, params).await)
+        }
+        "history" => {
+            let params = parse_params(params)?;
+            respond(history(node, params).await)
+        }
+        "friends" => respond(friends(node).await),
+        "friend_add" => {
+            let params = parse_params(params)?;
+            respond(friend_add(node, params).await)
+        }
+        "friend_remove" => {
+            let params = parse_params(params)?;
+            respond(friend_remove(node, params).await)
+        }
+        "peers" => respond(peers(node).await),
+        "outbox" => respond(outbox(node).await),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method: {}", method),
+        )),
+    }
+}
+
+/// Sends a message to a friend, returning its ID.
+async fn send(node: &Node, params: SendParams) -> Result<Value> {
+    let peer_id = node.resolve_peer(&params.peer).await?;
+    let message_id = node.send_text(peer_id, params.text).await?;
+    Ok(serde_json::json!({ "id": message_id }))
+}
+
+/// Returns the latest messages exchanged with a peer, oldest first.
+async fn history(node: &Node, params: HistoryParams) -> Result<Vec<HistoryEntry>> {
+    let peer_id = node.resolve_peer(&params.peer).await?;
+    let messages = node
+        .history
+        .get_history(&node.identity.peer_id, &peer_id, params.limit)
+        .await?;
+
+    Ok(history_entries(&messages))
+}
+
+/// Lists the friends and whether they are connected.
+async fn friends(node: &Node) -> Result<Vec<FriendEntry>> {
+    let friends = node.friends.list_friends().await?;
+    let online_peers = node.network.get_connected_peers().await.unwrap_or_default();
+    Ok(friend_entries(friends, &online_peers))
+}
+
+/// Adds a friend.
+async fn friend_add(node: &Node, params: FriendAddParams) -> Result<FriendEntry> {
+    let friend = friend_from_params(params)?;
+    node.save_friend(friend.clone()).await?;
+    Ok(friend_entries(vec![friend], &[]).remove(0))
+}
+
+/// Removes a friend, returning whether it was one.
+async fn friend_remove(node: &Node, params: FriendRemoveParams) -> Result<Value> {
+    let peer_id = node.resolve_peer(&params.peer).await?;
+    let removed = node.friends.remove_friend(&peer_id).await?;
+    Ok(serde_json::json!({ "removed": removed }))
+}
+
+/// Lists the connected peers.
+async fn peers(node: &Node) -> Result<Vec<String>> {
+    let peers = node.network.get_connected_peers().await?;
+    Ok(peers.iter().map(ToString::to_string).collect())
+}
+
+/// Returns the messages waiting in the outbox.
+async fn outbox(node: &Node) -> Result<OutboxStatus> {
+    let pending = node.outbox.get_pending().await?;
+
+    Ok(OutboxStatus {
+        pending: pending.len(),
+        messages: pending
+            .iter()
+            .map(|message| OutboxEntry {
+                id: message.id.to_string(),
+                recipient: message.recipient.to_string(),
+                timestamp: message.timestamp,
+                group_id: message.group_id.map(|id| id.to_string()),
+            })
+            .collect(),
+    })
+}
+
+/// Converts a UI notification to an event for subscribers.
+///
+/// # Arguments
+///
+/// * `notification` - The notification to convert.
+pub fn event_from(notification: UiNotification) -> Event {
+    match notification {
+        UiNotification::NewMessage(message) => Event::NewMessage {
+            id: message.id.to_string(),
+            sender: message.sender.to_string(),
+            recipient: message.recipient.to_string(),
+            content: message.text(),
+            timestamp: message.timestamp,
+            group_id: message.group_id.map(|id| id.to_string()),
+        },
+        UiNotification::PeerConnected(peer_id) => Event::PeerConnected {
+            peer_id: peer_id.to_string(),
+        },
+        UiNotification::PeerDisconnected(peer_id) => Event::PeerDisconnected {
+            peer_id: peer_id.to_string(),
+        },
+        UiNotification::DeliveryStatusUpdate {
+            message_id,
+            new_status,
+        } => Event::DeliveryStatus {
+            message_id: message_id.to_string(),
+            status: format!("{:?}", new_status),
+        },
+        UiNotification::ContactRequestReceived {
+            peer_id,
+            display_name,
+        } => Event::ContactRequest {
+            peer_id: peer_id.to_string(),
+            display_name,
+        },
+        UiNotification::ContactAccepted(peer_id) => Event::ContactAccepted {
+            peer_id: peer_id.to_string(),
+        },
+        UiNotification::ContactRejected(peer_id) => Event::ContactRejected {
+            peer_id: peer_id.to_string(),
+        },
+        UiNotification::MessageRequestReceived(peer_id) => Event::MessageRequest {
+            peer_id: peer_id.to_string(),
+        },
+        UiNotification::GroupUpdated(group) => Event::GroupUpdated {
+            group_id: group.id.to_string(),
+            name: group.name,
+        },
+        UiNotification::VerifiedKeyChanged(peer_id) => Event::VerifiedKeyChanged {
+            peer_id: peer_id.to_string(),
+        },
+        UiNotification::MessagesDeleted(message_ids) => Event::MessagesDeleted {
+            message_ids: message_ids.iter().map(|id| id.to_string()).collect(),
+        },
+    }
+}
+
+/// Builds a friend from the parameters of the `friend_add` method.
+///
+/// # Errors
+///
+/// This function will return an error if the Peer ID or the public key is invalid.
+pub fn friend_from_params(params: FriendAddParams) -> Result<Friend> {
+    let peer_id =
+        PeerId::from_str(&params.peer_id).map_err(|e| anyhow!("Invalid peer ID: {}", e))?;
+    let e2e_public_key = BASE64_STANDARD
+        .decode(&params.e2e_public_key)
+        .map_err(|e| anyhow!("Invalid base64 key: {}", e))?;
+
+    Ok(Friend {
+        peer_id,
+        e2e_public_key,
+        nickname: params.nickname,
+        verified: false,
+        devices: Vec::new(),
+        devices_timestamp: 0,
+    })
+}
+
+/// Converts friends to the entries returned by the `friends` method.
+///
+/// # Arguments
+///
+/// * `friends` - The friends to convert.
+/// * `online_peers` - The connected peers.
+pub fn friend_entries(friends: Vec<Friend>, online_peers: &[PeerId]) -> Vec<FriendEntry> {
+    friends
+        .into_iter()
+        .map(|friend| FriendEntry {
+            online: online_peers.contains(&friend.peer_id),
+            verified: friend.verified,
+            peer_id: friend.peer_id.to_string(),
+            nickname: friend.nickname,
+            e2e_public_key: BASE64_STANDARD.encode(&friend.e2e_public_key),
+        })
+        .collect()
+}
+
+/// Converts the history of a conversation to the entries returned by the
+/// `history` method.
+///
+/// # Arguments
+///
+/// * `messages` - The messages of the conversation.
+pub fn history_entries(messages: &[Message]) -> Vec<HistoryEntry> {
+    messages
+        .iter()
+        .map(|message| HistoryEntry {
+            id: message.id.to_string(),
+            sender: message.sender.to_string(),
+            recipient: message.recipient.to_string(),
+            content: message.text(),
+            timestamp: message.timestamp,
+            delivery_status: format!("{:?}", message.delivery_status),
+        })
+        .collect()
+}
+
+/// Deserializes the parameters of a method.
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+/// Serializes the result of a method, or turns its error into an `RpcError`.
+fn respond<T: Serialize>(outcome: Result<T>) -> Result<Value, RpcError> {
+    outcome
+        .and_then(|result| Ok(serde_json::to_value(result)?))
+        .map_err(|e| RpcError::new(METHOD_FAILED, e.to_string()))
+}
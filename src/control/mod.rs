@@ -0,0 +1,233 @@
+//! This module contains the control socket of a client running in headless
+//! mode.
+//!
+//! The socket is a Unix domain socket in the data directory that speaks
+//! newline-delimited JSON-RPC 2.0. It exposes sending, the history, friends,
+//! connected peers and the outbox, and pushes events to connections that
+//! subscribed to them. The `ctl` subcommand is a client for it.
+pub mod client;
+pub mod methods;
+pub mod protocol;
+
+use crate::cli::commands::{Node, UiNotification};
+use anyhow::{bail, Result};
+use protocol::{Event, Notification, Request, Response, RpcError, INVALID_REQUEST, PARSE_ERROR};
+use serde::Serialize;
+use serde_json::Value;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, error, info, warn};
+
+/// Returns the path of the control socket in a data directory.
+pub fn socket_path(data_dir: &str) -> PathBuf {
+    Path::new(data_dir).join("control.sock")
+}
+
+/// Runs the control socket server until the process is interrupted or
+/// terminated, then removes the socket.
+///
+/// # Arguments
+///
+/// * `node` - A shared reference to the application's core `Node`.
+/// * `path` - The path of the socket to create.
+/// * `ui_notify_rx` - Receiver for UI notifications, pushed to subscribers as events.
+///
+/// # Errors
+///
+/// Returns an error if another client is already listening on the socket or
+/// the socket cannot be created.
+pub async fn run_server(
+    node: Arc<Node>,
+    path: &Path,
+    mut ui_notify_rx: mpsc::UnboundedReceiver<UiNotification>,
+) -> Result<()> {
+    let listener = bind(path).await?;
+    info!("Control socket listening on {}", path.display());
+    println!("🔌 Control socket: {}", path.display());
+
+    let (events_tx, _) = broadcast::channel::<Event>(256);
+
+    // Spawn task to convert UI notifications to events for subscribers.
+    let events_tx_clone = events_tx.clone();
+    tokio::spawn(async move {
+        while let Some(notification) = ui_notify_rx.recv().await {
+            let _ = events_tx_clone.send(methods::event_from(notification));
+        }
+    });
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let node = node.clone();
+                    let events_tx = events_tx.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(node, stream, events_tx).await {
+                            debug!("Control connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to accept control connection: {}", e),
+            },
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+
+    info!("Shutting down, removing {}", path.display());
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Binds the control socket, replacing a stale one left by a client that
+/// did not shut down cleanly.
+async fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            bail!(
+                "Another client is already running with the control socket {}",
+                path.display()
+            );
+        }
+        warn!("Removing stale control socket {}", path.display());
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    // Only the owner may control the client.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Serves the requests of a connection and pushes events to it once it
+/// subscribed.
+async fn handle_connection(
+    node: Arc<Node>,
+    stream: UnixStream,
+    events_tx: broadcast::Sender<Event>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut subscription: Option<broadcast::Receiver<Event>> = None;
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let request = match parse_request(&line) {
+                    Ok(request) => request,
+                    Err(error) => {
+                        write_line(&mut writer, &Response::new(Value::Null, Err(error))).await?;
+                        continue;
+                    }
+                };
+
+                let outcome = if request.method == "subscribe" {
+                    subscription.get_or_insert_with(|| events_tx.subscribe());
+                    Ok(serde_json::json!({ "subscribed": true }))
+                } else {
+                    methods::dispatch(&node, &request.method, request.params).await
+                };
+
+                // Requests without an ID are notifications and get no response.
+                if let Some(id) = request.id {
+                    write_line(&mut writer, &Response::new(id, outcome)).await?;
+                }
+            }
+            Some(event) = next_event(&mut subscription) => {
+                write_line(&mut writer, &Notification::event(event)).await?;
+            }
+        }
+    }
+}
+
+/// Parses a line into a request.
+fn parse_request(line: &str) -> Result<Request, RpcError> {
+    let value: Value =
+        serde_json::from_str(line).map_err(|e| RpcError::new(PARSE_ERROR, e.to_string()))?;
+    serde_json::from_value(value).map_err(|e| RpcError::new(INVALID_REQUEST, e.to_string()))
+}
+
+/// Waits for the next event of a subscription, or forever without one.
+async fn next_event(subscription: &mut Option<broadcast::Receiver<Event>>) -> Option<Event> {
+    let Some(receiver) = subscription else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        match receiver.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Control subscriber lagged, skipped {} events", skipped);
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Writes a message as a line of JSON.
+async fn write_line<T: Serialize>(writer: &mut OwnedWriteHalf, message: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary data directory, removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_socket_is_private_and_not_shared() {
+        let dir =
+            TestDir(std::env::temp_dir().join(format!("p2p-chat-test-{}", uuid::Uuid::new_v4())));
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let path = socket_path(dir.0.to_str().unwrap());
+
+        let listener = bind(&path).await.unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(bind(&path).await.is_err());
+
+        // The socket of a client that did not shut down cleanly is replaced.
+        drop(listener);
+        assert!(path.exists());
+        bind(&path).await.unwrap();
+    }
+
+    #[test]
+    fn malformed_requests_get_json_rpc_errors() {
+        assert_eq!(parse_request("{").unwrap_err().code, PARSE_ERROR);
+        assert_eq!(
+            parse_request(r#"{"jsonrpc":"2.0","id":1}"#)
+                .unwrap_err()
+                .code,
+            INVALID_REQUEST
+        );
+        let request = parse_request(r#"{"jsonrpc":"2.0","id":1,"method":"peers"}"#).unwrap();
+        assert_eq!(request.method, "peers");
+    }
+}
@@ -0,0 +1,310 @@
+//! This module defines the JSON-RPC 2.0 messages exchanged over the control
+//! socket.
+//!
+//! Every message is a single JSON object on its own line. Requests carrying an
+//! `id` get a response with the same `id`; after a `subscribe` request, events
+//! are pushed as `event` notifications on the same connection.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The JSON-RPC version sent in every message.
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// The request is not valid JSON.
+pub const PARSE_ERROR: i64 = -32700;
+/// The request is not a valid JSON-RPC request.
+pub const INVALID_REQUEST: i64 = -32600;
+/// The requested method does not exist.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// The parameters of the request are invalid.
+pub const INVALID_PARAMS: i64 = -32602;
+/// The method failed, e.g. because the peer is not a friend.
+pub const METHOD_FAILED: i64 = -32000;
+
+/// The method name of pushed event notifications.
+pub const EVENT_METHOD: &str = "event";
+
+/// Represents a JSON-RPC request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    /// The JSON-RPC version.
+    pub jsonrpc: String,
+    /// The name of the method to call.
+    pub method: String,
+    /// The parameters of the method.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+    /// The request ID; requests without one get no response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+}
+
+impl Request {
+    /// Creates a new request.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The request ID.
+    /// * `method` - The name of the method to call.
+    /// * `params` - The parameters of the method.
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            method: method.to_string(),
+            params,
+            id: Some(Value::from(id)),
+        }
+    }
+}
+
+/// Represents a JSON-RPC response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    /// The JSON-RPC version.
+    pub jsonrpc: String,
+    /// The result of a successful call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The error of a failed call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    /// The ID of the request this response answers.
+    pub id: Value,
+}
+
+impl Response {
+    /// Creates a response from the outcome of a call.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the request.
+    /// * `outcome` - The result or error of the call.
+    pub fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+/// Represents the error of a failed call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcError {
+    /// The error code.
+    pub code: i64,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl RpcError {
+    /// Creates a new error.
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Represents a notification pushed to subscribed connections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    /// The JSON-RPC version.
+    pub jsonrpc: String,
+    /// The notification method, always `event`.
+    pub method: String,
+    /// The event.
+    pub params: Event,
+}
+
+impl Notification {
+    /// Wraps an event in a notification.
+    pub fn event(event: Event) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            method: EVENT_METHOD.to_string(),
+            params: event,
+        }
+    }
+}
+
+/// Represents an event of the running client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A message was received or sent.
+    NewMessage {
+        /// The ID of the message.
+        id: String,
+        /// The Peer ID of the sender.
+        sender: String,
+        /// The Peer ID of the recipient.
+        recipient: String,
+        /// The message text.
+        content: String,
+        /// The timestamp of the message, in milliseconds.
+        timestamp: i64,
+        /// The ID of the group, for group messages.
+        group_id: Option<String>,
+    },
+    /// A peer connected.
+    PeerConnected {
+        /// The Peer ID of the peer.
+        peer_id: String,
+    },
+    /// A peer disconnected.
+    PeerDisconnected {
+        /// The Peer ID of the peer.
+        peer_id: String,
+    },
+    /// The delivery status of a sent message changed.
+    DeliveryStatus {
+        /// The ID of the message.
+        message_id: String,
+        /// The new delivery status.
+        status: String,
+    },
+    /// A friend request was received.
+    ContactRequest {
+        /// The Peer ID of the requester.
+        peer_id: String,
+        /// The display name from the requester's contact card.
+        display_name: Option<String>,
+    },
+    /// A friend request was accepted.
+    ContactAccepted {
+        /// The Peer ID of the new friend.
+        peer_id: String,
+    },
+    /// A friend request we sent was rejected.
+    ContactRejected {
+        /// The Peer ID of the peer.
+        peer_id: String,
+    },
+    /// A peer that is not a friend sent a message.
+    MessageRequest {
+        /// The Peer ID of the sender.
+        peer_id: String,
+    },
+    /// A group was joined or its members changed.
+    GroupUpdated {
+        /// The ID of the group.
+        group_id: String,
+        /// The name of the group.
+        name: String,
+    },
+    /// The key of a verified friend changed.
+    VerifiedKeyChanged {
+        /// The Peer ID of the friend.
+        peer_id: String,
+    },
+    /// Messages were deleted by their sender.
+    MessagesDeleted {
+        /// The IDs of the deleted messages.
+        message_ids: Vec<String>,
+    },
+}
+
+/// The parameters of the `send` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendParams {
+    /// The Peer ID or nickname of the friend.
+    pub peer: String,
+    /// The message text.
+    pub text: String,
+}
+
+/// The parameters of the `friend_add` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendAddParams {
+    /// The Peer ID of the friend.
+    pub peer_id: String,
+    /// The base64-encoded E2E public key of the friend.
+    pub e2e_public_key: String,
+    /// An optional nickname for the friend.
+    #[serde(default)]
+    pub nickname: Option<String>,
+}
+
+/// The parameters of the `friend_remove` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendRemoveParams {
+    /// The Peer ID or nickname of the friend.
+    pub peer: String,
+}
+
+/// The parameters of the `history` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryParams {
+    /// The Peer ID or nickname of the peer.
+    pub peer: String,
+    /// The maximum number of messages to return.
+    #[serde(default = "default_history_limit")]
+    pub limit: usize,
+}
+
+/// The default number of messages returned by the `history` method.
+fn default_history_limit() -> usize {
+    50
+}
+
+/// A message returned by the `history` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The ID of the message.
+    pub id: String,
+    /// The Peer ID of the sender.
+    pub sender: String,
+    /// The Peer ID of the recipient.
+    pub recipient: String,
+    /// The message text.
+    pub content: String,
+    /// The timestamp of the message, in milliseconds.
+    pub timestamp: i64,
+    /// The delivery status of the message.
+    pub delivery_status: String,
+}
+
+/// A friend returned by the `friends` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendEntry {
+    /// The Peer ID of the friend.
+    pub peer_id: String,
+    /// The nickname of the friend, if any.
+    pub nickname: Option<String>,
+    /// The base64-encoded E2E public key of the friend.
+    pub e2e_public_key: String,
+    /// Whether the friend is connected.
+    pub online: bool,
+    /// Whether the friend's keys were verified with the safety number.
+    pub verified: bool,
+}
+
+/// The result of the `outbox` method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxStatus {
+    /// The number of messages waiting for delivery.
+    pub pending: usize,
+    /// The messages waiting for delivery, oldest first.
+    pub messages: Vec<OutboxEntry>,
+}
+
+/// A message waiting in the outbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// The ID of the message.
+    pub id: String,
+    /// The Peer ID of the recipient.
+    pub recipient: String,
+    /// The timestamp of the message, in milliseconds.
+    pub timestamp: i64,
+    /// The ID of the group, for group messages.
+    pub group_id: Option<String>,
+}
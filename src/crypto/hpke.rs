@@ -57,6 +57,11 @@ impl HpkeContext {
         self.private_key.to_bytes().to_vec()
     }
 
+    /// Returns the private key, for use by the ratchet sessions.
+    pub(crate) fn private_key(&self) -> &StaticSecret {
+        &self.private_key
+    }
+
     /// Derives a shared secret and uses it to encrypt a message for a recipient.
     ///
     /// # Arguments
@@ -1,11 +1,23 @@
 //! This module manages the user's identity, which consists of a libp2p keypair
 //! and an HPKE keypair.
-use crate::crypto::HpkeContext;
-use anyhow::Result;
+use crate::crypto::ratchet::RatchetMessage;
+use crate::crypto::sender_key::{self, SenderKeyDistribution, SenderKeyMessage, SenderKeyState};
+use crate::crypto::{identity_file, mailbox_auth, signing, HpkeContext, StorageEncryption};
+use crate::storage::{SenderKeyStore, SessionStore};
+use crate::types::{
+    ContactCard, DeleteRequest, Device, DeviceList, EncryptedMessage, KeyUpdate, MailboxChallenge,
+    Message,
+};
+use anyhow::{anyhow, bail, Result};
 use libp2p::{identity, PeerId};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+use uuid::Uuid;
 
 /// A serializable representation of the user's keypairs.
 #[derive(Serialize, Deserialize)]
@@ -16,6 +28,28 @@ pub struct KeyPair {
     pub hpke_private_key: Vec<u8>,
     /// The X25519 public key for HPKE.
     pub hpke_public_key: Vec<u8>,
+    /// The HPKE private keys replaced by key rotations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retired_hpke_keys: Vec<RetiredHpkeKey>,
+}
+
+/// A serializable HPKE private key that was replaced by a key rotation.
+#[derive(Serialize, Deserialize)]
+pub struct RetiredHpkeKey {
+    /// The X25519 private key.
+    pub private_key: Vec<u8>,
+    /// When the key was replaced (milliseconds since epoch).
+    pub retired_at: i64,
+}
+
+/// How long, in milliseconds, messages encrypted to a replaced HPKE key are
+/// still decrypted, so that friends have time to learn the new key.
+pub const RETIRED_KEY_GRACE_PERIOD_MS: i64 = 14 * 24 * 60 * 60 * 1000;
+
+/// An HPKE keypair that was replaced by a key rotation.
+struct RetiredKey {
+    context: HpkeContext,
+    retired_at: i64,
 }
 
 /// Represents the user's identity, including their libp2p and HPKE keypairs.
@@ -26,6 +60,16 @@ pub struct Identity {
     pub libp2p_keypair: identity::Keypair,
     /// The HPKE context, containing the HPKE keypair.
     pub hpke_context: HpkeContext,
+    /// The HPKE keypairs replaced by key rotations, oldest first.
+    retired_keys: Vec<RetiredKey>,
+    /// The store for ratchet sessions, if E2E sessions are enabled.
+    sessions: Option<Arc<dyn SessionStore>>,
+    /// Serializes session updates so that message keys are never reused.
+    session_lock: Mutex<()>,
+    /// The store for group sender keys, if group messaging is enabled.
+    sender_keys: Option<Arc<dyn SenderKeyStore>>,
+    /// Serializes sender key updates so that group message keys are never reused.
+    sender_key_lock: Mutex<()>,
 }
 
 impl Identity {
@@ -49,6 +93,11 @@ impl Identity {
             peer_id,
             libp2p_keypair,
             hpke_context,
+            retired_keys: Vec::new(),
+            sessions: None,
+            session_lock: Mutex::new(()),
+            sender_keys: None,
+            sender_key_lock: Mutex::new(()),
         })
     }
 
@@ -57,34 +106,89 @@ impl Identity {
     /// # Arguments
     ///
     /// * `path` - The path to the identity file.
+    /// * `passphrase` - The passphrase protecting the file, if any.
     ///
     /// # Errors
     ///
     /// This function will return an error if loading or generating the identity fails.
-    pub fn load_or_generate(path: &str) -> Result<Self> {
+    pub fn load_or_generate(path: &str, passphrase: Option<&str>) -> Result<Self> {
         if Path::new(path).exists() {
-            Self::load(path)
+            Self::load(path, passphrase)
         } else {
             let identity = Self::generate()?;
-            identity.save(path)?;
+            identity.save(path, passphrase)?;
             Ok(identity)
         }
     }
 
     /// Loads an identity from a file.
     ///
+    /// Files in an older format are rewritten in the current one, and
+    /// plaintext files are sealed when a passphrase is given.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the identity file.
+    /// * `passphrase` - The passphrase protecting the file, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read, the
+    /// passphrase is missing or wrong, or the keypair data is invalid.
+    pub fn load(path: &str, passphrase: Option<&str>) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let decoded = identity_file::decode(&content, passphrase)?;
+        let identity = Self::from_keypair(&decoded.keypair)?;
+
+        if decoded.outdated || (passphrase.is_some() && !decoded.sealed) {
+            info!(
+                "Migrating identity file {} to version {}{}",
+                path,
+                identity_file::IDENTITY_FILE_VERSION,
+                if passphrase.is_some() { ", sealed" } else { "" }
+            );
+            identity.save(path, passphrase)?;
+        }
+
+        Ok(identity)
+    }
+
+    /// Restores an identity from an identity file or an export.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the identity file.
+    /// * `passphrase` - The passphrase protecting the content, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the passphrase is missing or
+    /// wrong, or the keypair data is invalid.
+    pub fn import(content: &str, passphrase: Option<&str>) -> Result<Self> {
+        let decoded = identity_file::decode(content, passphrase)?;
+        Self::from_keypair(&decoded.keypair)
+    }
+
+    /// Changes the passphrase of an identity file.
+    ///
     /// # Arguments
     ///
     /// * `path` - The path to the identity file.
+    /// * `current` - The current passphrase, if the file is sealed.
+    /// * `new` - The new passphrase, or `None` to store the file in plaintext.
     ///
     /// # Errors
     ///
-    /// This function will return an error if the file cannot be read or if the
-    /// keypair data is invalid.
-    pub fn load(path: &str) -> Result<Self> {
+    /// This function will return an error if the current passphrase is
+    /// missing or wrong, or the file cannot be rewritten.
+    pub fn change_passphrase(path: &str, current: Option<&str>, new: Option<&str>) -> Result<()> {
         let content = fs::read_to_string(path)?;
-        let keypair_data: KeyPair = serde_json::from_str(&content)?;
+        let decoded = identity_file::decode(&content, current)?;
+        Self::from_keypair(&decoded.keypair)?.save(path, new)
+    }
 
+    /// Reconstructs an identity from its keypairs.
+    fn from_keypair(keypair_data: &KeyPair) -> Result<Self> {
         // Reconstruct libp2p keypair.
         let libp2p_keypair =
             identity::Keypair::from_protobuf_encoding(&keypair_data.libp2p_keypair)?;
@@ -92,58 +196,407 @@ impl Identity {
 
         // Reconstruct HPKE context.
         let hpke_context = HpkeContext::from_private_key(&keypair_data.hpke_private_key)?;
+        let retired_keys = keypair_data
+            .retired_hpke_keys
+            .iter()
+            .map(|key| {
+                Ok(RetiredKey {
+                    context: HpkeContext::from_private_key(&key.private_key)?,
+                    retired_at: key.retired_at,
+                })
+            })
+            .collect::<Result<_>>()?;
 
         Ok(Self {
             peer_id,
             libp2p_keypair,
             hpke_context,
+            retired_keys,
+            sessions: None,
+            session_lock: Mutex::new(()),
+            sender_keys: None,
+            sender_key_lock: Mutex::new(()),
+        })
+    }
+
+    /// Returns the keypairs of the identity in their serializable form.
+    fn keypair(&self) -> Result<KeyPair> {
+        Ok(KeyPair {
+            libp2p_keypair: self.libp2p_keypair.to_protobuf_encoding()?,
+            hpke_private_key: self.hpke_context.private_key_bytes(),
+            hpke_public_key: self.hpke_context.public_key_bytes(),
+            retired_hpke_keys: self
+                .retired_keys
+                .iter()
+                .map(|key| RetiredHpkeKey {
+                    private_key: key.context.private_key_bytes(),
+                    retired_at: key.retired_at,
+                })
+                .collect(),
         })
     }
 
     /// Saves the identity to a file.
     ///
+    /// The file is replaced atomically and readable only by its owner.
+    ///
     /// # Arguments
     ///
     /// * `path` - The path to the identity file.
+    /// * `passphrase` - The passphrase to seal the file with, or `None` for plaintext.
     ///
     /// # Errors
     ///
     /// This function will return an error if the identity cannot be saved.
-    pub fn save(&self, path: &str) -> Result<()> {
-        let keypair_data = KeyPair {
-            libp2p_keypair: self.libp2p_keypair.to_protobuf_encoding()?,
-            hpke_private_key: self.hpke_context.private_key_bytes(),
-            hpke_public_key: self.hpke_context.public_key_bytes(),
-        };
-
-        let content = serde_json::to_string_pretty(&keypair_data)?;
+    pub fn save(&self, path: &str, passphrase: Option<&str>) -> Result<()> {
+        let content = identity_file::encode(self.keypair()?, passphrase)?;
 
         if let Some(parent) = Path::new(path).parent() {
             fs::create_dir_all(parent)?;
         }
 
-        fs::write(path, content)?;
+        // A leftover from an interrupted save is replaced, so that the file
+        // is never readable by others, not even before it is written.
+        let temp_path = format!("{}.tmp", path);
+        match fs::remove_file(&temp_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
         Ok(())
     }
 
+    /// Exports the identity as an unsealed identity file.
+    ///
+    /// The output contains the private keys.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the keypairs cannot be encoded.
+    pub fn export(&self) -> Result<String> {
+        identity_file::encode(self.keypair()?, None)
+    }
+
     /// Returns the HPKE public key bytes.
     pub fn hpke_public_key(&self) -> Vec<u8> {
         self.hpke_context.public_key_bytes()
     }
 
+    /// Signs a direct chat message with our libp2p keypair.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The sealed message to sign.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing fails.
+    pub fn sign_message(&self, message: &Message) -> Result<Vec<u8>> {
+        Ok(self
+            .libp2p_keypair
+            .sign(&signing::message_signing_bytes(message))?)
+    }
+
+    /// Signs a mailbox message with our libp2p keypair.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The mailbox message to sign.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing fails.
+    pub fn sign_mailbox_message(&self, message: &EncryptedMessage) -> Result<Vec<u8>> {
+        Ok(self
+            .libp2p_keypair
+            .sign(&signing::mailbox_message_signing_bytes(message))?)
+    }
+
+    /// Returns the hash under which mailbox nodes store messages for us.
+    pub fn recipient_hash(&self) -> [u8; 32] {
+        StorageEncryption::derive_recipient_hash(&self.hpke_public_key())
+    }
+
+    /// Returns the E2E public keys mailbox nodes may hold messages for: the
+    /// current key, followed by the keys still within their grace period.
+    pub fn mailbox_keys(&self) -> Vec<Vec<u8>> {
+        std::iter::once(&self.hpke_context)
+            .chain(self.grace_period_keys())
+            .map(HpkeContext::public_key_bytes)
+            .collect()
+    }
+
+    /// Answers a mailbox node's challenge, proving that we own an E2E key.
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - The key whose messages are requested, one of `mailbox_keys`.
+    /// * `challenge` - The challenge issued by the mailbox node.
+    ///
+    /// # Returns
+    ///
+    /// The proof to present in `Fetch` and `Ack` requests.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if we do not hold the key or the
+    /// challenge is malformed.
+    pub fn answer_mailbox_challenge(
+        &self,
+        public_key: &[u8],
+        challenge: &MailboxChallenge,
+    ) -> Result<[u8; 32]> {
+        let key = std::iter::once(&self.hpke_context)
+            .chain(self.grace_period_keys())
+            .find(|key| key.public_key_bytes() == public_key)
+            .ok_or_else(|| anyhow!("No E2E key matches the mailbox challenge"))?;
+
+        mailbox_auth::answer_challenge(
+            key,
+            challenge,
+            &StorageEncryption::derive_recipient_hash(public_key),
+            &self.peer_id,
+        )
+    }
+
+    /// Signs an announcement that our E2E key is being replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_key` - The new E2E public key.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing fails.
+    pub fn sign_key_update(&self, new_key: &[u8]) -> Result<KeyUpdate> {
+        let mut update = KeyUpdate {
+            peer_id: self.peer_id,
+            previous_key: self.hpke_public_key(),
+            new_key: new_key.to_vec(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            signature: Vec::new(),
+        };
+        update.signature = self
+            .libp2p_keypair
+            .sign(&signing::key_update_signing_bytes(&update))?;
+        Ok(update)
+    }
+
+    /// Signs the list of devices linked to this identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `devices` - The linked devices, without this one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing fails.
+    pub fn sign_device_list(&self, devices: Vec<Device>) -> Result<DeviceList> {
+        let mut list = DeviceList {
+            account: self.peer_id,
+            devices,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            signature: Vec::new(),
+        };
+        list.signature = self
+            .libp2p_keypair
+            .sign(&signing::device_list_signing_bytes(&list))?;
+        Ok(list)
+    }
+
+    /// Signs a request to delete messages we sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_ids` - The IDs of the messages, as stored by the recipient.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing fails.
+    pub fn sign_delete_request(&self, message_ids: Vec<Uuid>) -> Result<DeleteRequest> {
+        let mut request = DeleteRequest {
+            peer_id: self.peer_id,
+            message_ids,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            signature: Vec::new(),
+        };
+        request.signature = self
+            .libp2p_keypair
+            .sign(&signing::delete_request_signing_bytes(&request))?;
+        Ok(request)
+    }
+
+    /// Replaces our HPKE keypair.
+    ///
+    /// The previous key is kept to decrypt messages for the grace period,
+    /// and all ratchet sessions are retired, so that messages we send from
+    /// now on start new sessions with the new key.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_context` - The new HPKE keypair.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sessions cannot be retired.
+    pub fn rotate_hpke_key(&mut self, new_context: HpkeContext) -> Result<()> {
+        let previous = std::mem::replace(&mut self.hpke_context, new_context);
+        self.retired_keys.push(RetiredKey {
+            context: previous,
+            retired_at: chrono::Utc::now().timestamp_millis(),
+        });
+
+        let Some(ref sessions) = self.sessions else {
+            return Ok(());
+        };
+        let _guard = self
+            .session_lock
+            .lock()
+            .map_err(|_| anyhow!("Session lock poisoned"))?;
+
+        for remote_public_key in sessions.list_sessions()? {
+            if let Some(mut record) = sessions.load_session(&remote_public_key)? {
+                record.retire_current();
+                sessions.store_session(&remote_public_key, &record)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries the ratchet sessions held with a peer over to its new key.
+    ///
+    /// This is done when a friend rotates its key, so that messages it sent
+    /// before rotating can still be decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_key` - The peer's previous E2E public key.
+    /// * `new_key` - The peer's new E2E public key.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sessions cannot be copied.
+    pub fn carry_over_sessions(&self, previous_key: &[u8], new_key: &[u8]) -> Result<()> {
+        let Some(ref sessions) = self.sessions else {
+            return Ok(());
+        };
+        let _guard = self
+            .session_lock
+            .lock()
+            .map_err(|_| anyhow!("Session lock poisoned"))?;
+
+        if sessions.load_session(new_key)?.is_some() {
+            return Ok(());
+        }
+        if let Some(record) = sessions.load_session(previous_key)? {
+            sessions.store_session(new_key, &record)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the retired HPKE keypairs still within their grace period.
+    fn grace_period_keys(&self) -> impl Iterator<Item = &HpkeContext> {
+        let cutoff = chrono::Utc::now().timestamp_millis() - RETIRED_KEY_GRACE_PERIOD_MS;
+        self.retired_keys
+            .iter()
+            .rev()
+            .filter(move |key| key.retired_at > cutoff)
+            .map(|key| &key.context)
+    }
+
+    /// Creates a signed contact card describing this identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `display_name` - The display name to advertise, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if signing fails.
+    pub fn contact_card(&self, display_name: Option<String>) -> Result<ContactCard> {
+        let mut card = ContactCard {
+            peer_id: self.peer_id,
+            e2e_public_key: self.hpke_public_key(),
+            display_name,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            signature: Vec::new(),
+        };
+        card.signature = self
+            .libp2p_keypair
+            .sign(&signing::contact_card_signing_bytes(&card))?;
+        Ok(card)
+    }
+
+    /// Attaches a session store, enabling forward-secret ratchet sessions.
+    ///
+    /// Without a session store, `encrypt_for` falls back to static-key encryption.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The `SessionStore` to keep ratchet sessions in.
+    pub fn with_session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.sessions = Some(store);
+        self
+    }
+
+    /// Attaches a sender key store, enabling group messaging.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The `SenderKeyStore` to keep group sender keys in.
+    pub fn with_sender_key_store(mut self, store: Arc<dyn SenderKeyStore>) -> Self {
+        self.sender_keys = Some(store);
+        self
+    }
+
     /// Encrypts a message for a recipient using their public key.
     ///
+    /// When a session store is attached, the message is encrypted with the
+    /// ratchet session held with the recipient, which is created on first use.
+    ///
     /// # Arguments
     ///
     /// * `recipient_public_key` - The public key of the recipient.
     /// * `plaintext` - The data to encrypt.
-    /// This function will return an error if encryption fails.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if encryption fails or the session
+    /// cannot be persisted.
     pub fn encrypt_for(&self, recipient_public_key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
-        self.hpke_context.seal(recipient_public_key, plaintext)
+        let Some(ref sessions) = self.sessions else {
+            return self.hpke_context.seal(recipient_public_key, plaintext);
+        };
+
+        let remote_key = parse_public_key(recipient_public_key)?;
+        let _guard = self
+            .session_lock
+            .lock()
+            .map_err(|_| anyhow!("Session lock poisoned"))?;
+
+        let mut record = sessions
+            .load_session(recipient_public_key)?
+            .unwrap_or_default();
+        let message = record.encrypt(self.hpke_context.private_key(), &remote_key, plaintext)?;
+        sessions.store_session(recipient_public_key, &record)?;
+
+        message.to_bytes()
     }
 
     /// Decrypts a message from a sender using their public key.
     ///
+    /// Ratchet messages are decrypted with the session held with the sender.
+    /// Anything else is treated as legacy static-key ciphertext. Messages
+    /// that our current key cannot decrypt are tried with the keys replaced
+    /// within the grace period.
+    ///
     /// # Arguments
     ///
     /// * `sender_public_key` - The public key of the sender.
@@ -153,6 +606,369 @@ impl Identity {
     ///
     /// This function will return an error if decryption fails.
     pub fn decrypt_from(&self, sender_public_key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
-        self.hpke_context.open(sender_public_key, ciphertext)
+        let (Some(ref sessions), Ok(message)) =
+            (&self.sessions, RatchetMessage::from_bytes(ciphertext))
+        else {
+            return self
+                .hpke_context
+                .open(sender_public_key, ciphertext)
+                .or_else(|e| {
+                    self.grace_period_keys()
+                        .find_map(|key| key.open(sender_public_key, ciphertext).ok())
+                        .ok_or(e)
+                });
+        };
+
+        let remote_key = parse_public_key(sender_public_key)?;
+        let _guard = self
+            .session_lock
+            .lock()
+            .map_err(|_| anyhow!("Session lock poisoned"))?;
+
+        let mut record = sessions
+            .load_session(sender_public_key)?
+            .unwrap_or_default();
+        let plaintext = match record.decrypt(self.hpke_context.private_key(), &remote_key, &message)
+        {
+            Ok(plaintext) => plaintext,
+            Err(e) => {
+                // The sender may not have learned our new key yet.
+                let plaintext = self
+                    .grace_period_keys()
+                    .find_map(|key| {
+                        record
+                            .decrypt(key.private_key(), &remote_key, &message)
+                            .ok()
+                    })
+                    .ok_or(e)?;
+                // Keep sending on sessions with our current key.
+                record.retire_current();
+                plaintext
+            }
+        };
+        sessions.store_session(sender_public_key, &record)?;
+
+        Ok(plaintext)
+    }
+
+    /// Returns our sender key for a group, recording which members receive it.
+    ///
+    /// A new sender key is generated if we have none for the group yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `members` - The members the key is about to be sent to.
+    ///
+    /// # Returns
+    ///
+    /// The current state of our sender key, and the members among `members`
+    /// that have not been sent this key before.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no sender key store is attached
+    /// or the key cannot be persisted.
+    pub fn distribute_sender_key(
+        &self,
+        group_id: &Uuid,
+        members: &[PeerId],
+    ) -> Result<(SenderKeyDistribution, Vec<PeerId>)> {
+        let store = self.sender_key_store()?;
+        let _guard = self
+            .sender_key_lock
+            .lock()
+            .map_err(|_| anyhow!("Sender key lock poisoned"))?;
+
+        let mut state = store
+            .load_sender_key(group_id, &self.peer_id)?
+            .unwrap_or_else(SenderKeyState::generate);
+        let missing = state.mark_distributed(members);
+        store.store_sender_key(group_id, &self.peer_id, &state)?;
+
+        Ok((state.distribution(), missing))
+    }
+
+    /// Encrypts a group message with our sender key for the group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `message_id` - The ID of the group message.
+    /// * `plaintext` - The data to encrypt.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no sender key store is attached,
+    /// encryption fails, or the advanced key cannot be persisted.
+    pub fn group_encrypt(
+        &self,
+        group_id: &Uuid,
+        message_id: &Uuid,
+        plaintext: &[u8],
+    ) -> Result<SenderKeyMessage> {
+        let store = self.sender_key_store()?;
+        let _guard = self
+            .sender_key_lock
+            .lock()
+            .map_err(|_| anyhow!("Sender key lock poisoned"))?;
+
+        let mut state = store
+            .load_sender_key(group_id, &self.peer_id)?
+            .unwrap_or_else(SenderKeyState::generate);
+        let associated_data = sender_key::associated_data(group_id, &self.peer_id, message_id);
+        let message = state.encrypt(&associated_data, plaintext)?;
+        store.store_sender_key(group_id, &self.peer_id, &state)?;
+
+        Ok(message)
+    }
+
+    /// Decrypts a group message with the sender's sender key for the group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `sender` - The `PeerId` of the member who sent the message.
+    /// * `message_id` - The ID of the group message.
+    /// * `message` - The encrypted message.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if we do not hold the sender's key
+    /// or decryption fails.
+    pub fn group_decrypt(
+        &self,
+        group_id: &Uuid,
+        sender: &PeerId,
+        message_id: &Uuid,
+        message: &SenderKeyMessage,
+    ) -> Result<Vec<u8>> {
+        let store = self.sender_key_store()?;
+        let _guard = self
+            .sender_key_lock
+            .lock()
+            .map_err(|_| anyhow!("Sender key lock poisoned"))?;
+
+        let mut state = store
+            .load_sender_key(group_id, sender)?
+            .ok_or_else(|| anyhow!("No sender key from {} for group {}", sender, group_id))?;
+        let associated_data = sender_key::associated_data(group_id, sender, message_id);
+        let plaintext = state.decrypt(&associated_data, message)?;
+        store.store_sender_key(group_id, sender, &state)?;
+
+        Ok(plaintext)
+    }
+
+    /// Stores a sender key received from another group member.
+    ///
+    /// A key we already hold is kept, so that a repeated distribution cannot
+    /// rewind its chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `sender` - The `PeerId` of the member owning the key.
+    /// * `distribution` - The received sender key.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no sender key store is attached
+    /// or the key cannot be persisted.
+    pub fn process_sender_key(
+        &self,
+        group_id: &Uuid,
+        sender: &PeerId,
+        distribution: &SenderKeyDistribution,
+    ) -> Result<()> {
+        if *sender == self.peer_id {
+            bail!("Refusing to replace our own sender key");
+        }
+
+        let store = self.sender_key_store()?;
+        let _guard = self
+            .sender_key_lock
+            .lock()
+            .map_err(|_| anyhow!("Sender key lock poisoned"))?;
+
+        if let Some(existing) = store.load_sender_key(group_id, sender)? {
+            if existing.key_id() == distribution.key_id {
+                return Ok(());
+            }
+        }
+
+        store.store_sender_key(
+            group_id,
+            sender,
+            &SenderKeyState::from_distribution(distribution),
+        )
+    }
+
+    /// Forgets a member's sender key for a group.
+    ///
+    /// Forgetting our own key makes the next group message use a fresh one,
+    /// which is done when a member leaves.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `sender` - The `PeerId` of the member owning the key.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no sender key store is attached
+    /// or the key cannot be removed.
+    pub fn forget_sender_key(&self, group_id: &Uuid, sender: &PeerId) -> Result<()> {
+        let store = self.sender_key_store()?;
+        let _guard = self
+            .sender_key_lock
+            .lock()
+            .map_err(|_| anyhow!("Sender key lock poisoned"))?;
+        store.remove_sender_key(group_id, sender)
+    }
+
+    /// Forgets all sender keys for a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no sender key store is attached
+    /// or the keys cannot be removed.
+    pub fn forget_group_keys(&self, group_id: &Uuid) -> Result<()> {
+        let store = self.sender_key_store()?;
+        let _guard = self
+            .sender_key_lock
+            .lock()
+            .map_err(|_| anyhow!("Sender key lock poisoned"))?;
+        store.remove_group_keys(group_id)
+    }
+
+    /// Returns the attached sender key store.
+    fn sender_key_store(&self) -> Result<&Arc<dyn SenderKeyStore>> {
+        self.sender_keys
+            .as_ref()
+            .ok_or_else(|| anyhow!("Group messaging is not available without a sender key store"))
+    }
+
+    /// Opens message content that an older version stored in the history
+    /// sealed with the static key, also with keys replaced since.
+    ///
+    /// This is only used to migrate such records to plaintext.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_public_key` - The public key of the conversation partner.
+    /// * `content` - The stored message content.
+    ///
+    /// # Returns
+    ///
+    /// The plaintext, or `None` if no key opens the content.
+    pub fn open_legacy(&self, peer_public_key: &[u8], content: &[u8]) -> Option<Vec<u8>> {
+        std::iter::once(&self.hpke_context)
+            .chain(self.retired_keys.iter().rev().map(|key| &key.context))
+            .find_map(|key| key.open(peer_public_key, content).ok())
+    }
+}
+
+/// Parses an X25519 public key from a byte slice.
+fn parse_public_key(key_bytes: &[u8]) -> Result<[u8; 32]> {
+    key_bytes
+        .try_into()
+        .map_err(|_| anyhow!("Public key must be 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A temporary directory, removed when dropped.
+    struct TestDir(std::path::PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("p2p-chat-test-{}", Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn identity_path(&self) -> String {
+            self.0.join("identity.json").to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn sealed_identities_need_the_right_passphrase() {
+        let dir = TestDir::new();
+        let path = dir.identity_path();
+        let identity = Identity::generate().unwrap();
+        identity.save(&path, Some("correct horse")).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("\"sealed\""));
+        assert!(!content.contains("hpke_private_key"));
+
+        let loaded = Identity::load(&path, Some("correct horse")).unwrap();
+        assert_eq!(loaded.peer_id, identity.peer_id);
+        assert_eq!(loaded.hpke_public_key(), identity.hpke_public_key());
+        assert!(Identity::load(&path, Some("wrong")).is_err());
+        assert!(Identity::load(&path, None).is_err());
+    }
+
+    #[test]
+    fn plaintext_identities_are_migrated_and_sealed() {
+        let dir = TestDir::new();
+        let path = dir.identity_path();
+        let identity = Identity::generate().unwrap();
+        // Older releases wrote the bare keypairs, without a version.
+        let legacy = serde_json::to_string(&identity.keypair().unwrap()).unwrap();
+        fs::write(&path, legacy).unwrap();
+        // A leftover of an interrupted save does not get in the way.
+        fs::write(format!("{}.tmp", path), "stale").unwrap();
+
+        let loaded = Identity::load(&path, Some("passphrase")).unwrap();
+        assert_eq!(loaded.peer_id, identity.peer_id);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        assert!(Identity::load(&path, None).is_err());
+
+        Identity::change_passphrase(&path, Some("passphrase"), Some("new passphrase")).unwrap();
+        assert!(Identity::load(&path, Some("passphrase")).is_err());
+        let loaded = Identity::load(&path, Some("new passphrase")).unwrap();
+        assert_eq!(loaded.peer_id, identity.peer_id);
+    }
+
+    #[test]
+    fn replaced_keys_still_decrypt_within_the_grace_period() {
+        let sender = Identity::generate().unwrap();
+        let mut recipient = Identity::generate().unwrap();
+        let sent = sender
+            .encrypt_for(&recipient.hpke_public_key(), b"before the rotation")
+            .unwrap();
+
+        recipient
+            .rotate_hpke_key(HpkeContext::new().unwrap())
+            .unwrap();
+        let opened = recipient
+            .decrypt_from(&sender.hpke_public_key(), &sent)
+            .unwrap();
+        assert_eq!(opened, b"before the rotation");
+        assert_eq!(recipient.mailbox_keys().len(), 2);
+
+        // Once the grace period is over, the replaced key is dropped.
+        recipient.retired_keys[0].retired_at -= RETIRED_KEY_GRACE_PERIOD_MS;
+        assert!(recipient
+            .decrypt_from(&sender.hpke_public_key(), &sent)
+            .is_err());
+        assert_eq!(recipient.mailbox_keys(), [recipient.hpke_public_key()]);
     }
 }
@@ -0,0 +1,151 @@
+//! This module defines the on-disk format of the identity file.
+//!
+//! The file is versioned JSON. Version 1 holds the keypairs either in
+//! plaintext or sealed with a key derived from a passphrase with Argon2id,
+//! using the same parameters as `StorageEncryption` and a salt of its own.
+//! Files without a version are the plaintext keypairs written by older
+//! releases.
+use crate::crypto::identity::KeyPair;
+use crate::crypto::StorageEncryption;
+use anyhow::{anyhow, bail, Result};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The current version of the identity file format.
+pub const IDENTITY_FILE_VERSION: u32 = 1;
+
+/// The key derivation function recorded in sealed identity files.
+const KDF_ARGON2ID: &str = "argon2id";
+
+/// Represents the contents of a versioned identity file.
+#[derive(Serialize, Deserialize)]
+struct IdentityFile {
+    /// The version of the format.
+    version: u32,
+    /// The keypairs, if stored in plaintext.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keypair: Option<KeyPair>,
+    /// The keypairs, if sealed with a passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed: Option<SealedKeyPair>,
+}
+
+/// Represents keypairs sealed with a passphrase.
+#[derive(Serialize, Deserialize)]
+struct SealedKeyPair {
+    /// The key derivation function, always `argon2id`.
+    kdf: String,
+    /// The base64-encoded 16-byte salt of the key derivation.
+    salt: String,
+    /// The base64-encoded nonce and ciphertext of the JSON keypairs.
+    ciphertext: String,
+}
+
+/// Represents an identity file as read from disk.
+pub struct DecodedIdentityFile {
+    /// The keypairs.
+    pub keypair: KeyPair,
+    /// Whether the file was sealed with a passphrase.
+    pub sealed: bool,
+    /// Whether the file uses an older version of the format.
+    pub outdated: bool,
+}
+
+/// Encodes keypairs as an identity file of the current version.
+///
+/// # Arguments
+///
+/// * `keypair` - The keypairs to store.
+/// * `passphrase` - The passphrase to seal them with, or `None` for plaintext.
+///
+/// # Errors
+///
+/// This function will return an error if key derivation or encryption fails.
+pub fn encode(keypair: KeyPair, passphrase: Option<&str>) -> Result<String> {
+    let file = match passphrase {
+        Some(passphrase) => {
+            let salt = StorageEncryption::generate_salt();
+            let encryption = StorageEncryption::new(passphrase, &salt)?;
+            let ciphertext = encryption.encrypt_value(&serde_json::to_vec(&keypair)?)?;
+
+            IdentityFile {
+                version: IDENTITY_FILE_VERSION,
+                keypair: None,
+                sealed: Some(SealedKeyPair {
+                    kdf: KDF_ARGON2ID.to_string(),
+                    salt: BASE64_STANDARD.encode(salt),
+                    ciphertext: BASE64_STANDARD.encode(ciphertext),
+                }),
+            }
+        }
+        None => IdentityFile {
+            version: IDENTITY_FILE_VERSION,
+            keypair: Some(keypair),
+            sealed: None,
+        },
+    };
+
+    Ok(serde_json::to_string_pretty(&file)?)
+}
+
+/// Decodes an identity file of any version.
+///
+/// # Arguments
+///
+/// * `content` - The content of the file.
+/// * `passphrase` - The passphrase, needed if the file is sealed.
+///
+/// # Errors
+///
+/// This function will return an error if the file is malformed or from a
+/// newer release, or if it is sealed and the passphrase is missing or wrong.
+pub fn decode(content: &str, passphrase: Option<&str>) -> Result<DecodedIdentityFile> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    if value.get("version").is_none() {
+        return Ok(DecodedIdentityFile {
+            keypair: serde_json::from_value(value)?,
+            sealed: false,
+            outdated: true,
+        });
+    }
+
+    let file: IdentityFile = serde_json::from_value(value)?;
+    if file.version > IDENTITY_FILE_VERSION {
+        bail!(
+            "The identity file has version {}, but this release only reads up to version {}",
+            file.version,
+            IDENTITY_FILE_VERSION
+        );
+    }
+
+    match (file.keypair, file.sealed) {
+        (Some(keypair), None) => Ok(DecodedIdentityFile {
+            keypair,
+            sealed: false,
+            outdated: file.version < IDENTITY_FILE_VERSION,
+        }),
+        (None, Some(sealed)) => {
+            if sealed.kdf != KDF_ARGON2ID {
+                bail!("Unsupported key derivation function: {}", sealed.kdf);
+            }
+            let passphrase = passphrase.ok_or_else(|| {
+                anyhow!(
+                    "The identity file is protected by a passphrase. Supply --identity-passphrase or set P2P_MESSENGER_IDENTITY_PASSPHRASE."
+                )
+            })?;
+
+            let salt = BASE64_STANDARD.decode(&sealed.salt)?;
+            let ciphertext = BASE64_STANDARD.decode(&sealed.ciphertext)?;
+            let plaintext = StorageEncryption::new(passphrase, &salt)?
+                .decrypt_value(&ciphertext)
+                .map_err(|_| anyhow!("Wrong identity passphrase"))?;
+
+            Ok(DecodedIdentityFile {
+                keypair: serde_json::from_slice(&plaintext)?,
+                sealed: true,
+                outdated: file.version < IDENTITY_FILE_VERSION,
+            })
+        }
+        _ => bail!("The identity file must hold either a keypair or a sealed keypair"),
+    }
+}
@@ -0,0 +1,224 @@
+//! This module implements the proof of key possession that mailbox nodes require
+//! before they hand out or delete queued messages.
+//!
+//! A recipient hash only shows that the requester knows the recipient's public
+//! key. To show that it also holds the private key, the requester sends the
+//! public key and the mailbox node answers with a random nonce and an ephemeral
+//! X25519 key. Only the owner of the private key can derive the shared secret,
+//! and with it the proof that the node expects. The proof is bound to the peer
+//! that asked for the challenge, so it is useless to anyone else.
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use hmac::{Hmac, Mac};
+use libp2p::PeerId;
+use rand::RngCore;
+use rand_core::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::crypto::{HpkeContext, StorageEncryption};
+use crate::types::MailboxChallenge;
+
+/// How long a proof stays valid after its challenge was issued.
+pub const MAILBOX_AUTH_TTL: Duration = Duration::from_secs(300);
+
+/// The proof a mailbox node expects in answer to a challenge it issued.
+#[derive(Clone, Debug)]
+pub struct MailboxGrant {
+    /// The proof the requester must present.
+    expected_proof: [u8; 32],
+    /// The time after which the proof is no longer accepted.
+    expires_at: Instant,
+}
+
+impl MailboxGrant {
+    /// Returns `true` if the grant has expired.
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+
+    /// Returns the time after which the proof is no longer accepted.
+    pub fn expires_at(&self) -> Instant {
+        self.expires_at
+    }
+
+    /// Checks a presented proof in constant time.
+    pub fn accepts(&self, proof: &[u8; 32]) -> bool {
+        let difference = self
+            .expected_proof
+            .iter()
+            .zip(proof.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+        difference == 0 && !self.is_expired()
+    }
+}
+
+/// Issues a challenge for the owner of a recipient hash.
+///
+/// # Arguments
+///
+/// * `recipient` - The recipient hash the requester wants to access.
+/// * `public_key` - The E2E public key the requester claims to own.
+/// * `requester` - The `PeerId` of the requester.
+///
+/// # Returns
+///
+/// The challenge to send to the requester, and the grant to check its proof against.
+///
+/// # Errors
+///
+/// This function will return an error if the public key does not match the
+/// recipient hash or is not a usable X25519 key.
+pub fn issue_challenge(
+    recipient: &[u8; 32],
+    public_key: &[u8],
+    requester: &PeerId,
+) -> Result<(MailboxChallenge, MailboxGrant)> {
+    if StorageEncryption::derive_recipient_hash(public_key) != *recipient {
+        bail!("Public key does not match the recipient hash");
+    }
+    let public_key: [u8; 32] = public_key
+        .try_into()
+        .map_err(|_| anyhow!("Public key must be 32 bytes"))?;
+
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let shared = ephemeral.diffie_hellman(&PublicKey::from(public_key));
+    if !shared.was_contributory() {
+        bail!("Public key is a low-order point");
+    }
+
+    let mut nonce = [0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+
+    let challenge = MailboxChallenge {
+        nonce,
+        ephemeral_key: PublicKey::from(&ephemeral).to_bytes(),
+    };
+    let grant = MailboxGrant {
+        expected_proof: derive_proof(shared.as_bytes(), &challenge.nonce, recipient, requester),
+        expires_at: Instant::now() + MAILBOX_AUTH_TTL,
+    };
+    Ok((challenge, grant))
+}
+
+/// Answers a challenge with the proof that we own the key behind a recipient hash.
+///
+/// # Arguments
+///
+/// * `hpke` - The HPKE context holding our private key.
+/// * `challenge` - The challenge issued by the mailbox node.
+/// * `recipient` - Our recipient hash.
+/// * `requester` - Our own `PeerId`.
+///
+/// # Errors
+///
+/// This function will return an error if the ephemeral key of the challenge is
+/// not a usable X25519 key.
+pub fn answer_challenge(
+    hpke: &HpkeContext,
+    challenge: &MailboxChallenge,
+    recipient: &[u8; 32],
+    requester: &PeerId,
+) -> Result<[u8; 32]> {
+    let shared = hpke
+        .private_key()
+        .diffie_hellman(&PublicKey::from(challenge.ephemeral_key));
+    if !shared.was_contributory() {
+        bail!("Mailbox challenge uses a low-order point");
+    }
+
+    Ok(derive_proof(
+        shared.as_bytes(),
+        &challenge.nonce,
+        recipient,
+        requester,
+    ))
+}
+
+/// Derives the proof from the shared secret and the context it applies to.
+fn derive_proof(
+    shared_secret: &[u8; 32],
+    nonce: &[u8; 32],
+    recipient: &[u8; 32],
+    requester: &PeerId,
+) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(shared_secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(b"p2p-chat/mailbox-auth/v1");
+    mac.update(nonce);
+    mac.update(recipient);
+    mac.update(&requester.to_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Issues a challenge for the key of `hpke` on behalf of `requester`.
+    fn challenge_for(
+        hpke: &HpkeContext,
+        requester: &PeerId,
+    ) -> ([u8; 32], MailboxChallenge, MailboxGrant) {
+        let public_key = hpke.public_key_bytes();
+        let recipient = StorageEncryption::derive_recipient_hash(&public_key);
+        let (challenge, grant) = issue_challenge(&recipient, &public_key, requester).unwrap();
+        (recipient, challenge, grant)
+    }
+
+    #[test]
+    fn accepts_the_proof_of_the_key_owner() {
+        let hpke = HpkeContext::new().unwrap();
+        let requester = PeerId::random();
+        let (recipient, challenge, grant) = challenge_for(&hpke, &requester);
+
+        let proof = answer_challenge(&hpke, &challenge, &recipient, &requester).unwrap();
+        assert!(grant.accepts(&proof));
+    }
+
+    #[test]
+    fn rejects_other_proofs() {
+        let hpke = HpkeContext::new().unwrap();
+        let requester = PeerId::random();
+        let (recipient, challenge, grant) = challenge_for(&hpke, &requester);
+
+        // Someone without the private key.
+        let other = HpkeContext::new().unwrap();
+        let proof = answer_challenge(&other, &challenge, &recipient, &requester).unwrap();
+        assert!(!grant.accepts(&proof));
+
+        // A proof made for another peer.
+        let proof = answer_challenge(&hpke, &challenge, &recipient, &PeerId::random()).unwrap();
+        assert!(!grant.accepts(&proof));
+
+        // A proof for another challenge of the same key.
+        let (_, other_challenge, _) = challenge_for(&hpke, &requester);
+        let proof = answer_challenge(&hpke, &other_challenge, &recipient, &requester).unwrap();
+        assert!(!grant.accepts(&proof));
+
+        assert!(!grant.accepts(&[0u8; 32]));
+    }
+
+    #[test]
+    fn rejects_expired_grants() {
+        let hpke = HpkeContext::new().unwrap();
+        let requester = PeerId::random();
+        let (recipient, challenge, mut grant) = challenge_for(&hpke, &requester);
+        let proof = answer_challenge(&hpke, &challenge, &recipient, &requester).unwrap();
+
+        assert!(!grant.is_expired());
+        grant.expires_at = Instant::now() - Duration::from_secs(1);
+        assert!(grant.is_expired());
+        assert!(!grant.accepts(&proof));
+    }
+
+    #[test]
+    fn refuses_keys_that_do_not_match_the_recipient() {
+        let hpke = HpkeContext::new().unwrap();
+        let other = HpkeContext::new().unwrap();
+        let recipient = StorageEncryption::derive_recipient_hash(&other.public_key_bytes());
+
+        assert!(issue_challenge(&recipient, &hpke.public_key_bytes(), &PeerId::random()).is_err());
+    }
+}
@@ -3,9 +3,21 @@
 //! It includes modules for:
 //! * `hpke`: A simplified implementation of Hybrid Public Key Encryption.
 //! * `identity`: Management of the user's identity, including libp2p and HPKE keypairs.
+//! * `identity_file`: The versioned, optionally passphrase-sealed identity file format.
+//! * `mailbox_auth`: Proof of key possession for fetching and acknowledging mailbox messages.
+//! * `ratchet`: Forward-secret Double Ratchet sessions between peers.
+//! * `safety_number`: Safety numbers for verifying a friend's keys out of band.
+//! * `sender_key`: Sender keys for encrypting group messages once for all members.
+//! * `signing`: Signing and verification of messages with the libp2p identity.
 //! * `storage`: Encryption of data at rest.
 pub mod hpke;
 pub mod identity;
+pub mod identity_file;
+pub mod mailbox_auth;
+pub mod ratchet;
+pub mod safety_number;
+pub mod sender_key;
+pub mod signing;
 pub mod storage;
 
 pub use hpke::HpkeContext;
@@ -0,0 +1,798 @@
+//! This module implements the Double Ratchet sessions used for E2E encryption.
+//!
+//! A session is bootstrapped X3DH-style from both parties' static X25519 keys
+//! and an ephemeral base key chosen by the initiator. From then on every
+//! message advances a symmetric chain and every change of speaker performs a
+//! DH ratchet step, so a leaked static key does not reveal earlier messages.
+//!
+//! The initiator has no one-time pre-keys to use, so messages sent before the
+//! first reply are only protected by the recipient's static key and the
+//! initiator's ephemeral base key.
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// The wire format version of `RatchetMessage`.
+const WIRE_VERSION: u8 = 1;
+/// The maximum number of message keys skipped within a single chain.
+const MAX_SKIP: u32 = 1000;
+/// The maximum number of skipped message keys kept per session.
+const MAX_STORED_SKIPPED_KEYS: usize = 2000;
+/// The maximum number of superseded sessions kept per peer.
+const MAX_ARCHIVED_SESSIONS: usize = 4;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The X3DH bootstrap data attached to messages until the peer has replied.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PreKeyHeader {
+    /// The initiator's static X25519 public key.
+    pub identity_key: [u8; 32],
+    /// The initiator's ephemeral base key.
+    pub base_key: [u8; 32],
+}
+
+/// The ratchet header of a single message.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MessageHeader {
+    /// The sender's current ratchet public key.
+    pub ratchet_key: [u8; 32],
+    /// The number of messages in the sender's previous sending chain.
+    pub previous_chain_length: u32,
+    /// The number of this message in the current sending chain.
+    pub message_number: u32,
+}
+
+impl MessageHeader {
+    /// Returns the canonical byte encoding used as associated data.
+    fn to_bytes(&self) -> [u8; 40] {
+        let mut bytes = [0u8; 40];
+        bytes[..32].copy_from_slice(&self.ratchet_key);
+        bytes[32..36].copy_from_slice(&self.previous_chain_length.to_be_bytes());
+        bytes[36..].copy_from_slice(&self.message_number.to_be_bytes());
+        bytes
+    }
+}
+
+/// An encrypted message produced by a ratchet session.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RatchetMessage {
+    /// The wire format version.
+    pub version: u8,
+    /// The X3DH bootstrap data, present until the initiator hears back.
+    pub prekey: Option<PreKeyHeader>,
+    /// The ratchet header.
+    pub header: MessageHeader,
+    /// The nonce-prefixed ChaCha20Poly1305 ciphertext.
+    pub ciphertext: Vec<u8>,
+}
+
+impl RatchetMessage {
+    /// Serializes the message for transport.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if serialization fails.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Parses a message produced by `to_bytes`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the bytes are not a ratchet
+    /// message or use an unsupported version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let message: Self = serde_json::from_slice(bytes)?;
+        if message.version != WIRE_VERSION {
+            bail!("Unsupported ratchet message version {}", message.version);
+        }
+        Ok(message)
+    }
+}
+
+/// A message key kept for a message that has not arrived yet.
+#[derive(Serialize, Deserialize, Clone)]
+struct SkippedKey {
+    ratchet_key: [u8; 32],
+    message_number: u32,
+    message_key: [u8; 32],
+}
+
+/// The state of a single Double Ratchet session with a peer.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RatchetSession {
+    remote_identity_key: [u8; 32],
+    base_key: [u8; 32],
+    pending_prekey: Option<PreKeyHeader>,
+    root_key: [u8; 32],
+    sending_ratchet_private: [u8; 32],
+    sending_ratchet_public: [u8; 32],
+    receiving_ratchet_key: Option<[u8; 32]>,
+    sending_chain_key: Option<[u8; 32]>,
+    receiving_chain_key: Option<[u8; 32]>,
+    sending_count: u32,
+    receiving_count: u32,
+    previous_sending_count: u32,
+    skipped_keys: Vec<SkippedKey>,
+}
+
+impl RatchetSession {
+    /// Starts a new session as the initiator.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_identity` - Our static X25519 secret.
+    /// * `remote_identity_key` - The peer's static X25519 public key.
+    pub fn initiate(local_identity: &StaticSecret, remote_identity_key: &[u8; 32]) -> Self {
+        let remote_identity = PublicKey::from(*remote_identity_key);
+        let base = StaticSecret::random_from_rng(OsRng);
+        let base_key = PublicKey::from(&base).to_bytes();
+
+        let shared_secret = x3dh_secret(
+            local_identity.diffie_hellman(&remote_identity).as_bytes(),
+            base.diffie_hellman(&remote_identity).as_bytes(),
+        );
+
+        // The responder's first ratchet key is its identity key.
+        let ratchet = StaticSecret::random_from_rng(OsRng);
+        let (root_key, sending_chain_key) = kdf_root(
+            &shared_secret,
+            ratchet.diffie_hellman(&remote_identity).as_bytes(),
+        );
+
+        Self {
+            remote_identity_key: *remote_identity_key,
+            base_key,
+            pending_prekey: Some(PreKeyHeader {
+                identity_key: PublicKey::from(local_identity).to_bytes(),
+                base_key,
+            }),
+            root_key,
+            sending_ratchet_public: PublicKey::from(&ratchet).to_bytes(),
+            sending_ratchet_private: ratchet.to_bytes(),
+            receiving_ratchet_key: Some(*remote_identity_key),
+            sending_chain_key: Some(sending_chain_key),
+            receiving_chain_key: None,
+            sending_count: 0,
+            receiving_count: 0,
+            previous_sending_count: 0,
+            skipped_keys: Vec::new(),
+        }
+    }
+
+    /// Builds the responder side of a session from an incoming pre-key message.
+    ///
+    /// The first DH ratchet step is performed immediately, so our static
+    /// secret is never stored in the session state.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_identity` - Our static X25519 secret.
+    /// * `prekey` - The bootstrap data attached to the incoming message.
+    /// * `remote_ratchet_key` - The ratchet key from the incoming message header.
+    pub fn respond(
+        local_identity: &StaticSecret,
+        prekey: &PreKeyHeader,
+        remote_ratchet_key: &[u8; 32],
+    ) -> Self {
+        let shared_secret = x3dh_secret(
+            local_identity
+                .diffie_hellman(&PublicKey::from(prekey.identity_key))
+                .as_bytes(),
+            local_identity
+                .diffie_hellman(&PublicKey::from(prekey.base_key))
+                .as_bytes(),
+        );
+
+        let remote_ratchet = PublicKey::from(*remote_ratchet_key);
+        let (root_key, receiving_chain_key) = kdf_root(
+            &shared_secret,
+            local_identity.diffie_hellman(&remote_ratchet).as_bytes(),
+        );
+
+        let ratchet = StaticSecret::random_from_rng(OsRng);
+        let (root_key, sending_chain_key) = kdf_root(
+            &root_key,
+            ratchet.diffie_hellman(&remote_ratchet).as_bytes(),
+        );
+
+        Self {
+            remote_identity_key: prekey.identity_key,
+            base_key: prekey.base_key,
+            pending_prekey: None,
+            root_key,
+            sending_ratchet_public: PublicKey::from(&ratchet).to_bytes(),
+            sending_ratchet_private: ratchet.to_bytes(),
+            receiving_ratchet_key: Some(*remote_ratchet_key),
+            sending_chain_key: Some(sending_chain_key),
+            receiving_chain_key: Some(receiving_chain_key),
+            sending_count: 0,
+            receiving_count: 0,
+            previous_sending_count: 0,
+            skipped_keys: Vec::new(),
+        }
+    }
+
+    /// Encrypts a message, advancing the sending chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_identity_key` - Our static X25519 public key.
+    /// * `plaintext` - The data to encrypt.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the session has no sending chain
+    /// or encryption fails.
+    pub fn encrypt(
+        &mut self,
+        local_identity_key: &[u8; 32],
+        plaintext: &[u8],
+    ) -> Result<RatchetMessage> {
+        let chain_key = self
+            .sending_chain_key
+            .ok_or_else(|| anyhow!("Session has no sending chain"))?;
+        let (next_chain_key, message_key) = kdf_chain(&chain_key);
+        self.sending_chain_key = Some(next_chain_key);
+
+        let header = MessageHeader {
+            ratchet_key: self.sending_ratchet_public,
+            previous_chain_length: self.previous_sending_count,
+            message_number: self.sending_count,
+        };
+        self.sending_count += 1;
+
+        let associated_data =
+            associated_data(local_identity_key, &self.remote_identity_key, &header);
+        let ciphertext = seal(&message_key, &associated_data, plaintext)?;
+
+        Ok(RatchetMessage {
+            version: WIRE_VERSION,
+            prekey: self.pending_prekey.clone(),
+            header,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts a message, advancing the receiving side of the session.
+    ///
+    /// The session may be left in an inconsistent state when this fails, so
+    /// callers should decrypt on a copy and only keep it on success.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_identity_key` - Our static X25519 public key.
+    /// * `message` - The message to decrypt.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message does not belong to
+    /// this session, skips too many messages, or fails authentication.
+    pub fn decrypt(
+        &mut self,
+        local_identity_key: &[u8; 32],
+        message: &RatchetMessage,
+    ) -> Result<Vec<u8>> {
+        let header = &message.header;
+        let associated_data =
+            associated_data(&self.remote_identity_key, local_identity_key, header);
+
+        if let Some(message_key) = self.take_skipped_key(&header.ratchet_key, header.message_number)
+        {
+            let plaintext = open(&message_key, &associated_data, &message.ciphertext)?;
+            self.pending_prekey = None;
+            return Ok(plaintext);
+        }
+
+        if self.receiving_ratchet_key != Some(header.ratchet_key) {
+            self.skip_message_keys(header.previous_chain_length)?;
+            self.ratchet_step(&header.ratchet_key);
+        }
+
+        self.skip_message_keys(header.message_number)?;
+
+        let chain_key = self
+            .receiving_chain_key
+            .ok_or_else(|| anyhow!("Session has no receiving chain"))?;
+        let (next_chain_key, message_key) = kdf_chain(&chain_key);
+        self.receiving_chain_key = Some(next_chain_key);
+        self.receiving_count += 1;
+
+        let plaintext = open(&message_key, &associated_data, &message.ciphertext)?;
+        self.pending_prekey = None;
+        Ok(plaintext)
+    }
+
+    /// Removes and returns a stored key for a skipped message.
+    fn take_skipped_key(
+        &mut self,
+        ratchet_key: &[u8; 32],
+        message_number: u32,
+    ) -> Option<[u8; 32]> {
+        let index = self.skipped_keys.iter().position(|skipped| {
+            skipped.ratchet_key == *ratchet_key && skipped.message_number == message_number
+        })?;
+        Some(self.skipped_keys.remove(index).message_key)
+    }
+
+    /// Stores the keys of receiving-chain messages up to `until`.
+    fn skip_message_keys(&mut self, until: u32) -> Result<()> {
+        if until.saturating_sub(self.receiving_count) > MAX_SKIP {
+            bail!("Too many skipped messages");
+        }
+
+        let (Some(mut chain_key), Some(ratchet_key)) =
+            (self.receiving_chain_key, self.receiving_ratchet_key)
+        else {
+            return Ok(());
+        };
+
+        while self.receiving_count < until {
+            let (next_chain_key, message_key) = kdf_chain(&chain_key);
+            self.skipped_keys.push(SkippedKey {
+                ratchet_key,
+                message_number: self.receiving_count,
+                message_key,
+            });
+            chain_key = next_chain_key;
+            self.receiving_count += 1;
+        }
+        self.receiving_chain_key = Some(chain_key);
+
+        if self.skipped_keys.len() > MAX_STORED_SKIPPED_KEYS {
+            let excess = self.skipped_keys.len() - MAX_STORED_SKIPPED_KEYS;
+            self.skipped_keys.drain(0..excess);
+        }
+
+        Ok(())
+    }
+
+    /// Performs a DH ratchet step towards a new remote ratchet key.
+    fn ratchet_step(&mut self, remote_ratchet_key: &[u8; 32]) {
+        let remote_ratchet = PublicKey::from(*remote_ratchet_key);
+        let current = StaticSecret::from(self.sending_ratchet_private);
+
+        self.previous_sending_count = self.sending_count;
+        self.sending_count = 0;
+        self.receiving_count = 0;
+        self.receiving_ratchet_key = Some(*remote_ratchet_key);
+
+        let (root_key, receiving_chain_key) = kdf_root(
+            &self.root_key,
+            current.diffie_hellman(&remote_ratchet).as_bytes(),
+        );
+
+        let next = StaticSecret::random_from_rng(OsRng);
+        let (root_key, sending_chain_key) =
+            kdf_root(&root_key, next.diffie_hellman(&remote_ratchet).as_bytes());
+
+        self.root_key = root_key;
+        self.receiving_chain_key = Some(receiving_chain_key);
+        self.sending_chain_key = Some(sending_chain_key);
+        self.sending_ratchet_public = PublicKey::from(&next).to_bytes();
+        self.sending_ratchet_private = next.to_bytes();
+    }
+}
+
+/// All ratchet sessions we hold with a single peer.
+///
+/// Besides the session used for sending, a few superseded sessions are kept
+/// so that messages still in flight on them (for example when both sides
+/// started a session at the same time) can be decrypted.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SessionRecord {
+    current: Option<RatchetSession>,
+    archived: Vec<RatchetSession>,
+}
+
+impl SessionRecord {
+    /// Encrypts a message for the peer, starting a session if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_identity` - Our static X25519 secret.
+    /// * `remote_identity_key` - The peer's static X25519 public key.
+    /// * `plaintext` - The data to encrypt.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if encryption fails.
+    pub fn encrypt(
+        &mut self,
+        local_identity: &StaticSecret,
+        remote_identity_key: &[u8; 32],
+        plaintext: &[u8],
+    ) -> Result<RatchetMessage> {
+        let local_identity_key = PublicKey::from(local_identity).to_bytes();
+        let session = match self.current {
+            Some(ref mut session) if session.remote_identity_key == *remote_identity_key => session,
+            _ => {
+                let session = RatchetSession::initiate(local_identity, remote_identity_key);
+                self.promote(session);
+                self.current.as_mut().expect("session was just promoted")
+            }
+        };
+        session.encrypt(&local_identity_key, plaintext)
+    }
+
+    /// Decrypts a message from the peer.
+    ///
+    /// Existing sessions are tried first. A pre-key message that none of them
+    /// can open starts a new responder session. Whichever session decrypts
+    /// the message becomes the one used for sending.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_identity` - Our static X25519 secret.
+    /// * `remote_identity_key` - The peer's static X25519 public key.
+    /// * `message` - The message to decrypt.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no session can decrypt the message.
+    pub fn decrypt(
+        &mut self,
+        local_identity: &StaticSecret,
+        remote_identity_key: &[u8; 32],
+        message: &RatchetMessage,
+    ) -> Result<Vec<u8>> {
+        let local_identity_key = PublicKey::from(local_identity).to_bytes();
+
+        if let Some(ref current) = self.current {
+            let mut candidate = current.clone();
+            if let Ok(plaintext) = candidate.decrypt(&local_identity_key, message) {
+                self.current = Some(candidate);
+                return Ok(plaintext);
+            }
+        }
+
+        for index in 0..self.archived.len() {
+            let mut candidate = self.archived[index].clone();
+            if let Ok(plaintext) = candidate.decrypt(&local_identity_key, message) {
+                self.archived.remove(index);
+                self.promote(candidate);
+                return Ok(plaintext);
+            }
+        }
+
+        let Some(ref prekey) = message.prekey else {
+            bail!("No session can decrypt the message");
+        };
+
+        if prekey.identity_key != *remote_identity_key {
+            bail!("Pre-key message identity does not match the sender");
+        }
+
+        let known_base_key = self
+            .current
+            .iter()
+            .chain(self.archived.iter())
+            .any(|session| session.base_key == prekey.base_key);
+        if known_base_key {
+            bail!("Message was already decrypted or is a replay");
+        }
+
+        let mut session =
+            RatchetSession::respond(local_identity, prekey, &message.header.ratchet_key);
+        let plaintext = session.decrypt(&local_identity_key, message)?;
+        self.promote(session);
+        Ok(plaintext)
+    }
+
+    /// Archives the current session, so that the next message sent starts a
+    /// new one. This is done when our static key changes.
+    pub fn retire_current(&mut self) {
+        if let Some(previous) = self.current.take() {
+            self.archived.insert(0, previous);
+            self.archived.truncate(MAX_ARCHIVED_SESSIONS);
+        }
+    }
+
+    /// Makes `session` the current session, archiving the previous one.
+    fn promote(&mut self, session: RatchetSession) {
+        self.retire_current();
+        self.current = Some(session);
+    }
+}
+
+/// Derives the initial shared secret from the X3DH DH outputs.
+fn x3dh_secret(identity_dh: &[u8], base_dh: &[u8]) -> [u8; 32] {
+    let mut input_key_material = Vec::with_capacity(64);
+    input_key_material.extend_from_slice(identity_dh);
+    input_key_material.extend_from_slice(base_dh);
+
+    let mut secret = [0u8; 32];
+    Hkdf::<Sha256>::new(None, &input_key_material)
+        .expand(b"p2p-chat-x3dh", &mut secret)
+        .expect("32 bytes is a valid HKDF output length");
+    secret
+}
+
+/// The root-key KDF, returning the next root key and a new chain key.
+fn kdf_root(root_key: &[u8; 32], dh_output: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut output = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(root_key), dh_output)
+        .expand(b"p2p-chat-ratchet", &mut output)
+        .expect("64 bytes is a valid HKDF output length");
+
+    let mut next_root_key = [0u8; 32];
+    let mut chain_key = [0u8; 32];
+    next_root_key.copy_from_slice(&output[..32]);
+    chain_key.copy_from_slice(&output[32..]);
+    (next_root_key, chain_key)
+}
+
+/// The chain-key KDF, returning the next chain key and a message key.
+pub(crate) fn kdf_chain(chain_key: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let derive = |label: u8| -> [u8; 32] {
+        let mut mac =
+            <HmacSha256 as Mac>::new_from_slice(chain_key).expect("HMAC accepts any key length");
+        mac.update(&[label]);
+        mac.finalize().into_bytes().into()
+    };
+    (derive(0x02), derive(0x01))
+}
+
+/// Builds the associated data binding both identities and the header.
+fn associated_data(
+    sender_identity_key: &[u8; 32],
+    recipient_identity_key: &[u8; 32],
+    header: &MessageHeader,
+) -> Vec<u8> {
+    let mut data = Vec::with_capacity(104);
+    data.extend_from_slice(sender_identity_key);
+    data.extend_from_slice(recipient_identity_key);
+    data.extend_from_slice(&header.to_bytes());
+    data
+}
+
+/// Encrypts with a message key, prepending the nonce.
+pub(crate) fn seal(
+    message_key: &[u8; 32],
+    associated_data: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(message_key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: associated_data,
+            },
+        )
+        .map_err(|e| anyhow!("Encryption failed: {}", e))?;
+
+    let mut result = nonce.to_vec();
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
+
+/// Decrypts nonce-prefixed ciphertext with a message key.
+pub(crate) fn open(
+    message_key: &[u8; 32],
+    associated_data: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>> {
+    if ciphertext.len() < 12 {
+        bail!("Ciphertext is too short");
+    }
+
+    let (nonce_bytes, encrypted_data) = ciphertext.split_at(12);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(message_key));
+
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce_bytes),
+            Payload {
+                msg: encrypted_data,
+                aad: associated_data,
+            },
+        )
+        .map_err(|e| anyhow!("Decryption failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One side of a conversation.
+    struct Party {
+        secret: StaticSecret,
+        record: SessionRecord,
+    }
+
+    impl Party {
+        fn new() -> Self {
+            Self {
+                secret: StaticSecret::random_from_rng(OsRng),
+                record: SessionRecord::default(),
+            }
+        }
+
+        fn public_key(&self) -> [u8; 32] {
+            PublicKey::from(&self.secret).to_bytes()
+        }
+
+        fn send(&mut self, to: &Party, text: &str) -> RatchetMessage {
+            self.record
+                .encrypt(&self.secret, &to.public_key(), text.as_bytes())
+                .unwrap()
+        }
+
+        fn receive(&mut self, from: &Party, message: &RatchetMessage) -> Result<String> {
+            let plaintext = self
+                .record
+                .decrypt(&self.secret, &from.public_key(), message)?;
+            Ok(String::from_utf8(plaintext)?)
+        }
+
+        fn state(&self) -> Vec<u8> {
+            serde_json::to_vec(&self.record).unwrap()
+        }
+    }
+
+    #[test]
+    fn delivers_in_order() {
+        let mut alice = Party::new();
+        let mut bob = Party::new();
+
+        for round in 0..3 {
+            let first = alice.send(&bob, &format!("ping {}", round));
+            let second = alice.send(&bob, &format!("ping {} again", round));
+            assert_eq!(
+                bob.receive(&alice, &first).unwrap(),
+                format!("ping {}", round)
+            );
+            assert_eq!(
+                bob.receive(&alice, &second).unwrap(),
+                format!("ping {} again", round)
+            );
+
+            let reply = bob.send(&alice, &format!("pong {}", round));
+            assert_eq!(
+                alice.receive(&bob, &reply).unwrap(),
+                format!("pong {}", round)
+            );
+        }
+    }
+
+    #[test]
+    fn delivers_out_of_order() {
+        let mut alice = Party::new();
+        let mut bob = Party::new();
+
+        let messages: Vec<_> = (0..5).map(|i| alice.send(&bob, &i.to_string())).collect();
+        for index in [3, 0, 4, 1, 2] {
+            assert_eq!(
+                bob.receive(&alice, &messages[index]).unwrap(),
+                index.to_string()
+            );
+        }
+
+        // Messages of an earlier chain still open after a DH ratchet step.
+        let reply = bob.send(&alice, "reply");
+        let late = alice.send(&bob, "before reply");
+        assert_eq!(alice.receive(&bob, &reply).unwrap(), "reply");
+        let next = alice.send(&bob, "after reply");
+        assert_eq!(bob.receive(&alice, &next).unwrap(), "after reply");
+        assert_eq!(bob.receive(&alice, &late).unwrap(), "before reply");
+    }
+
+    #[test]
+    fn limits_skipped_messages() {
+        let mut alice = Party::new();
+        let mut bob = Party::new();
+
+        let first = alice.send(&bob, "first");
+        bob.receive(&alice, &first).unwrap();
+
+        let messages: Vec<_> = (1..=MAX_SKIP + 2)
+            .map(|i| alice.send(&bob, &i.to_string()))
+            .collect();
+
+        // Message 1002 would need 1001 skipped keys.
+        let state = bob.state();
+        assert!(bob
+            .receive(&alice, &messages[MAX_SKIP as usize + 1])
+            .is_err());
+        assert_eq!(bob.state(), state);
+
+        // Message 1001 needs exactly MAX_SKIP skipped keys.
+        let last = &messages[MAX_SKIP as usize];
+        assert_eq!(
+            bob.receive(&alice, last).unwrap(),
+            (MAX_SKIP + 1).to_string()
+        );
+        assert_eq!(bob.receive(&alice, &messages[0]).unwrap(), "1");
+    }
+
+    #[test]
+    fn rejects_replays() {
+        let mut alice = Party::new();
+        let mut bob = Party::new();
+
+        let prekey_message = alice.send(&bob, "hello");
+        assert!(prekey_message.prekey.is_some());
+        bob.receive(&alice, &prekey_message).unwrap();
+        assert!(bob.receive(&alice, &prekey_message).is_err());
+
+        let reply = bob.send(&alice, "hi");
+        alice.receive(&bob, &reply).unwrap();
+        let message = alice.send(&bob, "again");
+        assert!(message.prekey.is_none());
+        bob.receive(&alice, &message).unwrap();
+        assert!(bob.receive(&alice, &message).is_err());
+
+        // A skipped key is only used once.
+        let skipped = alice.send(&bob, "skipped");
+        let next = alice.send(&bob, "next");
+        bob.receive(&alice, &next).unwrap();
+        bob.receive(&alice, &skipped).unwrap();
+        assert!(bob.receive(&alice, &skipped).is_err());
+    }
+
+    #[test]
+    fn failed_decrypt_leaves_state_unchanged() {
+        let mut alice = Party::new();
+        let mut bob = Party::new();
+
+        let first = alice.send(&bob, "first");
+        bob.receive(&alice, &first).unwrap();
+
+        let mut tampered = alice.send(&bob, "second");
+        let last = tampered.ciphertext.len() - 1;
+        tampered.ciphertext[last] ^= 1;
+        let state = bob.state();
+        assert!(bob.receive(&alice, &tampered).is_err());
+        assert_eq!(bob.state(), state);
+
+        // A header from a chain that does not exist.
+        let mut forged = alice.send(&bob, "third");
+        forged.header.ratchet_key =
+            PublicKey::from(&StaticSecret::random_from_rng(OsRng)).to_bytes();
+        assert!(bob.receive(&alice, &forged).is_err());
+        assert_eq!(bob.state(), state);
+
+        let fourth = alice.send(&bob, "fourth");
+        assert_eq!(bob.receive(&alice, &fourth).unwrap(), "fourth");
+    }
+
+    #[test]
+    fn handles_simultaneous_initiation() {
+        let mut alice = Party::new();
+        let mut bob = Party::new();
+
+        let from_alice = alice.send(&bob, "hi bob");
+        let from_bob = bob.send(&alice, "hi alice");
+        assert!(from_alice.prekey.is_some() && from_bob.prekey.is_some());
+
+        assert_eq!(bob.receive(&alice, &from_alice).unwrap(), "hi bob");
+        assert_eq!(alice.receive(&bob, &from_bob).unwrap(), "hi alice");
+
+        for round in 0..3 {
+            let message = alice.send(&bob, &format!("a{}", round));
+            assert_eq!(
+                bob.receive(&alice, &message).unwrap(),
+                format!("a{}", round)
+            );
+            let message = bob.send(&alice, &format!("b{}", round));
+            assert_eq!(
+                alice.receive(&bob, &message).unwrap(),
+                format!("b{}", round)
+            );
+        }
+    }
+}
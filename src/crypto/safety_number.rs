@@ -0,0 +1,138 @@
+//! This module derives safety numbers, which two friends compare out of band
+//! to confirm that each has the other's real keys.
+//!
+//! Each party's fingerprint is an iterated SHA-512 hash of its Ed25519
+//! identity key, embedded in its `PeerId`, and its X25519 E2E key, shown as
+//! 30 digits. The safety number is both fingerprints, lower first, so both
+//! parties compute the same 60 digits. A man in the middle who swapped
+//! either key changes the number one of them sees.
+use crate::crypto::signing::public_key_from_peer_id;
+use anyhow::{anyhow, Result};
+use libp2p::PeerId;
+use sha2::{Digest, Sha512};
+
+/// The version of the fingerprint derivation.
+const FINGERPRINT_VERSION: u16 = 0;
+
+/// The number of hash iterations, which makes finding keys with a chosen
+/// fingerprint costlier.
+const FINGERPRINT_ITERATIONS: usize = 5200;
+
+/// The number of digits in a group of the displayed safety number.
+const GROUP_DIGITS: usize = 5;
+
+/// Computes the safety number of two parties.
+///
+/// # Arguments
+///
+/// * `our_peer_id` - Our `PeerId`.
+/// * `our_e2e_key` - Our E2E public key.
+/// * `their_peer_id` - The friend's `PeerId`.
+/// * `their_e2e_key` - The friend's E2E public key.
+///
+/// # Returns
+///
+/// The 60 digits of the safety number in groups of five, separated by spaces.
+///
+/// # Errors
+///
+/// This function will return an error if either `PeerId` does not embed an
+/// Ed25519 key.
+pub fn safety_number(
+    our_peer_id: &PeerId,
+    our_e2e_key: &[u8],
+    their_peer_id: &PeerId,
+    their_e2e_key: &[u8],
+) -> Result<String> {
+    let ours = fingerprint(our_peer_id, our_e2e_key)?;
+    let theirs = fingerprint(their_peer_id, their_e2e_key)?;
+
+    let digits = if ours <= theirs {
+        ours + &theirs
+    } else {
+        theirs + &ours
+    };
+
+    Ok(digits
+        .as_bytes()
+        .chunks(GROUP_DIGITS)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Computes the 30-digit fingerprint of one party's keys.
+fn fingerprint(peer_id: &PeerId, e2e_key: &[u8]) -> Result<String> {
+    let identity_key = public_key_from_peer_id(peer_id)?
+        .try_into_ed25519()
+        .map_err(|_| anyhow!("Peer ID {} does not embed an Ed25519 key", peer_id))?
+        .to_bytes();
+
+    let mut hash = Sha512::new()
+        .chain_update(FINGERPRINT_VERSION.to_be_bytes())
+        .chain_update(identity_key)
+        .chain_update(e2e_key)
+        .finalize();
+    for _ in 0..FINGERPRINT_ITERATIONS {
+        hash = Sha512::new()
+            .chain_update(hash)
+            .chain_update(identity_key)
+            .chain_update(e2e_key)
+            .finalize();
+    }
+
+    // Six chunks of five bytes, each reduced to five digits.
+    Ok(hash[..30]
+        .chunks(5)
+        .map(|chunk| {
+            let value = chunk
+                .iter()
+                .fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+            format!("{:05}", value % 100_000)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Identity;
+
+    #[test]
+    fn both_parties_see_the_same_number() {
+        let alice = Identity::generate().unwrap();
+        let bob = Identity::generate().unwrap();
+        let (alice_key, bob_key) = (alice.hpke_public_key(), bob.hpke_public_key());
+
+        let number = safety_number(&alice.peer_id, &alice_key, &bob.peer_id, &bob_key).unwrap();
+        let groups: Vec<&str> = number.split(' ').collect();
+        assert_eq!(groups.len(), 12);
+        assert!(groups
+            .iter()
+            .all(|group| group.len() == GROUP_DIGITS && group.bytes().all(|b| b.is_ascii_digit())));
+        assert_eq!(
+            safety_number(&bob.peer_id, &bob_key, &alice.peer_id, &alice_key).unwrap(),
+            number
+        );
+    }
+
+    #[test]
+    fn swapped_keys_change_the_number() {
+        let alice = Identity::generate().unwrap();
+        let bob = Identity::generate().unwrap();
+        let mallory = Identity::generate().unwrap();
+        let (alice_key, bob_key) = (alice.hpke_public_key(), bob.hpke_public_key());
+        let number = safety_number(&alice.peer_id, &alice_key, &bob.peer_id, &bob_key).unwrap();
+
+        let swapped_e2e_key = safety_number(
+            &alice.peer_id,
+            &alice_key,
+            &bob.peer_id,
+            &mallory.hpke_public_key(),
+        );
+        assert_ne!(swapped_e2e_key.unwrap(), number);
+        let swapped_identity =
+            safety_number(&alice.peer_id, &alice_key, &mallory.peer_id, &bob_key);
+        assert_ne!(swapped_identity.unwrap(), number);
+    }
+}
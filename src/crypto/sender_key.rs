@@ -0,0 +1,280 @@
+//! This module implements the sender keys used to encrypt group messages.
+//!
+//! Every member owns a sender key per group: a symmetric hash chain that it
+//! advances for each message it sends to the group. The current chain state is
+//! handed to the other members over their pairwise ratchet sessions, so a
+//! group message is encrypted once no matter how many members receive it.
+//!
+//! A member that learns a sender key can decrypt messages from that point on,
+//! but not earlier ones. Sender keys are replaced when a member leaves, so
+//! that former members cannot read later messages.
+use anyhow::{bail, Result};
+use libp2p::PeerId;
+use rand::RngCore;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::ratchet::{kdf_chain, open, seal};
+
+/// The maximum number of message keys skipped within a single chain.
+const MAX_SKIP: u32 = 1000;
+/// The maximum number of skipped message keys kept per sender key.
+const MAX_STORED_SKIPPED_KEYS: usize = 2000;
+
+/// A sender key as handed to another group member.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SenderKeyDistribution {
+    /// Identifies the sender key, which changes whenever the key is replaced.
+    pub key_id: u32,
+    /// The iteration of the chain key below.
+    pub iteration: u32,
+    /// The chain key at `iteration`.
+    pub chain_key: [u8; 32],
+}
+
+/// A group message encrypted with a sender key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SenderKeyMessage {
+    /// The ID of the sender key used.
+    pub key_id: u32,
+    /// The chain iteration the message key was derived from.
+    pub iteration: u32,
+    /// The nonce-prefixed ChaCha20Poly1305 ciphertext.
+    pub ciphertext: Vec<u8>,
+}
+
+/// A message key kept for a group message that has not arrived yet.
+#[derive(Serialize, Deserialize, Clone)]
+struct SkippedKey {
+    iteration: u32,
+    message_key: [u8; 32],
+}
+
+/// The state of one member's sender key in one group.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SenderKeyState {
+    key_id: u32,
+    iteration: u32,
+    chain_key: [u8; 32],
+    skipped: Vec<SkippedKey>,
+    /// The members that have been sent this key; only used for our own keys.
+    #[serde(default)]
+    distributed_to: Vec<PeerId>,
+}
+
+impl SenderKeyState {
+    /// Generates a fresh sender key for ourselves.
+    pub fn generate() -> Self {
+        let mut chain_key = [0u8; 32];
+        OsRng.fill_bytes(&mut chain_key);
+
+        Self {
+            key_id: OsRng.next_u32(),
+            iteration: 0,
+            chain_key,
+            skipped: Vec::new(),
+            distributed_to: Vec::new(),
+        }
+    }
+
+    /// Creates the state for another member's sender key.
+    pub fn from_distribution(distribution: &SenderKeyDistribution) -> Self {
+        Self {
+            key_id: distribution.key_id,
+            iteration: distribution.iteration,
+            chain_key: distribution.chain_key,
+            skipped: Vec::new(),
+            distributed_to: Vec::new(),
+        }
+    }
+
+    /// Returns the ID of the sender key.
+    pub fn key_id(&self) -> u32 {
+        self.key_id
+    }
+
+    /// Returns the current chain state, for handing to other members.
+    pub fn distribution(&self) -> SenderKeyDistribution {
+        SenderKeyDistribution {
+            key_id: self.key_id,
+            iteration: self.iteration,
+            chain_key: self.chain_key,
+        }
+    }
+
+    /// Records that the key is being sent to some members.
+    ///
+    /// # Returns
+    ///
+    /// The members that did not have the key yet.
+    pub fn mark_distributed(&mut self, members: &[PeerId]) -> Vec<PeerId> {
+        let missing: Vec<PeerId> = members
+            .iter()
+            .filter(|member| !self.distributed_to.contains(member))
+            .copied()
+            .collect();
+        self.distributed_to.extend(missing.iter().copied());
+        missing
+    }
+
+    /// Encrypts a group message and advances the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `associated_data` - Data bound to the ciphertext, see `associated_data`.
+    /// * `plaintext` - The message to encrypt.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if encryption fails.
+    pub fn encrypt(
+        &mut self,
+        associated_data: &[u8],
+        plaintext: &[u8],
+    ) -> Result<SenderKeyMessage> {
+        let (next_chain_key, message_key) = kdf_chain(&self.chain_key);
+        let ciphertext = seal(&message_key, associated_data, plaintext)?;
+
+        let message = SenderKeyMessage {
+            key_id: self.key_id,
+            iteration: self.iteration,
+            ciphertext,
+        };
+        self.chain_key = next_chain_key;
+        self.iteration += 1;
+        Ok(message)
+    }
+
+    /// Decrypts a group message, advancing the chain as needed.
+    ///
+    /// The state is only modified if decryption succeeds.
+    ///
+    /// # Arguments
+    ///
+    /// * `associated_data` - Data bound to the ciphertext, see `associated_data`.
+    /// * `message` - The message to decrypt.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message uses another sender
+    /// key, was already decrypted, is too far ahead, or fails to authenticate.
+    pub fn decrypt(
+        &mut self,
+        associated_data: &[u8],
+        message: &SenderKeyMessage,
+    ) -> Result<Vec<u8>> {
+        if message.key_id != self.key_id {
+            bail!("Unknown sender key {}", message.key_id);
+        }
+
+        if message.iteration < self.iteration {
+            let Some(index) = self
+                .skipped
+                .iter()
+                .position(|key| key.iteration == message.iteration)
+            else {
+                bail!("Duplicate or expired group message {}", message.iteration);
+            };
+            let plaintext = open(
+                &self.skipped[index].message_key,
+                associated_data,
+                &message.ciphertext,
+            )?;
+            self.skipped.remove(index);
+            return Ok(plaintext);
+        }
+
+        if message.iteration - self.iteration > MAX_SKIP {
+            bail!("Group message is too far ahead of the chain");
+        }
+
+        let mut state = self.clone();
+        while state.iteration < message.iteration {
+            let (next_chain_key, message_key) = kdf_chain(&state.chain_key);
+            state.skipped.push(SkippedKey {
+                iteration: state.iteration,
+                message_key,
+            });
+            state.chain_key = next_chain_key;
+            state.iteration += 1;
+        }
+
+        let (next_chain_key, message_key) = kdf_chain(&state.chain_key);
+        let plaintext = open(&message_key, associated_data, &message.ciphertext)?;
+        state.chain_key = next_chain_key;
+        state.iteration += 1;
+
+        if state.skipped.len() > MAX_STORED_SKIPPED_KEYS {
+            let excess = state.skipped.len() - MAX_STORED_SKIPPED_KEYS;
+            state.skipped.drain(..excess);
+        }
+
+        *self = state;
+        Ok(plaintext)
+    }
+}
+
+/// Builds the associated data binding a group message to its group, sender and ID.
+pub fn associated_data(group_id: &Uuid, sender: &PeerId, message_id: &Uuid) -> Vec<u8> {
+    let mut data = b"p2p-chat/group-message/v1".to_vec();
+    data.extend_from_slice(group_id.as_bytes());
+    data.extend_from_slice(message_id.as_bytes());
+    data.extend_from_slice(&sender.to_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn members_decrypt_out_of_order_but_only_once() {
+        let group_id = Uuid::new_v4();
+        let sender = PeerId::random();
+        let mut own = SenderKeyState::generate();
+        let mut theirs = SenderKeyState::from_distribution(&own.distribution());
+
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let messages: Vec<SenderKeyMessage> = ids
+            .iter()
+            .map(|id| {
+                own.encrypt(&associated_data(&group_id, &sender, id), id.as_bytes())
+                    .unwrap()
+            })
+            .collect();
+
+        for index in [2, 0, 1] {
+            let ad = associated_data(&group_id, &sender, &ids[index]);
+            let plaintext = theirs.decrypt(&ad, &messages[index]).unwrap();
+            assert_eq!(plaintext, ids[index].as_bytes());
+        }
+        let ad = associated_data(&group_id, &sender, &ids[1]);
+        assert!(theirs.decrypt(&ad, &messages[1]).is_err());
+    }
+
+    #[test]
+    fn messages_are_bound_to_their_sender_and_key() {
+        let group_id = Uuid::new_v4();
+        let sender = PeerId::random();
+        let id = Uuid::new_v4();
+        let mut own = SenderKeyState::generate();
+        let early = own
+            .encrypt(&associated_data(&group_id, &sender, &id), b"before")
+            .unwrap();
+        let mut theirs = SenderKeyState::from_distribution(&own.distribution());
+        let message = own
+            .encrypt(&associated_data(&group_id, &sender, &id), b"after")
+            .unwrap();
+
+        // Another member cannot pass the message off as their own.
+        let forged = associated_data(&group_id, &PeerId::random(), &id);
+        assert!(theirs.decrypt(&forged, &message).is_err());
+
+        // A key learned later does not open earlier messages.
+        let ad = associated_data(&group_id, &sender, &id);
+        assert!(theirs.decrypt(&ad, &early).is_err());
+        assert!(SenderKeyState::generate().decrypt(&ad, &message).is_err());
+        assert_eq!(theirs.decrypt(&ad, &message).unwrap(), b"after");
+    }
+}
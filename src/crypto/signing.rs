@@ -0,0 +1,305 @@
+//! This module handles sender authentication for chat and mailbox messages.
+//!
+//! Messages are signed with the sender's libp2p Ed25519 keypair. Ed25519 peer
+//! IDs embed the public key, so a signature can be verified against the
+//! claimed sender `PeerId` without any additional key material.
+use crate::types::{ContactCard, DeleteRequest, DeviceList, EncryptedMessage, KeyUpdate, Message};
+use anyhow::{anyhow, bail, Result};
+use libp2p::{identity, PeerId};
+use uuid::Uuid;
+
+/// The multihash code of the identity hash, used by peer IDs that inline their key.
+const IDENTITY_MULTIHASH_CODE: u64 = 0x00;
+
+/// Returns the bytes covered by the signature of a direct chat message.
+pub fn message_signing_bytes(message: &Message) -> Vec<u8> {
+    let mut bytes = b"p2p-chat/message/v1".to_vec();
+    bytes.extend_from_slice(message.id.as_bytes());
+    push_field(&mut bytes, &message.sender.to_bytes());
+    push_field(&mut bytes, &message.recipient.to_bytes());
+    bytes.extend_from_slice(&message.timestamp.to_be_bytes());
+    bytes.extend_from_slice(&message.nonce.to_be_bytes());
+    push_field(&mut bytes, &message.content);
+    push_group_id(&mut bytes, message.group_id.as_ref());
+    bytes
+}
+
+/// Returns the bytes covered by the signature of a mailbox message.
+pub fn mailbox_message_signing_bytes(message: &EncryptedMessage) -> Vec<u8> {
+    let mut bytes = b"p2p-chat/mailbox-message/v1".to_vec();
+    bytes.extend_from_slice(message.id.as_bytes());
+    push_field(&mut bytes, &message.sender.to_bytes());
+    bytes.extend_from_slice(&message.recipient_hash);
+    bytes.extend_from_slice(&message.timestamp.to_be_bytes());
+    bytes.extend_from_slice(&message.nonce.to_be_bytes());
+    push_field(&mut bytes, &message.sender_pub_key);
+    push_field(&mut bytes, &message.encrypted_content);
+    push_group_id(&mut bytes, message.group_id.as_ref());
+    bytes
+}
+
+/// Returns the bytes covered by the signature of a contact card.
+pub fn contact_card_signing_bytes(card: &ContactCard) -> Vec<u8> {
+    let mut bytes = b"p2p-chat/contact-card/v1".to_vec();
+    push_field(&mut bytes, &card.peer_id.to_bytes());
+    push_field(&mut bytes, &card.e2e_public_key);
+    push_field(
+        &mut bytes,
+        card.display_name.as_deref().unwrap_or_default().as_bytes(),
+    );
+    bytes.extend_from_slice(&card.timestamp.to_be_bytes());
+    bytes
+}
+
+/// Returns the bytes covered by the signature of a key update.
+pub fn key_update_signing_bytes(update: &KeyUpdate) -> Vec<u8> {
+    let mut bytes = b"p2p-chat/key-update/v1".to_vec();
+    push_field(&mut bytes, &update.peer_id.to_bytes());
+    push_field(&mut bytes, &update.previous_key);
+    push_field(&mut bytes, &update.new_key);
+    bytes.extend_from_slice(&update.timestamp.to_be_bytes());
+    bytes
+}
+
+/// Returns the bytes covered by the signature of a device list.
+pub fn device_list_signing_bytes(list: &DeviceList) -> Vec<u8> {
+    let mut bytes = b"p2p-chat/device-list/v1".to_vec();
+    push_field(&mut bytes, &list.account.to_bytes());
+    bytes.extend_from_slice(&(list.devices.len() as u32).to_be_bytes());
+    for device in &list.devices {
+        push_field(&mut bytes, &device.peer_id.to_bytes());
+        push_field(&mut bytes, &device.e2e_public_key);
+    }
+    bytes.extend_from_slice(&list.timestamp.to_be_bytes());
+    bytes
+}
+
+/// Returns the bytes covered by the signature of a delete request.
+pub fn delete_request_signing_bytes(request: &DeleteRequest) -> Vec<u8> {
+    let mut bytes = b"p2p-chat/delete-request/v1".to_vec();
+    push_field(&mut bytes, &request.peer_id.to_bytes());
+    bytes.extend_from_slice(&(request.message_ids.len() as u32).to_be_bytes());
+    for message_id in &request.message_ids {
+        bytes.extend_from_slice(message_id.as_bytes());
+    }
+    bytes.extend_from_slice(&request.timestamp.to_be_bytes());
+    bytes
+}
+
+/// Verifies that a direct chat message was signed by its claimed sender.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is missing or invalid.
+pub fn verify_message(message: &Message) -> Result<()> {
+    verify_signature(
+        &message.sender,
+        &message_signing_bytes(message),
+        &message.signature,
+    )
+}
+
+/// Verifies that a mailbox message was signed by its claimed sender.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is missing or invalid.
+pub fn verify_mailbox_message(message: &EncryptedMessage) -> Result<()> {
+    verify_signature(
+        &message.sender,
+        &mailbox_message_signing_bytes(message),
+        &message.signature,
+    )
+}
+
+/// Verifies that a contact card was signed by the peer it describes.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is missing or invalid.
+pub fn verify_contact_card(card: &ContactCard) -> Result<()> {
+    verify_signature(
+        &card.peer_id,
+        &contact_card_signing_bytes(card),
+        &card.signature,
+    )
+}
+
+/// Verifies that a key update was signed by the peer whose key it replaces.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is missing or invalid.
+pub fn verify_key_update(update: &KeyUpdate) -> Result<()> {
+    verify_signature(
+        &update.peer_id,
+        &key_update_signing_bytes(update),
+        &update.signature,
+    )
+}
+
+/// Verifies that a device list was signed by the primary device of its identity.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is missing or invalid.
+pub fn verify_device_list(list: &DeviceList) -> Result<()> {
+    verify_signature(
+        &list.account,
+        &device_list_signing_bytes(list),
+        &list.signature,
+    )
+}
+
+/// Verifies that a delete request was signed by the device that sent it.
+///
+/// # Errors
+///
+/// This function will return an error if the signature is missing or invalid.
+pub fn verify_delete_request(request: &DeleteRequest) -> Result<()> {
+    verify_signature(
+        &request.peer_id,
+        &delete_request_signing_bytes(request),
+        &request.signature,
+    )
+}
+
+/// Verifies a signature against the Ed25519 key embedded in a `PeerId`.
+///
+/// # Arguments
+///
+/// * `peer_id` - The `PeerId` of the claimed signer.
+/// * `data` - The signed data.
+/// * `signature` - The signature to verify.
+///
+/// # Errors
+///
+/// This function will return an error if the `PeerId` does not embed a public
+/// key or if the signature does not verify.
+pub fn verify_signature(peer_id: &PeerId, data: &[u8], signature: &[u8]) -> Result<()> {
+    if signature.is_empty() {
+        bail!("Message from {} is not signed", peer_id);
+    }
+
+    let public_key = public_key_from_peer_id(peer_id)?;
+    if !public_key.verify(data, signature) {
+        bail!("Invalid signature from {}", peer_id);
+    }
+
+    Ok(())
+}
+
+/// Extracts the public key inlined in a `PeerId`.
+///
+/// # Errors
+///
+/// This function will return an error if the `PeerId` does not embed a
+/// valid public key it was derived from.
+pub fn public_key_from_peer_id(peer_id: &PeerId) -> Result<identity::PublicKey> {
+    let multihash = peer_id.as_ref();
+    if multihash.code() != IDENTITY_MULTIHASH_CODE {
+        bail!("Peer ID {} does not embed its public key", peer_id);
+    }
+
+    let public_key = identity::PublicKey::try_decode_protobuf(multihash.digest())
+        .map_err(|e| anyhow!("Invalid public key in peer ID {}: {}", peer_id, e))?;
+
+    // Guard against a peer ID that embeds a key it was not derived from.
+    if PeerId::from_public_key(&public_key) != *peer_id {
+        bail!("Peer ID {} does not match its embedded key", peer_id);
+    }
+
+    Ok(public_key)
+}
+
+/// Appends the group ID of a group message; direct messages are left unchanged.
+fn push_group_id(bytes: &mut Vec<u8>, group_id: Option<&Uuid>) {
+    if let Some(group_id) = group_id {
+        bytes.extend_from_slice(b"group");
+        bytes.extend_from_slice(group_id.as_bytes());
+    }
+}
+
+/// Appends a length-prefixed field.
+fn push_field(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(field);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Identity;
+    use crate::types::DeliveryStatus;
+
+    fn signed_message(sender: &Identity) -> Message {
+        let mut message = Message {
+            id: Uuid::new_v4(),
+            sender: sender.peer_id,
+            recipient: PeerId::random(),
+            timestamp: 1,
+            content: b"hello".to_vec(),
+            nonce: 7,
+            delivery_status: DeliveryStatus::Sent,
+            signature: Vec::new(),
+            group_id: None,
+            attachment: None,
+            sender_pub_key: sender.hpke_public_key(),
+        };
+        message.signature = sender.sign_message(&message).unwrap();
+        message
+    }
+
+    #[test]
+    fn signatures_are_bound_to_the_sender_and_the_content() {
+        let sender = Identity::generate().unwrap();
+        let message = signed_message(&sender);
+        verify_message(&message).unwrap();
+
+        let forged = Message {
+            sender: Identity::generate().unwrap().peer_id,
+            ..message.clone()
+        };
+        assert!(verify_message(&forged).is_err());
+        let tampered = Message {
+            content: b"goodbye".to_vec(),
+            ..message.clone()
+        };
+        assert!(verify_message(&tampered).is_err());
+        let unsigned = Message {
+            signature: Vec::new(),
+            ..message
+        };
+        assert!(verify_message(&unsigned).is_err());
+    }
+
+    #[test]
+    fn mailbox_signatures_cover_the_sender_key() {
+        let sender = Identity::generate().unwrap();
+        let mut message = EncryptedMessage {
+            id: Uuid::new_v4(),
+            sender: sender.peer_id,
+            recipient_hash: [1; 32],
+            encrypted_content: b"sealed".to_vec(),
+            timestamp: 1,
+            nonce: 7,
+            sender_pub_key: sender.hpke_public_key(),
+            signature: Vec::new(),
+            group_id: None,
+        };
+        message.signature = sender.sign_mailbox_message(&message).unwrap();
+        verify_mailbox_message(&message).unwrap();
+
+        // An attacker cannot swap in their own key to have it decrypted.
+        message.sender_pub_key = Identity::generate().unwrap().hpke_public_key();
+        assert!(verify_mailbox_message(&message).is_err());
+    }
+
+    #[test]
+    fn peer_ids_without_an_inlined_key_are_refused() {
+        let hashed =
+            PeerId::from_multihash(libp2p::multihash::Multihash::wrap(0x12, &[0; 32]).unwrap())
+                .unwrap();
+        assert!(public_key_from_peer_id(&hashed).is_err());
+    }
+}
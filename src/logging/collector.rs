@@ -8,7 +8,7 @@ use tracing::{Event, Subscriber};
 use tracing_subscriber::{
     layer::{Context, SubscriberExt},
     registry::LookupSpan,
-    Layer,
+    EnvFilter, Layer,
 };
 
 /// A `tracing` layer that collects log entries and sends them to a `LogBuffer`.
@@ -33,6 +33,7 @@ impl TUILogCollector {
     /// # Arguments
     ///
     /// * `buffer` - The `LogBuffer` to use for collecting logs.
+    /// * `filter` - The `tracing` filter directives, if logging is filtered.
     ///
     /// # Errors
     ///
@@ -40,11 +41,13 @@ impl TUILogCollector {
     /// be set.
     pub fn init_subscriber(
         buffer: Arc<LogBuffer>,
+        filter: Option<&str>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let collector = TUILogCollector::new(buffer);
+        let filter = filter.map(EnvFilter::try_new).transpose()?;
 
         // Create a layered subscriber with only TUI output (no console output).
-        let subscriber = tracing_subscriber::registry().with(collector);
+        let subscriber = tracing_subscriber::registry().with(filter).with(collector);
 
         tracing::subscriber::set_global_default(subscriber)?;
         Ok(())
@@ -1,7 +1,7 @@
 //! This module defines the `MailboxNode`, which is responsible for storing and
 //! forwarding messages for other peers in the network.
 use crate::crypto::{Identity, StorageEncryption};
-use crate::network::NetworkLayer;
+use crate::network::{NetworkHandle, NetworkLayer};
 use crate::storage::{MailboxStore, SledMailboxStore};
 use anyhow::Result;
 use libp2p::kad;
@@ -64,11 +64,16 @@ impl MailboxNode {
     /// # Arguments
     ///
     /// * `network_layer` - The network layer to use for communication.
+    /// * `network_handle` - The handle used to report the limit counters.
     ///
     /// # Errors
     ///
     /// This function will return an error if the mailbox node fails to run.
-    pub async fn run_with_network(&mut self, network_layer: NetworkLayer) -> Result<()> {
+    pub async fn run_with_network(
+        &mut self,
+        network_layer: NetworkLayer,
+        network_handle: NetworkHandle,
+    ) -> Result<()> {
         info!(
             "Starting mailbox node with network layer: {}",
             self.identity.peer_id
@@ -103,6 +108,17 @@ impl MailboxNode {
         loop {
             tokio::time::sleep(Duration::from_secs(60)).await;
             info!("Mailbox node still running...");
+            if let Ok(counters) = network_handle.get_limit_counters().await {
+                info!(
+                    "Limits: {} chat, {} mailbox and {} DHT requests dropped, {} connections refused, {} peers blocked ({} now)",
+                    counters.chat_requests_limited,
+                    counters.mailbox_requests_limited,
+                    counters.dht_writes_limited,
+                    counters.connections_denied,
+                    counters.peers_blocked,
+                    counters.currently_blocked
+                );
+            }
         }
     }
 
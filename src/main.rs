@@ -1,6 +1,7 @@
 //! The main entry point for the p2p-chat application.
 mod app;
 mod cli;
+mod control;
 mod crypto;
 mod logging;
 mod mailbox;
@@ -0,0 +1,127 @@
+//! This module defines the codec for the attachment protocol, which is used for
+//! fetching the encrypted chunks of an attachment from the peer that sent it.
+//!
+//! Requests are length-prefixed JSON like the other protocols. Chunks are sent
+//! as raw bytes rather than JSON, and both directions are size-capped.
+use super::wire::{read_frame, write_frame};
+use crate::storage::attachments::MAX_ATTACHMENT_CHUNK_SIZE;
+use crate::types::{AttachmentRequest, AttachmentResponse};
+use futures::prelude::*;
+use libp2p::request_response::{self, Codec, ProtocolSupport};
+use std::io;
+
+/// The largest request accepted.
+const MAX_REQUEST_SIZE: usize = 1024;
+/// The largest chunk accepted, leaving room for the encryption overhead.
+const MAX_CHUNK_FRAME_SIZE: usize = MAX_ATTACHMENT_CHUNK_SIZE as usize + 64;
+
+/// Tags a response carrying a chunk.
+const TAG_CHUNK: u8 = 1;
+/// Tags a response for a chunk the peer does not have.
+const TAG_NOT_FOUND: u8 = 0;
+
+/// The codec for the attachment protocol.
+///
+/// This codec is used by the `libp2p` `request_response` behaviour to encode
+/// and decode chunk requests and responses.
+#[derive(Clone, Default)]
+pub struct AttachmentCodec;
+
+impl AttachmentCodec {
+    /// The protocol name for the attachment protocol.
+    pub const PROTOCOL: &'static str = "/p2p-chat/attachment/1.0.0";
+}
+
+#[async_trait::async_trait]
+impl Codec for AttachmentCodec {
+    type Protocol = &'static str;
+    type Request = AttachmentRequest;
+    type Response = AttachmentResponse;
+
+    /// Reads a length-prefixed JSON-encoded request from the given I/O stream.
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_frame(io, MAX_REQUEST_SIZE).await?;
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reads a tagged response, followed by the raw chunk if there is one.
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut tag = [0u8; 1];
+        io.read_exact(&mut tag).await?;
+
+        match tag[0] {
+            TAG_CHUNK => Ok(AttachmentResponse::Chunk {
+                data: read_frame(io, MAX_CHUNK_FRAME_SIZE).await?,
+            }),
+            TAG_NOT_FOUND => Ok(AttachmentResponse::NotFound),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown attachment response tag {}", other),
+            )),
+        }
+    }
+
+    /// Writes a length-prefixed JSON-encoded request to the given I/O stream.
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data =
+            serde_json::to_vec(&req).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        write_frame(io, &data, MAX_REQUEST_SIZE).await?;
+        io.flush().await?;
+        Ok(())
+    }
+
+    /// Writes a tagged response, followed by the raw chunk if there is one.
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        res: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        match res {
+            AttachmentResponse::Chunk { data } => {
+                io.write_all(&[TAG_CHUNK]).await?;
+                write_frame(io, &data, MAX_CHUNK_FRAME_SIZE).await?;
+            }
+            AttachmentResponse::NotFound => {
+                io.write_all(&[TAG_NOT_FOUND]).await?;
+            }
+        }
+
+        io.flush().await?;
+        Ok(())
+    }
+}
+
+/// The `libp2p` `request_response` behaviour for the attachment protocol.
+pub type AttachmentBehaviour = request_response::Behaviour<AttachmentCodec>;
+
+/// Creates a new `AttachmentBehaviour`.
+pub fn create_attachment_behaviour() -> AttachmentBehaviour {
+    use std::time::Duration;
+
+    let config = request_response::Config::default().with_request_timeout(Duration::from_secs(30));
+
+    request_response::Behaviour::new([(AttachmentCodec::PROTOCOL, ProtocolSupport::Full)], config)
+}
@@ -1,10 +1,20 @@
 //! This module defines the codec for the chat protocol, which is used for
 //! sending and receiving chat messages over the network.
+//!
+//! Two versions of the protocol are offered. `/chat/2.0.0` encodes payloads
+//! as CBOR and is preferred, while `/chat/1.0.0` keeps the JSON encoding for
+//! older peers. Both are size-capped.
+use super::wire::{read_frame, write_frame, WireFormat};
 use crate::types::{ChatRequest, ChatResponse};
 use futures::prelude::*;
 use libp2p::request_response::{self, Codec, ProtocolSupport};
 use std::io;
 
+/// The largest request accepted, which carries a single message.
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+/// The largest response accepted.
+const MAX_RESPONSE_SIZE: usize = 16 * 1024;
+
 /// The codec for the chat protocol.
 ///
 /// This codec is used by the `libp2p` `request_response` behaviour to encode
@@ -13,8 +23,19 @@ use std::io;
 pub struct ChatCodec;
 
 impl ChatCodec {
-    /// The protocol name for the chat protocol.
+    /// The protocol name for the JSON-encoded chat protocol.
     pub const PROTOCOL: &'static str = "/chat/1.0.0";
+    /// The protocol name for the CBOR-encoded chat protocol.
+    pub const PROTOCOL_V2: &'static str = "/chat/2.0.0";
+
+    /// Returns the encoding used by a negotiated protocol.
+    fn format(protocol: &str) -> WireFormat {
+        if protocol == Self::PROTOCOL {
+            WireFormat::Json
+        } else {
+            WireFormat::Cbor
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -23,76 +44,62 @@ impl Codec for ChatCodec {
     type Request = ChatRequest;
     type Response = ChatResponse;
 
-    /// Reads a length-prefixed JSON-encoded request from the given I/O stream.
-    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    /// Reads a length-prefixed request from the given I/O stream.
+    async fn read_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let mut length_buf = [0u8; 4];
-        io.read_exact(&mut length_buf).await?;
-        let length = u32::from_be_bytes(length_buf) as usize;
-
-        let mut data = vec![0u8; length];
-        io.read_exact(&mut data).await?;
-
-        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        let data = read_frame(io, MAX_REQUEST_SIZE).await?;
+        Self::format(protocol).decode(&data)
     }
 
-    /// Reads a length-prefixed JSON-encoded response from the given I/O stream.
+    /// Reads a length-prefixed response from the given I/O stream.
     async fn read_response<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
     ) -> io::Result<Self::Response>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let mut length_buf = [0u8; 4];
-        io.read_exact(&mut length_buf).await?;
-        let length = u32::from_be_bytes(length_buf) as usize;
-
-        let mut data = vec![0u8; length];
-        io.read_exact(&mut data).await?;
-
-        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        let data = read_frame(io, MAX_RESPONSE_SIZE).await?;
+        Self::format(protocol).decode(&data)
     }
 
-    /// Writes a length-prefixed JSON-encoded request to the given I/O stream.
+    /// Writes a length-prefixed request to the given I/O stream.
     async fn write_request<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         req: Self::Request,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let data =
-            serde_json::to_vec(&req).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        let length = data.len() as u32;
+        let data = Self::format(protocol).encode(&req)?;
 
-        io.write_all(&length.to_be_bytes()).await?;
-        io.write_all(&data).await?;
+        write_frame(io, &data, MAX_REQUEST_SIZE).await?;
         io.flush().await?;
         Ok(())
     }
 
-    /// Writes a length-prefixed JSON-encoded response to the given I/O stream.
+    /// Writes a length-prefixed response to the given I/O stream.
     async fn write_response<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         res: Self::Response,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let data =
-            serde_json::to_vec(&res).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        let length = data.len() as u32;
+        let data = Self::format(protocol).encode(&res)?;
 
-        io.write_all(&length.to_be_bytes()).await?;
-        io.write_all(&data).await?;
+        write_frame(io, &data, MAX_RESPONSE_SIZE).await?;
         io.flush().await?;
         Ok(())
     }
@@ -102,10 +109,18 @@ impl Codec for ChatCodec {
 pub type ChatBehaviour = request_response::Behaviour<ChatCodec>;
 
 /// Creates a new `ChatBehaviour`.
+///
+/// The CBOR protocol is listed first, so it is chosen whenever the peer supports it.
 pub fn create_chat_behaviour() -> ChatBehaviour {
     use std::time::Duration;
 
     let config = request_response::Config::default().with_request_timeout(Duration::from_secs(10));
 
-    request_response::Behaviour::new([(ChatCodec::PROTOCOL, ProtocolSupport::Full)], config)
+    request_response::Behaviour::new(
+        [
+            (ChatCodec::PROTOCOL_V2, ProtocolSupport::Full),
+            (ChatCodec::PROTOCOL, ProtocolSupport::Full),
+        ],
+        config,
+    )
 }
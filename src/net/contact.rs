@@ -0,0 +1,145 @@
+//! This module defines the codec for the contact protocol, which is used for
+//! exchanging friend requests and signed contact cards.
+use super::wire::{read_frame, write_frame};
+use crate::types::{ContactRequest, ContactResponse};
+use futures::prelude::*;
+use libp2p::request_response::{self, Codec, ProtocolSupport};
+use std::io;
+
+/// The largest request or response accepted.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// The codec for the contact protocol.
+///
+/// This codec is used by the `libp2p` `request_response` behaviour to encode
+/// and decode contact requests and responses.
+#[derive(Clone, Default)]
+pub struct ContactCodec;
+
+impl ContactCodec {
+    /// The protocol name for the contact protocol.
+    pub const PROTOCOL: &'static str = "/p2p-chat/contact/1.0.0";
+}
+
+#[async_trait::async_trait]
+impl Codec for ContactCodec {
+    type Protocol = &'static str;
+    type Request = ContactRequest;
+    type Response = ContactResponse;
+
+    /// Reads a length-prefixed JSON-encoded request from the given I/O stream.
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_frame(io, MAX_FRAME_SIZE).await?;
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reads a length-prefixed JSON-encoded response from the given I/O stream.
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_frame(io, MAX_FRAME_SIZE).await?;
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes a length-prefixed JSON-encoded request to the given I/O stream.
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data =
+            serde_json::to_vec(&req).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        write_frame(io, &data, MAX_FRAME_SIZE).await?;
+        io.flush().await?;
+        Ok(())
+    }
+
+    /// Writes a length-prefixed JSON-encoded response to the given I/O stream.
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        res: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data =
+            serde_json::to_vec(&res).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        write_frame(io, &data, MAX_FRAME_SIZE).await?;
+        io.flush().await?;
+        Ok(())
+    }
+}
+
+/// The `libp2p` `request_response` behaviour for the contact protocol.
+pub type ContactBehaviour = request_response::Behaviour<ContactCodec>;
+
+/// Creates a new `ContactBehaviour`.
+pub fn create_contact_behaviour() -> ContactBehaviour {
+    use std::time::Duration;
+
+    let config = request_response::Config::default().with_request_timeout(Duration::from_secs(10));
+
+    request_response::Behaviour::new([(ContactCodec::PROTOCOL, ProtocolSupport::Full)], config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{signing, Identity};
+    use futures::io::Cursor;
+
+    #[tokio::test]
+    async fn signed_cards_survive_the_codec() {
+        let identity = Identity::generate().unwrap();
+        let card = identity.contact_card(Some("alice".to_string())).unwrap();
+        let mut io = Cursor::new(Vec::new());
+        ContactCodec
+            .write_request(
+                &ContactCodec::PROTOCOL,
+                &mut io,
+                ContactRequest::Request { card },
+            )
+            .await
+            .unwrap();
+
+        io.set_position(0);
+        let request = ContactCodec
+            .read_request(&ContactCodec::PROTOCOL, &mut io)
+            .await
+            .unwrap();
+        signing::verify_contact_card(request.card()).unwrap();
+
+        // A relabelled card no longer verifies.
+        let mut card = request.card().clone();
+        card.display_name = Some("mallory".to_string());
+        assert!(signing::verify_contact_card(&card).is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_requests_are_refused() {
+        let mut data = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes().to_vec();
+        data.resize(4 + MAX_FRAME_SIZE + 1, b' ');
+        let mut io = Cursor::new(data);
+        let err = ContactCodec
+            .read_request(&ContactCodec::PROTOCOL, &mut io)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
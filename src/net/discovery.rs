@@ -2,6 +2,7 @@
 //!
 //! It combines mDNS for local peer discovery and Kademlia for decentralized
 //! peer discovery in the wider network.
+use crate::storage::SledRecordStore;
 use anyhow::Result;
 use libp2p::{kad, mdns, PeerId};
 
@@ -11,7 +12,7 @@ pub struct DiscoveryBehaviour {
     /// The mDNS behaviour for local peer discovery.
     pub mdns: mdns::tokio::Behaviour,
     /// The Kademlia behaviour for decentralized peer discovery.
-    pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    pub kademlia: kad::Behaviour<SledRecordStore>,
 }
 
 impl DiscoveryBehaviour {
@@ -20,17 +21,20 @@ impl DiscoveryBehaviour {
     /// # Arguments
     ///
     /// * `local_peer_id` - The `PeerId` of the local node.
+    /// * `store` - The persistent store for Kademlia records.
     ///
     /// # Errors
     ///
     /// This function will return an error if the mDNS behaviour cannot be created.
-    pub fn new(local_peer_id: PeerId) -> Result<Self> {
+    pub fn new(local_peer_id: PeerId, store: SledRecordStore) -> Result<Self> {
         // Initialize mDNS for local discovery.
         let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)?;
 
-        // Initialize Kademlia DHT.
-        let store = kad::store::MemoryStore::new(local_peer_id);
-        let mut kademlia = kad::Behaviour::new(local_peer_id, store);
+        // Initialize Kademlia DHT. Inbound records are handed to the network
+        // layer instead of being stored directly, so writes can be rate limited.
+        let mut config = kad::Config::default();
+        config.set_record_filtering(kad::StoreInserts::FilterBoth);
+        let mut kademlia = kad::Behaviour::with_config(local_peer_id, store, config);
 
         // Set Kademlia to server mode to participate in the DHT.
         kademlia.set_mode(Some(kad::Mode::Server));
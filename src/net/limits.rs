@@ -0,0 +1,188 @@
+//! This module defines the connection guard of the swarm.
+//!
+//! Global and per-peer connection limits are enforced by
+//! `libp2p::connection_limits`. The guard adds what that behaviour cannot
+//! express: a limit on the connections from a single IP address, which keeps
+//! one host from exhausting the global limit with many peer IDs, and the
+//! refusal of connections from blocked peers.
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::task::{Context, Poll};
+
+use libp2p::core::Endpoint;
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::{
+    dummy, ConnectionClosed, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler,
+    THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+
+/// The reason a connection was refused by the `ConnectionGuard`.
+#[derive(Debug, thiserror::Error)]
+pub enum GuardError {
+    /// The remote IP address already holds the maximum number of connections.
+    #[error("connection limit of {limit} for {ip} reached")]
+    IpLimitExceeded {
+        /// The remote IP address.
+        ip: IpAddr,
+        /// The configured limit.
+        limit: u32,
+    },
+    /// The remote peer is blocked.
+    #[error("peer {0} is blocked")]
+    Blocked(PeerId),
+}
+
+/// A `NetworkBehaviour` that limits connections per IP address and refuses
+/// connections from blocked peers.
+#[derive(Debug, Default)]
+pub struct ConnectionGuard {
+    /// The most connections accepted from one IP address, if limited.
+    max_per_ip: Option<u32>,
+    /// The number of established connections per remote IP address.
+    per_ip: HashMap<IpAddr, u32>,
+    /// The remote IP address of each counted connection.
+    connections: HashMap<ConnectionId, IpAddr>,
+    /// The peers whose connections are refused.
+    blocked: HashSet<PeerId>,
+}
+
+impl ConnectionGuard {
+    /// Creates a new `ConnectionGuard`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_per_ip` - The most connections accepted from one IP address, if limited.
+    pub fn new(max_per_ip: Option<u32>) -> Self {
+        Self {
+            max_per_ip,
+            ..Default::default()
+        }
+    }
+
+    /// Changes the most connections accepted from one IP address.
+    ///
+    /// Existing connections are kept, even if they exceed the new limit.
+    pub fn set_max_per_ip(&mut self, max_per_ip: Option<u32>) {
+        self.max_per_ip = max_per_ip;
+    }
+
+    /// Refuses further connections from a peer.
+    pub fn block(&mut self, peer_id: PeerId) {
+        self.blocked.insert(peer_id);
+    }
+
+    /// Accepts connections from a peer again.
+    pub fn unblock(&mut self, peer_id: &PeerId) {
+        self.blocked.remove(peer_id);
+    }
+
+    /// Checks that another connection from an address stays within the per-IP limit.
+    fn check_ip(&self, remote_addr: &Multiaddr) -> Result<(), ConnectionDenied> {
+        let (Some(limit), Some(ip)) = (self.max_per_ip, remote_ip(remote_addr)) else {
+            return Ok(());
+        };
+        if self.per_ip.get(&ip).copied().unwrap_or(0) >= limit {
+            return Err(ConnectionDenied::new(GuardError::IpLimitExceeded {
+                ip,
+                limit,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Checks that a peer is not blocked.
+    fn check_peer(&self, peer_id: PeerId) -> Result<(), ConnectionDenied> {
+        if self.blocked.contains(&peer_id) {
+            return Err(ConnectionDenied::new(GuardError::Blocked(peer_id)));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the IP address of a direct connection.
+///
+/// Relayed connections are attributed to the relay, so they are not counted.
+fn remote_ip(address: &Multiaddr) -> Option<IpAddr> {
+    if address.iter().any(|p| p == Protocol::P2pCircuit) {
+        return None;
+    }
+    address.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+impl NetworkBehaviour for ConnectionGuard {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.check_ip(remote_addr)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_peer(peer)?;
+        self.check_ip(remote_addr)?;
+
+        if let Some(ip) = remote_ip(remote_addr) {
+            *self.per_ip.entry(ip).or_default() += 1;
+            self.connections.insert(connection_id, ip);
+        }
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_peer(peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        if let FromSwarm::ConnectionClosed(ConnectionClosed { connection_id, .. }) = event {
+            let Some(ip) = self.connections.remove(&connection_id) else {
+                return;
+            };
+            if let Some(count) = self.per_ip.get_mut(&ip) {
+                *count -= 1;
+                if *count == 0 {
+                    self.per_ip.remove(&ip);
+                }
+            }
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
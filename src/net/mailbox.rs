@@ -1,10 +1,51 @@
 //! This module defines the codec for the mailbox protocol, which is used for
 //! interacting with mailbox nodes.
-use crate::types::{MailboxRequest, MailboxResponse};
+//!
+//! Two versions of the protocol are offered. `/mailbox/2.0.0` encodes
+//! payloads as CBOR and is preferred, while `/mailbox/1.0.0` keeps the JSON
+//! encoding for older peers. Both are size-capped, so a fetch returns only
+//! as many messages as fit in one response; the rest wait for the next fetch.
+use super::wire::{read_frame, write_frame, WireFormat};
+use crate::types::{EncryptedMessage, MailboxRequest, MailboxResponse};
 use futures::prelude::*;
 use libp2p::request_response::{self, Codec, ProtocolSupport};
 use std::io;
 
+/// The largest request accepted, which carries at most a single message.
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+/// The largest response accepted, which may carry a batch of messages.
+const MAX_RESPONSE_SIZE: usize = 32 * 1024 * 1024;
+/// The room kept in a response for everything but the messages themselves.
+const RESPONSE_OVERHEAD: usize = 1024;
+
+/// The most messages returned by one fetch, whatever limit the client asks for.
+pub const MAX_FETCH_MESSAGES: usize = 100;
+
+/// Keeps the leading messages that fit in one fetch response.
+///
+/// The protocol a response will be sent with is not known when it is built,
+/// so the messages must fit in both encodings. JSON writes byte strings as
+/// arrays of numbers and is the larger one for most messages.
+///
+/// # Errors
+///
+/// This function will return an error if a message cannot be serialized.
+pub fn fit_fetch_response(messages: Vec<EncryptedMessage>) -> io::Result<Vec<EncryptedMessage>> {
+    let mut json_size = RESPONSE_OVERHEAD;
+    let mut cbor_size = RESPONSE_OVERHEAD;
+    let mut fitting = Vec::new();
+    for msg in messages.into_iter().take(MAX_FETCH_MESSAGES) {
+        // One more byte for the separator between JSON array items.
+        json_size += WireFormat::Json.encode(&msg)?.len() + 1;
+        cbor_size += WireFormat::Cbor.encode(&msg)?.len();
+        if json_size > MAX_RESPONSE_SIZE || cbor_size > MAX_RESPONSE_SIZE {
+            break;
+        }
+        fitting.push(msg);
+    }
+    Ok(fitting)
+}
+
 /// The codec for the mailbox protocol.
 ///
 /// This codec is used by the `libp2p` `request_response` behaviour to encode
@@ -13,8 +54,19 @@ use std::io;
 pub struct MailboxCodec;
 
 impl MailboxCodec {
-    /// The protocol name for the mailbox protocol.
+    /// The protocol name for the JSON-encoded mailbox protocol.
     pub const PROTOCOL: &'static str = "/mailbox/1.0.0";
+    /// The protocol name for the CBOR-encoded mailbox protocol.
+    pub const PROTOCOL_V2: &'static str = "/mailbox/2.0.0";
+
+    /// Returns the encoding used by a negotiated protocol.
+    fn format(protocol: &str) -> WireFormat {
+        if protocol == Self::PROTOCOL {
+            WireFormat::Json
+        } else {
+            WireFormat::Cbor
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -23,76 +75,62 @@ impl Codec for MailboxCodec {
     type Request = MailboxRequest;
     type Response = MailboxResponse;
 
-    /// Reads a length-prefixed JSON-encoded request from the given I/O stream.
-    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    /// Reads a length-prefixed request from the given I/O stream.
+    async fn read_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let mut length_buf = [0u8; 4];
-        io.read_exact(&mut length_buf).await?;
-        let length = u32::from_be_bytes(length_buf) as usize;
-
-        let mut data = vec![0u8; length];
-        io.read_exact(&mut data).await?;
-
-        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        let data = read_frame(io, MAX_REQUEST_SIZE).await?;
+        Self::format(protocol).decode(&data)
     }
 
-    /// Reads a length-prefixed JSON-encoded response from the given I/O stream.
+    /// Reads a length-prefixed response from the given I/O stream.
     async fn read_response<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
     ) -> io::Result<Self::Response>
     where
         T: AsyncRead + Unpin + Send,
     {
-        let mut length_buf = [0u8; 4];
-        io.read_exact(&mut length_buf).await?;
-        let length = u32::from_be_bytes(length_buf) as usize;
-
-        let mut data = vec![0u8; length];
-        io.read_exact(&mut data).await?;
-
-        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        let data = read_frame(io, MAX_RESPONSE_SIZE).await?;
+        Self::format(protocol).decode(&data)
     }
 
-    /// Writes a length-prefixed JSON-encoded request to the given I/O stream.
+    /// Writes a length-prefixed request to the given I/O stream.
     async fn write_request<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         req: Self::Request,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let data =
-            serde_json::to_vec(&req).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        let length = data.len() as u32;
+        let data = Self::format(protocol).encode(&req)?;
 
-        io.write_all(&length.to_be_bytes()).await?;
-        io.write_all(&data).await?;
+        write_frame(io, &data, MAX_REQUEST_SIZE).await?;
         io.flush().await?;
         Ok(())
     }
 
-    /// Writes a length-prefixed JSON-encoded response to the given I/O stream.
+    /// Writes a length-prefixed response to the given I/O stream.
     async fn write_response<T>(
         &mut self,
-        _: &Self::Protocol,
+        protocol: &Self::Protocol,
         io: &mut T,
         res: Self::Response,
     ) -> io::Result<()>
     where
         T: AsyncWrite + Unpin + Send,
     {
-        let data =
-            serde_json::to_vec(&res).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        let length = data.len() as u32;
+        let data = Self::format(protocol).encode(&res)?;
 
-        io.write_all(&length.to_be_bytes()).await?;
-        io.write_all(&data).await?;
+        write_frame(io, &data, MAX_RESPONSE_SIZE).await?;
         io.flush().await?;
         Ok(())
     }
@@ -102,10 +140,101 @@ impl Codec for MailboxCodec {
 pub type MailboxBehaviour = request_response::Behaviour<MailboxCodec>;
 
 /// Creates a new `MailboxBehaviour`.
+///
+/// The CBOR protocol is listed first, so it is chosen whenever the peer supports it.
 pub fn create_mailbox_behaviour() -> MailboxBehaviour {
     use std::time::Duration;
 
     let config = request_response::Config::default().with_request_timeout(Duration::from_secs(2));
 
-    request_response::Behaviour::new([(MailboxCodec::PROTOCOL, ProtocolSupport::Full)], config)
+    request_response::Behaviour::new(
+        [
+            (MailboxCodec::PROTOCOL_V2, ProtocolSupport::Full),
+            (MailboxCodec::PROTOCOL, ProtocolSupport::Full),
+        ],
+        config,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{MailboxStore, SledMailboxStore};
+    use libp2p::PeerId;
+    use rand::RngCore;
+
+    /// Returns a message with `size` bytes of random content.
+    fn message(recipient: [u8; 32], size: usize) -> EncryptedMessage {
+        let mut encrypted_content = vec![0u8; size];
+        rand::thread_rng().fill_bytes(&mut encrypted_content);
+        EncryptedMessage {
+            id: uuid::Uuid::new_v4(),
+            sender: PeerId::random(),
+            recipient_hash: recipient,
+            encrypted_content,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            nonce: rand::random(),
+            sender_pub_key: vec![7; 32],
+            signature: vec![9; 64],
+            group_id: None,
+        }
+    }
+
+    /// Returns a message whose `Put` request just fits in `MAX_REQUEST_SIZE`.
+    fn max_size_message(recipient: [u8; 32]) -> EncryptedMessage {
+        let msg = message(recipient, MAX_REQUEST_SIZE - 1024);
+        let put = MailboxRequest::Put {
+            recipient,
+            message: Box::new(msg.clone()),
+        };
+        assert!(WireFormat::Cbor.encode(&put).unwrap().len() <= MAX_REQUEST_SIZE);
+        msg
+    }
+
+    #[tokio::test]
+    async fn full_mailbox_drains_in_fitting_responses() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = SledMailboxStore::new(db, None, 1000).unwrap();
+        let recipient = [3u8; 32];
+        let count = 12;
+        for _ in 0..count {
+            store
+                .store_message(recipient, max_size_message(recipient))
+                .await
+                .unwrap();
+        }
+
+        let mut drained = 0;
+        let mut fetches = 0;
+        loop {
+            let fetched = store.fetch_messages(recipient, 100).await.unwrap();
+            if fetched.is_empty() {
+                break;
+            }
+            let items = fit_fetch_response(fetched).unwrap();
+            assert!(!items.is_empty());
+
+            let ids = items.iter().map(|msg| msg.id).collect();
+            let response = MailboxResponse::Messages { items };
+            for format in [WireFormat::Json, WireFormat::Cbor] {
+                assert!(format.encode(&response).unwrap().len() <= MAX_RESPONSE_SIZE);
+            }
+
+            drained += store.delete_messages(recipient, ids).await.unwrap();
+            fetches += 1;
+        }
+        assert_eq!(drained, count);
+        assert!(fetches > 1);
+    }
+
+    #[test]
+    fn fetch_responses_are_capped_in_count() {
+        let recipient = [5u8; 32];
+        let messages: Vec<EncryptedMessage> = (0..MAX_FETCH_MESSAGES + 20)
+            .map(|_| message(recipient, 16))
+            .collect();
+
+        let items = fit_fetch_response(messages).unwrap();
+        assert_eq!(items.len(), MAX_FETCH_MESSAGES);
+    }
 }
@@ -1,46 +1,151 @@
 //! This module provides the networking capabilities for the application.
 //!
 //! It is responsible for building the `libp2p` transport and defining the
-//! network behaviours for chat, discovery, and mailboxes.
+//! network behaviours for chat, contacts, attachments, discovery, mailboxes,
+//! NAT traversal, and connection limits.
+pub mod attachment;
 pub mod chat;
+pub mod contact;
 pub mod discovery;
+pub mod limits;
 pub mod mailbox;
+pub mod nat;
+pub mod wire;
 
 use anyhow::Result;
+use futures::future::Either;
 use libp2p::{
-    core::{transport::Boxed, upgrade::Version},
-    identity, noise, tcp, yamux, PeerId, Transport,
+    core::{muxing::StreamMuxerBox, transport::Boxed, upgrade::Version},
+    identity,
+    multiaddr::Protocol,
+    noise, quic, relay, tcp, yamux, Multiaddr, PeerId, Transport,
 };
 
 // Type alias for the transport.
-type BoxedTransport = Boxed<(PeerId, libp2p::core::muxing::StreamMuxerBox)>;
+type BoxedTransport = Boxed<(PeerId, StreamMuxerBox)>;
 
+pub use attachment::AttachmentBehaviour;
 pub use chat::ChatBehaviour;
+pub use contact::ContactBehaviour;
 pub use discovery::DiscoveryBehaviour;
+pub use limits::ConnectionGuard;
 pub use mailbox::MailboxBehaviour;
+pub use nat::NatBehaviour;
 
 /// Builds the `libp2p` transport.
 ///
-/// This function creates a TCP-based transport that is secured with Noise
-/// and multiplexed with Yamux.
+/// This function creates a QUIC transport alongside a TCP-based one. The TCP
+/// transport is combined with the relay client transport for circuit
+/// connections, secured with Noise, and multiplexed with Yamux. QUIC brings
+/// its own encryption and multiplexing and avoids head-of-line blocking on
+/// lossy links.
 ///
 /// # Arguments
 ///
 /// * `keypair` - The `identity::Keypair` of the local node.
+/// * `relay_transport` - The relay client transport paired with the relay client behaviour.
 ///
 /// # Errors
 ///
 /// This function will return an error if the transport cannot be built.
-pub fn build_transport(keypair: &identity::Keypair) -> Result<BoxedTransport> {
+pub fn build_transport(
+    keypair: &identity::Keypair,
+    relay_transport: relay::client::Transport,
+) -> Result<BoxedTransport> {
     let tcp = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true));
     let noise = noise::Config::new(keypair)?;
     let yamux = yamux::Config::default();
 
-    let transport = tcp
+    let tcp = tcp
+        .or_transport(relay_transport)
         .upgrade(Version::V1)
         .authenticate(noise)
-        .multiplex(yamux)
+        .multiplex(yamux);
+
+    let quic = quic::tokio::Transport::new(quic::Config::new(keypair));
+
+    let transport = quic
+        .or_transport(tcp)
+        .map(|output, _| match output {
+            Either::Left((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+            Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+        })
         .boxed();
 
     Ok(transport)
 }
+
+/// Returns `true` if an address uses the QUIC transport.
+///
+/// # Arguments
+///
+/// * `address` - The `Multiaddr` to check.
+pub fn is_quic(address: &Multiaddr) -> bool {
+    address.iter().any(|p| matches!(p, Protocol::QuicV1))
+}
+
+/// Orders addresses so that QUIC addresses are dialed first.
+///
+/// The relative order of the addresses is otherwise kept.
+///
+/// # Arguments
+///
+/// * `addresses` - The addresses to order.
+pub fn prefer_quic(addresses: &mut [Multiaddr]) {
+    addresses.sort_by_key(|address| !is_quic(address));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use libp2p::core::transport::{ListenerId, TransportEvent};
+
+    fn transport(keypair: &identity::Keypair) -> BoxedTransport {
+        let (relay_transport, _) = relay::client::new(keypair.public().to_peer_id());
+        build_transport(keypair, relay_transport).unwrap()
+    }
+
+    #[test]
+    fn quic_addresses_are_dialed_first() {
+        let tcp: Multiaddr = "/ip4/192.0.2.1/tcp/4001".parse().unwrap();
+        let quic: Multiaddr = "/ip4/192.0.2.1/udp/4001/quic-v1".parse().unwrap();
+        let other_tcp: Multiaddr = "/ip6/::1/tcp/4001".parse().unwrap();
+        let mut addresses = vec![tcp.clone(), other_tcp.clone(), quic.clone()];
+        prefer_quic(&mut addresses);
+        assert_eq!(addresses, [quic, tcp, other_tcp]);
+    }
+
+    #[tokio::test]
+    async fn peers_connect_over_quic() {
+        let listener_key = identity::Keypair::generate_ed25519();
+        let mut listener = transport(&listener_key);
+        listener
+            .listen_on(
+                ListenerId::next(),
+                "/ip4/127.0.0.1/udp/0/quic-v1".parse().unwrap(),
+            )
+            .unwrap();
+        let address = loop {
+            if let TransportEvent::NewAddress { listen_addr, .. } =
+                listener.select_next_some().await
+            {
+                break listen_addr;
+            }
+        };
+        assert!(is_quic(&address));
+
+        let mut dialer = transport(&identity::Keypair::generate_ed25519());
+        let dial = dialer.dial(address).unwrap();
+        let accept = async {
+            loop {
+                if let TransportEvent::Incoming { upgrade, .. } = listener.select_next_some().await
+                {
+                    return upgrade.await.unwrap();
+                }
+            }
+        };
+        let ((peer_id, _), _) = futures::join!(async { dial.await.unwrap() }, accept);
+        assert_eq!(peer_id, listener_key.public().to_peer_id());
+    }
+}
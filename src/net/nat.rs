@@ -0,0 +1,67 @@
+//! This module defines the NAT traversal behaviours for the network.
+//!
+//! It combines identify, AutoNAT, circuit relay v2 and DCUtR. Clients use
+//! AutoNAT to learn whether they are publicly reachable. If they are not,
+//! they reserve a slot on a relay, so that peers can reach them through it,
+//! and DCUtR then tries to upgrade relayed connections to direct ones by
+//! hole punching. Mailbox nodes additionally act as relays.
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::{autonat, dcutr, identify, identity, relay};
+
+/// The protocol version advertised through identify.
+pub const IDENTIFY_PROTOCOL_VERSION: &str = "/p2p-chat/1.0.0";
+
+/// The `libp2p` network behaviour for NAT traversal.
+#[derive(libp2p::swarm::NetworkBehaviour)]
+pub struct NatBehaviour {
+    /// The identify behaviour, which tells peers our addresses and protocols.
+    pub identify: identify::Behaviour,
+    /// The AutoNAT behaviour, which determines whether we are publicly reachable.
+    pub autonat: autonat::Behaviour,
+    /// The relay client behaviour, for reserving slots on relays.
+    pub relay_client: relay::client::Behaviour,
+    /// The relay server behaviour, enabled on mailbox nodes.
+    pub relay_server: Toggle<relay::Behaviour>,
+    /// The DCUtR behaviour, which upgrades relayed connections to direct ones.
+    pub dcutr: dcutr::Behaviour,
+}
+
+impl NatBehaviour {
+    /// Creates a new `NatBehaviour`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keypair` - The `identity::Keypair` of the local node.
+    /// * `relay_client` - The relay client behaviour paired with the relay transport.
+    /// * `is_relay` - Whether the node serves as a relay for other peers.
+    pub fn new(
+        keypair: &identity::Keypair,
+        relay_client: relay::client::Behaviour,
+        is_relay: bool,
+    ) -> Self {
+        let local_peer_id = keypair.public().to_peer_id();
+
+        let identify = identify::Behaviour::new(
+            identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), keypair.public())
+                .with_push_listen_addr_updates(true),
+        );
+        let autonat = autonat::Behaviour::new(local_peer_id, autonat::Config::default());
+        let relay_server = is_relay
+            .then(|| relay::Behaviour::new(local_peer_id, relay::Config::default()))
+            .into();
+        let dcutr = dcutr::Behaviour::new(local_peer_id);
+
+        Self {
+            identify,
+            autonat,
+            relay_client,
+            relay_server,
+            dcutr,
+        }
+    }
+
+    /// Returns `true` if the node serves as a relay for other peers.
+    pub fn is_relay(&self) -> bool {
+        self.relay_server.is_enabled()
+    }
+}
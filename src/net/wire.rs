@@ -0,0 +1,189 @@
+//! This module contains the framing and encodings shared by the
+//! request-response codecs.
+//!
+//! Every payload is sent as a frame with a big-endian `u32` length prefix.
+//! Readers check the length against a per-protocol limit before allocating,
+//! so a peer cannot make us reserve arbitrary amounts of memory.
+//!
+//! The 1.0.0 protocols encode payloads as JSON. The 2.0.0 protocols use CBOR,
+//! which stores byte strings as-is, behind a one-byte format version so the
+//! encoding can evolve without another protocol ID.
+use futures::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+
+/// The version byte that precedes CBOR payloads.
+pub const CBOR_FORMAT_VERSION: u8 = 1;
+
+/// The encoding of the payloads of a protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireFormat {
+    /// JSON, used by the 1.0.0 protocols.
+    Json,
+    /// Versioned CBOR, used by the 2.0.0 protocols.
+    Cbor,
+}
+
+impl WireFormat {
+    /// Encodes a value in this format.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the value cannot be serialized.
+    pub fn encode<T: Serialize>(self, value: &T) -> io::Result<Vec<u8>> {
+        match self {
+            WireFormat::Json => serde_json::to_vec(value).map_err(invalid_data),
+            WireFormat::Cbor => {
+                let mut data = vec![CBOR_FORMAT_VERSION];
+                ciborium::into_writer(value, &mut data).map_err(invalid_data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Decodes a value in this format.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the data is malformed or uses an
+    /// unknown format version.
+    pub fn decode<T: DeserializeOwned>(self, data: &[u8]) -> io::Result<T> {
+        match self {
+            WireFormat::Json => serde_json::from_slice(data).map_err(invalid_data),
+            WireFormat::Cbor => match data.split_first() {
+                Some((&CBOR_FORMAT_VERSION, payload)) => {
+                    ciborium::from_reader(payload).map_err(invalid_data)
+                }
+                Some((version, _)) => Err(invalid_data(format!(
+                    "Unsupported wire format version {}",
+                    version
+                ))),
+                None => Err(invalid_data("Empty frame")),
+            },
+        }
+    }
+}
+
+/// Reads a big-endian length prefix and the frame that follows it.
+///
+/// # Errors
+///
+/// This function will return an error if the frame is larger than `max_size`
+/// or the stream ends early.
+pub async fn read_frame<T>(io: &mut T, max_size: usize) -> io::Result<Vec<u8>>
+where
+    T: AsyncRead + Unpin + Send,
+{
+    let mut length_buf = [0u8; 4];
+    io.read_exact(&mut length_buf).await?;
+    let length = u32::from_be_bytes(length_buf) as usize;
+    if length > max_size {
+        return Err(invalid_data(format!(
+            "Frame of {} bytes exceeds the limit of {} bytes",
+            length, max_size
+        )));
+    }
+
+    let mut data = vec![0u8; length];
+    io.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// Writes a big-endian length prefix followed by the frame.
+///
+/// # Errors
+///
+/// This function will return an error if the frame is larger than `max_size`,
+/// since the peer would reject it anyway, or writing fails.
+pub async fn write_frame<T>(io: &mut T, data: &[u8], max_size: usize) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+{
+    if data.len() > max_size {
+        return Err(invalid_data(format!(
+            "Frame of {} bytes exceeds the limit of {} bytes",
+            data.len(),
+            max_size
+        )));
+    }
+
+    io.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    io.write_all(data).await
+}
+
+/// Wraps an error as `io::ErrorKind::InvalidData`.
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+
+    #[tokio::test]
+    async fn frames_round_trip_within_the_limit() {
+        let mut io = Cursor::new(Vec::new());
+        write_frame(&mut io, b"hello", 5).await.unwrap();
+        assert_eq!(io.get_ref()[..4], 5u32.to_be_bytes());
+
+        io.set_position(0);
+        assert_eq!(read_frame(&mut io, 5).await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_are_refused() {
+        let mut io = Cursor::new(Vec::new());
+        let err = write_frame(&mut io, b"hello", 4).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(io.get_ref().is_empty());
+
+        // The length is checked before the frame is read.
+        let mut io = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        let err = read_frame(&mut io, 1024).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn truncated_frames_are_refused() {
+        let mut data = 10u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"short");
+        let mut io = Cursor::new(data);
+        let err = read_frame(&mut io, 1024).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn cbor_payloads_carry_the_version_byte() {
+        let value = vec!["a".to_string(), "b".to_string()];
+        let data = WireFormat::Cbor.encode(&value).unwrap();
+        assert_eq!(data[0], CBOR_FORMAT_VERSION);
+        assert_eq!(
+            WireFormat::Cbor.decode::<Vec<String>>(&data).unwrap(),
+            value
+        );
+
+        let data = WireFormat::Json.encode(&value).unwrap();
+        assert_eq!(data, br#"["a","b"]"#);
+        assert_eq!(
+            WireFormat::Json.decode::<Vec<String>>(&data).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn unknown_versions_and_empty_frames_are_refused() {
+        let mut data = WireFormat::Cbor.encode(&1u32).unwrap();
+        data[0] = CBOR_FORMAT_VERSION + 1;
+        let err = WireFormat::Cbor.decode::<u32>(&data).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("version"));
+
+        let err = WireFormat::Cbor.decode::<u32>(&[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
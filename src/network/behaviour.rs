@@ -1,7 +1,10 @@
 //! This module defines the composite `NetworkBehaviour` for the application.
-use libp2p::{ping, swarm::NetworkBehaviour};
+use libp2p::{connection_limits, ping, swarm::NetworkBehaviour};
 
-use crate::net::{ChatBehaviour, DiscoveryBehaviour, MailboxBehaviour};
+use crate::net::{
+    AttachmentBehaviour, ChatBehaviour, ConnectionGuard, ContactBehaviour, DiscoveryBehaviour,
+    MailboxBehaviour, NatBehaviour,
+};
 
 /// The composite `NetworkBehaviour` for the application.
 ///
@@ -9,12 +12,22 @@ use crate::net::{ChatBehaviour, DiscoveryBehaviour, MailboxBehaviour};
 /// behaviour that can be used by the `libp2p` `Swarm`.
 #[derive(NetworkBehaviour)]
 pub struct P2PBehaviour {
+    /// The global and per-peer connection limits.
+    pub connection_limits: connection_limits::Behaviour,
+    /// The per-IP connection limit and the refusal of blocked peers.
+    pub guard: ConnectionGuard,
     /// The behaviour for sending and receiving chat messages.
     pub chat: ChatBehaviour,
+    /// The behaviour for exchanging friend requests.
+    pub contact: ContactBehaviour,
+    /// The behaviour for fetching attachment chunks.
+    pub attachment: AttachmentBehaviour,
     /// The behaviour for interacting with mailbox nodes.
     pub mailbox: MailboxBehaviour,
     /// The behaviour for peer discovery.
     pub discovery: DiscoveryBehaviour,
+    /// The behaviours for NAT traversal.
+    pub nat: NatBehaviour,
     /// The behaviour for pinging other peers to keep connections alive.
     pub ping: ping::Behaviour,
 }
@@ -34,7 +34,9 @@ impl NetworkLayer {
                     return Ok(());
                 }
 
-                let request = ChatRequest::SendMessage { message };
+                let request = ChatRequest::SendMessage {
+                    message: Box::new(message),
+                };
                 let request_id = self
                     .swarm
                     .behaviour_mut()
@@ -65,13 +67,76 @@ impl NetworkLayer {
                 self.pending_requests.insert(request_id, response);
             }
 
+            NetworkCommand::SendContactRequest {
+                peer_id,
+                request,
+                response,
+            } => {
+                if !self.swarm.is_connected(&peer_id) {
+                    debug!(
+                        "Peer {} not connected, failing contact request immediately.",
+                        peer_id
+                    );
+                    let _ = response.send(NetworkResponse::Error("Peer not connected".to_string()));
+                    return Ok(());
+                }
+
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .contact
+                    .send_request(&peer_id, request);
+                self.pending_requests.insert(request_id, response);
+            }
+
+            NetworkCommand::FetchAttachmentChunk {
+                peer_id,
+                request,
+                response,
+            } => {
+                if !self.swarm.is_connected(&peer_id) {
+                    let _ = response.send(NetworkResponse::Error("Peer not connected".to_string()));
+                    return Ok(());
+                }
+
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .attachment
+                    .send_request(&peer_id, request);
+                self.pending_requests.insert(request_id, response);
+            }
+
             NetworkCommand::MailboxPut {
                 peer_id,
                 recipient,
                 message,
                 response,
             } => {
-                let request = MailboxRequest::Put { recipient, message };
+                let request = MailboxRequest::Put {
+                    recipient,
+                    message: Box::new(message),
+                };
+                self.dial_known_peer(peer_id).await;
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .mailbox
+                    .send_request(&peer_id, request);
+                self.pending_requests.insert(request_id, response);
+            }
+
+            NetworkCommand::MailboxChallenge {
+                peer_id,
+                recipient,
+                public_key,
+                response,
+            } => {
+                let request = MailboxRequest::Challenge {
+                    recipient,
+                    public_key,
+                };
+                self.dial_known_peer(peer_id).await;
                 let request_id = self
                     .swarm
                     .behaviour_mut()
@@ -84,9 +149,15 @@ impl NetworkLayer {
                 peer_id,
                 recipient,
                 limit,
+                proof,
                 response,
             } => {
-                let request = MailboxRequest::Fetch { recipient, limit };
+                let request = MailboxRequest::Fetch {
+                    recipient,
+                    limit,
+                    proof,
+                };
+                self.dial_known_peer(peer_id).await;
                 let request_id = self
                     .swarm
                     .behaviour_mut()
@@ -99,9 +170,36 @@ impl NetworkLayer {
                 peer_id,
                 recipient,
                 msg_ids,
+                proof,
                 response,
             } => {
-                let request = MailboxRequest::Ack { recipient, msg_ids };
+                let request = MailboxRequest::Ack {
+                    recipient,
+                    msg_ids,
+                    proof,
+                };
+                self.dial_known_peer(peer_id).await;
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .mailbox
+                    .send_request(&peer_id, request);
+                self.pending_requests.insert(request_id, response);
+            }
+
+            NetworkCommand::MailboxSetBlocked {
+                peer_id,
+                recipient,
+                blocked,
+                proof,
+                response,
+            } => {
+                let request = MailboxRequest::SetBlocked {
+                    recipient,
+                    blocked,
+                    proof,
+                };
+                self.dial_known_peer(peer_id).await;
                 let request_id = self
                     .swarm
                     .behaviour_mut()
@@ -115,6 +213,14 @@ impl NetworkLayer {
                 let _ = response.send(NetworkResponse::ConnectedPeers { peers });
             }
 
+            NetworkCommand::GetReachability { response } => {
+                let _ = response.send(NetworkResponse::Reachability(self.reachability_info()));
+            }
+
+            NetworkCommand::GetLimitCounters { response } => {
+                let _ = response.send(NetworkResponse::LimitCounters(self.limit_counters()));
+            }
+
             NetworkCommand::StartDhtProviderQuery { key, response } => {
                 let query_id = self.swarm.behaviour_mut().discovery.get_providers(key);
                 let _ = response.send(Ok(query_id));
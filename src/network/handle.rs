@@ -4,9 +4,12 @@ use anyhow::{anyhow, Result};
 use libp2p::{kad, PeerId};
 use tokio::sync::{mpsc, oneshot};
 
-use crate::types::{ChatRequest, EncryptedMessage, Message};
+use crate::types::{
+    AttachmentRequest, ChatRequest, ContactRequest, EncryptedMessage, MailboxChallenge, Message,
+};
 
 use super::message::{NetworkCommand, NetworkResponse};
+use super::{LimitCounters, ReachabilityInfo};
 
 /// A handle for interacting with the `NetworkLayer`.
 ///
@@ -70,6 +73,73 @@ impl NetworkHandle {
         }
     }
 
+    /// Sends a contact request to a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the recipient.
+    /// * `request` - The contact request to send.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the peer is not connected or
+    /// does not acknowledge the request.
+    pub async fn send_contact_request(
+        &self,
+        peer_id: PeerId,
+        request: ContactRequest,
+    ) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.command_sender
+            .send(NetworkCommand::SendContactRequest {
+                peer_id,
+                request,
+                response: tx,
+            })?;
+
+        match rx.await? {
+            NetworkResponse::MessageSent => Ok(()),
+            NetworkResponse::Error(e) => Err(anyhow!(e)),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
+    /// Fetches an attachment chunk from a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer holding the attachment.
+    /// * `hash` - The hash of the attachment.
+    /// * `index` - The index of the chunk.
+    ///
+    /// # Returns
+    ///
+    /// The encrypted chunk, or `None` if the peer does not have it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the peer is not connected or the request fails.
+    pub async fn fetch_attachment_chunk(
+        &self,
+        peer_id: PeerId,
+        hash: String,
+        index: u32,
+    ) -> Result<Option<Vec<u8>>> {
+        let (tx, rx) = oneshot::channel();
+        self.command_sender
+            .send(NetworkCommand::FetchAttachmentChunk {
+                peer_id,
+                request: AttachmentRequest { hash, index },
+                response: tx,
+            })?;
+
+        match rx.await? {
+            NetworkResponse::AttachmentChunk { data } => Ok(data),
+            NetworkResponse::Error(e) => Err(anyhow!(e)),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
     /// Gets the list of connected peers.
     ///
     /// # Errors
@@ -87,6 +157,40 @@ impl NetworkHandle {
         }
     }
 
+    /// Gets the reachability of the local node.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the reachability cannot be retrieved.
+    pub async fn get_reachability(&self) -> Result<ReachabilityInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.command_sender
+            .send(NetworkCommand::GetReachability { response: tx })?;
+
+        match rx.await? {
+            NetworkResponse::Reachability(info) => Ok(info),
+            NetworkResponse::Error(e) => Err(anyhow!(e)),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
+    /// Gets the counters of the requests and connections refused by the limits.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the counters cannot be retrieved.
+    pub async fn get_limit_counters(&self) -> Result<LimitCounters> {
+        let (tx, rx) = oneshot::channel();
+        self.command_sender
+            .send(NetworkCommand::GetLimitCounters { response: tx })?;
+
+        match rx.await? {
+            NetworkResponse::LimitCounters(counters) => Ok(counters),
+            NetworkResponse::Error(e) => Err(anyhow!(e)),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
     /// Puts a message into a mailbox.
     ///
     /// # Arguments
@@ -118,6 +222,37 @@ impl NetworkHandle {
         }
     }
 
+    /// Asks a mailbox for a challenge proving ownership of a recipient's key.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the mailbox node.
+    /// * `recipient` - The hash of the recipient's public key.
+    /// * `public_key` - The recipient's public key.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mailbox refuses to issue a challenge.
+    pub async fn mailbox_challenge(
+        &self,
+        peer_id: PeerId,
+        recipient: [u8; 32],
+        public_key: Vec<u8>,
+    ) -> Result<MailboxChallenge> {
+        let (tx, rx) = oneshot::channel();
+        self.command_sender.send(NetworkCommand::MailboxChallenge {
+            peer_id,
+            recipient,
+            public_key,
+            response: tx,
+        })?;
+        match rx.await? {
+            NetworkResponse::MailboxChallenge { challenge } => Ok(challenge),
+            NetworkResponse::Error(e) => Err(anyhow!(e)),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
     /// Fetches messages from a mailbox.
     ///
     /// # Arguments
@@ -125,6 +260,7 @@ impl NetworkHandle {
     /// * `peer_id` - The `PeerId` of the mailbox node.
     /// * `recipient` - The hash of the recipient's public key.
     /// * `limit` - The maximum number of messages to fetch.
+    /// * `proof` - The answer to the mailbox's challenge.
     ///
     /// # Errors
     ///
@@ -134,12 +270,14 @@ impl NetworkHandle {
         peer_id: PeerId,
         recipient: [u8; 32],
         limit: usize,
+        proof: [u8; 32],
     ) -> Result<Vec<EncryptedMessage>> {
         let (tx, rx) = oneshot::channel();
         self.command_sender.send(NetworkCommand::MailboxFetch {
             peer_id,
             recipient,
             limit,
+            proof,
             response: tx,
         })?;
         match rx.await? {
@@ -158,6 +296,7 @@ impl NetworkHandle {
     /// * `peer_id` - The `PeerId` of the mailbox node.
     /// * `recipient` - The hash of the recipient's public key.
     /// * `msg_ids` - The IDs of the messages to acknowledge.
+    /// * `proof` - The answer to the mailbox's challenge.
     ///
     /// # Errors
     ///
@@ -167,12 +306,14 @@ impl NetworkHandle {
         peer_id: PeerId,
         recipient: [u8; 32],
         msg_ids: Vec<uuid::Uuid>,
+        proof: [u8; 32],
     ) -> Result<usize> {
         let (tx, rx) = oneshot::channel();
         self.command_sender.send(NetworkCommand::MailboxAck {
             peer_id,
             recipient,
             msg_ids,
+            proof,
             response: tx,
         })?;
         match rx.await? {
@@ -182,6 +323,45 @@ impl NetworkHandle {
         }
     }
 
+    /// Replaces the senders a mailbox refuses to store messages from for us.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the mailbox node.
+    /// * `recipient` - The hash of the recipient's public key.
+    /// * `blocked` - The `PeerId`s of the blocked senders.
+    /// * `proof` - The answer to the mailbox's challenge.
+    ///
+    /// # Returns
+    ///
+    /// The number of blocked senders the mailbox stored.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mailbox refuses the list.
+    pub async fn mailbox_set_blocked(
+        &self,
+        peer_id: PeerId,
+        recipient: [u8; 32],
+        blocked: Vec<PeerId>,
+        proof: [u8; 32],
+    ) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.command_sender
+            .send(NetworkCommand::MailboxSetBlocked {
+                peer_id,
+                recipient,
+                blocked,
+                proof,
+                response: tx,
+            })?;
+        match rx.await? {
+            NetworkResponse::MailboxBlockedSet { count } => Ok(count),
+            NetworkResponse::Error(e) => Err(anyhow!(e)),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
     /// Starts a Kademlia DHT query to find providers for a key.
     ///
     /// # Arguments
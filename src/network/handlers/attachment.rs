@@ -0,0 +1,85 @@
+//! This module contains the handlers for attachment protocol events.
+use super::super::{NetworkLayer, NetworkResponse};
+use crate::types::{AttachmentRequest, AttachmentResponse};
+use libp2p::request_response;
+use tracing::{debug, warn};
+
+impl NetworkLayer {
+    /// Handles an event from the `AttachmentBehaviour`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The `request_response::Event<AttachmentRequest, AttachmentResponse>` to handle.
+    pub(super) fn handle_attachment_event(
+        &mut self,
+        event: request_response::Event<AttachmentRequest, AttachmentResponse>,
+    ) {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request {
+                    request, channel, ..
+                } => {
+                    let response = self.serve_attachment_chunk(&request);
+                    if matches!(response, AttachmentResponse::NotFound) {
+                        debug!(
+                            "Peer {} asked for unknown chunk {} of attachment {}",
+                            peer, request.index, request.hash
+                        );
+                    }
+
+                    let _ = self
+                        .swarm
+                        .behaviour_mut()
+                        .attachment
+                        .send_response(channel, response);
+                }
+                request_response::Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(sender) = self.pending_requests.remove(&request_id) {
+                        let data = match response {
+                            AttachmentResponse::Chunk { data } => Some(data),
+                            AttachmentResponse::NotFound => None,
+                        };
+                        let _ = sender.send(NetworkResponse::AttachmentChunk { data });
+                    }
+                }
+            },
+            request_response::Event::OutboundFailure {
+                request_id, error, ..
+            } => {
+                warn!("Attachment request failed: {:?}", error);
+                if let Some(sender) = self.pending_requests.remove(&request_id) {
+                    let _ = sender.send(NetworkResponse::Error(format!(
+                        "Request failed: {:?}",
+                        error
+                    )));
+                }
+            }
+            request_response::Event::InboundFailure { error, .. } => {
+                warn!("Attachment inbound failure: {:?}", error);
+            }
+            _ => {}
+        }
+    }
+
+    /// Looks up a requested chunk in the attachment store.
+    ///
+    /// Chunks are encrypted with a key that only travels inside end-to-end
+    /// encrypted messages, so they are served to any peer that asks.
+    fn serve_attachment_chunk(&self, request: &AttachmentRequest) -> AttachmentResponse {
+        let Some(ref store) = self.attachment_store else {
+            return AttachmentResponse::NotFound;
+        };
+
+        match store.read_chunk(&request.hash, request.index) {
+            Ok(Some(data)) => AttachmentResponse::Chunk { data },
+            Ok(None) => AttachmentResponse::NotFound,
+            Err(e) => {
+                debug!("Failed to read attachment chunk: {}", e);
+                AttachmentResponse::NotFound
+            }
+        }
+    }
+}
@@ -1,11 +1,14 @@
 //! This module contains the handlers for chat-related network events.
+use super::super::layer::RequestKind;
 use super::super::{NetworkLayer, NetworkResponse};
 use crate::cli::commands::UiNotification;
+use crate::crypto::signing;
 use crate::types::{ChatRequest, ChatResponse, DeliveryStatus, Message};
 use anyhow::Result;
 use libp2p::request_response::{self, OutboundRequestId, ResponseChannel};
+use libp2p::PeerId;
 use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 impl NetworkLayer {
     /// Handles an event from the `ChatBehaviour`.
@@ -27,11 +30,11 @@ impl NetworkLayer {
         incoming_messages: &mpsc::UnboundedSender<Message>,
     ) -> Result<()> {
         match event {
-            request_response::Event::Message { message, .. } => match message {
+            request_response::Event::Message { peer, message } => match message {
                 request_response::Message::Request {
                     request, channel, ..
                 } => {
-                    self.handle_chat_request(request, channel, incoming_messages)
+                    self.handle_chat_request(peer, request, channel, incoming_messages)
                         .await?;
                 }
                 request_response::Message::Response {
@@ -62,17 +65,55 @@ impl NetworkLayer {
     }
 
     /// Handles an inbound chat request.
+    ///
+    /// Messages are only accepted if they were sent by the peer on the other
+    /// end of the connection and carry a valid signature from that peer.
+    /// Requests from blocked peers, or over the peer's rate limit, are dropped
+    /// without a response.
     async fn handle_chat_request(
         &mut self,
+        peer: PeerId,
         request: ChatRequest,
         channel: ResponseChannel<ChatResponse>,
         incoming_messages: &mpsc::UnboundedSender<Message>,
     ) -> Result<()> {
+        if self.is_peer_blocked(&peer).await {
+            debug!("Dropping chat request from blocked peer {}", peer);
+            return Ok(());
+        }
+        if !self.allow_request(peer, RequestKind::Chat) {
+            return Ok(());
+        }
+
         match request {
             ChatRequest::SendMessage { message } => {
                 info!("Received message from {}: {}", message.sender, message.id);
 
-                if let Err(e) = incoming_messages.send(message.clone()) {
+                let authenticated = if message.sender != peer {
+                    warn!(
+                        "Rejecting message {} from {} claiming to be from {}",
+                        message.id, peer, message.sender
+                    );
+                    false
+                } else if let Err(e) = signing::verify_message(&message) {
+                    warn!(
+                        "Rejecting forged message {} from {}: {}",
+                        message.id, peer, e
+                    );
+                    false
+                } else {
+                    true
+                };
+
+                if !authenticated {
+                    let _ = self.swarm.behaviour_mut().chat.send_response(
+                        channel,
+                        ChatResponse::MessageResult {
+                            success: false,
+                            message_id: None,
+                        },
+                    );
+                } else if let Err(e) = incoming_messages.send((*message).clone()) {
                     error!("Failed to forward incoming message: {}", e);
                     let _ = self.swarm.behaviour_mut().chat.send_response(
                         channel,
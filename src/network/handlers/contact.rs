@@ -0,0 +1,104 @@
+//! This module contains the handlers for contact protocol events.
+use super::super::{NetworkLayer, NetworkResponse};
+use crate::crypto::signing;
+use crate::types::{ContactRequest, ContactResponse};
+use anyhow::Result;
+use libp2p::request_response::{self, ResponseChannel};
+use libp2p::PeerId;
+use tracing::{error, info, warn};
+
+impl NetworkLayer {
+    /// Handles an event from the `ContactBehaviour`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The `request_response::Event<ContactRequest, ContactResponse>` to handle.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if handling the event fails.
+    pub(super) async fn handle_contact_event(
+        &mut self,
+        event: request_response::Event<ContactRequest, ContactResponse>,
+    ) -> Result<()> {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request {
+                    request, channel, ..
+                } => {
+                    self.handle_contact_request(peer, request, channel);
+                }
+                request_response::Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(sender) = self.pending_requests.remove(&request_id) {
+                        let ContactResponse::Ack { success } = response;
+                        let _ = sender.send(if success {
+                            NetworkResponse::MessageSent
+                        } else {
+                            NetworkResponse::Error("Contact request rejected by peer".to_string())
+                        });
+                    }
+                }
+            },
+            request_response::Event::OutboundFailure {
+                request_id, error, ..
+            } => {
+                warn!("Contact request failed: {:?}", error);
+                if let Some(sender) = self.pending_requests.remove(&request_id) {
+                    let _ = sender.send(NetworkResponse::Error(format!(
+                        "Request failed: {:?}",
+                        error
+                    )));
+                }
+            }
+            request_response::Event::InboundFailure { error, .. } => {
+                warn!("Contact inbound failure: {:?}", error);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Handles an inbound contact request.
+    ///
+    /// The request is only passed on if its contact card belongs to the peer
+    /// on the other end of the connection and carries a valid signature.
+    fn handle_contact_request(
+        &mut self,
+        peer: PeerId,
+        request: ContactRequest,
+        channel: ResponseChannel<ContactResponse>,
+    ) {
+        let card = request.card();
+        let success = if card.peer_id != peer {
+            warn!(
+                "Rejecting contact card for {} received from {}",
+                card.peer_id, peer
+            );
+            false
+        } else if let Err(e) = signing::verify_contact_card(card) {
+            warn!("Rejecting forged contact card from {}: {}", peer, e);
+            false
+        } else if let Some(ref contact_tx) = self.contact_event_tx {
+            info!("Received contact request from {}", peer);
+            match contact_tx.send(request) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("Failed to forward contact request: {}", e);
+                    false
+                }
+            }
+        } else {
+            false
+        };
+
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .contact
+            .send_response(channel, ContactResponse::Ack { success });
+    }
+}
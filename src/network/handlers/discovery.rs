@@ -1,11 +1,15 @@
 //! This module contains the handlers for discovery-related network events.
+use super::super::layer::RequestKind;
 use super::super::NetworkLayer;
 use crate::net::discovery::DiscoveryBehaviourEvent;
+use crate::net::prefer_quic;
 use crate::sync::{DhtQueryResult, SyncEvent};
 use anyhow::Result;
-use libp2p::kad;
-use std::collections::HashSet;
-use tracing::{debug, error, info, trace};
+use libp2p::kad::{self, store::RecordStore};
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, error, info, trace, warn};
 
 impl NetworkLayer {
     /// Handles an event from the `DiscoveryBehaviour`.
@@ -27,10 +31,12 @@ impl NetworkLayer {
         match event {
             DiscoveryBehaviourEvent::Mdns(mdns_event) => match mdns_event {
                 libp2p::mdns::Event::Discovered(list) => {
+                    // A peer is announced once per address, over TCP and QUIC.
+                    let mut discovered: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
                     for (peer_id, multiaddr) in list {
                         info!("Discovered peer via mDNS: {} at {}", peer_id, multiaddr);
 
-                        if self.blocked_peers.contains_key(&peer_id) {
+                        if self.is_peer_blocked(&peer_id).await {
                             debug!("Skipping mDNS discovery for blocked peer {}", peer_id);
                             continue;
                         }
@@ -39,8 +45,19 @@ impl NetworkLayer {
                             .behaviour_mut()
                             .discovery
                             .add_peer_address(peer_id, multiaddr.clone());
+                        discovered.entry(peer_id).or_default().push(multiaddr);
+                    }
+
+                    for (peer_id, mut addresses) in discovered {
+                        prefer_quic(&mut addresses);
+                        self.remember_peer_addresses(peer_id, addresses.clone())
+                            .await;
 
-                        if let Err(e) = self.swarm.dial(multiaddr) {
+                        let opts = DialOpts::peer_id(peer_id)
+                            .condition(PeerCondition::DisconnectedAndNotDialing)
+                            .addresses(addresses)
+                            .build();
+                        if let Err(e) = self.swarm.dial(opts) {
                             trace!(
                                 "Failed to proactively dial discovered peer {}: {}",
                                 peer_id,
@@ -118,12 +135,52 @@ impl NetworkLayer {
                 }
                 _ => {}
             },
-            kad::Event::RoutingUpdated { peer, .. } => {
+            kad::Event::RoutingUpdated {
+                peer, addresses, ..
+            } => {
                 trace!("Kademlia routing table updated for peer: {}", peer);
+                self.remember_peer_addresses(peer, addresses.into_vec())
+                    .await;
+            }
+            kad::Event::InboundRequest { request } => {
+                self.handle_kademlia_inbound_request(request);
             }
             _ => {}
         }
 
         Ok(())
     }
+
+    /// Stores the records and provider announcements sent by other peers,
+    /// unless the sender exceeds its rate limit.
+    fn handle_kademlia_inbound_request(&mut self, request: kad::InboundRequest) {
+        match request {
+            kad::InboundRequest::PutRecord {
+                source,
+                record: Some(record),
+                ..
+            } => {
+                if !self.allow_request(source, RequestKind::DhtWrite) {
+                    return;
+                }
+                let store = self.swarm.behaviour_mut().discovery.kademlia.store_mut();
+                if let Err(e) = store.put(record) {
+                    warn!("Failed to store DHT record from {}: {}", source, e);
+                }
+            }
+            kad::InboundRequest::AddProvider {
+                record: Some(record),
+            } => {
+                let provider = record.provider;
+                if !self.allow_request(provider, RequestKind::DhtWrite) {
+                    return;
+                }
+                let store = self.swarm.behaviour_mut().discovery.kademlia.store_mut();
+                if let Err(e) = store.add_provider(record) {
+                    warn!("Failed to store DHT provider {}: {}", provider, e);
+                }
+            }
+            _ => {}
+        }
+    }
 }
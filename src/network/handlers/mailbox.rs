@@ -1,11 +1,24 @@
 //! This module contains the handlers for mailbox-related network events.
+use super::super::layer::RequestKind;
 use super::super::{NetworkLayer, NetworkResponse};
-use crate::storage::MailboxStore;
+use crate::crypto::mailbox_auth::{self, MailboxGrant};
+use crate::net::mailbox::{fit_fetch_response, MAX_FETCH_MESSAGES};
+use crate::storage::{MailboxStore, SledMailboxStore};
 use crate::types::{MailboxRequest, MailboxResponse};
 use anyhow::Result;
 use libp2p::request_response::{self, OutboundRequestId, ResponseChannel};
+use libp2p::PeerId;
+use std::collections::HashMap;
 use tracing::{debug, error, info, warn};
 
+/// The most challenges a mailbox node keeps outstanding at once.
+const MAX_MAILBOX_GRANTS: usize = 4096;
+/// The most challenges a mailbox node keeps outstanding for a single peer,
+/// which only needs one for each of its current and recently replaced keys.
+const MAX_MAILBOX_GRANTS_PER_PEER: usize = 8;
+/// The most blocked senders a mailbox node stores per recipient.
+const MAX_BLOCKED_SENDERS: usize = 1024;
+
 impl NetworkLayer {
     /// Handles an event from the `MailboxBehaviour`.
     ///
@@ -24,11 +37,11 @@ impl NetworkLayer {
         event: request_response::Event<MailboxRequest, MailboxResponse>,
     ) -> Result<()> {
         match event {
-            request_response::Event::Message { message, .. } => match message {
+            request_response::Event::Message { peer, message } => match message {
                 request_response::Message::Request {
                     request, channel, ..
                 } => {
-                    self.handle_mailbox_request(request, channel).await?;
+                    self.handle_mailbox_request(peer, request, channel).await?;
                 }
                 request_response::Message::Response {
                     request_id,
@@ -58,8 +71,14 @@ impl NetworkLayer {
     }
 
     /// Handles an inbound mailbox request.
+    ///
+    /// `Fetch` and `Ack` requests are refused unless they carry the answer to a
+    /// challenge this node issued to the same peer for the same recipient.
+    /// `Put`, `Fetch` and `Ack` requests over the peer's rate limit are dropped
+    /// without a response.
     async fn handle_mailbox_request(
         &mut self,
+        peer: PeerId,
         request: MailboxRequest,
         channel: ResponseChannel<MailboxResponse>,
     ) -> Result<()> {
@@ -73,26 +92,84 @@ impl NetworkLayer {
                     message.sender
                 );
             }
-            MailboxRequest::Fetch { recipient, limit } => {
+            MailboxRequest::Challenge { recipient, .. } => {
+                debug!(
+                    "Network mailbox request: Challenge {{ recipient: {}, peer: {} }}",
+                    hex::encode(&recipient[..8]),
+                    peer
+                );
+            }
+            MailboxRequest::Fetch {
+                recipient, limit, ..
+            } => {
                 debug!(
                     "Network mailbox request: Fetch {{ recipient: {}, limit: {} }}",
                     hex::encode(&recipient[..8]),
                     limit
                 );
             }
-            MailboxRequest::Ack { recipient, msg_ids } => {
+            MailboxRequest::Ack {
+                recipient, msg_ids, ..
+            } => {
                 debug!(
                     "Network mailbox request: Ack {{ recipient: {}, msg_ids: {:?} }}",
                     hex::encode(&recipient[..8]),
                     msg_ids
                 );
             }
+            MailboxRequest::SetBlocked {
+                recipient, blocked, ..
+            } => {
+                debug!(
+                    "Network mailbox request: SetBlocked {{ recipient: {}, blocked: {} }}",
+                    hex::encode(&recipient[..8]),
+                    blocked.len()
+                );
+            }
+        }
+
+        if !self.allow_request(peer, RequestKind::Mailbox) {
+            return Ok(());
         }
 
-        let response = if let Some(ref storage) = self.mailbox_storage {
+        let authorized = match &request {
+            MailboxRequest::Fetch {
+                recipient, proof, ..
+            }
+            | MailboxRequest::Ack {
+                recipient, proof, ..
+            }
+            | MailboxRequest::SetBlocked {
+                recipient, proof, ..
+            } => self.check_mailbox_proof(peer, recipient, proof),
+            _ => true,
+        };
+
+        let response = if !authorized {
+            warn!(
+                "Refusing mailbox request from {}: ownership of the recipient key not proven",
+                peer
+            );
+            MailboxResponse::Unauthorized
+        } else if let Some(ref storage) = self.mailbox_storage {
             match request {
+                MailboxRequest::Challenge {
+                    recipient,
+                    public_key,
+                } => self.issue_mailbox_challenge(peer, recipient, &public_key),
+                MailboxRequest::Put { recipient, message }
+                    if is_sender_blocked(storage, recipient, &[peer, message.sender]).await =>
+                {
+                    info!(
+                        "Refusing message {} from {}: blocked by recipient {}",
+                        message.id,
+                        message.sender,
+                        hex::encode(&recipient[..8])
+                    );
+                    MailboxResponse::PutResult { success: false }
+                }
                 MailboxRequest::Put { recipient, message } => {
-                    match storage.store_message(recipient, message).await {
+                    match storage.store_message(recipient, *message).await {
                         Ok(()) => {
                             info!(
                                 "Successfully stored message in mailbox for recipient: {}",
@@ -120,54 +197,87 @@ impl NetworkLayer {
                         }
                     }
                 }
-                MailboxRequest::Fetch { recipient, limit } => {
-                    match storage.fetch_messages(recipient, limit).await {
-                        Ok(messages) => {
-                            info!(
-                                "Fetched {} messages for recipient: {}",
-                                messages.len(),
-                                hex::encode(&recipient[..8])
-                            );
-                            MailboxResponse::Messages { items: messages }
-                        }
-                        Err(e) => {
-                            error!("Failed to fetch mailbox messages: {}", e);
-                            MailboxResponse::Messages { items: vec![] }
-                        }
+                MailboxRequest::Fetch {
+                    recipient, limit, ..
+                } => match storage
+                    .fetch_messages(recipient, limit.min(MAX_FETCH_MESSAGES))
+                    .await
+                    .and_then(|messages| Ok(fit_fetch_response(messages)?))
+                {
+                    Ok(messages) => {
+                        info!(
+                            "Fetched {} messages for recipient: {}",
+                            messages.len(),
+                            hex::encode(&recipient[..8])
+                        );
+                        MailboxResponse::Messages { items: messages }
                     }
-                }
-                MailboxRequest::Ack { recipient, msg_ids } => {
-                    match storage.delete_messages(recipient, msg_ids).await {
-                        Ok(deleted) => {
-                            info!(
-                                "Deleted {} messages for recipient: {}",
-                                deleted,
-                                hex::encode(&recipient[..8])
-                            );
+                    Err(e) => {
+                        error!("Failed to fetch mailbox messages: {}", e);
+                        MailboxResponse::Messages { items: vec![] }
+                    }
+                },
+                MailboxRequest::Ack {
+                    recipient, msg_ids, ..
+                } => match storage.delete_messages(recipient, msg_ids).await {
+                    Ok(deleted) => {
+                        info!(
+                            "Deleted {} messages for recipient: {}",
+                            deleted,
+                            hex::encode(&recipient[..8])
+                        );
 
-                            match storage.fetch_messages(recipient, 1).await {
-                                Ok(remaining_messages) if remaining_messages.is_empty() => {
-                                    debug!(
+                        match storage.fetch_messages(recipient, 1).await {
+                            Ok(remaining_messages) if remaining_messages.is_empty() => {
+                                debug!(
                                         "No more messages for recipient {}, could stop DHT announcement",
                                         hex::encode(&recipient[..8])
                                     );
-                                }
-                                Ok(_) => {
-                                    debug!(
+                            }
+                            Ok(_) => {
+                                debug!(
                                         "Still have messages for recipient {}, keeping DHT announcement",
                                         hex::encode(&recipient[..8])
                                     );
-                                }
-                                Err(e) => {
-                                    debug!("Failed to check remaining messages for cleanup: {}", e);
-                                }
                             }
+                            Err(e) => {
+                                debug!("Failed to check remaining messages for cleanup: {}", e);
+                            }
+                        }
 
-                            MailboxResponse::AckResult { deleted }
+                        MailboxResponse::AckResult { deleted }
+                    }
+                    Err(e) => {
+                        error!("Failed to delete mailbox messages: {}", e);
+                        MailboxResponse::AckResult { deleted: 0 }
+                    }
+                },
+                MailboxRequest::SetBlocked {
+                    recipient,
+                    mut blocked,
+                    ..
+                } => {
+                    if blocked.len() > MAX_BLOCKED_SENDERS {
+                        warn!(
+                            "Truncating block list of {} senders from {}",
+                            blocked.len(),
+                            peer
+                        );
+                        blocked.truncate(MAX_BLOCKED_SENDERS);
+                    }
+                    let count = blocked.len();
+                    match storage.set_blocked_senders(recipient, blocked).await {
+                        Ok(()) => {
+                            info!(
+                                "Stored {} blocked senders for recipient: {}",
+                                count,
+                                hex::encode(&recipient[..8])
+                            );
+                            MailboxResponse::BlockedSet { count }
                         }
                         Err(e) => {
-                            error!("Failed to delete mailbox messages: {}", e);
-                            MailboxResponse::AckResult { deleted: 0 }
+                            error!("Failed to store blocked senders: {}", e);
+                            MailboxResponse::BlockedSet { count: 0 }
                         }
                     }
                 }
@@ -176,8 +286,10 @@ impl NetworkLayer {
             debug!("No mailbox storage available, returning default responses");
             match request {
                 MailboxRequest::Put { .. } => MailboxResponse::PutResult { success: false },
+                MailboxRequest::Challenge { .. } => MailboxResponse::Unauthorized,
                 MailboxRequest::Fetch { .. } => MailboxResponse::Messages { items: vec![] },
                 MailboxRequest::Ack { .. } => MailboxResponse::AckResult { deleted: 0 },
+                MailboxRequest::SetBlocked { .. } => MailboxResponse::Unauthorized,
             }
         };
 
@@ -189,6 +301,40 @@ impl NetworkLayer {
         Ok(())
     }
 
+    /// Issues a challenge for a peer that wants to access a recipient's messages.
+    ///
+    /// The grant replaces any earlier one for the same peer and recipient, and
+    /// the peer's oldest grant once it holds `MAX_MAILBOX_GRANTS_PER_PEER`.
+    fn issue_mailbox_challenge(
+        &mut self,
+        peer: PeerId,
+        recipient: [u8; 32],
+        public_key: &[u8],
+    ) -> MailboxResponse {
+        if !make_room_for_grant(&mut self.mailbox_grants, peer, recipient) {
+            warn!("Too many outstanding mailbox challenges, refusing {}", peer);
+            return MailboxResponse::Unauthorized;
+        }
+
+        match mailbox_auth::issue_challenge(&recipient, public_key, &peer) {
+            Ok((challenge, grant)) => {
+                self.mailbox_grants.insert((peer, recipient), grant);
+                MailboxResponse::Challenge(challenge)
+            }
+            Err(e) => {
+                warn!("Refusing mailbox challenge for {}: {}", peer, e);
+                MailboxResponse::Unauthorized
+            }
+        }
+    }
+
+    /// Checks a proof presented by a peer for a recipient.
+    fn check_mailbox_proof(&self, peer: PeerId, recipient: &[u8; 32], proof: &[u8; 32]) -> bool {
+        self.mailbox_grants
+            .get(&(peer, *recipient))
+            .is_some_and(|grant| grant.accepts(proof))
+    }
+
     /// Handles an outbound mailbox response.
     async fn handle_mailbox_response(
         &mut self,
@@ -206,9 +352,148 @@ impl NetworkLayer {
                 MailboxResponse::AckResult { deleted } => {
                     let _ = sender.send(NetworkResponse::MailboxAckResult { deleted });
                 }
+                MailboxResponse::Challenge(challenge) => {
+                    let _ = sender.send(NetworkResponse::MailboxChallenge { challenge });
+                }
+                MailboxResponse::BlockedSet { count } => {
+                    let _ = sender.send(NetworkResponse::MailboxBlockedSet { count });
+                }
+                MailboxResponse::Unauthorized => {
+                    let _ = sender.send(NetworkResponse::Error(
+                        "Mailbox refused the request: key ownership not proven".to_string(),
+                    ));
+                }
             }
         }
 
         Ok(())
     }
 }
+
+/// Checks whether a recipient has blocked any of the given senders.
+///
+/// Both the peer delivering a message and the sender it names are checked.
+/// Errors are logged and treated as not blocked, so a storage problem does
+/// not lose messages.
+async fn is_sender_blocked(
+    storage: &SledMailboxStore,
+    recipient: [u8; 32],
+    senders: &[PeerId],
+) -> bool {
+    for sender in senders {
+        match storage.is_sender_blocked(recipient, sender).await {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(e) => warn!("Failed to check the block list: {}", e),
+        }
+    }
+    false
+}
+
+/// Drops expired grants and makes room for a new grant for a peer and
+/// recipient, evicting the peer's oldest grant if it holds too many.
+///
+/// Returns `false` if all peers together hold too many grants.
+fn make_room_for_grant(
+    grants: &mut HashMap<(PeerId, [u8; 32]), MailboxGrant>,
+    peer: PeerId,
+    recipient: [u8; 32],
+) -> bool {
+    grants.retain(|_, grant| !grant.is_expired());
+
+    let peer_grants = grants
+        .iter()
+        .filter(|((holder, held), _)| *holder == peer && *held != recipient);
+    if peer_grants.clone().count() >= MAX_MAILBOX_GRANTS_PER_PEER {
+        if let Some(oldest) = peer_grants
+            .min_by_key(|(_, grant)| grant.expires_at())
+            .map(|(key, _)| *key)
+        {
+            debug!("Evicting the oldest mailbox challenge of {}", peer);
+            grants.remove(&oldest);
+        }
+    }
+
+    if grants.len() >= MAX_MAILBOX_GRANTS && !grants.contains_key(&(peer, recipient)) {
+        warn!("Too many outstanding mailbox challenges, refusing {}", peer);
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{HpkeContext, StorageEncryption};
+
+    /// Issues a grant to `peer` for a fresh key and returns its recipient hash.
+    fn grant_for(grants: &mut HashMap<(PeerId, [u8; 32]), MailboxGrant>, peer: PeerId) -> [u8; 32] {
+        let public_key = HpkeContext::new().unwrap().public_key_bytes();
+        let recipient = StorageEncryption::derive_recipient_hash(&public_key);
+        assert!(make_room_for_grant(grants, peer, recipient));
+        let (_, grant) = mailbox_auth::issue_challenge(&recipient, &public_key, &peer).unwrap();
+        grants.insert((peer, recipient), grant);
+        recipient
+    }
+
+    #[test]
+    fn a_peer_evicts_its_own_oldest_grant() {
+        let mut grants = HashMap::new();
+        let other = PeerId::random();
+        let other_recipient = grant_for(&mut grants, other);
+
+        let peer = PeerId::random();
+        let first = grant_for(&mut grants, peer);
+        for _ in 0..MAX_MAILBOX_GRANTS_PER_PEER * 2 {
+            grant_for(&mut grants, peer);
+        }
+
+        let held = grants.keys().filter(|(holder, _)| *holder == peer).count();
+        assert_eq!(held, MAX_MAILBOX_GRANTS_PER_PEER);
+        assert!(!grants.contains_key(&(peer, first)));
+        assert!(grants.contains_key(&(other, other_recipient)));
+    }
+
+    #[test]
+    fn the_total_number_of_grants_is_capped() {
+        let mut grants = HashMap::new();
+        while grants.len() < MAX_MAILBOX_GRANTS {
+            grant_for(&mut grants, PeerId::random());
+        }
+        assert!(!make_room_for_grant(
+            &mut grants,
+            PeerId::random(),
+            [1u8; 32]
+        ));
+
+        // Renewing a grant a peer already holds does not add one.
+        let (&(peer, recipient), _) = grants.iter().next().unwrap();
+        assert!(make_room_for_grant(&mut grants, peer, recipient));
+    }
+
+    #[tokio::test]
+    async fn blocked_senders_are_refused_per_recipient() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let storage = SledMailboxStore::new(db, None, 10).unwrap();
+        let (blocked, relay, stranger) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let recipient = [1u8; 32];
+        storage
+            .set_blocked_senders(recipient, vec![blocked])
+            .await
+            .unwrap();
+
+        // A blocked sender is refused whether it delivers or is relayed.
+        assert!(is_sender_blocked(&storage, recipient, &[blocked, stranger]).await);
+        assert!(is_sender_blocked(&storage, recipient, &[relay, blocked]).await);
+        assert!(!is_sender_blocked(&storage, recipient, &[relay, stranger]).await);
+        assert!(!is_sender_blocked(&storage, [2u8; 32], &[blocked]).await);
+
+        // A new list replaces the old one.
+        storage
+            .set_blocked_senders(recipient, vec![stranger])
+            .await
+            .unwrap();
+        assert!(!is_sender_blocked(&storage, recipient, &[blocked]).await);
+        assert!(is_sender_blocked(&storage, recipient, &[stranger]).await);
+    }
+}
@@ -2,7 +2,10 @@
 //!
 //! The individual modules extend the `NetworkLayer` implementation with
 //! specialized handlers for each of the behaviours.
+mod attachment;
 mod chat;
+mod contact;
 mod discovery;
 mod mailbox;
+mod nat;
 mod swarm;
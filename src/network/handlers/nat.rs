@@ -0,0 +1,98 @@
+//! This module contains the handlers for NAT traversal events.
+use super::super::NetworkLayer;
+use crate::net::nat::NatBehaviourEvent;
+use libp2p::multiaddr::Protocol;
+use libp2p::{autonat, dcutr, identify, relay};
+use tracing::{debug, info, trace, warn};
+
+impl NetworkLayer {
+    /// Handles an event from the `NatBehaviour`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The `NatBehaviourEvent` to handle.
+    pub(super) fn handle_nat_event(&mut self, event: NatBehaviourEvent) {
+        match event {
+            NatBehaviourEvent::Identify(identify_event) => {
+                self.handle_identify_event(identify_event);
+            }
+            NatBehaviourEvent::Autonat(autonat::Event::StatusChanged { old, new }) => {
+                info!("Reachability changed from {:?} to {:?}", old, new);
+                let was_public = old.is_public();
+                let is_public = new.is_public();
+                self.nat.status = new;
+
+                if is_public && !was_public {
+                    self.release_relays();
+                } else if !is_public {
+                    self.reserve_relays();
+                }
+            }
+            NatBehaviourEvent::Autonat(event) => {
+                trace!("AutoNAT event: {:?}", event);
+            }
+            NatBehaviourEvent::RelayClient(relay::client::Event::ReservationReqAccepted {
+                relay_peer_id,
+                renewal,
+                ..
+            }) => {
+                if !renewal {
+                    info!("Relay {} accepted our reservation", relay_peer_id);
+                }
+            }
+            NatBehaviourEvent::RelayClient(event) => {
+                debug!("Relay client event: {:?}", event);
+            }
+            NatBehaviourEvent::RelayServer(event) => {
+                trace!("Relay server event: {:?}", event);
+            }
+            NatBehaviourEvent::Dcutr(dcutr::Event {
+                remote_peer_id,
+                result,
+            }) => match result {
+                Ok(_) => {
+                    info!(
+                        "Upgraded relayed connection to {} to a direct one",
+                        remote_peer_id
+                    );
+                    self.nat.hole_punches_succeeded += 1;
+                }
+                Err(e) => {
+                    warn!("Hole punching to {} failed: {}", remote_peer_id, e);
+                    self.nat.hole_punches_failed += 1;
+                }
+            },
+        }
+    }
+
+    /// Handles an identify event.
+    ///
+    /// The addresses a peer listens on are added to Kademlia, and peers that
+    /// offer the relay protocol are remembered as relays.
+    fn handle_identify_event(&mut self, event: identify::Event) {
+        let identify::Event::Received { peer_id, info } = event else {
+            return;
+        };
+        if self.blocked_peers.contains_key(&peer_id) {
+            return;
+        }
+        trace!("Identified peer {} ({})", peer_id, info.agent_version);
+
+        for address in &info.listen_addrs {
+            self.swarm
+                .behaviour_mut()
+                .discovery
+                .add_peer_address(peer_id, address.clone());
+        }
+
+        if info.protocols.contains(&relay::HOP_PROTOCOL_NAME) {
+            let direct_address = info
+                .listen_addrs
+                .into_iter()
+                .find(|address| !address.iter().any(|p| p == Protocol::P2pCircuit));
+            if let Some(address) = direct_address {
+                self.add_relay_candidate(peer_id, address);
+            }
+        }
+    }
+}
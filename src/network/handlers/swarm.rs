@@ -3,9 +3,9 @@ use super::super::{NetworkLayer, P2PBehaviourEvent};
 use crate::sync::SyncEvent;
 use crate::types::Message;
 use anyhow::Result;
-use libp2p::swarm::SwarmEvent;
+use libp2p::swarm::{ListenError, SwarmEvent};
 use tokio::sync::mpsc;
-use tracing::{info, trace, warn};
+use tracing::{debug, info, trace, warn};
 
 impl NetworkLayer {
     /// Handles a `SwarmEvent`.
@@ -36,6 +36,14 @@ impl NetworkLayer {
                     .await?;
             }
 
+            SwarmEvent::Behaviour(P2PBehaviourEvent::Contact(contact_event)) => {
+                self.handle_contact_event(contact_event).await?;
+            }
+
+            SwarmEvent::Behaviour(P2PBehaviourEvent::Attachment(attachment_event)) => {
+                self.handle_attachment_event(attachment_event);
+            }
+
             SwarmEvent::Behaviour(P2PBehaviourEvent::Mailbox(mailbox_event)) => {
                 self.handle_mailbox_event(mailbox_event).await?;
             }
@@ -44,6 +52,10 @@ impl NetworkLayer {
                 self.handle_discovery_event(discovery_event).await?;
             }
 
+            SwarmEvent::Behaviour(P2PBehaviourEvent::Nat(nat_event)) => {
+                self.handle_nat_event(nat_event);
+            }
+
             SwarmEvent::Behaviour(P2PBehaviourEvent::Ping(ping_event)) => {
                 let libp2p::ping::Event { peer, result, .. } = ping_event;
                 match result {
@@ -56,8 +68,18 @@ impl NetworkLayer {
                 }
             }
 
-            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+            SwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => {
                 info!("Connection established with peer: {}", peer_id);
+                // Only addresses we dialed are known to accept connections.
+                if endpoint.is_dialer() {
+                    self.remember_peer_addresses(
+                        peer_id,
+                        vec![endpoint.get_remote_address().clone()],
+                    )
+                    .await;
+                }
                 if let Some(ref sync_tx) = self.sync_event_tx {
                     let _ = sync_tx.send(SyncEvent::PeerConnected(peer_id));
                 }
@@ -73,6 +95,10 @@ impl NetworkLayer {
                 }
             }
 
+            SwarmEvent::ListenerClosed { listener_id, .. } => {
+                self.forget_relay_listener(listener_id);
+            }
+
             SwarmEvent::IncomingConnection { .. } => {
                 trace!("Incoming connection");
             }
@@ -87,6 +113,15 @@ impl NetworkLayer {
                 }
             }
 
+            SwarmEvent::IncomingConnectionError {
+                error: ListenError::Denied { cause },
+                send_back_addr,
+                ..
+            } => {
+                debug!("Refused connection from {}: {}", send_back_addr, cause);
+                self.rate_limiter.counters.connections_denied += 1;
+            }
+
             SwarmEvent::IncomingConnectionError { error, .. } => {
                 warn!("Incoming connection error: {}", error);
             }
@@ -0,0 +1,102 @@
+//! This module connects the `NetworkLayer` to the persisted peer address book.
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::{Multiaddr, PeerId};
+use tracing::{debug, info, trace, warn};
+
+use crate::net::prefer_quic;
+
+use super::NetworkLayer;
+
+impl NetworkLayer {
+    /// Adds the addresses of all known peers to Kademlia and bootstraps the DHT.
+    ///
+    /// This lets the node rejoin the network after a restart, even if no
+    /// peer is in mDNS range.
+    pub(crate) async fn seed_from_address_book(&mut self) {
+        let peers = match self.address_book.list_peers().await {
+            Ok(peers) => peers,
+            Err(e) => {
+                warn!("Failed to load the peer address book: {}", e);
+                return;
+            }
+        };
+        if peers.is_empty() {
+            return;
+        }
+
+        let mut seeded = 0;
+        for peer in peers {
+            if peer.peer_id == *self.swarm.local_peer_id() {
+                continue;
+            }
+            for address in peer.addresses {
+                self.swarm
+                    .behaviour_mut()
+                    .discovery
+                    .add_peer_address(peer.peer_id, address);
+            }
+            seeded += 1;
+        }
+
+        info!(
+            "Seeded Kademlia with {} peers from the address book",
+            seeded
+        );
+        if let Err(e) = self.bootstrap_dht() {
+            warn!("DHT bootstrap from the address book failed: {}", e);
+        }
+    }
+
+    /// Records addresses at which a peer was reachable.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer.
+    /// * `addresses` - The addresses of the peer.
+    pub(crate) async fn remember_peer_addresses(
+        &mut self,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+    ) {
+        if self.blocked_peers.contains_key(&peer_id) {
+            return;
+        }
+
+        if let Err(e) = self.address_book.add_addresses(&peer_id, addresses).await {
+            debug!("Failed to record addresses of {}: {}", peer_id, e);
+        }
+    }
+
+    /// Dials a disconnected peer at its addresses from the address book.
+    ///
+    /// Requests to the peer wait for this connection, so a mailbox that has
+    /// dropped out of the routing table can still be reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer to dial.
+    pub(crate) async fn dial_known_peer(&mut self, peer_id: PeerId) {
+        if self.swarm.is_connected(&peer_id) {
+            return;
+        }
+
+        let mut addresses = match self.address_book.get_peer(&peer_id).await {
+            Ok(Some(peer)) => peer.addresses,
+            Ok(None) => return,
+            Err(e) => {
+                debug!("Failed to look up addresses of {}: {}", peer_id, e);
+                return;
+            }
+        };
+        prefer_quic(&mut addresses);
+
+        let opts = DialOpts::peer_id(peer_id)
+            .condition(PeerCondition::DisconnectedAndNotDialing)
+            .addresses(addresses)
+            .extend_addresses_through_behaviour()
+            .build();
+        if let Err(e) = self.swarm.dial(opts) {
+            trace!("Failed to redial {} from the address book: {}", peer_id, e);
+        }
+    }
+}
@@ -1,21 +1,20 @@
 //! This module contains the builder logic for the `NetworkLayer`.
-use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use libp2p::{ping, swarm::Swarm, Multiaddr};
+use libp2p::{connection_limits, ping, relay, swarm::Swarm, Multiaddr};
 use tokio::sync::mpsc;
 use tracing::{info, warn};
 
 use crate::crypto::Identity;
-use crate::net::{build_transport, DiscoveryBehaviour};
-use crate::storage::SledMailboxStore;
+use crate::net::{build_transport, ConnectionGuard, DiscoveryBehaviour, NatBehaviour};
+use crate::storage::{PeerAddressStore, SledMailboxStore, SledRecordStore};
 
 use super::super::behaviour::P2PBehaviour;
 use super::super::handle::NetworkHandle;
 use super::super::message::NetworkCommand;
-use super::NetworkLayer;
+use super::{NetworkLayer, RateLimits};
 
 impl NetworkLayer {
     /// Creates a new `NetworkLayer` and `NetworkHandle`.
@@ -23,20 +22,32 @@ impl NetworkLayer {
     /// # Arguments
     ///
     /// * `identity` - The identity of the local node.
-    /// * `listen_addr` - The address to listen on for incoming connections.
+    /// * `listen_addrs` - The addresses to listen on for incoming connections.
     /// * `is_mailbox` - Whether the node is a mailbox node.
     /// * `bootstrap_nodes` - A list of bootstrap nodes to connect to.
+    /// * `record_store` - The persistent store for Kademlia records.
+    /// * `address_book` - The persisted addresses of known peers.
     ///
     /// # Errors
     ///
     /// This function will return an error if the network layer cannot be created.
     pub fn new(
         identity: Arc<Identity>,
-        listen_addr: Multiaddr,
+        listen_addrs: Vec<Multiaddr>,
         is_mailbox: bool,
-        bootstrap_nodes: Vec<&str>,
+        bootstrap_nodes: Vec<Multiaddr>,
+        record_store: SledRecordStore,
+        address_book: Arc<dyn PeerAddressStore + Send + Sync>,
     ) -> Result<(Self, NetworkHandle)> {
-        Self::new_with_mailbox_storage(identity, listen_addr, is_mailbox, None, bootstrap_nodes)
+        Self::new_with_mailbox_storage(
+            identity,
+            listen_addrs,
+            is_mailbox,
+            None,
+            bootstrap_nodes,
+            record_store,
+            address_book,
+        )
     }
 
     /// Creates a new `NetworkLayer` and `NetworkHandle` with optional mailbox storage.
@@ -44,56 +55,60 @@ impl NetworkLayer {
     /// # Arguments
     ///
     /// * `identity` - The identity of the local node.
-    /// * `listen_addr` - The address to listen on for incoming connections.
+    /// * `listen_addrs` - The addresses to listen on for incoming connections.
     /// * `is_mailbox` - Whether the node is a mailbox node.
     /// * `mailbox_storage` - The storage for the mailbox, if this is a mailbox node.
     /// * `bootstrap_nodes` - A list of bootstrap nodes to connect to.
+    /// * `record_store` - The persistent store for Kademlia records.
+    /// * `address_book` - The persisted addresses of known peers.
     ///
     /// # Errors
     ///
     /// This function will return an error if the network layer cannot be created.
     pub fn new_with_mailbox_storage(
         identity: Arc<Identity>,
-        listen_addr: Multiaddr,
+        listen_addrs: Vec<Multiaddr>,
         is_mailbox: bool,
         mailbox_storage: Option<Arc<SledMailboxStore>>,
-        bootstrap_nodes: Vec<&str>,
+        bootstrap_nodes: Vec<Multiaddr>,
+        record_store: SledRecordStore,
+        address_book: Arc<dyn PeerAddressStore + Send + Sync>,
     ) -> Result<(Self, NetworkHandle)> {
         let keypair = identity.libp2p_keypair.clone();
         let peer_id = identity.peer_id;
 
-        let transport = build_transport(&keypair)?;
+        let (relay_transport, relay_client) = relay::client::new(peer_id);
+        let transport = build_transport(&keypair, relay_transport)?;
 
         let ping_config = ping::Config::new()
             .with_interval(Duration::from_secs(30))
             .with_timeout(Duration::from_secs(10));
 
+        let limits = RateLimits::default();
         let mut behaviour = P2PBehaviour {
+            connection_limits: connection_limits::Behaviour::new(limits.connection_limits()),
+            guard: ConnectionGuard::new(Some(limits.max_connections_per_ip)),
             chat: crate::net::chat::create_chat_behaviour(),
+            contact: crate::net::contact::create_contact_behaviour(),
+            attachment: crate::net::attachment::create_attachment_behaviour(),
             mailbox: crate::net::mailbox::create_mailbox_behaviour(),
-            discovery: DiscoveryBehaviour::new(peer_id)?,
+            discovery: DiscoveryBehaviour::new(peer_id, record_store)?,
+            nat: NatBehaviour::new(&keypair, relay_client, is_mailbox),
             ping: ping::Behaviour::new(ping_config),
         };
 
-        for node in bootstrap_nodes {
-            match Multiaddr::from_str(node) {
-                Ok(addr) => {
-                    if let Some(peer_id) = addr.iter().find_map(|p| {
-                        if let libp2p::multiaddr::Protocol::P2p(peer_id) = p {
-                            Some(peer_id)
-                        } else {
-                            None
-                        }
-                    }) {
-                        info!("Adding bootstrap node: {} -> {}", peer_id, addr);
-                        behaviour.discovery.kademlia.add_address(&peer_id, addr);
-                    } else {
-                        warn!("Bootstrap address did not contain a PeerId: {}", node);
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to parse bootstrap address '{}': {}", node, e);
+        for addr in bootstrap_nodes {
+            if let Some(peer_id) = addr.iter().find_map(|p| {
+                if let libp2p::multiaddr::Protocol::P2p(peer_id) = p {
+                    Some(peer_id)
+                } else {
+                    None
                 }
+            }) {
+                info!("Adding bootstrap node: {} -> {}", peer_id, addr);
+                behaviour.discovery.kademlia.add_address(&peer_id, addr);
+            } else {
+                warn!("Bootstrap address did not contain a PeerId: {}", addr);
             }
         }
 
@@ -101,7 +116,9 @@ impl NetworkLayer {
             .with_idle_connection_timeout(Duration::from_secs(60 * 60));
 
         let mut swarm = Swarm::new(transport, behaviour, peer_id, swarm_config);
-        swarm.listen_on(listen_addr)?;
+        for listen_addr in listen_addrs {
+            swarm.listen_on(listen_addr)?;
+        }
 
         if let Err(e) = swarm.behaviour_mut().discovery.bootstrap() {
             warn!("Initial DHT bootstrap failed: {}", e);
@@ -115,8 +132,15 @@ impl NetworkLayer {
             pending_requests: Default::default(),
             sync_event_tx: None,
             ui_notify_tx: None,
+            contact_event_tx: None,
+            attachment_store: None,
             mailbox_storage,
+            mailbox_grants: Default::default(),
+            address_book,
+            nat: Default::default(),
+            rate_limiter: Default::default(),
             blocked_peers: Default::default(),
+            block_list: None,
         };
 
         let handle = NetworkHandle { command_sender };
@@ -0,0 +1,308 @@
+//! This module enforces the request rate limits of the `NetworkLayer` and
+//! blocks peers that keep exceeding them.
+//!
+//! Every peer gets a token bucket per kind of request. A bucket holds up to
+//! a minute's worth of requests and refills continuously, so short bursts are
+//! accepted while a sustained flood is not. Requests over the limit are
+//! dropped without a response.
+//!
+//! Only DHT writes are limited: Kademlia does not report which peer sent a
+//! read query, so reads cannot be attributed to a bucket.
+//!
+//! Peers on the user's block list are refused like temporarily blocked ones,
+//! but never expire.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::connection_limits::ConnectionLimits;
+use libp2p::PeerId;
+use tracing::{info, warn};
+
+use super::NetworkLayer;
+
+/// How long violations are remembered when counting towards an automatic block.
+const VIOLATION_WINDOW: Duration = Duration::from_secs(60);
+/// How long an idle, full bucket is kept before it is dropped.
+const BUCKET_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// The request and connection limits of the network layer.
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    /// The chat requests accepted per peer and minute.
+    pub chat_requests_per_minute: u32,
+    /// The mailbox `Put`, `Fetch` and `Ack` requests accepted per peer and minute.
+    pub mailbox_requests_per_minute: u32,
+    /// The DHT records and provider announcements accepted per peer and minute.
+    pub dht_writes_per_minute: u32,
+    /// The most established connections in total.
+    pub max_connections: u32,
+    /// The most established connections to a single peer.
+    pub max_connections_per_peer: u32,
+    /// The most established inbound connections from a single IP address.
+    pub max_connections_per_ip: u32,
+    /// The violations within a minute after which a peer is blocked, or `0` to never block.
+    pub auto_block_after: u32,
+    /// How long a peer stays blocked.
+    pub block_duration: Duration,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            chat_requests_per_minute: 120,
+            mailbox_requests_per_minute: 60,
+            dht_writes_per_minute: 60,
+            max_connections: 256,
+            max_connections_per_peer: 4,
+            max_connections_per_ip: 16,
+            auto_block_after: 20,
+            block_duration: Duration::from_secs(600),
+        }
+    }
+}
+
+impl RateLimits {
+    /// Returns the limits enforced by `libp2p::connection_limits`.
+    pub(crate) fn connection_limits(&self) -> ConnectionLimits {
+        ConnectionLimits::default()
+            .with_max_established(Some(self.max_connections))
+            .with_max_established_per_peer(Some(self.max_connections_per_peer))
+    }
+}
+
+/// Counters of the requests and connections refused by the limits.
+#[derive(Debug, Clone, Default)]
+pub struct LimitCounters {
+    /// The chat requests dropped for exceeding the rate limit.
+    pub chat_requests_limited: u64,
+    /// The mailbox requests dropped for exceeding the rate limit.
+    pub mailbox_requests_limited: u64,
+    /// The DHT writes dropped for exceeding the rate limit.
+    pub dht_writes_limited: u64,
+    /// The inbound connections refused by a connection limit or block.
+    pub connections_denied: u64,
+    /// The peers blocked for repeatedly exceeding the limits.
+    pub peers_blocked: u64,
+    /// The peers that are blocked at the moment.
+    pub currently_blocked: usize,
+}
+
+/// A kind of request with its own rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum RequestKind {
+    /// A chat request.
+    Chat,
+    /// A mailbox `Put`, `Fetch` or `Ack` request.
+    Mailbox,
+    /// A DHT record or provider announcement.
+    DhtWrite,
+}
+
+/// A token bucket that refills continuously.
+#[derive(Debug)]
+struct TokenBucket {
+    /// The requests that may be made right now.
+    tokens: f64,
+    /// When the bucket was last refilled.
+    last_refill: Instant,
+}
+
+/// The rate limiting state of the `NetworkLayer`.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// The configured limits.
+    pub(crate) limits: RateLimits,
+    /// The token buckets, by peer and kind of request.
+    buckets: HashMap<(PeerId, RequestKind), TokenBucket>,
+    /// The recent violations of each peer, with the time of the first one.
+    violations: HashMap<PeerId, (u32, Instant)>,
+    /// The counters exposed for monitoring.
+    pub(crate) counters: LimitCounters,
+}
+
+impl RateLimiter {
+    /// Returns the number of requests of a kind accepted per minute.
+    fn per_minute(&self, kind: RequestKind) -> u32 {
+        match kind {
+            RequestKind::Chat => self.limits.chat_requests_per_minute,
+            RequestKind::Mailbox => self.limits.mailbox_requests_per_minute,
+            RequestKind::DhtWrite => self.limits.dht_writes_per_minute,
+        }
+    }
+
+    /// Takes a token from a peer's bucket.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the request is within the limit.
+    fn try_acquire(&mut self, peer_id: PeerId, kind: RequestKind) -> bool {
+        let capacity = f64::from(self.per_minute(kind));
+        let now = Instant::now();
+        let bucket = self.buckets.entry((peer_id, kind)).or_insert(TokenBucket {
+            tokens: capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * capacity / 60.0).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records a request over the limit.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the peer has now exceeded the limits often enough to be blocked.
+    fn record_violation(&mut self, peer_id: PeerId, kind: RequestKind) -> bool {
+        match kind {
+            RequestKind::Chat => self.counters.chat_requests_limited += 1,
+            RequestKind::Mailbox => self.counters.mailbox_requests_limited += 1,
+            RequestKind::DhtWrite => self.counters.dht_writes_limited += 1,
+        }
+
+        let now = Instant::now();
+        let (count, since) = self.violations.entry(peer_id).or_insert((0, now));
+        if now.duration_since(*since) > VIOLATION_WINDOW {
+            *count = 0;
+            *since = now;
+        }
+        *count += 1;
+
+        self.limits.auto_block_after > 0 && *count >= self.limits.auto_block_after
+    }
+
+    /// Drops the state of a peer, once it has been blocked.
+    fn forget_peer(&mut self, peer_id: &PeerId) {
+        self.buckets.retain(|(id, _), _| id != peer_id);
+        self.violations.remove(peer_id);
+    }
+
+    /// Drops buckets and violations that no longer affect any decision.
+    fn prune(&mut self) {
+        self.buckets
+            .retain(|_, bucket| bucket.last_refill.elapsed() < BUCKET_IDLE_TIMEOUT);
+        self.violations
+            .retain(|_, (_, since)| since.elapsed() <= VIOLATION_WINDOW);
+    }
+}
+
+impl NetworkLayer {
+    /// Replaces the request and connection limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - The new limits.
+    pub fn set_rate_limits(&mut self, limits: RateLimits) {
+        let behaviour = self.swarm.behaviour_mut();
+        *behaviour.connection_limits.limits_mut() = limits.connection_limits();
+        behaviour
+            .guard
+            .set_max_per_ip(Some(limits.max_connections_per_ip));
+        self.rate_limiter.limits = limits;
+    }
+
+    /// Returns the counters of the refused requests and connections.
+    pub(crate) fn limit_counters(&self) -> LimitCounters {
+        LimitCounters {
+            currently_blocked: self.blocked_peers.len(),
+            ..self.rate_limiter.counters.clone()
+        }
+    }
+
+    /// Checks whether a peer is blocked, temporarily or by the user.
+    ///
+    /// A block list that cannot be read is logged and treated as empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer to check.
+    pub(crate) async fn is_peer_blocked(&mut self, peer_id: &PeerId) -> bool {
+        if self.blocked_peers.contains_key(peer_id) {
+            return true;
+        }
+        let Some(block_list) = self.block_list.clone() else {
+            return false;
+        };
+        match block_list.is_blocked(peer_id).await {
+            Ok(blocked) => blocked,
+            Err(e) => {
+                warn!("Failed to read the block list: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Checks whether an inbound request from a peer may be served.
+    ///
+    /// Requests from blocked peers are always refused. A peer that keeps
+    /// exceeding its limits is blocked.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the requesting peer.
+    /// * `kind` - The kind of request.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the request is within the limits.
+    pub(crate) fn allow_request(&mut self, peer_id: PeerId, kind: RequestKind) -> bool {
+        if self.blocked_peers.contains_key(&peer_id) {
+            return false;
+        }
+        if self.rate_limiter.try_acquire(peer_id, kind) {
+            return true;
+        }
+
+        warn!(
+            "Dropping {:?} request from {}: rate limit exceeded",
+            kind, peer_id
+        );
+        if self.rate_limiter.record_violation(peer_id, kind) {
+            self.block_peer(peer_id);
+        }
+        false
+    }
+
+    /// Blocks a peer for the configured duration and closes its connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the peer to block.
+    pub(crate) fn block_peer(&mut self, peer_id: PeerId) {
+        info!(
+            "Blocking peer {} for {:?} after repeated limit violations",
+            peer_id, self.rate_limiter.limits.block_duration
+        );
+        self.blocked_peers.insert(peer_id, Instant::now());
+        self.rate_limiter.forget_peer(&peer_id);
+        self.rate_limiter.counters.peers_blocked += 1;
+        self.swarm.behaviour_mut().guard.block(peer_id);
+        let _ = self.swarm.disconnect_peer_id(peer_id);
+    }
+
+    /// Unblocks peers whose block has expired and drops stale limiter state.
+    pub(crate) fn cleanup_blocked_peers(&mut self) {
+        let block_duration = self.rate_limiter.limits.block_duration;
+        let expired_peers: Vec<PeerId> = self
+            .blocked_peers
+            .iter()
+            .filter(|(_, blocked_time)| blocked_time.elapsed() > block_duration)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+
+        for peer_id in expired_peers {
+            info!("Unblocking peer {} after timeout", peer_id);
+            self.blocked_peers.remove(&peer_id);
+            self.swarm.behaviour_mut().guard.unblock(&peer_id);
+        }
+
+        self.rate_limiter.prune();
+    }
+}
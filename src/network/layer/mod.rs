@@ -3,9 +3,16 @@
 //!
 //! It is responsible for creating and managing the `libp2p` `Swarm`, and for
 //! handling network events.
+mod address_book;
 mod builder;
+mod limits;
+mod nat;
 mod providers;
 mod runtime;
 mod state;
 
+pub use limits::{LimitCounters, RateLimits};
+pub(crate) use limits::{RateLimiter, RequestKind};
+pub(crate) use nat::NatState;
+pub use nat::{Reachability, ReachabilityInfo};
 pub use state::NetworkLayer;
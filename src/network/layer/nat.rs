@@ -0,0 +1,264 @@
+//! This module tracks the reachability of the local node and manages relay
+//! reservations for the `NetworkLayer`.
+use std::collections::HashMap;
+
+use libp2p::autonat::NatStatus;
+use libp2p::core::transport::ListenerId;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use super::NetworkLayer;
+
+/// The most relays a client keeps reservations on at the same time.
+const MAX_RELAY_RESERVATIONS: usize = 2;
+
+/// Whether the local node can be reached by other peers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reachability {
+    /// AutoNAT has not determined the reachability yet.
+    Unknown,
+    /// Other peers can dial the node directly.
+    Public,
+    /// The node is behind a NAT or firewall and can only be reached through a relay.
+    Private,
+}
+
+impl std::fmt::Display for Reachability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reachability::Unknown => write!(f, "unknown"),
+            Reachability::Public => write!(f, "public"),
+            Reachability::Private => write!(f, "private (behind NAT)"),
+        }
+    }
+}
+
+/// A snapshot of the NAT traversal state of the local node.
+#[derive(Debug, Clone)]
+pub struct ReachabilityInfo {
+    /// Whether the node can be reached by other peers.
+    pub reachability: Reachability,
+    /// The address AutoNAT confirmed as publicly reachable, if any.
+    pub public_address: Option<Multiaddr>,
+    /// Whether the node serves as a relay for other peers.
+    pub is_relay: bool,
+    /// The relayed addresses at which the node can be reached.
+    pub relay_addresses: Vec<Multiaddr>,
+    /// The number of relayed connections upgraded to direct ones.
+    pub hole_punches_succeeded: u64,
+    /// The number of failed attempts to upgrade a relayed connection.
+    pub hole_punches_failed: u64,
+}
+
+/// The NAT traversal state of the `NetworkLayer`.
+#[derive(Debug)]
+pub struct NatState {
+    /// The reachability last reported by AutoNAT.
+    pub(crate) status: NatStatus,
+    /// The relays we know of, with an address to reach each of them.
+    pub(crate) known_relays: HashMap<PeerId, Multiaddr>,
+    /// The relays we listen on, by the listener of the reservation.
+    pub(crate) reservations: HashMap<PeerId, ListenerId>,
+    /// The number of relayed connections upgraded to direct ones.
+    pub(crate) hole_punches_succeeded: u64,
+    /// The number of failed attempts to upgrade a relayed connection.
+    pub(crate) hole_punches_failed: u64,
+}
+
+impl Default for NatState {
+    fn default() -> Self {
+        Self {
+            status: NatStatus::Unknown,
+            known_relays: HashMap::new(),
+            reservations: HashMap::new(),
+            hole_punches_succeeded: 0,
+            hole_punches_failed: 0,
+        }
+    }
+}
+
+impl NetworkLayer {
+    /// Returns a snapshot of the NAT traversal state.
+    pub(crate) fn reachability_info(&self) -> ReachabilityInfo {
+        let (reachability, public_address) = match &self.nat.status {
+            NatStatus::Public(address) => (Reachability::Public, Some(address.clone())),
+            NatStatus::Private => (Reachability::Private, None),
+            NatStatus::Unknown => (Reachability::Unknown, None),
+        };
+        let relay_addresses = self
+            .swarm
+            .listeners()
+            .filter(|address| address.iter().any(|p| p == Protocol::P2pCircuit))
+            .cloned()
+            .collect();
+
+        ReachabilityInfo {
+            reachability,
+            public_address,
+            is_relay: self.swarm.behaviour().nat.is_relay(),
+            relay_addresses,
+            hole_punches_succeeded: self.nat.hole_punches_succeeded,
+            hole_punches_failed: self.nat.hole_punches_failed,
+        }
+    }
+
+    /// Records a peer that offers to relay connections.
+    ///
+    /// If we are not known to be publicly reachable, a reservation is made
+    /// on the relay right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the relay.
+    /// * `address` - An address at which the relay is reachable.
+    pub(crate) fn add_relay_candidate(&mut self, peer_id: PeerId, address: Multiaddr) {
+        if self.swarm.behaviour().nat.is_relay() {
+            return;
+        }
+        if self.nat.known_relays.insert(peer_id, address).is_none() {
+            debug!("Peer {} offers to act as a relay", peer_id);
+        }
+        if !self.nat.status.is_public() {
+            self.reserve_relays();
+        }
+    }
+
+    /// Reserves slots on known relays until enough reservations are held.
+    pub(crate) fn reserve_relays(&mut self) {
+        let candidates: Vec<(PeerId, Multiaddr)> = self
+            .nat
+            .known_relays
+            .iter()
+            .filter(|(peer_id, _)| !self.nat.reservations.contains_key(peer_id))
+            .map(|(peer_id, address)| (*peer_id, address.clone()))
+            .collect();
+
+        for (relay_peer_id, relay_address) in candidates {
+            if self.nat.reservations.len() >= MAX_RELAY_RESERVATIONS {
+                break;
+            }
+            if self.blocked_peers.contains_key(&relay_peer_id) {
+                continue;
+            }
+
+            let circuit_address = relay_address
+                .with(Protocol::P2p(relay_peer_id))
+                .with(Protocol::P2pCircuit);
+            match self.swarm.listen_on(circuit_address.clone()) {
+                Ok(listener_id) => {
+                    info!("Requesting a relay reservation on {}", circuit_address);
+                    self.nat.reservations.insert(relay_peer_id, listener_id);
+                }
+                Err(e) => {
+                    warn!("Failed to listen via relay {}: {}", relay_peer_id, e);
+                }
+            }
+        }
+    }
+
+    /// Drops all relay reservations, once we are directly reachable.
+    pub(crate) fn release_relays(&mut self) {
+        for (relay_peer_id, listener_id) in self.nat.reservations.drain() {
+            debug!("Releasing relay reservation on {}", relay_peer_id);
+            self.swarm.remove_listener(listener_id);
+        }
+    }
+
+    /// Forgets a relay reservation whose listener has closed.
+    ///
+    /// The relay is dropped from the known relays until identify reports it
+    /// again, and a reservation is made on another relay instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener_id` - The listener that closed.
+    pub(crate) fn forget_relay_listener(&mut self, listener_id: ListenerId) {
+        let Some(relay_peer_id) = self
+            .nat
+            .reservations
+            .iter()
+            .find_map(|(peer_id, id)| (*id == listener_id).then_some(*peer_id))
+        else {
+            return;
+        };
+
+        info!("Relay reservation on {} closed", relay_peer_id);
+        self.nat.reservations.remove(&relay_peer_id);
+        self.nat.known_relays.remove(&relay_peer_id);
+        if !self.nat.status.is_public() {
+            self.reserve_relays();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Identity;
+    use crate::storage::{SledPeerAddressStore, SledRecordStore};
+    use std::sync::Arc;
+
+    fn network_layer(is_mailbox: bool) -> NetworkLayer {
+        let identity = Arc::new(Identity::generate().unwrap());
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let record_store = SledRecordStore::new(identity.peer_id, db.clone(), None).unwrap();
+        let address_book = Arc::new(SledPeerAddressStore::new(db, None).unwrap());
+        let (layer, _handle) = NetworkLayer::new(
+            identity,
+            Vec::new(),
+            is_mailbox,
+            Vec::new(),
+            record_store,
+            address_book,
+        )
+        .unwrap();
+        layer
+    }
+
+    fn relay_address() -> Multiaddr {
+        "/ip4/203.0.113.7/tcp/4001".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn clients_keep_a_limited_number_of_reservations() {
+        let mut layer = network_layer(false);
+        for _ in 0..MAX_RELAY_RESERVATIONS + 1 {
+            layer.add_relay_candidate(PeerId::random(), relay_address());
+        }
+        assert_eq!(layer.nat.reservations.len(), MAX_RELAY_RESERVATIONS);
+        assert_eq!(
+            layer.reachability_info().reachability,
+            Reachability::Unknown
+        );
+
+        // A closed reservation is replaced by one on the remaining relay.
+        let (&closed, &listener_id) = layer.nat.reservations.iter().next().unwrap();
+        layer.forget_relay_listener(listener_id);
+        assert_eq!(layer.nat.reservations.len(), MAX_RELAY_RESERVATIONS);
+        assert!(!layer.nat.reservations.contains_key(&closed));
+        assert!(!layer.nat.known_relays.contains_key(&closed));
+
+        layer.release_relays();
+        assert!(layer.nat.reservations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn relays_and_public_nodes_make_no_reservations() {
+        let mut relay = network_layer(true);
+        relay.add_relay_candidate(PeerId::random(), relay_address());
+        assert!(relay.nat.reservations.is_empty());
+        assert!(relay.reachability_info().is_relay);
+
+        let mut public = network_layer(false);
+        public.nat.status = NatStatus::Public(relay_address());
+        public.add_relay_candidate(PeerId::random(), relay_address());
+        assert!(public.nat.reservations.is_empty());
+        assert_eq!(
+            public.reachability_info().public_address,
+            Some(relay_address())
+        );
+    }
+}
@@ -1,10 +1,14 @@
 //! This module contains functions for interacting with the Kademlia DHT.
+use std::sync::Arc;
+
 use anyhow::Result;
 use tokio::sync::mpsc;
 
 use crate::cli::commands::UiNotification;
 use crate::mailbox::{make_mailbox_provider_key, make_recipient_mailbox_key};
+use crate::storage::{AttachmentStore, BlockListStore};
 use crate::sync::SyncEvent;
+use crate::types::ContactRequest;
 
 use super::NetworkLayer;
 
@@ -19,6 +23,21 @@ impl NetworkLayer {
         self.ui_notify_tx = Some(sender);
     }
 
+    /// Sets the sender for verified incoming contact requests.
+    pub fn set_contact_event_sender(&mut self, sender: mpsc::UnboundedSender<ContactRequest>) {
+        self.contact_event_tx = Some(sender);
+    }
+
+    /// Sets the store of attachments whose chunks are served to other peers.
+    pub fn set_attachment_store(&mut self, store: Arc<AttachmentStore>) {
+        self.attachment_store = Some(store);
+    }
+
+    /// Sets the user's block list, whose peers are ignored.
+    pub fn set_block_list(&mut self, store: Arc<dyn BlockListStore + Send + Sync>) {
+        self.block_list = Some(store);
+    }
+
     /// Bootstraps the Kademlia DHT.
     ///
     /// # Errors
@@ -12,28 +12,12 @@ use crate::types::Message;
 use super::NetworkLayer;
 
 impl NetworkLayer {
-    /// Periodically cleans up the list of blocked peers.
-    fn cleanup_blocked_peers(&mut self) {
-        let block_duration = Duration::from_secs(600);
-        let mut expired_peers = Vec::new();
-
-        for (&peer_id, &blocked_time) in &self.blocked_peers {
-            if blocked_time.elapsed() > block_duration {
-                expired_peers.push(peer_id);
-            }
-        }
-
-        for peer_id in expired_peers {
-            info!("Unblocking peer {} after timeout", peer_id);
-            self.blocked_peers.remove(&peer_id);
-        }
-    }
-
     /// Runs the main event loop for the `NetworkLayer`.
     ///
-    /// This function listens for events from the `libp2p` `Swarm` and for
-    /// commands from other parts of the application. It also periodically
-    /// cleans up the list of blocked peers.
+    /// This function seeds Kademlia from the peer address book, then listens
+    /// for events from the `libp2p` `Swarm` and for commands from other parts
+    /// of the application. It also periodically unblocks peers whose block has
+    /// expired.
     ///
     /// # Arguments
     ///
@@ -45,7 +29,9 @@ impl NetworkLayer {
     pub async fn run(&mut self, incoming_messages: mpsc::UnboundedSender<Message>) -> Result<()> {
         info!("Starting network event loop");
 
-        let mut cleanup_timer = tokio::time::interval(Duration::from_secs(300));
+        self.seed_from_address_book().await;
+
+        let mut cleanup_timer = tokio::time::interval(Duration::from_secs(60));
 
         loop {
             select! {
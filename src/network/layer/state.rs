@@ -6,11 +6,14 @@ use libp2p::{request_response::OutboundRequestId, swarm::Swarm, PeerId};
 use tokio::sync::{mpsc, oneshot};
 
 use crate::cli::commands::UiNotification;
-use crate::storage::SledMailboxStore;
+use crate::crypto::mailbox_auth::MailboxGrant;
+use crate::storage::{AttachmentStore, BlockListStore, PeerAddressStore, SledMailboxStore};
 use crate::sync::SyncEvent;
+use crate::types::ContactRequest;
 
 use super::super::behaviour::P2PBehaviour;
 use super::super::message::{NetworkCommand, NetworkResponse};
+use super::{NatState, RateLimiter};
 
 /// The state of the network layer.
 ///
@@ -28,8 +31,22 @@ pub struct NetworkLayer {
     pub(crate) sync_event_tx: Option<mpsc::UnboundedSender<SyncEvent>>,
     /// The sender for UI notifications.
     pub(crate) ui_notify_tx: Option<mpsc::UnboundedSender<UiNotification>>,
+    /// The sender for verified incoming contact requests.
+    pub(crate) contact_event_tx: Option<mpsc::UnboundedSender<ContactRequest>>,
+    /// The store of attachments served to other peers.
+    pub(crate) attachment_store: Option<Arc<AttachmentStore>>,
     /// The storage for the mailbox.
     pub(crate) mailbox_storage: Option<Arc<SledMailboxStore>>,
-    /// A map of peers that are currently blocked.
+    /// Outstanding mailbox challenges, by requesting peer and recipient hash.
+    pub(crate) mailbox_grants: HashMap<(PeerId, [u8; 32]), MailboxGrant>,
+    /// The persisted addresses of known peers.
+    pub(crate) address_book: Arc<dyn PeerAddressStore + Send + Sync>,
+    /// The reachability of the node and its relay reservations.
+    pub(crate) nat: NatState,
+    /// The per-peer request rate limits and their counters.
+    pub(crate) rate_limiter: RateLimiter,
+    /// A map of peers that are temporarily blocked for exceeding the limits.
     pub(crate) blocked_peers: HashMap<PeerId, std::time::Instant>,
+    /// The peers the user blocked, if this node has a block list.
+    pub(crate) block_list: Option<Arc<dyn BlockListStore + Send + Sync>>,
 }
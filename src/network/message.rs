@@ -1,9 +1,13 @@
 //! This module defines the messages that are sent to and from the `NetworkLayer`.
-use crate::types::{ChatRequest, EncryptedMessage, Message};
+use crate::types::{
+    AttachmentRequest, ChatRequest, ContactRequest, EncryptedMessage, MailboxChallenge, Message,
+};
 use anyhow::Result;
 use libp2p::{kad, PeerId};
 use tokio::sync::oneshot;
 
+use super::{LimitCounters, ReachabilityInfo};
+
 /// A response from the `NetworkLayer`.
 #[derive(Debug)]
 pub enum NetworkResponse {
@@ -31,6 +35,25 @@ pub enum NetworkResponse {
         /// The number of messages that were deleted.
         deleted: usize,
     },
+    /// The result of a mailbox `set_blocked` operation.
+    MailboxBlockedSet {
+        /// The number of blocked senders the mailbox stored.
+        count: usize,
+    },
+    /// A challenge issued by a mailbox node.
+    MailboxChallenge {
+        /// The challenge to answer.
+        challenge: MailboxChallenge,
+    },
+    /// An attachment chunk fetched from a peer.
+    AttachmentChunk {
+        /// The encrypted chunk, or `None` if the peer does not have it.
+        data: Option<Vec<u8>>,
+    },
+    /// The reachability of the local node.
+    Reachability(ReachabilityInfo),
+    /// The counters of the requests and connections refused by the limits.
+    LimitCounters(LimitCounters),
 }
 
 /// A command to be sent to the `NetworkLayer`.
@@ -54,11 +77,39 @@ pub enum NetworkCommand {
         /// The channel to send the response on.
         response: oneshot::Sender<NetworkResponse>,
     },
+    /// Send a contact request to a peer.
+    SendContactRequest {
+        /// The `PeerId` of the recipient.
+        peer_id: PeerId,
+        /// The request to send.
+        request: ContactRequest,
+        /// The channel to send the response on.
+        response: oneshot::Sender<NetworkResponse>,
+    },
+    /// Fetch an attachment chunk from a peer.
+    FetchAttachmentChunk {
+        /// The `PeerId` of the peer holding the attachment.
+        peer_id: PeerId,
+        /// The chunk to fetch.
+        request: AttachmentRequest,
+        /// The channel to send the response on.
+        response: oneshot::Sender<NetworkResponse>,
+    },
     /// Get the list of connected peers.
     GetConnectedPeers {
         /// The channel to send the response on.
         response: oneshot::Sender<NetworkResponse>,
     },
+    /// Get the reachability of the local node.
+    GetReachability {
+        /// The channel to send the response on.
+        response: oneshot::Sender<NetworkResponse>,
+    },
+    /// Get the counters of the requests and connections refused by the limits.
+    GetLimitCounters {
+        /// The channel to send the response on.
+        response: oneshot::Sender<NetworkResponse>,
+    },
     /// Put a message into a mailbox.
     MailboxPut {
         /// The `PeerId` of the mailbox node.
@@ -70,6 +121,17 @@ pub enum NetworkCommand {
         /// The channel to send the response on.
         response: oneshot::Sender<NetworkResponse>,
     },
+    /// Ask a mailbox for a challenge proving ownership of a recipient's key.
+    MailboxChallenge {
+        /// The `PeerId` of the mailbox node.
+        peer_id: PeerId,
+        /// The hash of the recipient's public key.
+        recipient: [u8; 32],
+        /// The recipient's public key.
+        public_key: Vec<u8>,
+        /// The channel to send the response on.
+        response: oneshot::Sender<NetworkResponse>,
+    },
     /// Fetch messages from a mailbox.
     MailboxFetch {
         /// The `PeerId` of the mailbox node.
@@ -78,6 +140,8 @@ pub enum NetworkCommand {
         recipient: [u8; 32],
         /// The maximum number of messages to fetch.
         limit: usize,
+        /// The answer to the mailbox's challenge.
+        proof: [u8; 32],
         /// The channel to send the response on.
         response: oneshot::Sender<NetworkResponse>,
     },
@@ -89,6 +153,21 @@ pub enum NetworkCommand {
         recipient: [u8; 32],
         /// The IDs of the messages to acknowledge.
         msg_ids: Vec<uuid::Uuid>,
+        /// The answer to the mailbox's challenge.
+        proof: [u8; 32],
+        /// The channel to send the response on.
+        response: oneshot::Sender<NetworkResponse>,
+    },
+    /// Replace the senders a mailbox refuses messages from for us.
+    MailboxSetBlocked {
+        /// The `PeerId` of the mailbox node.
+        peer_id: PeerId,
+        /// The hash of the recipient's public key.
+        recipient: [u8; 32],
+        /// The `PeerId`s of the blocked senders.
+        blocked: Vec<PeerId>,
+        /// The answer to the mailbox's challenge.
+        proof: [u8; 32],
         /// The channel to send the response on.
         response: oneshot::Sender<NetworkResponse>,
     },
@@ -11,5 +11,5 @@ mod message;
 
 pub use behaviour::P2PBehaviourEvent;
 pub use handle::NetworkHandle;
-pub use layer::NetworkLayer;
+pub use layer::{LimitCounters, NetworkLayer, RateLimits, Reachability, ReachabilityInfo};
 pub use message::{NetworkCommand, NetworkResponse};
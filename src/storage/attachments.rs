@@ -0,0 +1,671 @@
+//! This module stores attachments as encrypted chunks under the data directory.
+//!
+//! Each attachment lives in its own directory, named after the hash of the
+//! file, holding a record with the manifest and one file per chunk. Chunks stay
+//! encrypted with the attachment key, so they can be served to other peers as
+//! they are and a partial download can resume with the chunks that are missing.
+//! Importing a file that is already stored reuses its chunks and key.
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use libp2p::PeerId;
+use rand::RngCore;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::crypto::StorageEncryption;
+use crate::types::AttachmentManifest;
+
+/// The size of each chunk before encryption.
+pub const ATTACHMENT_CHUNK_SIZE: u32 = 256 * 1024;
+/// The largest chunk size accepted in a manifest.
+pub const MAX_ATTACHMENT_CHUNK_SIZE: u32 = 1024 * 1024;
+/// The largest file that can be sent as an attachment.
+pub const MAX_ATTACHMENT_SIZE: u64 = 100 * 1024 * 1024;
+/// The overhead ChaCha20Poly1305 adds to each chunk.
+const CHUNK_TAG_SIZE: usize = 16;
+
+/// The local record for an attachment.
+#[derive(Serialize, Deserialize)]
+struct AttachmentRecord {
+    /// The manifest of the attachment.
+    manifest: AttachmentManifest,
+    /// The peer to fetch missing chunks from, or `None` once all chunks are here.
+    source: Option<PeerId>,
+}
+
+/// A store for attachments, kept as files under the data directory.
+pub struct AttachmentStore {
+    dir: PathBuf,
+    encryption: Option<StorageEncryption>,
+    downloading: Mutex<HashSet<String>>,
+}
+
+impl AttachmentStore {
+    /// Creates a new `AttachmentStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_dir` - The application's data directory.
+    /// * `encryption` - The optional `StorageEncryption` to use for encrypting manifests.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `attachments` directory cannot be created.
+    pub fn new(data_dir: &str, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let dir = Path::new(data_dir).join("attachments");
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            encryption,
+            downloading: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Splits a local file into encrypted chunks and stores it as an attachment.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file to import.
+    ///
+    /// # Returns
+    ///
+    /// The manifest to send along with the message.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read, is
+    /// empty or too large, or the chunks cannot be stored.
+    pub fn import_file(&self, path: &Path) -> Result<AttachmentManifest> {
+        let size = fs::metadata(path)?.len();
+        if size == 0 {
+            bail!("Cannot send an empty file");
+        }
+        if size > MAX_ATTACHMENT_SIZE {
+            bail!(
+                "File is too large ({} bytes, the limit is {} bytes)",
+                size,
+                MAX_ATTACHMENT_SIZE
+            );
+        }
+
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("attachment")
+            .to_string();
+        let mime = mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string();
+
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+
+        // Chunks are written to a staging directory, since the file hash is only known at the end.
+        let staging = self.dir.join(format!(".import-{}", hex::encode(&key[..8])));
+        fs::create_dir_all(&staging)?;
+
+        let result = (|| {
+            let mut file = fs::File::open(path)?;
+            let mut file_hasher = Sha256::new();
+            let mut chunks = Vec::new();
+            let mut buffer = vec![0u8; ATTACHMENT_CHUNK_SIZE as usize];
+
+            loop {
+                let read = read_full(&mut file, &mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+
+                let index = chunks.len() as u32;
+                file_hasher.update(&buffer[..read]);
+                let encrypted = encrypt_chunk(&key, index, &buffer[..read])?;
+                chunks.push(hex::encode(Sha256::digest(&encrypted)));
+                fs::write(staging.join(chunk_file_name(index)), encrypted)?;
+            }
+
+            Ok::<_, anyhow::Error>((hex::encode(file_hasher.finalize()), chunks))
+        })();
+
+        let (hash, chunks) = match result {
+            Ok(done) => done,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(e);
+            }
+        };
+
+        // The file is already known: keep its chunks and key, which manifests
+        // sent before and downloads in progress rely on.
+        if let Some(record) = self.read_record(&hash)? {
+            let _ = fs::remove_dir_all(&staging);
+            if record.source.is_some() {
+                self.fill_download(path, &record.manifest)?;
+            }
+            return Ok(AttachmentManifest {
+                file_name,
+                mime,
+                ..record.manifest
+            });
+        }
+
+        let manifest = AttachmentManifest {
+            hash,
+            file_name,
+            mime,
+            size,
+            chunk_size: ATTACHMENT_CHUNK_SIZE,
+            chunks,
+            key,
+        };
+
+        let target = self.dir.join(&manifest.hash);
+        if target.exists() {
+            fs::remove_dir_all(&target)?;
+        }
+        fs::rename(&staging, &target)?;
+        self.write_record(&AttachmentRecord {
+            manifest: manifest.clone(),
+            source: None,
+        })?;
+
+        Ok(manifest)
+    }
+
+    /// Completes a download in progress from a local copy of the file.
+    ///
+    /// The missing chunks are encrypted with the key of the download, so they
+    /// match its manifest.
+    fn fill_download(&self, path: &Path, manifest: &AttachmentManifest) -> Result<()> {
+        let mut file = fs::File::open(path)?;
+        let mut buffer = vec![0u8; manifest.chunk_size as usize];
+        let missing: HashSet<u32> = self.missing_chunks(manifest).into_iter().collect();
+
+        for index in 0..manifest.chunks.len() as u32 {
+            let read = read_full(&mut file, &mut buffer)?;
+            if missing.contains(&index) {
+                let encrypted = encrypt_chunk(&manifest.key, index, &buffer[..read])?;
+                self.write_chunk(manifest, index, &encrypted)?;
+            }
+        }
+
+        self.complete_download(&manifest.hash)
+    }
+
+    /// Records an attachment received in a message, to be fetched from its sender.
+    ///
+    /// Attachments that are already known are left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The manifest received in the message.
+    /// * `source` - The peer that sent the message.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the manifest is invalid or the
+    /// record cannot be stored.
+    pub fn register_download(&self, manifest: &AttachmentManifest, source: PeerId) -> Result<()> {
+        validate_manifest(manifest)?;
+
+        if self.read_record(&manifest.hash)?.is_some() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(self.dir.join(&manifest.hash))?;
+        self.write_record(&AttachmentRecord {
+            manifest: manifest.clone(),
+            source: Some(source),
+        })
+    }
+
+    /// Retrieves the manifest of an attachment.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the attachment.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the manifest if the attachment is known, otherwise `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the record cannot be read.
+    pub fn get_manifest(&self, hash: &str) -> Result<Option<AttachmentManifest>> {
+        Ok(self.read_record(hash)?.map(|record| record.manifest))
+    }
+
+    /// Returns whether all chunks of an attachment have been received.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the record cannot be read.
+    pub fn is_complete(&self, hash: &str) -> Result<bool> {
+        Ok(self
+            .read_record(hash)?
+            .is_some_and(|record| record.source.is_none()))
+    }
+
+    /// Lists the attachments that still have chunks to fetch, with the peer to fetch them from.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the attachments directory cannot be read.
+    pub fn pending_downloads(&self) -> Result<Vec<(AttachmentManifest, PeerId)>> {
+        let mut pending = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let Some(hash) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if validate_hash(&hash).is_err() {
+                continue;
+            }
+
+            if let Some(AttachmentRecord {
+                manifest,
+                source: Some(source),
+            }) = self.read_record(&hash)?
+            {
+                pending.push((manifest, source));
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Returns the indices of the chunks that have not been received yet.
+    pub fn missing_chunks(&self, manifest: &AttachmentManifest) -> Vec<u32> {
+        let dir = self.dir.join(&manifest.hash);
+        (0..manifest.chunks.len() as u32)
+            .filter(|index| !dir.join(chunk_file_name(*index)).exists())
+            .collect()
+    }
+
+    /// Reads an encrypted chunk, for serving it to another peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the attachment.
+    /// * `index` - The index of the chunk.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the encrypted chunk if it is here, otherwise `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the chunk cannot be read.
+    pub fn read_chunk(&self, hash: &str, index: u32) -> Result<Option<Vec<u8>>> {
+        validate_hash(hash)?;
+
+        let path = self.dir.join(hash).join(chunk_file_name(index));
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(path)?))
+    }
+
+    /// Stores a chunk fetched from another peer after checking it against the manifest.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The manifest of the attachment.
+    /// * `index` - The index of the chunk.
+    /// * `data` - The encrypted chunk.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the chunk does not match the
+    /// manifest or cannot be stored.
+    pub fn write_chunk(
+        &self,
+        manifest: &AttachmentManifest,
+        index: u32,
+        data: &[u8],
+    ) -> Result<()> {
+        let expected = manifest
+            .chunks
+            .get(index as usize)
+            .ok_or_else(|| anyhow!("Attachment {} has no chunk {}", manifest.hash, index))?;
+        if hex::encode(Sha256::digest(data)) != *expected {
+            bail!("Chunk {} of attachment {} is corrupt", index, manifest.hash);
+        }
+
+        let dir = self.dir.join(&manifest.hash);
+        let temp = dir.join(format!("{}.part", chunk_file_name(index)));
+        fs::write(&temp, data)?;
+        fs::rename(temp, dir.join(chunk_file_name(index)))?;
+        Ok(())
+    }
+
+    /// Marks a download as complete once the whole file checks out against its hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the attachment.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if chunks are missing, the file
+    /// does not match its hash, or the record cannot be updated.
+    pub fn complete_download(&self, hash: &str) -> Result<()> {
+        let mut record = self
+            .read_record(hash)?
+            .ok_or_else(|| anyhow!("Unknown attachment {}", hash))?;
+
+        self.assemble(&record.manifest)?;
+        record.source = None;
+        self.write_record(&record)
+    }
+
+    /// Reads and decrypts a complete attachment.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the attachment.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the manifest and the file contents if the
+    /// attachment is known, otherwise `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the attachment is incomplete or
+    /// does not match its hash.
+    pub fn read_file(&self, hash: &str) -> Result<Option<(AttachmentManifest, Vec<u8>)>> {
+        let Some(record) = self.read_record(hash)? else {
+            return Ok(None);
+        };
+        if record.source.is_some() {
+            bail!("Attachment {} is still being downloaded", hash);
+        }
+
+        let data = self.assemble(&record.manifest)?;
+        Ok(Some((record.manifest, data)))
+    }
+
+    /// Claims an attachment for downloading.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the attachment is already being downloaded.
+    pub fn begin_download(&self, hash: &str) -> bool {
+        self.downloading
+            .lock()
+            .map(|mut active| active.insert(hash.to_string()))
+            .unwrap_or(false)
+    }
+
+    /// Releases an attachment claimed with `begin_download`.
+    pub fn end_download(&self, hash: &str) {
+        if let Ok(mut active) = self.downloading.lock() {
+            active.remove(hash);
+        }
+    }
+
+    /// Decrypts all chunks of an attachment and checks the result against its hash.
+    fn assemble(&self, manifest: &AttachmentManifest) -> Result<Vec<u8>> {
+        let dir = self.dir.join(&manifest.hash);
+        let mut data = Vec::with_capacity(manifest.size as usize);
+
+        for index in 0..manifest.chunks.len() as u32 {
+            let encrypted = fs::read(dir.join(chunk_file_name(index))).map_err(|e| {
+                anyhow!(
+                    "Chunk {} of attachment {} is missing: {}",
+                    index,
+                    manifest.hash,
+                    e
+                )
+            })?;
+            data.extend_from_slice(&decrypt_chunk(&manifest.key, index, &encrypted)?);
+        }
+
+        if data.len() as u64 != manifest.size || hex::encode(Sha256::digest(&data)) != manifest.hash
+        {
+            bail!("Attachment {} does not match its hash", manifest.hash);
+        }
+        Ok(data)
+    }
+
+    /// Reads the record of an attachment.
+    fn read_record(&self, hash: &str) -> Result<Option<AttachmentRecord>> {
+        validate_hash(hash)?;
+
+        let path = self.dir.join(hash).join("record");
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read(path)?;
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(&data)?
+        } else {
+            data
+        };
+        Ok(Some(serde_json::from_slice(&decrypted)?))
+    }
+
+    /// Writes the record of an attachment, replacing it atomically.
+    fn write_record(&self, record: &AttachmentRecord) -> Result<()> {
+        let serialized = serde_json::to_vec(record)?;
+        let data = if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)?
+        } else {
+            serialized
+        };
+
+        let dir = self.dir.join(&record.manifest.hash);
+        let temp = dir.join("record.tmp");
+        fs::write(&temp, data)?;
+        fs::rename(temp, dir.join("record"))?;
+        Ok(())
+    }
+}
+
+/// Checks that a manifest received from another peer is well-formed.
+fn validate_manifest(manifest: &AttachmentManifest) -> Result<()> {
+    validate_hash(&manifest.hash)?;
+
+    if manifest.size == 0 || manifest.size > MAX_ATTACHMENT_SIZE {
+        bail!("Attachment size {} is out of range", manifest.size);
+    }
+    if manifest.chunk_size == 0 || manifest.chunk_size > MAX_ATTACHMENT_CHUNK_SIZE {
+        bail!(
+            "Attachment chunk size {} is out of range",
+            manifest.chunk_size
+        );
+    }
+
+    let expected_chunks = manifest.size.div_ceil(manifest.chunk_size as u64);
+    if manifest.chunks.len() as u64 != expected_chunks {
+        bail!(
+            "Attachment has {} chunks, expected {}",
+            manifest.chunks.len(),
+            expected_chunks
+        );
+    }
+
+    for chunk in &manifest.chunks {
+        validate_hash(chunk)?;
+    }
+    Ok(())
+}
+
+/// Checks that a hash is a hex-encoded SHA-256 hash, which also makes it a safe directory name.
+fn validate_hash(hash: &str) -> Result<()> {
+    if hash.len() != 64
+        || !hash
+            .bytes()
+            .all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+    {
+        bail!("Invalid attachment hash '{}'", hash);
+    }
+    Ok(())
+}
+
+/// Returns the file name of a chunk.
+fn chunk_file_name(index: u32) -> String {
+    format!("{:08}.chunk", index)
+}
+
+/// Builds the nonce for a chunk; the key is never reused across attachments.
+fn chunk_nonce(index: u32) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[8..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+/// Encrypts a chunk with the attachment key.
+fn encrypt_chunk(key: &[u8; 32], index: u32, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = chunk_nonce(index);
+    cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: b"p2p-chat/attachment-chunk/v1",
+            },
+        )
+        .map_err(|e| anyhow!("Chunk encryption failed: {}", e))
+}
+
+/// Decrypts a chunk with the attachment key.
+fn decrypt_chunk(key: &[u8; 32], index: u32, ciphertext: &[u8]) -> Result<Vec<u8>> {
+    if ciphertext.len() < CHUNK_TAG_SIZE {
+        bail!("Chunk {} is too short", index);
+    }
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = chunk_nonce(index);
+    cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: ciphertext,
+                aad: b"p2p-chat/attachment-chunk/v1",
+            },
+        )
+        .map_err(|e| anyhow!("Chunk {} failed to decrypt: {}", index, e))
+}
+
+/// Reads until the buffer is full or the end of the file is reached.
+fn read_full(file: &mut fs::File, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let read = file.read(&mut buffer[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store in a fresh directory, removed when dropped.
+    struct TestStore {
+        dir: PathBuf,
+        store: AttachmentStore,
+    }
+
+    impl TestStore {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("p2p-chat-test-{}", uuid::Uuid::new_v4()));
+            let store = AttachmentStore::new(dir.to_str().unwrap(), None).unwrap();
+            Self { dir, store }
+        }
+
+        fn write_file(&self, name: &str, data: &[u8]) -> PathBuf {
+            let path = self.dir.join(name);
+            fs::write(&path, data).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TestStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn sample_data() -> Vec<u8> {
+        (0..ATTACHMENT_CHUNK_SIZE as usize * 2 + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn reimport_keeps_the_existing_key() {
+        let test = TestStore::new();
+        let data = sample_data();
+
+        let first = test
+            .store
+            .import_file(&test.write_file("first.bin", &data))
+            .unwrap();
+        let second = test
+            .store
+            .import_file(&test.write_file("second.bin", &data))
+            .unwrap();
+
+        assert_eq!(second.hash, first.hash);
+        assert_eq!(second.key, first.key);
+        assert_eq!(second.chunks, first.chunks);
+        assert_eq!(second.file_name, "second.bin");
+
+        let (_, read) = test.store.read_file(&first.hash).unwrap().unwrap();
+        assert_eq!(read, data);
+        for index in 0..first.chunks.len() as u32 {
+            let chunk = test.store.read_chunk(&first.hash, index).unwrap().unwrap();
+            assert_eq!(
+                hex::encode(Sha256::digest(&chunk)),
+                first.chunks[index as usize]
+            );
+        }
+    }
+
+    #[test]
+    fn import_completes_a_download_in_progress() {
+        let sender = TestStore::new();
+        let receiver = TestStore::new();
+        let data = sample_data();
+
+        let manifest = sender
+            .store
+            .import_file(&sender.write_file("file.bin", &data))
+            .unwrap();
+        receiver
+            .store
+            .register_download(&manifest, PeerId::random())
+            .unwrap();
+        let chunk = sender.store.read_chunk(&manifest.hash, 1).unwrap().unwrap();
+        receiver.store.write_chunk(&manifest, 1, &chunk).unwrap();
+
+        let imported = receiver
+            .store
+            .import_file(&receiver.write_file("copy.bin", &data))
+            .unwrap();
+
+        assert_eq!(imported.key, manifest.key);
+        assert!(receiver.store.is_complete(&manifest.hash).unwrap());
+        let (_, read) = receiver.store.read_file(&manifest.hash).unwrap().unwrap();
+        assert_eq!(read, data);
+    }
+}
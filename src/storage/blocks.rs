@@ -0,0 +1,119 @@
+//! This module defines the storage interface and implementation for the block
+//! list, which holds the peers the user chose to ignore.
+//!
+//! Unlike the temporary blocks of the network layer, entries stay until the
+//! user removes them.
+use crate::crypto::StorageEncryption;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+/// Represents a peer on the block list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockedPeer {
+    /// The `PeerId` of the blocked peer.
+    pub peer_id: PeerId,
+    /// The timestamp of when the peer was blocked, in milliseconds.
+    pub blocked_at: i64,
+}
+
+/// A trait for managing the block list.
+#[async_trait]
+pub trait BlockListStore: Send + Sync {
+    /// Adds a peer to the block list. Returns `false` if it was already blocked.
+    async fn block(&self, peer_id: &PeerId) -> Result<bool>;
+    /// Removes a peer from the block list. Returns `false` if it was not blocked.
+    async fn unblock(&self, peer_id: &PeerId) -> Result<bool>;
+    /// Checks whether a peer is on the block list.
+    async fn is_blocked(&self, peer_id: &PeerId) -> Result<bool>;
+    /// Lists all blocked peers.
+    async fn list_blocked(&self) -> Result<Vec<BlockedPeer>>;
+}
+
+/// A `BlockListStore` implementation using `sled` for storage.
+pub struct SledBlockListStore {
+    tree: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledBlockListStore {
+    /// Creates a new `SledBlockListStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - Optional `StorageEncryption` for encrypting data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying `sled` tree cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let tree = db.open_tree("block_list")?;
+        Ok(Self { tree, encryption })
+    }
+
+    /// Serializes a `BlockedPeer` and optionally encrypts it.
+    fn serialize_entry(&self, entry: &BlockedPeer) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(entry)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Deserializes a `BlockedPeer` and optionally decrypts it.
+    fn deserialize_entry(&self, data: &[u8]) -> Result<BlockedPeer> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+#[async_trait]
+impl BlockListStore for SledBlockListStore {
+    async fn block(&self, peer_id: &PeerId) -> Result<bool> {
+        let key = peer_id.to_bytes();
+        if self.tree.contains_key(&key)? {
+            return Ok(false);
+        }
+
+        let entry = BlockedPeer {
+            peer_id: *peer_id,
+            blocked_at: Utc::now().timestamp_millis(),
+        };
+        self.tree.insert(key, self.serialize_entry(&entry)?)?;
+        self.tree.flush_async().await?;
+        Ok(true)
+    }
+
+    async fn unblock(&self, peer_id: &PeerId) -> Result<bool> {
+        let removed = self.tree.remove(peer_id.to_bytes())?.is_some();
+        self.tree.flush_async().await?;
+        Ok(removed)
+    }
+
+    async fn is_blocked(&self, peer_id: &PeerId) -> Result<bool> {
+        Ok(self.tree.contains_key(peer_id.to_bytes())?)
+    }
+
+    async fn list_blocked(&self) -> Result<Vec<BlockedPeer>> {
+        let mut blocked = Vec::new();
+
+        for result in self.tree.iter() {
+            let (_key, value) = result?;
+            blocked.push(self.deserialize_entry(&value)?);
+        }
+
+        blocked.sort_by_key(|entry| entry.blocked_at);
+        Ok(blocked)
+    }
+}
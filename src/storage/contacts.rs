@@ -0,0 +1,191 @@
+//! This module defines the storage interface and implementation for pending
+//! friend requests.
+use crate::crypto::StorageEncryption;
+use crate::types::ContactCard;
+use anyhow::Result;
+use async_trait::async_trait;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+/// The direction of a pending friend request.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContactDirection {
+    /// A request we received and have not answered yet.
+    Incoming,
+    /// A request we sent that has not been answered yet.
+    Outgoing,
+}
+
+impl ContactDirection {
+    /// Returns the key prefix used for requests in this direction.
+    fn prefix(self) -> u8 {
+        match self {
+            ContactDirection::Incoming => b'i',
+            ContactDirection::Outgoing => b'o',
+        }
+    }
+}
+
+/// A friend request that is waiting for an answer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingContact {
+    /// The Peer ID of the other party.
+    pub peer_id: PeerId,
+    /// Whether we received or sent the request.
+    pub direction: ContactDirection,
+    /// The other party's contact card, known for incoming requests.
+    pub card: Option<ContactCard>,
+    /// The timestamp when the request was sent or received (milliseconds since epoch).
+    pub created_at: i64,
+}
+
+/// A trait for managing pending friend requests.
+#[async_trait]
+pub trait ContactRequestsStore {
+    /// Stores a pending request, replacing any earlier one in the same direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The `PendingContact` to store.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request cannot be stored.
+    async fn add_pending(&self, pending: PendingContact) -> Result<()>;
+
+    /// Retrieves a pending request.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the request.
+    /// * `peer_id` - The `PeerId` of the other party.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the `PendingContact` if found, otherwise `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request cannot be retrieved.
+    async fn get_pending(
+        &self,
+        direction: ContactDirection,
+        peer_id: &PeerId,
+    ) -> Result<Option<PendingContact>>;
+
+    /// Lists all pending requests in one direction, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the requests to list.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the requests cannot be retrieved.
+    async fn list_pending(&self, direction: ContactDirection) -> Result<Vec<PendingContact>>;
+
+    /// Removes a pending request.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the request.
+    /// * `peer_id` - The `PeerId` of the other party.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request cannot be removed.
+    async fn remove_pending(&self, direction: ContactDirection, peer_id: &PeerId) -> Result<()>;
+}
+
+/// A `ContactRequestsStore` implementation using `sled` for storage.
+pub struct SledContactRequestsStore {
+    tree: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledContactRequestsStore {
+    /// Creates a new `SledContactRequestsStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - The optional `StorageEncryption` to use for encrypting requests.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `contact_requests` tree cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let tree = db.open_tree("contact_requests")?;
+        Ok(Self { tree, encryption })
+    }
+
+    /// Builds the key for a request: the direction prefix followed by the peer ID.
+    fn key(direction: ContactDirection, peer_id: &PeerId) -> Vec<u8> {
+        let mut key = vec![direction.prefix()];
+        key.extend_from_slice(&peer_id.to_bytes());
+        key
+    }
+
+    /// Serializes a `PendingContact` and encrypts it if encryption is enabled.
+    fn serialize_pending(&self, pending: &PendingContact) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(pending)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Decrypts and deserializes a `PendingContact`.
+    fn deserialize_pending(&self, data: &[u8]) -> Result<PendingContact> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+#[async_trait]
+impl ContactRequestsStore for SledContactRequestsStore {
+    async fn add_pending(&self, pending: PendingContact) -> Result<()> {
+        let key = Self::key(pending.direction, &pending.peer_id);
+        let value = self.serialize_pending(&pending)?;
+        self.tree.insert(key, value)?;
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+
+    async fn get_pending(
+        &self,
+        direction: ContactDirection,
+        peer_id: &PeerId,
+    ) -> Result<Option<PendingContact>> {
+        match self.tree.get(Self::key(direction, peer_id))? {
+            Some(data) => Ok(Some(self.deserialize_pending(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_pending(&self, direction: ContactDirection) -> Result<Vec<PendingContact>> {
+        let mut pending = Vec::new();
+
+        for result in self.tree.scan_prefix([direction.prefix()]) {
+            let (_key, value) = result?;
+            pending.push(self.deserialize_pending(&value)?);
+        }
+
+        pending.sort_by_key(|p| p.created_at);
+        Ok(pending)
+    }
+
+    async fn remove_pending(&self, direction: ContactDirection, peer_id: &PeerId) -> Result<()> {
+        self.tree.remove(Self::key(direction, peer_id))?;
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+}
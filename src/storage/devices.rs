@@ -0,0 +1,142 @@
+//! This module defines the storage interface and implementation for the
+//! devices linked to our identity.
+//!
+//! A primary device keeps the list of the devices it linked. A linked device
+//! keeps the primary device it joined and the latest list the primary sent.
+use crate::crypto::StorageEncryption;
+use crate::types::{Device, DeviceList};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use sled::Db;
+
+/// The key of the device list.
+const DEVICE_LIST_KEY: &[u8] = b"device_list";
+
+/// The key of the primary device.
+const PRIMARY_KEY: &[u8] = b"primary";
+
+/// A trait for managing the devices linked to our identity.
+#[async_trait]
+pub trait DeviceStore: Send + Sync {
+    /// Retrieves the list of devices linked to our identity.
+    ///
+    /// # Returns
+    ///
+    /// The latest signed list, or `None` if no device was ever linked.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the list cannot be retrieved.
+    async fn get_device_list(&self) -> Result<Option<DeviceList>>;
+
+    /// Stores the list of devices linked to our identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `list` - The signed device list, or `None` to remove it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the list cannot be stored.
+    async fn set_device_list(&self, list: Option<&DeviceList>) -> Result<()>;
+
+    /// Retrieves the primary device this device joined.
+    ///
+    /// # Returns
+    ///
+    /// The primary device, or `None` if this device is a primary device.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the device cannot be retrieved.
+    async fn get_primary(&self) -> Result<Option<Device>>;
+
+    /// Stores the primary device this device joined.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The primary device, or `None` to leave it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the device cannot be stored.
+    async fn set_primary(&self, primary: Option<&Device>) -> Result<()>;
+}
+
+/// A `DeviceStore` implementation using `sled` for storage.
+pub struct SledDeviceStore {
+    tree: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledDeviceStore {
+    /// Creates a new `SledDeviceStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - Optional `StorageEncryption` for encrypting data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying `sled` tree cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let tree = db.open_tree("devices")?;
+        Ok(Self { tree, encryption })
+    }
+
+    /// Reads and decrypts the value stored under `key`.
+    fn read<T: DeserializeOwned>(&self, key: &[u8]) -> Result<Option<T>> {
+        let Some(data) = self.tree.get(key)? else {
+            return Ok(None);
+        };
+
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(&data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(Some(serde_json::from_slice(&decrypted)?))
+    }
+
+    /// Encrypts and stores a value under `key`, or removes it.
+    async fn write<T: Serialize>(&self, key: &[u8], value: Option<&T>) -> Result<()> {
+        match value {
+            Some(value) => {
+                let serialized = serde_json::to_vec(value)?;
+                let data = if let Some(ref encryption) = self.encryption {
+                    encryption.encrypt_value(&serialized)?
+                } else {
+                    serialized
+                };
+                self.tree.insert(key, data)?;
+            }
+            None => {
+                self.tree.remove(key)?;
+            }
+        }
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeviceStore for SledDeviceStore {
+    async fn get_device_list(&self) -> Result<Option<DeviceList>> {
+        self.read(DEVICE_LIST_KEY)
+    }
+
+    async fn set_device_list(&self, list: Option<&DeviceList>) -> Result<()> {
+        self.write(DEVICE_LIST_KEY, list).await
+    }
+
+    async fn get_primary(&self) -> Result<Option<Device>> {
+        self.read(PRIMARY_KEY)
+    }
+
+    async fn set_primary(&self, primary: Option<&Device>) -> Result<()> {
+        self.write(PRIMARY_KEY, primary).await
+    }
+}
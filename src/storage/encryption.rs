@@ -0,0 +1,509 @@
+//! This module verifies the storage password and re-keys encrypted storage.
+//!
+//! A key-check record, a constant encrypted with the storage key, is kept
+//! next to the salt so that a wrong password is rejected when storage is
+//! opened rather than surfacing as decryption errors later.
+//!
+//! Re-keying copies every tree into a new database, re-encrypting the values
+//! that decrypt with the old key, and re-encrypts the attachment records
+//! alongside the old ones. The new database and records then replace the old
+//! ones. A journal records the new salt and the phase, so an interrupted
+//! re-key resumes where it stopped, and storage is not opened while one is
+//! pending.
+use crate::crypto::StorageEncryption;
+use anyhow::{anyhow, bail, Result};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// The name of the salt file in the data directory.
+pub const SALT_FILE: &str = "encryption_salt.bin";
+
+/// The name of the key-check file in the data directory.
+pub const KEY_CHECK_FILE: &str = "encryption_check.bin";
+
+/// The name of the re-key journal in the data directory.
+const REKEY_JOURNAL_FILE: &str = "rekey.json";
+
+/// The plaintext of the key-check record.
+const KEY_CHECK_PLAINTEXT: &[u8] = b"p2p-chat storage key check v1";
+
+/// Trees whose values are always encrypted, used to check the password of
+/// storage created before key-check records existed.
+const ENCRYPTED_TREES: [&str; 5] = ["friends", "history", "outbox", "known_mailboxes", "mailbox"];
+
+/// The number of values copied per batch while re-keying.
+const REKEY_BATCH_SIZE: usize = 1000;
+
+/// The phases of a re-key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RekeyPhase {
+    /// The data is being copied under the new key.
+    Copy,
+    /// The copies are replacing the originals.
+    Swap,
+}
+
+/// Represents the journal of a re-key in progress.
+#[derive(Debug, Serialize, Deserialize)]
+struct RekeyJournal {
+    /// The current phase.
+    phase: RekeyPhase,
+    /// The base64-encoded salt of the new key.
+    salt: String,
+    /// The base64-encoded key-check record of the new key.
+    key_check: String,
+}
+
+/// Summarizes a completed re-key.
+#[derive(Debug, Default)]
+pub struct RekeyReport {
+    /// The number of trees copied.
+    pub trees: usize,
+    /// The number of values re-encrypted.
+    pub reencrypted: usize,
+    /// The number of values copied as they were, because they are not encrypted.
+    pub copied: usize,
+    /// The number of attachment records re-encrypted.
+    pub attachments: usize,
+}
+
+/// Loads the encryption salt of a data directory, creating it if it doesn't exist.
+///
+/// # Errors
+///
+/// This function will return an error if the salt file cannot be read or
+/// written, or has the wrong length.
+pub fn load_or_create_salt(data_dir: &Path) -> Result<[u8; 16]> {
+    let path = data_dir.join(SALT_FILE);
+    if path.exists() {
+        let bytes = fs::read(&path)?;
+        if bytes.len() != 16 {
+            bail!(
+                "Encryption salt at '{}' has unexpected length {} (expected 16)",
+                path.display(),
+                bytes.len()
+            );
+        }
+        let mut salt = [0u8; 16];
+        salt.copy_from_slice(&bytes);
+        Ok(salt)
+    } else {
+        let generated = StorageEncryption::generate_salt();
+        fs::write(&path, generated)?;
+        Ok(generated)
+    }
+}
+
+/// Checks that the storage key matches the data directory.
+///
+/// The key-check record is written if there is none yet, after checking the
+/// key against existing data, if any.
+///
+/// # Arguments
+///
+/// * `data_dir` - The data directory.
+/// * `db` - The opened database of the data directory.
+/// * `encryption` - The storage key to check.
+///
+/// # Errors
+///
+/// This function will return an error if the key is wrong or the key-check
+/// record cannot be read or written.
+pub fn verify_key(data_dir: &Path, db: &sled::Db, encryption: &StorageEncryption) -> Result<()> {
+    let path = data_dir.join(KEY_CHECK_FILE);
+    if path.exists() {
+        if !key_check_matches(encryption, &fs::read(&path)?) {
+            bail!("Wrong encryption password for {}", data_dir.display());
+        }
+        return Ok(());
+    }
+
+    for name in ENCRYPTED_TREES {
+        if !db.tree_names().iter().any(|tree| tree == name.as_bytes()) {
+            continue;
+        }
+        if let Some((_key, value)) = db.open_tree(name)?.first()? {
+            if encryption.decrypt_value(&value).is_err() {
+                bail!(
+                    "Wrong encryption password for {}, or its data was stored without encryption",
+                    data_dir.display()
+                );
+            }
+            break;
+        }
+    }
+
+    write_atomically(&path, &encryption.encrypt_value(KEY_CHECK_PLAINTEXT)?)?;
+    info!("Wrote the storage key-check record");
+    Ok(())
+}
+
+/// Returns whether a re-key of the data directory was interrupted.
+pub fn rekey_pending(data_dir: &Path) -> bool {
+    data_dir.join(REKEY_JOURNAL_FILE).exists()
+}
+
+/// Re-encrypts the storage of a data directory under a new password, or
+/// resumes an interrupted re-key.
+///
+/// The database must not be open.
+///
+/// # Arguments
+///
+/// * `data_dir` - The data directory.
+/// * `old_password` - The current password.
+/// * `new_password` - The new password. When resuming, it must be the one
+///   the re-key was started with.
+///
+/// # Errors
+///
+/// This function will return an error if either password is wrong, the
+/// database is in use, or the data cannot be copied.
+pub fn rekey(data_dir: &Path, old_password: &str, new_password: &str) -> Result<RekeyReport> {
+    let journal_path = data_dir.join(REKEY_JOURNAL_FILE);
+    let mut report = RekeyReport::default();
+
+    let journal = if journal_path.exists() {
+        info!("Resuming the interrupted re-key");
+        let journal: RekeyJournal = serde_json::from_slice(&fs::read(&journal_path)?)?;
+        let new = StorageEncryption::new(new_password, &BASE64_STANDARD.decode(&journal.salt)?)?;
+        if !key_check_matches(&new, &BASE64_STANDARD.decode(&journal.key_check)?) {
+            bail!("The new password differs from the one the interrupted re-key was started with");
+        }
+        journal
+    } else {
+        let old = open_old_key(data_dir, old_password)?;
+        verify_key(data_dir, &sled::open(data_dir.join("db"))?, &old)?;
+
+        let salt = StorageEncryption::generate_salt();
+        let new = StorageEncryption::new(new_password, &salt)?;
+        let journal = RekeyJournal {
+            phase: RekeyPhase::Copy,
+            salt: BASE64_STANDARD.encode(salt),
+            key_check: BASE64_STANDARD.encode(new.encrypt_value(KEY_CHECK_PLAINTEXT)?),
+        };
+        write_atomically(&journal_path, &serde_json::to_vec(&journal)?)?;
+        journal
+    };
+
+    if journal.phase == RekeyPhase::Copy {
+        let old = open_old_key(data_dir, old_password)?;
+        let new = StorageEncryption::new(new_password, &BASE64_STANDARD.decode(&journal.salt)?)?;
+        copy_database(data_dir, &old, &new, &mut report)?;
+        report.attachments = copy_attachment_records(data_dir, &old, &new)?;
+
+        let journal = RekeyJournal {
+            phase: RekeyPhase::Swap,
+            ..journal
+        };
+        write_atomically(&journal_path, &serde_json::to_vec(&journal)?)?;
+        swap(data_dir, &journal)?;
+    } else {
+        swap(data_dir, &journal)?;
+    }
+
+    fs::remove_file(&journal_path)?;
+    info!("Re-key complete: {:?}", report);
+    Ok(report)
+}
+
+/// Derives the current storage key and checks it against the key-check record.
+fn open_old_key(data_dir: &Path, password: &str) -> Result<StorageEncryption> {
+    let salt_path = data_dir.join(SALT_FILE);
+    if !salt_path.exists() {
+        bail!("{} holds no encrypted storage", data_dir.display());
+    }
+
+    let old = StorageEncryption::new(password, &load_or_create_salt(data_dir)?)?;
+    let check_path = data_dir.join(KEY_CHECK_FILE);
+    if check_path.exists() && !key_check_matches(&old, &fs::read(&check_path)?) {
+        bail!("Wrong encryption password for {}", data_dir.display());
+    }
+    Ok(old)
+}
+
+/// Copies every tree of the database into the new database, continuing
+/// after the last key already copied to each tree.
+fn copy_database(
+    data_dir: &Path,
+    old: &StorageEncryption,
+    new: &StorageEncryption,
+    report: &mut RekeyReport,
+) -> Result<()> {
+    let source = sled::open(data_dir.join("db"))?;
+    let target = sled::open(data_dir.join("db.rekey"))?;
+
+    for name in source.tree_names() {
+        let source_tree = source.open_tree(&name)?;
+        let target_tree = target.open_tree(&name)?;
+        let start = match target_tree.last()? {
+            Some((key, _)) => Bound::Excluded(key),
+            None => Bound::Unbounded,
+        };
+
+        let mut batch = sled::Batch::default();
+        let mut batched = 0;
+        for result in source_tree.range::<sled::IVec, _>((start, Bound::Unbounded)) {
+            let (key, value) = result?;
+            let value = match old.decrypt_value(&value) {
+                Ok(plaintext) => {
+                    report.reencrypted += 1;
+                    new.encrypt_value(&plaintext)?
+                }
+                Err(_) => {
+                    report.copied += 1;
+                    value.to_vec()
+                }
+            };
+            batch.insert(key, value);
+            batched += 1;
+
+            if batched == REKEY_BATCH_SIZE {
+                target_tree.apply_batch(std::mem::take(&mut batch))?;
+                target.flush()?;
+                batched = 0;
+            }
+        }
+        target_tree.apply_batch(batch)?;
+        report.trees += 1;
+    }
+
+    target.flush()?;
+    Ok(())
+}
+
+/// Writes re-encrypted copies of the attachment records next to the originals.
+fn copy_attachment_records(
+    data_dir: &Path,
+    old: &StorageEncryption,
+    new: &StorageEncryption,
+) -> Result<usize> {
+    let mut count = 0;
+
+    for record in attachment_records(data_dir, "record")? {
+        let copy = record.with_file_name("record.rekey");
+        if !copy.exists() {
+            let plaintext = old
+                .decrypt_value(&fs::read(&record)?)
+                .map_err(|e| anyhow!("Failed to decrypt {}: {}", record.display(), e))?;
+            write_atomically(&copy, &new.encrypt_value(&plaintext)?)?;
+        }
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Replaces the database, the attachment records, the salt and the
+/// key-check record with their re-keyed versions. Every step can be
+/// repeated, so an interrupted swap is finished by running it again.
+fn swap(data_dir: &Path, journal: &RekeyJournal) -> Result<()> {
+    let db = data_dir.join("db");
+    let rekeyed_db = data_dir.join("db.rekey");
+    let old_db = data_dir.join("db.old");
+
+    if rekeyed_db.exists() {
+        if db.exists() {
+            fs::rename(&db, &old_db)?;
+        }
+        fs::rename(&rekeyed_db, &db)?;
+    }
+
+    for copy in attachment_records(data_dir, "record.rekey")? {
+        fs::rename(&copy, copy.with_file_name("record"))?;
+    }
+
+    write_atomically(
+        &data_dir.join(SALT_FILE),
+        &BASE64_STANDARD.decode(&journal.salt)?,
+    )?;
+    write_atomically(
+        &data_dir.join(KEY_CHECK_FILE),
+        &BASE64_STANDARD.decode(&journal.key_check)?,
+    )?;
+
+    if old_db.exists() {
+        fs::remove_dir_all(&old_db)?;
+    }
+    Ok(())
+}
+
+/// Lists the attachment files with the given name.
+fn attachment_records(data_dir: &Path, file_name: &str) -> Result<Vec<PathBuf>> {
+    let dir = data_dir.join("attachments");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut records = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path().join(file_name);
+        if path.exists() {
+            records.push(path);
+        }
+    }
+    Ok(records)
+}
+
+/// Returns whether a key-check record was written with a key.
+fn key_check_matches(encryption: &StorageEncryption, record: &[u8]) -> bool {
+    encryption
+        .decrypt_value(record)
+        .is_ok_and(|plaintext| plaintext == KEY_CHECK_PLAINTEXT)
+}
+
+/// Writes a file by writing a temporary file and renaming it.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, data)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD_PASSWORD: &str = "old password";
+    const NEW_PASSWORD: &str = "new password";
+    const MESSAGES: u32 = 10;
+
+    /// A data directory that is removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        /// Creates a data directory holding encrypted history, an unencrypted
+        /// tree and an attachment record, all under `OLD_PASSWORD`.
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("p2p-chat-test-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(dir.join("attachments").join("abc")).unwrap();
+            let old =
+                StorageEncryption::new(OLD_PASSWORD, &load_or_create_salt(&dir).unwrap()).unwrap();
+
+            let db = sled::open(dir.join("db")).unwrap();
+            let history = db.open_tree("history").unwrap();
+            for i in 0..MESSAGES {
+                let value = old.encrypt_value(format!("message {}", i).as_bytes());
+                history.insert(i.to_be_bytes(), value.unwrap()).unwrap();
+            }
+            db.open_tree("plain")
+                .unwrap()
+                .insert("key", "value")
+                .unwrap();
+            verify_key(&dir, &db, &old).unwrap();
+            db.flush().unwrap();
+
+            let record = old.encrypt_value(b"record").unwrap();
+            fs::write(dir.join("attachments").join("abc").join("record"), record).unwrap();
+            Self(dir)
+        }
+
+        /// Writes the journal of a re-key to `NEW_PASSWORD` in a phase, as if
+        /// it had been interrupted, and returns both keys.
+        fn interrupt(&self, phase: RekeyPhase) -> (StorageEncryption, StorageEncryption) {
+            let old = open_old_key(&self.0, OLD_PASSWORD).unwrap();
+            let salt = StorageEncryption::generate_salt();
+            let new = StorageEncryption::new(NEW_PASSWORD, &salt).unwrap();
+            let journal = RekeyJournal {
+                phase,
+                salt: BASE64_STANDARD.encode(salt),
+                key_check: BASE64_STANDARD.encode(new.encrypt_value(KEY_CHECK_PLAINTEXT).unwrap()),
+            };
+            write_atomically(
+                &self.0.join(REKEY_JOURNAL_FILE),
+                &serde_json::to_vec(&journal).unwrap(),
+            )
+            .unwrap();
+            (old, new)
+        }
+
+        /// Checks that everything is stored under `NEW_PASSWORD` and nothing
+        /// of the re-key is left behind.
+        fn assert_rekeyed(&self) {
+            assert!(!rekey_pending(&self.0));
+            assert!(!self.0.join("db.rekey").exists());
+            assert!(!self.0.join("db.old").exists());
+            assert!(open_old_key(&self.0, OLD_PASSWORD).is_err());
+
+            let new = open_old_key(&self.0, NEW_PASSWORD).unwrap();
+            let db = sled::open(self.0.join("db")).unwrap();
+            let history = db.open_tree("history").unwrap();
+            assert_eq!(history.len(), MESSAGES as usize);
+            for i in 0..MESSAGES {
+                let value = history.get(i.to_be_bytes()).unwrap().unwrap();
+                let plaintext = new.decrypt_value(&value).unwrap();
+                assert_eq!(plaintext, format!("message {}", i).as_bytes());
+            }
+            let plain = db.open_tree("plain").unwrap();
+            assert_eq!(plain.get("key").unwrap().unwrap(), "value");
+
+            let record = fs::read(self.0.join("attachments").join("abc").join("record")).unwrap();
+            assert_eq!(new.decrypt_value(&record).unwrap(), b"record");
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn resumes_an_interrupted_copy() {
+        let dir = TestDir::new();
+        let (old, new) = dir.interrupt(RekeyPhase::Copy);
+
+        // The first values were copied before the interruption.
+        {
+            let source = sled::open(dir.0.join("db")).unwrap();
+            let target = sled::open(dir.0.join("db.rekey")).unwrap();
+            let copied = target.open_tree("history").unwrap();
+            for result in source.open_tree("history").unwrap().iter().take(4) {
+                let (key, value) = result.unwrap();
+                let plaintext = old.decrypt_value(&value).unwrap();
+                copied
+                    .insert(key, new.encrypt_value(&plaintext).unwrap())
+                    .unwrap();
+            }
+            target.flush().unwrap();
+        }
+
+        let report = rekey(&dir.0, OLD_PASSWORD, NEW_PASSWORD).unwrap();
+        assert_eq!(report.reencrypted, MESSAGES as usize - 4);
+        assert_eq!(report.copied, 1);
+        assert_eq!(report.attachments, 1);
+        dir.assert_rekeyed();
+    }
+
+    #[test]
+    fn finishes_an_interrupted_swap() {
+        let dir = TestDir::new();
+        let (old, new) = dir.interrupt(RekeyPhase::Swap);
+        copy_database(&dir.0, &old, &new, &mut RekeyReport::default()).unwrap();
+        copy_attachment_records(&dir.0, &old, &new).unwrap();
+
+        // The swap stopped after moving the new database into place.
+        fs::rename(dir.0.join("db"), dir.0.join("db.old")).unwrap();
+        fs::rename(dir.0.join("db.rekey"), dir.0.join("db")).unwrap();
+
+        let report = rekey(&dir.0, OLD_PASSWORD, NEW_PASSWORD).unwrap();
+        assert_eq!(report.trees, 0);
+        dir.assert_rekeyed();
+    }
+
+    #[test]
+    fn resuming_requires_the_same_new_password() {
+        let dir = TestDir::new();
+        dir.interrupt(RekeyPhase::Copy);
+
+        assert!(rekey(&dir.0, OLD_PASSWORD, "another password").is_err());
+        assert!(rekey_pending(&dir.0));
+        assert!(open_old_key(&dir.0, OLD_PASSWORD).is_ok());
+    }
+}
@@ -1,6 +1,6 @@
 //! This module defines the storage interface and implementation for managing friends.
 use crate::crypto::StorageEncryption;
-use crate::types::Friend;
+use crate::types::{Device, Friend};
 use anyhow::Result;
 use async_trait::async_trait;
 use libp2p::PeerId;
@@ -9,7 +9,10 @@ use sled::Db;
 /// A trait for managing friends.
 #[async_trait]
 pub trait FriendsStore {
-    /// Adds a new friend to the store.
+    /// Adds a new friend to the store, or replaces an existing one.
+    ///
+    /// A friend added again keeps its linked devices, and stays verified if
+    /// its E2E public key is the same.
     ///
     /// # Arguments
     ///
@@ -45,6 +48,74 @@ pub trait FriendsStore {
     ///
     /// This function will return an error if the friends cannot be listed.
     async fn list_friends(&self) -> Result<Vec<Friend>>;
+
+    /// Removes a friend from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the friend to remove.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the peer was not a friend.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the friend cannot be removed.
+    async fn remove_friend(&self, peer_id: &PeerId) -> Result<bool>;
+
+    /// Marks a friend's keys as verified or unverified.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the friend.
+    /// * `verified` - Whether the keys were verified.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the peer is not a friend.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the friend cannot be updated.
+    async fn set_verified(&self, peer_id: &PeerId, verified: bool) -> Result<bool>;
+
+    /// Replaces the devices a friend linked to its identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - The `PeerId` of the friend.
+    /// * `devices` - The friend's linked devices.
+    /// * `timestamp` - The timestamp of the device list they came from.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the peer is not a friend.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the friend cannot be updated.
+    async fn set_devices(
+        &self,
+        peer_id: &PeerId,
+        devices: Vec<Device>,
+        timestamp: i64,
+    ) -> Result<bool>;
+
+    /// Finds the friend that linked a device.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `PeerId` of the device.
+    ///
+    /// # Returns
+    ///
+    /// The friend and the device, if a friend linked it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the friends cannot be read.
+    async fn find_by_device(&self, device: &PeerId) -> Result<Option<(Friend, Device)>>;
 }
 
 /// A `FriendsStore` implementation using `sled` for storage.
@@ -94,8 +165,15 @@ impl SledFriendsStore {
 
 #[async_trait]
 impl FriendsStore for SledFriendsStore {
-    async fn add_friend(&self, friend: Friend) -> Result<()> {
+    async fn add_friend(&self, mut friend: Friend) -> Result<()> {
         let key = friend.peer_id.to_bytes();
+        if let Some(data) = self.tree.get(&key)? {
+            let existing = self.deserialize_friend(&data)?;
+            friend.verified |=
+                existing.verified && existing.e2e_public_key == friend.e2e_public_key;
+            // Devices only change through the friend's signed device lists.
+            friend.devices = existing.devices;
+        }
         let value = self.serialize_friend(&friend)?;
         self.tree.insert(key, value)?;
         self.tree.flush_async().await?;
@@ -120,4 +198,92 @@ impl FriendsStore for SledFriendsStore {
 
         Ok(friends)
     }
+
+    async fn remove_friend(&self, peer_id: &PeerId) -> Result<bool> {
+        let removed = self.tree.remove(peer_id.to_bytes())?.is_some();
+        self.tree.flush_async().await?;
+        Ok(removed)
+    }
+
+    async fn set_verified(&self, peer_id: &PeerId, verified: bool) -> Result<bool> {
+        let key = peer_id.to_bytes();
+        let Some(data) = self.tree.get(&key)? else {
+            return Ok(false);
+        };
+
+        let mut friend = self.deserialize_friend(&data)?;
+        friend.verified = verified;
+        self.tree.insert(key, self.serialize_friend(&friend)?)?;
+        self.tree.flush_async().await?;
+        Ok(true)
+    }
+
+    async fn set_devices(
+        &self,
+        peer_id: &PeerId,
+        devices: Vec<Device>,
+        timestamp: i64,
+    ) -> Result<bool> {
+        let key = peer_id.to_bytes();
+        let Some(data) = self.tree.get(&key)? else {
+            return Ok(false);
+        };
+
+        let mut friend = self.deserialize_friend(&data)?;
+        friend.devices = devices;
+        friend.devices_timestamp = timestamp;
+        self.tree.insert(key, self.serialize_friend(&friend)?)?;
+        self.tree.flush_async().await?;
+        Ok(true)
+    }
+
+    async fn find_by_device(&self, device: &PeerId) -> Result<Option<(Friend, Device)>> {
+        for result in self.tree.iter() {
+            let (_key, value) = result?;
+            let friend = self.deserialize_friend(&value)?;
+            if let Some(linked) = friend.devices.iter().find(|d| d.peer_id == *device) {
+                let linked = linked.clone();
+                return Ok(Some((friend, linked)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::verified_key_changed;
+
+    fn friend(peer_id: PeerId, e2e_public_key: &[u8]) -> Friend {
+        Friend {
+            peer_id,
+            e2e_public_key: e2e_public_key.to_vec(),
+            nickname: None,
+            verified: false,
+            devices: Vec::new(),
+            devices_timestamp: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn verification_is_lost_when_the_key_changes() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let friends = SledFriendsStore::new(db, None).unwrap();
+        let peer_id = PeerId::random();
+        friends.add_friend(friend(peer_id, &[1; 32])).await.unwrap();
+        assert!(friends.set_verified(&peer_id, true).await.unwrap());
+        assert!(!friends.set_verified(&PeerId::random(), true).await.unwrap());
+
+        // Adding the friend again with the same key keeps the flag.
+        friends.add_friend(friend(peer_id, &[1; 32])).await.unwrap();
+        let verified = friends.get_friend(&peer_id).await.unwrap().unwrap();
+        assert!(verified.verified);
+
+        let replaced = friend(peer_id, &[2; 32]);
+        assert!(verified_key_changed(Some(&verified), &replaced));
+        friends.add_friend(replaced).await.unwrap();
+        let stored = friends.get_friend(&peer_id).await.unwrap().unwrap();
+        assert!(!stored.verified);
+    }
 }
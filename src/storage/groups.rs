@@ -0,0 +1,135 @@
+//! This module defines the storage interface and implementation for groups.
+use crate::crypto::StorageEncryption;
+use crate::types::Group;
+use anyhow::Result;
+use async_trait::async_trait;
+use sled::Db;
+use uuid::Uuid;
+
+/// A trait for managing the groups we are a member of.
+#[async_trait]
+pub trait GroupsStore {
+    /// Stores a group, replacing any earlier version of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The `Group` to store.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the group cannot be stored.
+    async fn store_group(&self, group: Group) -> Result<()>;
+
+    /// Retrieves a group by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the `Group` if found, otherwise `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the group cannot be retrieved.
+    async fn get_group(&self, group_id: &Uuid) -> Result<Option<Group>>;
+
+    /// Lists all groups, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the groups cannot be retrieved.
+    async fn list_groups(&self) -> Result<Vec<Group>>;
+
+    /// Removes a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group to remove.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the group cannot be removed.
+    async fn remove_group(&self, group_id: &Uuid) -> Result<()>;
+}
+
+/// A `GroupsStore` implementation using `sled` for storage.
+pub struct SledGroupsStore {
+    tree: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledGroupsStore {
+    /// Creates a new `SledGroupsStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - The optional `StorageEncryption` to use for encrypting group data.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `groups` tree cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let tree = db.open_tree("groups")?;
+        Ok(Self { tree, encryption })
+    }
+
+    /// Serializes a `Group` and encrypts it if encryption is enabled.
+    fn serialize_group(&self, group: &Group) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(group)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Decrypts and deserializes a `Group`.
+    fn deserialize_group(&self, data: &[u8]) -> Result<Group> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+#[async_trait]
+impl GroupsStore for SledGroupsStore {
+    async fn store_group(&self, group: Group) -> Result<()> {
+        let value = self.serialize_group(&group)?;
+        self.tree.insert(group.id.as_bytes(), value)?;
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+
+    async fn get_group(&self, group_id: &Uuid) -> Result<Option<Group>> {
+        match self.tree.get(group_id.as_bytes())? {
+            Some(data) => Ok(Some(self.deserialize_group(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_groups(&self) -> Result<Vec<Group>> {
+        let mut groups = Vec::new();
+
+        for result in self.tree.iter() {
+            let (_key, value) = result?;
+            groups.push(self.deserialize_group(&value)?);
+        }
+
+        groups.sort_by_key(|group| group.created_at);
+        Ok(groups)
+    }
+
+    async fn remove_group(&self, group_id: &Uuid) -> Result<()> {
+        self.tree.remove(group_id.as_bytes())?;
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+}
@@ -1,26 +1,185 @@
 //! This module defines the storage interface and implementation for managing
 //! the message history.
-use crate::crypto::StorageEncryption;
+//!
+//! Each message is stored in a versioned envelope that records how its
+//! content is represented. Messages are stored with their decrypted text,
+//! whichever way they were delivered. Versions before the envelope stored
+//! bare messages, some of them sealed with the static key; they are migrated
+//! once, when the client starts.
+//!
+//! Messages are keyed by conversation, timestamp and nonce, so a page of a
+//! conversation is a range query. A separate tree maps message IDs to these
+//! keys for lookups by ID, and the search index is kept up to date as
+//! messages are stored. Storing a message again under its ID is a no-op,
+//! while a different message with a stored ID or at a stored position is
+//! rejected, so a sender cannot hide an earlier message by reusing its ID,
+//! timestamp and nonce. The IDs of deleted messages are kept as well, so
+//! that late copies of them are not stored again.
+use crate::crypto::{Identity, StorageEncryption};
+use crate::storage::search::{SearchHit, SearchIndex};
+use crate::storage::FriendsStore;
 use crate::types::Message;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
+use base64::prelude::*;
+use chrono::Utc;
 use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
 use sled::Db;
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use tracing::{info, warn};
+
+/// The current version of the stored message envelope.
+const STORED_MESSAGE_VERSION: u8 = 1;
+
+/// The current version of the history format. Version 1 added the message
+/// envelope, version 2 the message ID index and version 3 the search index.
+const HISTORY_FORMAT_VERSION: u8 = 3;
+
+/// The key of the history format version in the metadata tree.
+const FORMAT_VERSION_KEY: &[u8] = b"format_version";
+
+/// The length of the timestamp and nonce that end a history key.
+const KEY_SUFFIX_LEN: usize = 16;
+
+/// Marks an ID index entry pointing into the direct conversations tree.
+const DIRECT_TREE_TAG: u8 = 0;
+
+/// Marks an ID index entry pointing into the group conversations tree.
+const GROUP_TREE_TAG: u8 = 1;
+
+/// Shown instead of legacy content that could not be opened during the migration.
+const UNREADABLE_NOTICE: &str = "[This message could not be decrypted]";
+
+/// How the content of a stored message is represented.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum StoredContent {
+    /// The message content is its decrypted text.
+    Plaintext,
+    /// The content was sealed with the static key by an older version and
+    /// could not be opened during the migration. It is kept here, and the
+    /// message content holds a notice instead.
+    Sealed(#[serde(with = "serde_bytes")] Vec<u8>),
+}
+
+/// The envelope a message is stored in.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StoredMessage {
+    /// The version of the envelope.
+    version: u8,
+    /// How the content of `message` is represented.
+    content: StoredContent,
+    /// The message.
+    message: Message,
+}
+
+/// A record of the history, in the current or the legacy format.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredRecord {
+    /// A message in an envelope.
+    Envelope(StoredMessage),
+    /// A bare message stored before the envelope existed.
+    Legacy(Message),
+}
+
+impl StoredRecord {
+    /// Returns the message of the record.
+    fn into_message(self) -> Message {
+        match self {
+            Self::Envelope(stored) => stored.message,
+            Self::Legacy(message) => message,
+        }
+    }
+
+    /// Returns the message of the record for updating.
+    fn message_mut(&mut self) -> &mut Message {
+        match self {
+            Self::Envelope(stored) => &mut stored.message,
+            Self::Legacy(message) => message,
+        }
+    }
+}
+
+/// An opaque position in a conversation, at a message.
+///
+/// Conversations are ordered by timestamp and nonce, which the cursor holds,
+/// so a page before or after it is a range query on the history keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageCursor {
+    timestamp: i64,
+    nonce: u64,
+}
+
+impl MessageCursor {
+    /// Returns the cursor at a message.
+    pub fn at(message: &Message) -> Self {
+        Self {
+            timestamp: message.timestamp,
+            nonce: message.nonce,
+        }
+    }
+
+    /// Encodes the cursor as URL-safe text.
+    pub fn encode(&self) -> String {
+        let mut bytes = self.timestamp.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        BASE64_URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// Decodes a cursor encoded by `encode`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the text is not a cursor.
+    pub fn decode(text: &str) -> Result<Self> {
+        let bytes = BASE64_URL_SAFE_NO_PAD.decode(text)?;
+        let bytes: [u8; 16] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Invalid cursor length"))?;
+        let (timestamp, nonce) = bytes.split_at(8);
+
+        Ok(Self {
+            timestamp: i64::from_be_bytes(timestamp.try_into()?),
+            nonce: u64::from_be_bytes(nonce.try_into()?),
+        })
+    }
+}
 
 /// A trait for storing and retrieving messages.
 #[async_trait]
 pub trait MessageStore {
     /// Stores a message in the history.
     ///
+    /// A message that is already stored is skipped.
+    ///
     /// # Arguments
     ///
     /// * `msg` - The `Message` to store.
     ///
     /// # Errors
     ///
-    /// This function will return an error if the message cannot be stored.
+    /// This function will return an error if a different message is stored
+    /// under the same ID or at the same position, or the message cannot be
+    /// stored.
     async fn store_message(&self, msg: Message) -> Result<()>;
 
+    /// Stores several messages in the history at once.
+    ///
+    /// Messages that are already stored are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `msgs` - The `Message`s to store.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a different message is stored
+    /// under the ID or at the position of one of them, in which case none is
+    /// stored, or the messages cannot be stored.
+    async fn store_messages(&self, msgs: Vec<Message>) -> Result<()>;
+
     /// Retrieves a message by its ID.
     ///
     /// # Arguments
@@ -78,7 +237,7 @@ pub trait MessageStore {
     /// This function will return an error if the messages cannot be retrieved.
     async fn get_recent_messages(&self, own_id: &PeerId, limit: usize) -> Result<Vec<Message>>;
 
-    /// Retrieves messages before a specific message in a conversation.
+    /// Retrieves the messages before a cursor in a conversation.
     ///
     /// Messages are returned in chronological order.
     ///
@@ -86,7 +245,7 @@ pub trait MessageStore {
     ///
     /// * `own_id` - The `PeerId` of the local user.
     /// * `peer` - The `PeerId` of the other participant in the conversation.
-    /// * `before_id` - The `Uuid` of the message to retrieve messages before.
+    /// * `before` - The cursor to retrieve messages before.
     /// * `limit` - The maximum number of messages to retrieve.
     ///
     /// # Returns
@@ -100,11 +259,11 @@ pub trait MessageStore {
         &self,
         own_id: &PeerId,
         peer: &PeerId,
-        before_id: &uuid::Uuid,
+        before: &MessageCursor,
         limit: usize,
     ) -> Result<Vec<Message>>;
 
-    /// Retrieves messages after a specific message in a conversation.
+    /// Retrieves the messages after a cursor in a conversation.
     ///
     /// Messages are returned in chronological order.
     ///
@@ -112,7 +271,7 @@ pub trait MessageStore {
     ///
     /// * `own_id` - The `PeerId` of the local user.
     /// * `peer` - The `PeerId` of the other participant in the conversation.
-    /// * `after_id` - The `Uuid` of the message to retrieve messages after.
+    /// * `after` - The cursor to retrieve messages after.
     /// * `limit` - The maximum number of messages to retrieve.
     ///
     /// # Returns
@@ -126,10 +285,54 @@ pub trait MessageStore {
         &self,
         own_id: &PeerId,
         peer: &PeerId,
-        after_id: &uuid::Uuid,
+        after: &MessageCursor,
         limit: usize,
     ) -> Result<Vec<Message>>;
 
+    /// Searches the text of the messages in the history.
+    ///
+    /// A message matches if it contains every word of the query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The words to search for.
+    /// * `peer` - The `PeerId` to restrict the search to messages from or to, if any.
+    /// * `limit` - The maximum number of matches to retrieve.
+    /// * `context` - The number of messages to retrieve before and after each match.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` of `SearchHit`s, best first, with the messages around them in
+    /// their conversation.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the search index or the
+    /// messages cannot be read.
+    async fn search(
+        &self,
+        query: &str,
+        peer: Option<&PeerId>,
+        limit: usize,
+        context: usize,
+    ) -> Result<Vec<SearchHit>>;
+
+    /// Retrieves the message history of a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `limit` - The maximum number of messages to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` of the most recent `Message`s in the group, in chronological order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the history cannot be retrieved.
+    async fn get_group_history(&self, group_id: &uuid::Uuid, limit: usize) -> Result<Vec<Message>>;
+
     /// Updates the delivery status of a message.
     ///
     /// # Arguments
@@ -145,11 +348,55 @@ pub trait MessageStore {
         msg_id: &uuid::Uuid,
         status: crate::types::DeliveryStatus,
     ) -> Result<()>;
+
+    /// Deletes a message from the history and the search index.
+    ///
+    /// Its ID is remembered, so the message is not stored again.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg_id` - The `Uuid` of the message to delete.
+    ///
+    /// # Returns
+    ///
+    /// The deleted `Message`, or `None` if it was not stored.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message cannot be deleted.
+    async fn delete_message(&self, msg_id: &uuid::Uuid) -> Result<Option<Message>>;
+
+    /// Deletes the whole conversation between two peers from the history and
+    /// the search index.
+    ///
+    /// The IDs of the messages are remembered, so they are not stored again.
+    ///
+    /// # Arguments
+    ///
+    /// * `own_id` - The `PeerId` of the local user.
+    /// * `peer` - The `PeerId` of the other peer in the conversation.
+    ///
+    /// # Returns
+    ///
+    /// The deleted `Message`s, in chronological order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the conversation cannot be deleted.
+    async fn delete_conversation(&self, own_id: &PeerId, peer: &PeerId) -> Result<Vec<Message>>;
 }
 
 /// A `MessageStore` implementation using `sled` for storage.
+///
+/// Direct conversations and group conversations are kept in separate trees,
+/// keyed by conversation ID and by group ID respectively.
 pub struct MessageHistory {
     tree: sled::Tree,
+    group_tree: sled::Tree,
+    id_tree: sled::Tree,
+    deleted_tree: sled::Tree,
+    meta_tree: sled::Tree,
+    search: SearchIndex,
     encryption: Option<StorageEncryption>,
 }
 
@@ -163,10 +410,206 @@ impl MessageHistory {
     ///
     /// # Errors
     ///
-    /// Returns an error if the underlying `sled` tree cannot be opened.
+    /// Returns an error if the underlying `sled` trees cannot be opened.
     pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
         let tree = db.open_tree("history")?;
-        Ok(Self { tree, encryption })
+        let group_tree = db.open_tree("group_history")?;
+        let id_tree = db.open_tree("history_ids")?;
+        let deleted_tree = db.open_tree("history_deleted")?;
+        let meta_tree = db.open_tree("history_meta")?;
+        let search = SearchIndex::open(&db, &meta_tree, encryption.clone())?;
+        Ok(Self {
+            tree,
+            group_tree,
+            id_tree,
+            deleted_tree,
+            meta_tree,
+            search,
+            encryption,
+        })
+    }
+
+    /// Migrates the history stored by older versions, once.
+    ///
+    /// Records stored before the message envelope are wrapped in it. Direct
+    /// messages that older versions stored sealed with the static key are
+    /// opened with the conversation partner's key; content that cannot be
+    /// opened is kept in the envelope and shown as a notice. The message ID
+    /// index and the search index are then built.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Our identity, used to open sealed content.
+    /// * `friends` - The friends store, for the keys of conversation partners.
+    ///
+    /// # Returns
+    ///
+    /// The number of records wrapped in the envelope.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the history cannot be read or written.
+    pub async fn migrate(
+        &self,
+        identity: &Identity,
+        friends: &(dyn FriendsStore + Send + Sync),
+    ) -> Result<usize> {
+        let version = self
+            .meta_tree
+            .get(FORMAT_VERSION_KEY)?
+            .and_then(|version| version.first().copied())
+            .unwrap_or(0);
+        if version >= HISTORY_FORMAT_VERSION {
+            return Ok(0);
+        }
+
+        let mut migrated = 0;
+        if version < 1 {
+            migrated = self.migrate_envelopes(identity, friends).await?;
+        }
+        if version < 2 {
+            self.build_id_index().await?;
+        }
+        if version < 3 {
+            self.build_search_index().await?;
+        }
+
+        self.meta_tree
+            .insert(FORMAT_VERSION_KEY, &[HISTORY_FORMAT_VERSION])?;
+        self.meta_tree.flush_async().await?;
+        Ok(migrated)
+    }
+
+    /// Wraps the records stored before the message envelope in it.
+    async fn migrate_envelopes(
+        &self,
+        identity: &Identity,
+        friends: &(dyn FriendsStore + Send + Sync),
+    ) -> Result<usize> {
+        let mut migrated = 0;
+        let mut unreadable = 0;
+        for tree in [&self.tree, &self.group_tree] {
+            for result in tree.iter() {
+                let (key, value) = result?;
+                let StoredRecord::Legacy(mut message) = self.deserialize_record(&value)? else {
+                    continue;
+                };
+
+                let content = match self
+                    .open_legacy_content(identity, friends, &message)
+                    .await?
+                {
+                    Some(plaintext) => {
+                        message.content = plaintext;
+                        StoredContent::Plaintext
+                    }
+                    None => {
+                        unreadable += 1;
+                        let sealed =
+                            std::mem::replace(&mut message.content, UNREADABLE_NOTICE.into());
+                        StoredContent::Sealed(sealed)
+                    }
+                };
+                let record = StoredRecord::Envelope(StoredMessage {
+                    version: STORED_MESSAGE_VERSION,
+                    content,
+                    message,
+                });
+                tree.insert(key, self.serialize_record(&record)?)?;
+                migrated += 1;
+            }
+            tree.flush_async().await?;
+        }
+
+        if migrated > 0 {
+            info!("Migrated {} messages in the history", migrated);
+        }
+        if unreadable > 0 {
+            warn!(
+                "{} messages in the history could not be decrypted during the migration",
+                unreadable
+            );
+        }
+        Ok(migrated)
+    }
+
+    /// Builds the index from message IDs to history keys.
+    async fn build_id_index(&self) -> Result<()> {
+        let mut indexed = 0;
+        for (tree, tag) in [
+            (&self.tree, DIRECT_TREE_TAG),
+            (&self.group_tree, GROUP_TREE_TAG),
+        ] {
+            for result in tree.iter() {
+                let (key, value) = result?;
+                let message = self.deserialize_message(&value)?;
+                self.id_tree
+                    .insert(message.id.as_bytes(), Self::make_index_entry(tag, &key))?;
+                indexed += 1;
+            }
+        }
+        self.id_tree.flush_async().await?;
+
+        if indexed > 0 {
+            info!("Indexed {} messages in the history", indexed);
+        }
+        Ok(())
+    }
+
+    /// Builds the search index from the stored messages.
+    async fn build_search_index(&self) -> Result<()> {
+        self.search.clear()?;
+        let mut indexed = 0;
+        for tree in [&self.tree, &self.group_tree] {
+            for result in tree.iter() {
+                let (_key, value) = result?;
+                self.search.index(&self.deserialize_message(&value)?)?;
+                indexed += 1;
+            }
+        }
+        self.search.flush().await?;
+
+        if indexed > 0 {
+            info!("Indexed {} messages for search", indexed);
+        }
+        Ok(())
+    }
+
+    /// Returns the plaintext of a legacy record.
+    ///
+    /// Legacy records hold plaintext, or, for direct messages stored before
+    /// the ratchet, content sealed with the static key.
+    ///
+    /// # Returns
+    ///
+    /// The plaintext, or `None` if the content is sealed and cannot be opened.
+    async fn open_legacy_content(
+        &self,
+        identity: &Identity,
+        friends: &(dyn FriendsStore + Send + Sync),
+        message: &Message,
+    ) -> Result<Option<Vec<u8>>> {
+        if message.group_id.is_none() {
+            let other_peer = if message.sender == identity.peer_id {
+                &message.recipient
+            } else {
+                &message.sender
+            };
+            if let Some(friend) = friends.get_friend(other_peer).await? {
+                if let Some(plaintext) =
+                    identity.open_legacy(&friend.e2e_public_key, &message.content)
+                {
+                    return Ok(Some(plaintext));
+                }
+            }
+        }
+
+        // Content that does not open is plaintext, unless it is not text.
+        if std::str::from_utf8(&message.content).is_ok() {
+            Ok(Some(message.content.clone()))
+        } else {
+            Ok(None)
+        }
     }
 
     /// Creates a canonical, ordered conversation ID from two `PeerId`s.
@@ -193,9 +636,128 @@ impl MessageHistory {
         key
     }
 
-    /// Serializes a `Message` and encrypts it if encryption is enabled.
+    /// Returns the tree a new message belongs in, the tag of that tree in
+    /// the ID index, and the key of the message.
+    fn placement(&self, msg: &Message) -> (&sled::Tree, u8, Vec<u8>) {
+        match msg.group_id {
+            Some(group_id) => (
+                &self.group_tree,
+                GROUP_TREE_TAG,
+                Self::make_composite_key(group_id.as_bytes(), msg.timestamp, msg.nonce),
+            ),
+            None => (
+                &self.tree,
+                DIRECT_TREE_TAG,
+                Self::make_composite_key(
+                    &Self::get_conversation_id(&msg.sender, &msg.recipient),
+                    msg.timestamp,
+                    msg.nonce,
+                ),
+            ),
+        }
+    }
+
+    /// Looks a stored message up in the ID index.
+    ///
+    /// # Returns
+    ///
+    /// The tree the message is stored in and its key, if it is indexed.
+    fn locate(&self, msg_id: &uuid::Uuid) -> Result<Option<(&sled::Tree, Vec<u8>)>> {
+        let Some(entry) = self.id_tree.get(msg_id.as_bytes())? else {
+            return Ok(None);
+        };
+
+        let (tag, key) = entry
+            .split_first()
+            .ok_or_else(|| anyhow!("Empty history index entry for message {}", msg_id))?;
+        let tree = match *tag {
+            DIRECT_TREE_TAG => &self.tree,
+            GROUP_TREE_TAG => &self.group_tree,
+            tag => bail!("Unknown history tree {} for message {}", tag, msg_id),
+        };
+        Ok(Some((tree, key.to_vec())))
+    }
+
+    /// Retrieves the messages around a stored message in its conversation.
+    ///
+    /// # Returns
+    ///
+    /// Up to `count` messages before and after it, in chronological order.
+    fn surrounding(
+        &self,
+        tree: &sled::Tree,
+        key: &[u8],
+        count: usize,
+    ) -> Result<(Vec<Message>, Vec<Message>)> {
+        let conversation_id = &key[..key.len().saturating_sub(KEY_SUFFIX_LEN)];
+
+        let mut before = Vec::new();
+        for result in tree.range(conversation_id..key).rev().take(count) {
+            let (_key, value) = result?;
+            before.push(self.deserialize_message(&value)?);
+        }
+        before.reverse();
+
+        let mut after = Vec::new();
+        for result in tree.range::<&[u8], _>((Bound::Excluded(key), Bound::Unbounded)) {
+            let (key, value) = result?;
+            if after.len() >= count || !key.starts_with(conversation_id) {
+                break;
+            }
+            after.push(self.deserialize_message(&value)?);
+        }
+
+        Ok((before, after))
+    }
+
+    /// Checks a message against the one stored under its ID, if any.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the same message is already stored, or was deleted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a different message is stored
+    /// under the ID.
+    fn is_new(&self, msg: &Message) -> Result<bool> {
+        if self.deleted_tree.contains_key(msg.id.as_bytes())? {
+            return Ok(false);
+        }
+        let Some((tree, key)) = self.locate(&msg.id)? else {
+            return Ok(true);
+        };
+        // An index entry whose message is gone is replaced.
+        let Some(value) = tree.get(key)? else {
+            return Ok(true);
+        };
+
+        if !same_message(&self.deserialize_message(&value)?, msg) {
+            bail!("A different message is already stored with ID {}", msg.id);
+        }
+        Ok(false)
+    }
+
+    /// Creates an ID index entry pointing to a key in a tree.
+    fn make_index_entry(tag: u8, key: &[u8]) -> Vec<u8> {
+        let mut entry = vec![tag];
+        entry.extend_from_slice(key);
+        entry
+    }
+
+    /// Serializes a `Message` with plaintext content in an envelope and
+    /// encrypts it if encryption is enabled.
     fn serialize_message(&self, msg: &Message) -> Result<Vec<u8>> {
-        let serialized = serde_json::to_vec(msg)?;
+        self.serialize_record(&StoredRecord::Envelope(StoredMessage {
+            version: STORED_MESSAGE_VERSION,
+            content: StoredContent::Plaintext,
+            message: msg.clone(),
+        }))
+    }
+
+    /// Serializes a record and encrypts it if encryption is enabled.
+    fn serialize_record(&self, record: &StoredRecord) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(record)?;
 
         if let Some(ref encryption) = self.encryption {
             encryption.encrypt_value(&serialized)
@@ -206,6 +768,11 @@ impl MessageHistory {
 
     /// Decrypts and deserializes a `Message`.
     fn deserialize_message(&self, data: &[u8]) -> Result<Message> {
+        Ok(self.deserialize_record(data)?.into_message())
+    }
+
+    /// Decrypts and deserializes a record.
+    fn deserialize_record(&self, data: &[u8]) -> Result<StoredRecord> {
         let decrypted = if let Some(ref encryption) = self.encryption {
             encryption.decrypt_value(data)?
         } else {
@@ -219,27 +786,90 @@ impl MessageHistory {
 #[async_trait]
 impl MessageStore for MessageHistory {
     async fn store_message(&self, msg: Message) -> Result<()> {
-        let conversation_id = Self::get_conversation_id(&msg.sender, &msg.recipient);
-        let key = Self::make_composite_key(&conversation_id, msg.timestamp, msg.nonce);
+        if !self.is_new(&msg)? {
+            return Ok(());
+        }
+
+        let (tree, tag, key) = self.placement(&msg);
         let value = self.serialize_message(&msg)?;
 
-        self.tree.insert(key, value)?;
-        self.tree.flush_async().await?;
+        // The position is chosen by the sender, so it may already be taken.
+        if tree
+            .compare_and_swap(&key, None as Option<&[u8]>, Some(value))?
+            .is_err()
+        {
+            bail!(
+                "Another message is already stored at the position of message {}",
+                msg.id
+            );
+        }
+        self.id_tree
+            .insert(msg.id.as_bytes(), Self::make_index_entry(tag, &key))?;
+        self.search.index(&msg)?;
+        tree.flush_async().await?;
+        self.id_tree.flush_async().await?;
+        self.search.flush().await?;
         Ok(())
     }
 
-    async fn get_message_by_id(&self, msg_id: &uuid::Uuid) -> Result<Option<Message>> {
-        // Scan all messages to find the one with the given ID.
-        for result in self.tree.iter() {
-            let (_key, value) = result?;
-            let msg = self.deserialize_message(&value)?;
+    async fn store_messages(&self, msgs: Vec<Message>) -> Result<()> {
+        let mut direct = sled::Batch::default();
+        let mut groups = sled::Batch::default();
+        let mut ids = sled::Batch::default();
 
-            if msg.id == *msg_id {
-                return Ok(Some(msg));
+        let mut batched: HashMap<uuid::Uuid, &Message> = HashMap::new();
+        for msg in &msgs {
+            if let Some(other) = batched.insert(msg.id, msg) {
+                if !same_message(other, msg) {
+                    bail!("Two different messages have the ID {}", msg.id);
+                }
+            }
+        }
+        let mut new = Vec::new();
+        let mut positions = HashSet::new();
+        for msg in batched.into_values() {
+            if !self.is_new(msg)? {
+                continue;
+            }
+            let (tree, tag, key) = self.placement(msg);
+            if tree.contains_key(&key)? || !positions.insert((tag, key.clone())) {
+                bail!(
+                    "Another message is already stored at the position of message {}",
+                    msg.id
+                );
             }
+            new.push((msg, tag, key));
         }
 
-        Ok(None)
+        for (msg, tag, key) in new {
+            ids.insert(msg.id.as_bytes(), Self::make_index_entry(tag, &key));
+            let value = self.serialize_message(msg)?;
+            self.search.index(msg)?;
+            if tag == GROUP_TREE_TAG {
+                groups.insert(key, value);
+            } else {
+                direct.insert(key, value);
+            }
+        }
+
+        self.tree.apply_batch(direct)?;
+        self.group_tree.apply_batch(groups)?;
+        self.id_tree.apply_batch(ids)?;
+        self.tree.flush_async().await?;
+        self.group_tree.flush_async().await?;
+        self.id_tree.flush_async().await?;
+        self.search.flush().await?;
+        Ok(())
+    }
+
+    async fn get_message_by_id(&self, msg_id: &uuid::Uuid) -> Result<Option<Message>> {
+        let Some((tree, key)) = self.locate(msg_id)? else {
+            return Ok(None);
+        };
+
+        tree.get(key)?
+            .map(|value| self.deserialize_message(&value))
+            .transpose()
     }
 
     async fn get_history(
@@ -263,28 +893,33 @@ impl MessageStore for MessageHistory {
     }
 
     async fn get_recent_messages(&self, own_id: &PeerId, limit: usize) -> Result<Vec<Message>> {
-        let tree = self.tree.clone();
-        let encryption = self.encryption.clone();
-        let own_id = *own_id;
-
-        let mut messages: Vec<Message> =
-            tokio::task::spawn_blocking(move || -> Result<Vec<Message>> {
-                let mut collected = Vec::new();
-                for result in tree.iter() {
-                    let (_key, value) = result?;
-                    let decrypted = if let Some(ref enc) = encryption {
-                        enc.decrypt_value(&value)?
-                    } else {
-                        value.to_vec()
-                    };
-                    let message: Message = serde_json::from_slice(&decrypted)?;
-                    if message.sender == own_id || message.recipient == own_id {
-                        collected.push(message);
-                    }
+        let mut messages = Vec::new();
+
+        // Only the latest messages of each conversation can be among the
+        // latest overall, so each conversation is read from its end and the
+        // next one is found by skipping past its last key.
+        let mut start = Bound::Unbounded;
+        while let Some(result) = self
+            .tree
+            .range::<Vec<u8>, _>((start, Bound::Unbounded))
+            .next()
+        {
+            let (key, _value) = result?;
+            let conversation_id = &key[..key.len().saturating_sub(KEY_SUFFIX_LEN)];
+
+            for result in self.tree.scan_prefix(conversation_id).rev().take(limit) {
+                let (_key, value) = result?;
+                let message = self.deserialize_message(&value)?;
+                if message.sender == *own_id || message.recipient == *own_id {
+                    messages.push(message);
                 }
-                Ok(collected)
-            })
-            .await??;
+            }
+
+            let Some(last) = self.tree.scan_prefix(conversation_id).next_back() else {
+                break;
+            };
+            start = Bound::Excluded(last?.0.to_vec());
+        }
 
         messages.sort_by_key(|msg| (msg.timestamp, msg.nonce));
 
@@ -300,49 +935,21 @@ impl MessageStore for MessageHistory {
         &self,
         own_id: &PeerId,
         peer: &PeerId,
-        before_id: &uuid::Uuid,
+        before: &MessageCursor,
         limit: usize,
     ) -> Result<Vec<Message>> {
         let conversation_id = Self::get_conversation_id(own_id, peer);
-
-        // First, find the message with before_id to get its timestamp.
-        let mut before_timestamp = None;
-        let mut before_nonce = None;
-
-        for result in self.tree.scan_prefix(&conversation_id) {
-            let (_key, value) = result?;
-            let msg = self.deserialize_message(&value)?;
-            if msg.id == *before_id {
-                before_timestamp = Some(msg.timestamp);
-                before_nonce = Some(msg.nonce);
-                break;
-            }
-        }
-
-        let (before_ts, before_n) = match (before_timestamp, before_nonce) {
-            (Some(ts), Some(n)) => (ts, n),
-            _ => return Ok(Vec::new()), // Message not found.
-        };
-
-        // Collect all messages before this timestamp+nonce.
+        let end = Self::make_composite_key(&conversation_id, before.timestamp, before.nonce);
         let mut messages = Vec::new();
-        for result in self.tree.scan_prefix(&conversation_id) {
-            let (_key, value) = result?;
-            let msg = self.deserialize_message(&value)?;
 
-            // Include messages that are strictly before (timestamp, nonce).
-            if msg.timestamp < before_ts || (msg.timestamp == before_ts && msg.nonce < before_n) {
-                messages.push(msg);
-            }
-        }
-
-        // Sort by timestamp and nonce, take last N (most recent before the target).
-        messages.sort_by_key(|msg| (msg.timestamp, msg.nonce));
-        if messages.len() > limit {
-            let start_idx = messages.len() - limit;
-            messages.drain(0..start_idx);
+        // Iterate in reverse from the cursor to get the closest messages first.
+        for result in self.tree.range(conversation_id..end).rev().take(limit) {
+            let (_key, value) = result?;
+            messages.push(self.deserialize_message(&value)?);
         }
 
+        // Reverse again to get chronological order.
+        messages.reverse();
         Ok(messages)
     }
 
@@ -350,48 +957,82 @@ impl MessageStore for MessageHistory {
         &self,
         own_id: &PeerId,
         peer: &PeerId,
-        after_id: &uuid::Uuid,
+        after: &MessageCursor,
         limit: usize,
     ) -> Result<Vec<Message>> {
         let conversation_id = Self::get_conversation_id(own_id, peer);
+        let start = Self::make_composite_key(&conversation_id, after.timestamp, after.nonce);
+        let mut messages = Vec::new();
 
-        // First, find the message with after_id to get its timestamp.
-        let mut after_timestamp = None;
-        let mut after_nonce = None;
-
-        for result in self.tree.scan_prefix(&conversation_id) {
-            let (_key, value) = result?;
-            let msg = self.deserialize_message(&value)?;
-            if msg.id == *after_id {
-                after_timestamp = Some(msg.timestamp);
-                after_nonce = Some(msg.nonce);
+        for result in self
+            .tree
+            .range::<Vec<u8>, _>((Bound::Excluded(start), Bound::Unbounded))
+        {
+            let (key, value) = result?;
+            if messages.len() >= limit || !key.starts_with(&conversation_id) {
                 break;
             }
+            messages.push(self.deserialize_message(&value)?);
         }
 
-        let (after_ts, after_n) = match (after_timestamp, after_nonce) {
-            (Some(ts), Some(n)) => (ts, n),
-            _ => return Ok(Vec::new()), // Message not found.
-        };
+        Ok(messages)
+    }
 
-        // Collect messages after this timestamp+nonce.
-        let mut messages = Vec::new();
-        for result in self.tree.scan_prefix(&conversation_id) {
-            let (_key, value) = result?;
-            let msg = self.deserialize_message(&value)?;
+    async fn search(
+        &self,
+        query: &str,
+        peer: Option<&PeerId>,
+        limit: usize,
+        context: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
 
-            // Include messages that are strictly after (timestamp, nonce).
-            if msg.timestamp > after_ts || (msg.timestamp == after_ts && msg.nonce > after_n) {
-                messages.push(msg);
-                if messages.len() >= limit {
-                    break;
-                }
+        for (msg_id, score) in self.search.search(query)? {
+            if hits.len() >= limit {
+                break;
             }
+
+            // Skip index entries of messages that are no longer stored.
+            let Some((tree, key)) = self.locate(&msg_id)? else {
+                continue;
+            };
+            let Some(value) = tree.get(&key)? else {
+                continue;
+            };
+
+            let message = self.deserialize_message(&value)?;
+            if peer.is_some_and(|peer| message.sender != *peer && message.recipient != *peer) {
+                continue;
+            }
+
+            let (before, after) = self.surrounding(tree, &key, context)?;
+            hits.push(SearchHit {
+                message,
+                score,
+                before,
+                after,
+            });
         }
 
-        // Sort by timestamp and nonce.
-        messages.sort_by_key(|msg| (msg.timestamp, msg.nonce));
+        Ok(hits)
+    }
+
+    async fn get_group_history(&self, group_id: &uuid::Uuid, limit: usize) -> Result<Vec<Message>> {
+        let mut messages = Vec::new();
+
+        // Iterate in reverse to get most recent messages first.
+        for result in self
+            .group_tree
+            .scan_prefix(group_id.as_bytes())
+            .rev()
+            .take(limit)
+        {
+            let (_key, value) = result?;
+            messages.push(self.deserialize_message(&value)?);
+        }
 
+        // Reverse again to get chronological order.
+        messages.reverse();
         Ok(messages)
     }
 
@@ -400,24 +1041,411 @@ impl MessageStore for MessageHistory {
         msg_id: &uuid::Uuid,
         status: crate::types::DeliveryStatus,
     ) -> Result<()> {
-        // Scan all messages to find the one with the given ID.
-        for result in self.tree.iter() {
+        // Message not found - not necessarily an error, might be old/deleted.
+        let Some((tree, key)) = self.locate(msg_id)? else {
+            return Ok(());
+        };
+        let Some(value) = tree.get(&key)? else {
+            return Ok(());
+        };
+
+        // Update the delivery status, keeping the content representation.
+        let mut record = self.deserialize_record(&value)?;
+        record.message_mut().delivery_status = status;
+
+        tree.insert(key, self.serialize_record(&record)?)?;
+        tree.flush_async().await?;
+        Ok(())
+    }
+
+    async fn delete_message(&self, msg_id: &uuid::Uuid) -> Result<Option<Message>> {
+        let Some((tree, key)) = self.locate(msg_id)? else {
+            return Ok(None);
+        };
+
+        let deleted = tree
+            .remove(&key)?
+            .map(|value| self.deserialize_message(&value))
+            .transpose()?;
+        self.id_tree.remove(msg_id.as_bytes())?;
+        self.deleted_tree.insert(
+            msg_id.as_bytes(),
+            &Utc::now().timestamp_millis().to_be_bytes(),
+        )?;
+        self.search.remove(msg_id)?;
+        tree.flush_async().await?;
+        self.id_tree.flush_async().await?;
+        self.deleted_tree.flush_async().await?;
+        self.search.flush().await?;
+        Ok(deleted)
+    }
+
+    async fn delete_conversation(&self, own_id: &PeerId, peer: &PeerId) -> Result<Vec<Message>> {
+        let conversation_id = Self::get_conversation_id(own_id, peer);
+        let mut messages = Vec::new();
+        let mut keys = sled::Batch::default();
+        let mut ids = sled::Batch::default();
+        let mut tombstones = sled::Batch::default();
+        let deleted_at = Utc::now().timestamp_millis().to_be_bytes();
+
+        for result in self.tree.scan_prefix(&conversation_id) {
             let (key, value) = result?;
-            let mut msg = self.deserialize_message(&value)?;
+            let msg = self.deserialize_message(&value)?;
+            keys.remove(key);
+            ids.remove(msg.id.as_bytes());
+            tombstones.insert(msg.id.as_bytes(), &deleted_at);
+            self.search.remove(&msg.id)?;
+            messages.push(msg);
+        }
+
+        self.tree.apply_batch(keys)?;
+        self.id_tree.apply_batch(ids)?;
+        self.deleted_tree.apply_batch(tombstones)?;
+        self.tree.flush_async().await?;
+        self.id_tree.flush_async().await?;
+        self.deleted_tree.flush_async().await?;
+        self.search.flush().await?;
+        Ok(messages)
+    }
+}
+
+/// Returns whether two messages are the same message: the same conversation,
+/// position and content. The delivery status may differ.
+fn same_message(a: &Message, b: &Message) -> bool {
+    a.sender == b.sender
+        && a.recipient == b.recipient
+        && a.group_id == b.group_id
+        && a.timestamp == b.timestamp
+        && a.nonce == b.nonce
+        && a.content == b.content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SledFriendsStore;
+    use crate::types::{DeliveryStatus, Friend};
+
+    fn message(sender: PeerId, recipient: PeerId, timestamp: i64, content: &[u8]) -> Message {
+        Message {
+            id: uuid::Uuid::new_v4(),
+            sender,
+            recipient,
+            timestamp,
+            content: content.to_vec(),
+            nonce: rand::random(),
+            delivery_status: DeliveryStatus::Delivered,
+            signature: Vec::new(),
+            group_id: None,
+            attachment: None,
+            sender_pub_key: Vec::new(),
+        }
+    }
 
-            if msg.id == *msg_id {
-                // Update the delivery status.
-                msg.delivery_status = status;
+    /// Stores a record the way versions before the envelope did.
+    fn store_legacy(db: &Db, msg: &Message) {
+        let key = MessageHistory::make_composite_key(
+            &MessageHistory::get_conversation_id(&msg.sender, &msg.recipient),
+            msg.timestamp,
+            msg.nonce,
+        );
+        let value = serde_json::to_vec(msg).unwrap();
+        db.open_tree("history").unwrap().insert(key, value).unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrates_legacy_records_to_the_envelope() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let identity = Identity::generate().unwrap();
+        let peer = Identity::generate().unwrap();
+        let friends = SledFriendsStore::new(db.clone(), None).unwrap();
+        friends
+            .add_friend(Friend {
+                peer_id: peer.peer_id,
+                e2e_public_key: peer.hpke_public_key(),
+                nickname: None,
+                verified: false,
+                devices: Vec::new(),
+                devices_timestamp: 0,
+            })
+            .await
+            .unwrap();
+
+        let plain = message(identity.peer_id, peer.peer_id, 1, b"plain text");
+        let sealed_content = peer
+            .encrypt_for(&identity.hpke_public_key(), b"sealed text")
+            .unwrap();
+        let sealed = message(peer.peer_id, identity.peer_id, 2, &sealed_content);
+        let unreadable = message(peer.peer_id, identity.peer_id, 3, &[0xff, 0xfe, 0xfd]);
+        for msg in [&plain, &sealed, &unreadable] {
+            store_legacy(&db, msg);
+        }
+
+        let history = MessageHistory::new(db, None).unwrap();
+        assert_eq!(history.migrate(&identity, &friends).await.unwrap(), 3);
+        assert_eq!(history.migrate(&identity, &friends).await.unwrap(), 0);
 
-                // Re-serialize and store.
-                let new_value = self.serialize_message(&msg)?;
-                self.tree.insert(key, new_value)?;
-                self.tree.flush_async().await?;
-                return Ok(());
+        let texts: Vec<String> = history
+            .get_history(&identity.peer_id, &peer.peer_id, 10)
+            .await
+            .unwrap()
+            .iter()
+            .map(|msg| msg.text())
+            .collect();
+        assert_eq!(texts, ["plain text", "sealed text", UNREADABLE_NOTICE]);
+
+        // Every record is now in an envelope, and content that could not be
+        // opened is kept in it.
+        for result in history.tree.iter() {
+            let (_key, value) = result.unwrap();
+            let StoredRecord::Envelope(stored) = history.deserialize_record(&value).unwrap() else {
+                panic!("A legacy record was not migrated");
+            };
+            assert_eq!(stored.version, STORED_MESSAGE_VERSION);
+            match stored.content {
+                StoredContent::Sealed(content) => {
+                    assert_eq!(stored.message.id, unreadable.id);
+                    assert_eq!(content, unreadable.content);
+                }
+                StoredContent::Plaintext => assert_ne!(stored.message.id, unreadable.id),
             }
         }
+    }
 
-        // Message not found - not necessarily an error, might be old/deleted.
-        Ok(())
+    fn temporary_history() -> MessageHistory {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        MessageHistory::new(db, None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn builds_the_id_index_of_older_histories() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let identity = Identity::generate().unwrap();
+        let peer = PeerId::random();
+        let friends = SledFriendsStore::new(db.clone(), None).unwrap();
+
+        // Version 1 stored envelopes, but no ID index.
+        let history = MessageHistory::new(db, None).unwrap();
+        let msgs: Vec<Message> = (0..3)
+            .map(|i| message(identity.peer_id, peer, i, b"text"))
+            .collect();
+        for msg in &msgs {
+            let (tree, _, key) = history.placement(msg);
+            tree.insert(key, history.serialize_message(msg).unwrap())
+                .unwrap();
+        }
+        history.meta_tree.insert(FORMAT_VERSION_KEY, &[1]).unwrap();
+        assert!(history
+            .get_message_by_id(&msgs[1].id)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(history.migrate(&identity, &friends).await.unwrap(), 0);
+        for msg in &msgs {
+            let found = history.get_message_by_id(&msg.id).await.unwrap().unwrap();
+            assert_eq!(found.timestamp, msg.timestamp);
+        }
+        assert_eq!(history.search("text", None, 10, 0).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn pages_conversations_with_cursors() {
+        let history = temporary_history();
+        let own = PeerId::random();
+        let peer = PeerId::random();
+        for i in 0..10 {
+            let (sender, recipient) = if i % 2 == 0 { (own, peer) } else { (peer, own) };
+            let text = format!("message {}", i);
+            history
+                .store_message(message(sender, recipient, i, text.as_bytes()))
+                .await
+                .unwrap();
+        }
+        // Another conversation must not show up in the pages.
+        history
+            .store_message(message(own, PeerId::random(), 5, b"other"))
+            .await
+            .unwrap();
+
+        let timestamps = |msgs: &[Message]| msgs.iter().map(|m| m.timestamp).collect::<Vec<_>>();
+        let latest = history.get_history(&own, &peer, 4).await.unwrap();
+        assert_eq!(timestamps(&latest), [6, 7, 8, 9]);
+
+        let cursor = MessageCursor::decode(&MessageCursor::at(&latest[0]).encode()).unwrap();
+        assert_eq!(cursor, MessageCursor::at(&latest[0]));
+        let before = history
+            .get_messages_before(&own, &peer, &cursor, 4)
+            .await
+            .unwrap();
+        assert_eq!(timestamps(&before), [2, 3, 4, 5]);
+        let first = history
+            .get_messages_before(&own, &peer, &MessageCursor::at(&before[0]), 4)
+            .await
+            .unwrap();
+        assert_eq!(timestamps(&first), [0, 1]);
+
+        let after = history
+            .get_messages_after(&own, &peer, &MessageCursor::at(&first[1]), 3)
+            .await
+            .unwrap();
+        assert_eq!(timestamps(&after), [2, 3, 4]);
+        let end = history
+            .get_messages_after(&own, &peer, &MessageCursor::at(&latest[3]), 3)
+            .await
+            .unwrap();
+        assert!(end.is_empty());
+
+        assert!(MessageCursor::decode("not a cursor").is_err());
+    }
+
+    #[tokio::test]
+    async fn recent_messages_span_conversations() {
+        let history = temporary_history();
+        let own = PeerId::random();
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        for i in 0..12 {
+            history
+                .store_message(message(own, peers[i as usize % 3], i, b"text"))
+                .await
+                .unwrap();
+        }
+
+        let recent = history.get_recent_messages(&own, 5).await.unwrap();
+        let timestamps: Vec<i64> = recent.iter().map(|m| m.timestamp).collect();
+        assert_eq!(timestamps, [7, 8, 9, 10, 11]);
+        assert!(history
+            .get_recent_messages(&own, 0)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn duplicate_ids_do_not_replace_stored_messages() {
+        let history = temporary_history();
+        let own = PeerId::random();
+        let peer = PeerId::random();
+        let original = message(peer, own, 1, b"original");
+        history.store_message(original.clone()).await.unwrap();
+
+        // The same message again is skipped.
+        let mut again = original.clone();
+        again.delivery_status = DeliveryStatus::Read;
+        history.store_message(again).await.unwrap();
+        assert_eq!(history.get_history(&own, &peer, 10).await.unwrap().len(), 1);
+
+        // A different message under the same ID is refused.
+        let conflicting = Message {
+            timestamp: 2,
+            content: b"replacement".to_vec(),
+            ..original.clone()
+        };
+        assert!(history.store_message(conflicting.clone()).await.is_err());
+        let other = message(peer, own, 3, b"other");
+        assert!(history
+            .store_messages(vec![other.clone(), conflicting])
+            .await
+            .is_err());
+        assert!(history
+            .get_message_by_id(&other.id)
+            .await
+            .unwrap()
+            .is_none());
+
+        history
+            .store_messages(vec![original.clone(), other.clone(), other.clone()])
+            .await
+            .unwrap();
+        let stored = history.get_history(&own, &peer, 10).await.unwrap();
+        let texts: Vec<String> = stored.iter().map(|msg| msg.text()).collect();
+        assert_eq!(texts, ["original", "other"]);
+        let found = history
+            .get_message_by_id(&original.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.text(), "original");
+    }
+
+    #[tokio::test]
+    async fn deleted_messages_are_not_stored_again() {
+        let history = temporary_history();
+        let own = PeerId::random();
+        let peer = PeerId::random();
+        let deleted = message(peer, own, 1, b"deleted");
+        let kept = message(peer, own, 2, b"kept");
+        history
+            .store_messages(vec![deleted.clone(), kept.clone()])
+            .await
+            .unwrap();
+
+        assert!(history.delete_message(&deleted.id).await.unwrap().is_some());
+        history.store_message(deleted.clone()).await.unwrap();
+        history.store_messages(vec![deleted.clone()]).await.unwrap();
+        assert!(history
+            .get_message_by_id(&deleted.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(history
+            .search("deleted", None, 10, 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            history
+                .delete_conversation(&own, &peer)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        history.store_message(kept.clone()).await.unwrap();
+        assert!(history
+            .get_history(&own, &peer, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn taken_positions_are_not_overwritten() {
+        let history = temporary_history();
+        let own = PeerId::random();
+        let peer = PeerId::random();
+        let sent = message(own, peer, 1, b"sent by us");
+        history.store_message(sent.clone()).await.unwrap();
+
+        // A fresh ID with the same timestamp and nonce.
+        let colliding = Message {
+            id: uuid::Uuid::new_v4(),
+            sender: peer,
+            recipient: own,
+            content: b"hiding it".to_vec(),
+            ..sent.clone()
+        };
+        assert!(history.store_message(colliding.clone()).await.is_err());
+        assert!(history
+            .store_messages(vec![colliding.clone()])
+            .await
+            .is_err());
+        let other = message(peer, own, 2, b"other");
+        let twin = Message {
+            id: uuid::Uuid::new_v4(),
+            ..other.clone()
+        };
+        assert!(history.store_messages(vec![other, twin]).await.is_err());
+
+        let stored = history.get_history(&own, &peer, 10).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        let found = history.get_message_by_id(&sent.id).await.unwrap().unwrap();
+        assert_eq!(found.text(), "sent by us");
+        assert!(history
+            .get_message_by_id(&colliding.id)
+            .await
+            .unwrap()
+            .is_none());
     }
 }
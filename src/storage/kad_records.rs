@@ -0,0 +1,331 @@
+//! This module implements a Kademlia record store that survives restarts.
+//!
+//! Records are kept in a `MemoryStore`, which Kademlia reads from, and every
+//! change is written through to `sled`. On startup the unexpired records are
+//! loaded back, so provider announcements and stored values are not lost.
+use std::borrow::Cow;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use libp2p::kad::store::{self, MemoryStore, RecordStore};
+use libp2p::kad::{ProviderRecord, Record, RecordKey};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use tracing::{debug, warn};
+
+use crate::crypto::StorageEncryption;
+
+/// A value record as persisted in `sled`.
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    /// The value of the record.
+    value: Vec<u8>,
+    /// The original publisher of the record.
+    publisher: Option<PeerId>,
+    /// The expiration time in milliseconds since the Unix epoch.
+    expires_at: Option<i64>,
+}
+
+/// A provider record as persisted in `sled`.
+#[derive(Serialize, Deserialize)]
+struct StoredProvider {
+    /// The provider of the key.
+    provider: PeerId,
+    /// The known addresses of the provider.
+    addresses: Vec<Multiaddr>,
+    /// The expiration time in milliseconds since the Unix epoch.
+    expires_at: Option<i64>,
+}
+
+/// A Kademlia `RecordStore` that persists its records in `sled`.
+pub struct SledRecordStore {
+    memory: MemoryStore,
+    records: sled::Tree,
+    providers: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledRecordStore {
+    /// Opens the record store and loads the records that have not expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `local_peer_id` - The `PeerId` of the local node.
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - Optional `StorageEncryption` for encrypting data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying `sled` trees cannot be opened or read.
+    pub fn new(
+        local_peer_id: PeerId,
+        db: Db,
+        encryption: Option<StorageEncryption>,
+    ) -> Result<Self> {
+        let mut store = Self {
+            memory: MemoryStore::new(local_peer_id),
+            records: db.open_tree("kad_records")?,
+            providers: db.open_tree("kad_providers")?,
+            encryption,
+        };
+        store.load()?;
+        Ok(store)
+    }
+
+    /// Loads the persisted records into memory, dropping the expired ones.
+    fn load(&mut self) -> Result<()> {
+        let mut loaded_records = 0;
+        for entry in self.records.iter() {
+            let (key, value) = entry?;
+            let stored: StoredRecord = match self.deserialize(&value) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    warn!("Dropping unreadable DHT record: {}", e);
+                    self.records.remove(&key)?;
+                    continue;
+                }
+            };
+            let Some(expires) = to_instant(stored.expires_at) else {
+                self.records.remove(&key)?;
+                continue;
+            };
+
+            let record = Record {
+                key: RecordKey::new(&key),
+                value: stored.value,
+                publisher: stored.publisher,
+                expires,
+            };
+            if self.memory.put(record).is_ok() {
+                loaded_records += 1;
+            }
+        }
+
+        let mut loaded_providers = 0;
+        for entry in self.providers.iter() {
+            let (key, value) = entry?;
+            let stored: Vec<StoredProvider> = match self.deserialize(&value) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    warn!("Dropping unreadable DHT provider records: {}", e);
+                    self.providers.remove(&key)?;
+                    continue;
+                }
+            };
+            for provider in stored {
+                let Some(expires) = to_instant(provider.expires_at) else {
+                    continue;
+                };
+
+                let record = ProviderRecord {
+                    key: RecordKey::new(&key),
+                    provider: provider.provider,
+                    expires,
+                    addresses: provider.addresses,
+                };
+                if self.memory.add_provider(record).is_ok() {
+                    loaded_providers += 1;
+                }
+            }
+        }
+
+        debug!(
+            "Loaded {} records and {} provider records from disk",
+            loaded_records, loaded_providers
+        );
+        Ok(())
+    }
+
+    /// Writes a value record to disk.
+    fn persist_record(&self, record: &Record) -> Result<()> {
+        let stored = StoredRecord {
+            value: record.value.clone(),
+            publisher: record.publisher,
+            expires_at: record.expires.map(to_unix_millis),
+        };
+        self.records
+            .insert(record.key.to_vec(), self.serialize(&stored)?)?;
+        Ok(())
+    }
+
+    /// Writes the current providers of a key to disk.
+    fn persist_providers(&self, key: &RecordKey) -> Result<()> {
+        let providers = self.memory.providers(key);
+        if providers.is_empty() {
+            self.providers.remove(key.to_vec())?;
+            return Ok(());
+        }
+
+        let stored: Vec<StoredProvider> = providers
+            .into_iter()
+            .map(|record| StoredProvider {
+                provider: record.provider,
+                addresses: record.addresses,
+                expires_at: record.expires.map(to_unix_millis),
+            })
+            .collect();
+        self.providers
+            .insert(key.to_vec(), self.serialize(&stored)?)?;
+        Ok(())
+    }
+
+    /// Serializes a value and optionally encrypts it.
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Deserializes a value and optionally decrypts it.
+    fn deserialize<T: for<'de> Deserialize<'de>>(&self, data: &[u8]) -> Result<T> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+impl RecordStore for SledRecordStore {
+    type RecordsIter<'a> = <MemoryStore as RecordStore>::RecordsIter<'a>;
+    type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+    fn get(&self, k: &RecordKey) -> Option<Cow<'_, Record>> {
+        self.memory.get(k)
+    }
+
+    fn put(&mut self, r: Record) -> store::Result<()> {
+        self.memory.put(r.clone())?;
+        if let Err(e) = self.persist_record(&r) {
+            warn!("Failed to persist DHT record: {}", e);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, k: &RecordKey) {
+        self.memory.remove(k);
+        if let Err(e) = self.records.remove(k.to_vec()) {
+            warn!("Failed to remove persisted DHT record: {}", e);
+        }
+    }
+
+    fn records(&self) -> Self::RecordsIter<'_> {
+        self.memory.records()
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> store::Result<()> {
+        let key = record.key.clone();
+        self.memory.add_provider(record)?;
+        if let Err(e) = self.persist_providers(&key) {
+            warn!("Failed to persist DHT provider record: {}", e);
+        }
+        Ok(())
+    }
+
+    fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+        self.memory.providers(key)
+    }
+
+    fn provided(&self) -> Self::ProvidedIter<'_> {
+        self.memory.provided()
+    }
+
+    fn remove_provider(&mut self, k: &RecordKey, p: &PeerId) {
+        self.memory.remove_provider(k, p);
+        if let Err(e) = self.persist_providers(k) {
+            warn!("Failed to persist DHT provider record: {}", e);
+        }
+    }
+}
+
+/// Converts a monotonic expiration time to milliseconds since the Unix epoch.
+fn to_unix_millis(instant: Instant) -> i64 {
+    let now = Instant::now();
+    let unix_now = unix_now_millis();
+    if instant >= now {
+        unix_now + instant.duration_since(now).as_millis() as i64
+    } else {
+        unix_now - now.duration_since(instant).as_millis() as i64
+    }
+}
+
+/// Converts a persisted expiration time back to a monotonic one.
+///
+/// Returns `None` if the record has expired, and `Some(None)` if it never expires.
+fn to_instant(expires_at: Option<i64>) -> Option<Option<Instant>> {
+    match expires_at {
+        None => Some(None),
+        Some(expires_at) => {
+            let remaining = expires_at - unix_now_millis();
+            if remaining <= 0 {
+                None
+            } else {
+                Some(Some(
+                    Instant::now() + Duration::from_millis(remaining as u64),
+                ))
+            }
+        }
+    }
+}
+
+/// Returns the current time in milliseconds since the Unix epoch.
+fn unix_now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: &[u8], expires: Option<Instant>) -> Record {
+        Record {
+            key: RecordKey::new(&key),
+            value: key.to_vec(),
+            publisher: None,
+            expires,
+        }
+    }
+
+    #[test]
+    fn unexpired_records_survive_a_restart() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let local = PeerId::random();
+        let provider = PeerId::random();
+        let key = RecordKey::new(&b"mailbox");
+        {
+            let mut store = SledRecordStore::new(local, db.clone(), None).unwrap();
+            store.put(record(b"kept", None)).unwrap();
+            let expired = Instant::now().checked_sub(Duration::from_secs(1));
+            store.put(record(b"expired", expired)).unwrap();
+            store.put(record(b"removed", None)).unwrap();
+            store.remove(&RecordKey::new(&b"removed"));
+            store
+                .add_provider(ProviderRecord {
+                    key: key.clone(),
+                    provider,
+                    expires: Some(Instant::now() + Duration::from_secs(60)),
+                    addresses: vec!["/ip4/127.0.0.1/tcp/4001".parse().unwrap()],
+                })
+                .unwrap();
+        }
+
+        let store = SledRecordStore::new(local, db, None).unwrap();
+        assert_eq!(store.get(&RecordKey::new(&b"kept")).unwrap().value, b"kept");
+        assert!(store.get(&RecordKey::new(&b"expired")).is_none());
+        assert!(store.get(&RecordKey::new(&b"removed")).is_none());
+        let providers = store.providers(&key);
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].provider, provider);
+        assert_eq!(providers[0].addresses.len(), 1);
+    }
+}
@@ -1,12 +1,14 @@
 //! This module defines the storage interface and implementation for the mailbox.
 //!
 //! The mailbox stores encrypted messages for recipients until they can be fetched.
+//! Recipients can also give it a list of senders whose messages it should refuse.
 mod operations;
 
 use crate::crypto::StorageEncryption;
 use crate::types::EncryptedMessage;
 use anyhow::Result;
 use async_trait::async_trait;
+use libp2p::PeerId;
 use sled::Db;
 use uuid::Uuid;
 
@@ -71,11 +73,40 @@ pub trait MailboxStore {
     ///
     /// This function will return an error if cleanup fails.
     async fn cleanup_expired(&self, max_age: std::time::Duration) -> Result<()>;
+
+    /// Replaces the list of senders a recipient has blocked.
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient_hash` - The hash of the recipient's public key.
+    /// * `blocked` - The `PeerId`s of the blocked senders.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the list cannot be stored.
+    async fn set_blocked_senders(
+        &self,
+        recipient_hash: [u8; 32],
+        blocked: Vec<PeerId>,
+    ) -> Result<()>;
+
+    /// Checks whether a recipient has blocked a sender.
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient_hash` - The hash of the recipient's public key.
+    /// * `sender` - The `PeerId` of the sender.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the list cannot be read.
+    async fn is_sender_blocked(&self, recipient_hash: [u8; 32], sender: &PeerId) -> Result<bool>;
 }
 
 /// A `MailboxStore` implementation using `sled` for storage.
 pub struct SledMailboxStore {
     pub(crate) tree: sled::Tree,
+    pub(crate) blocks: sled::Tree,
     pub(crate) encryption: Option<StorageEncryption>,
     pub(crate) max_storage_per_user: usize,
 }
@@ -91,15 +122,18 @@ impl SledMailboxStore {
     ///
     /// # Errors
     ///
-    /// This function will return an error if the `mailbox` tree cannot be opened.
+    /// This function will return an error if the `mailbox` or `mailbox_blocks`
+    /// tree cannot be opened.
     pub fn new(
         db: Db,
         encryption: Option<StorageEncryption>,
         max_storage_per_user: usize,
     ) -> Result<Self> {
         let tree = db.open_tree("mailbox")?;
+        let blocks = db.open_tree("mailbox_blocks")?;
         Ok(Self {
             tree,
+            blocks,
             encryption,
             max_storage_per_user,
         })
@@ -113,6 +147,14 @@ impl SledMailboxStore {
         key
     }
 
+    /// Creates the key under which a recipient's block of a sender is stored.
+    pub(crate) fn make_block_key(&self, recipient_hash: &[u8; 32], sender: &PeerId) -> Vec<u8> {
+        let mut key = Vec::new();
+        key.extend_from_slice(recipient_hash);
+        key.extend_from_slice(&sender.to_bytes());
+        key
+    }
+
     /// Serializes an `EncryptedMessage` and encrypts it if encryption is enabled.
     pub(crate) fn serialize_message(&self, msg: &EncryptedMessage) -> Result<Vec<u8>> {
         let serialized = serde_json::to_vec(msg)?;
@@ -4,6 +4,7 @@ use crate::types::EncryptedMessage;
 use anyhow::Result;
 use async_trait::async_trait;
 use chrono::Utc;
+use libp2p::PeerId;
 use std::time::Duration;
 use tracing::warn;
 use uuid::Uuid;
@@ -58,7 +59,7 @@ impl MailboxStore for SledMailboxStore {
         }
 
         if existing.len() > self.max_storage_per_user {
-            existing.sort_by(|a, b| (a.1, a.2).cmp(&(b.1, b.2)));
+            existing.sort_by_key(|entry| (entry.1, entry.2));
             let excess = existing.len() - self.max_storage_per_user;
             for (key, _, _) in existing.into_iter().take(excess) {
                 self.tree.remove(key)?;
@@ -199,4 +200,54 @@ impl MailboxStore for SledMailboxStore {
         self.tree.flush_async().await?;
         Ok(())
     }
+
+    /// Replaces the list of senders a recipient has blocked.
+    ///
+    /// The old list is dropped and the new one written in a single batch, so
+    /// the list is never seen half updated.
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient_hash` - The hash of the recipient's public key.
+    /// * `blocked` - The `PeerId`s of the blocked senders.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there are issues with the underlying
+    /// `sled` database.
+    async fn set_blocked_senders(
+        &self,
+        recipient_hash: [u8; 32],
+        blocked: Vec<PeerId>,
+    ) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for entry in self.blocks.scan_prefix(recipient_hash) {
+            let (key, _) = entry?;
+            batch.remove(key);
+        }
+        for sender in &blocked {
+            batch.insert(self.make_block_key(&recipient_hash, sender), &[]);
+        }
+
+        self.blocks.apply_batch(batch)?;
+        self.blocks.flush_async().await?;
+        Ok(())
+    }
+
+    /// Checks whether a recipient has blocked a sender.
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient_hash` - The hash of the recipient's public key.
+    /// * `sender` - The `PeerId` of the sender.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there are issues with the underlying
+    /// `sled` database.
+    async fn is_sender_blocked(&self, recipient_hash: [u8; 32], sender: &PeerId) -> Result<bool> {
+        Ok(self
+            .blocks
+            .contains_key(self.make_block_key(&recipient_hash, sender))?)
+    }
 }
@@ -0,0 +1,307 @@
+//! This module defines the storage interface and implementation for message
+//! requests: direct messages from peers that are not friends.
+//!
+//! Such messages are kept apart from the history, together with the E2E
+//! public key the sender claimed, until the user accepts or deletes them.
+//! The key of the first message is kept; later messages claiming another
+//! key are dropped, so they cannot change the key a request is accepted with.
+use crate::crypto::StorageEncryption;
+use crate::types::Message;
+use anyhow::Result;
+use async_trait::async_trait;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use tracing::warn;
+
+/// The most messages kept per sender; later ones are dropped.
+pub const MAX_MESSAGES_PER_REQUEST: usize = 100;
+
+/// Represents the pending messages of a sender that is not a friend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageRequest {
+    /// The `PeerId` of the sender.
+    pub peer_id: PeerId,
+    /// The E2E public key the sender's first message was encrypted with.
+    #[serde(with = "serde_bytes")]
+    pub e2e_public_key: Vec<u8>,
+    /// The number of pending messages.
+    #[serde(skip)]
+    pub message_count: usize,
+    /// The timestamp of the latest pending message, in milliseconds.
+    #[serde(skip)]
+    pub last_message_at: i64,
+}
+
+/// A trait for managing message requests.
+#[async_trait]
+pub trait MessageRequestsStore: Send + Sync {
+    /// Adds a decrypted message from a sender that is not a friend.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The decrypted `Message`.
+    /// * `e2e_public_key` - The E2E public key the sender claimed.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the message was dropped because the sender already has the
+    /// most pending messages allowed, or claimed a different key before.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message cannot be stored.
+    async fn add_message(&self, message: &Message, e2e_public_key: &[u8]) -> Result<bool>;
+
+    /// Retrieves the message request of a sender.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request cannot be read.
+    async fn get_request(&self, peer_id: &PeerId) -> Result<Option<MessageRequest>>;
+
+    /// Lists all message requests, the most recent first.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the requests cannot be read.
+    async fn list_requests(&self) -> Result<Vec<MessageRequest>>;
+
+    /// Retrieves the pending messages of a sender in chronological order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the messages cannot be read.
+    async fn get_messages(&self, peer_id: &PeerId) -> Result<Vec<Message>>;
+
+    /// Removes the message request of a sender with all its messages.
+    ///
+    /// # Returns
+    ///
+    /// The number of messages removed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request cannot be removed.
+    async fn remove_request(&self, peer_id: &PeerId) -> Result<usize>;
+}
+
+/// A `MessageRequestsStore` implementation using `sled` for storage.
+pub struct SledMessageRequestsStore {
+    /// The senders, keyed by `PeerId`.
+    senders: sled::Tree,
+    /// The messages, keyed by sender, timestamp and message ID.
+    messages: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledMessageRequestsStore {
+    /// Creates a new `SledMessageRequestsStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - Optional `StorageEncryption` for encrypting data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying `sled` trees cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let senders = db.open_tree("message_request_senders")?;
+        let messages = db.open_tree("message_requests")?;
+        Ok(Self {
+            senders,
+            messages,
+            encryption,
+        })
+    }
+
+    /// Builds the key prefix of a sender's messages: the length of the peer
+    /// ID followed by its bytes, so no prefix matches another sender.
+    fn sender_prefix(peer_id: &PeerId) -> Vec<u8> {
+        let bytes = peer_id.to_bytes();
+        let mut prefix = vec![bytes.len() as u8];
+        prefix.extend_from_slice(&bytes);
+        prefix
+    }
+
+    /// Builds the key of a message.
+    fn message_key(message: &Message) -> Vec<u8> {
+        let mut key = Self::sender_prefix(&message.sender);
+        key.extend_from_slice(&message.timestamp.to_be_bytes());
+        key.extend_from_slice(message.id.as_bytes());
+        key
+    }
+
+    /// Serializes a value and encrypts it if encryption is enabled.
+    fn seal<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Decrypts and deserializes a value.
+    fn open<T: for<'de> Deserialize<'de>>(&self, data: &[u8]) -> Result<T> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+
+    /// Fills in the message count and latest timestamp of a request.
+    fn summarize(&self, mut request: MessageRequest) -> Result<MessageRequest> {
+        let prefix = Self::sender_prefix(&request.peer_id);
+        request.message_count = self.messages.scan_prefix(&prefix).count();
+        request.last_message_at = match self.messages.scan_prefix(&prefix).next_back() {
+            Some(result) => self.open::<Message>(&result?.1)?.timestamp,
+            None => 0,
+        };
+        Ok(request)
+    }
+}
+
+#[async_trait]
+impl MessageRequestsStore for SledMessageRequestsStore {
+    async fn add_message(&self, message: &Message, e2e_public_key: &[u8]) -> Result<bool> {
+        let prefix = Self::sender_prefix(&message.sender);
+        if self.messages.scan_prefix(&prefix).count() >= MAX_MESSAGES_PER_REQUEST {
+            return Ok(false);
+        }
+
+        match self.senders.get(message.sender.to_bytes())? {
+            Some(data) => {
+                let request: MessageRequest = self.open(&data)?;
+                if request.e2e_public_key != e2e_public_key {
+                    warn!(
+                        "Dropping message {} from {}: it claims a different key than the pending request",
+                        message.id, message.sender
+                    );
+                    return Ok(false);
+                }
+            }
+            None => {
+                let request = MessageRequest {
+                    peer_id: message.sender,
+                    e2e_public_key: e2e_public_key.to_vec(),
+                    message_count: 0,
+                    last_message_at: 0,
+                };
+                self.senders
+                    .insert(message.sender.to_bytes(), self.seal(&request)?)?;
+            }
+        }
+        self.messages
+            .insert(Self::message_key(message), self.seal(message)?)?;
+        self.messages.flush_async().await?;
+        self.senders.flush_async().await?;
+        Ok(true)
+    }
+
+    async fn get_request(&self, peer_id: &PeerId) -> Result<Option<MessageRequest>> {
+        match self.senders.get(peer_id.to_bytes())? {
+            Some(data) => Ok(Some(self.summarize(self.open(&data)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_requests(&self) -> Result<Vec<MessageRequest>> {
+        let mut requests = Vec::new();
+
+        for result in self.senders.iter() {
+            let (_key, value) = result?;
+            requests.push(self.summarize(self.open(&value)?)?);
+        }
+
+        requests.sort_by_key(|request| std::cmp::Reverse(request.last_message_at));
+        Ok(requests)
+    }
+
+    async fn get_messages(&self, peer_id: &PeerId) -> Result<Vec<Message>> {
+        let mut messages = Vec::new();
+
+        for result in self.messages.scan_prefix(Self::sender_prefix(peer_id)) {
+            let (_key, value) = result?;
+            messages.push(self.open(&value)?);
+        }
+
+        Ok(messages)
+    }
+
+    async fn remove_request(&self, peer_id: &PeerId) -> Result<usize> {
+        let mut batch = sled::Batch::default();
+        let mut removed = 0;
+
+        for result in self.messages.scan_prefix(Self::sender_prefix(peer_id)) {
+            let (key, _value) = result?;
+            batch.remove(key);
+            removed += 1;
+        }
+
+        self.messages.apply_batch(batch)?;
+        self.senders.remove(peer_id.to_bytes())?;
+        self.messages.flush_async().await?;
+        self.senders.flush_async().await?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DeliveryStatus;
+
+    fn message(sender: PeerId, text: &str) -> Message {
+        Message {
+            id: uuid::Uuid::new_v4(),
+            sender,
+            recipient: PeerId::random(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            content: text.as_bytes().to_vec(),
+            nonce: rand::random(),
+            delivery_status: DeliveryStatus::Delivered,
+            signature: Vec::new(),
+            group_id: None,
+            attachment: None,
+            sender_pub_key: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_key_of_the_first_message() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = SledMessageRequestsStore::new(db, None).unwrap();
+        let sender = PeerId::random();
+
+        assert!(store
+            .add_message(&message(sender, "hi"), &[1; 32])
+            .await
+            .unwrap());
+        assert!(!store
+            .add_message(&message(sender, "spoof"), &[2; 32])
+            .await
+            .unwrap());
+        assert!(store
+            .add_message(&message(sender, "again"), &[1; 32])
+            .await
+            .unwrap());
+
+        let request = store.get_request(&sender).await.unwrap().unwrap();
+        assert_eq!(request.e2e_public_key, vec![1; 32]);
+        assert_eq!(request.message_count, 2);
+        let texts: Vec<String> = store
+            .get_messages(&sender)
+            .await
+            .unwrap()
+            .iter()
+            .map(|msg| msg.text())
+            .collect();
+        assert!(!texts.contains(&"spoof".to_string()));
+    }
+}
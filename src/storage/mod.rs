@@ -1,15 +1,43 @@
 //! This module defines the storage interfaces and implementations for various
-//! application data, including friends, message history, mailboxes, and seen messages.
+//! application data, including friends, linked devices, friend requests,
+//! groups, message history and its search index, message requests, mailboxes,
+//! seen messages, ratchet sessions, group sender keys, attachments, Kademlia
+//! records, the peer address book, and the block list, as well as storage
+//! password verification and re-keying.
+pub mod attachments;
+pub mod blocks;
+pub mod contacts;
+pub mod devices;
+pub mod encryption;
 pub mod friends;
+pub mod groups;
 pub mod history;
+pub mod kad_records;
 pub mod known_mailboxes;
 pub mod mailbox;
+pub mod message_requests;
 pub mod outbox;
+pub mod peers;
+pub mod search;
 pub mod seen;
+pub mod sender_keys;
+pub mod sessions;
 
+pub use attachments::AttachmentStore;
+pub use blocks::{BlockListStore, SledBlockListStore};
+pub use contacts::{
+    ContactDirection, ContactRequestsStore, PendingContact, SledContactRequestsStore,
+};
+pub use devices::{DeviceStore, SledDeviceStore};
 pub use friends::{FriendsStore, SledFriendsStore};
-pub use history::{MessageHistory, MessageStore};
+pub use groups::{GroupsStore, SledGroupsStore};
+pub use history::{MessageCursor, MessageHistory, MessageStore};
+pub use kad_records::SledRecordStore;
 pub use known_mailboxes::{KnownMailbox, KnownMailboxesStore, SledKnownMailboxesStore};
 pub use mailbox::{MailboxStore, SledMailboxStore};
+pub use message_requests::{MessageRequestsStore, SledMessageRequestsStore};
 pub use outbox::{OutboxStore, SledOutboxStore};
+pub use peers::{PeerAddressStore, SledPeerAddressStore};
 pub use seen::{SeenTracker, SledSeenTracker};
+pub use sender_keys::{SenderKeyStore, SledSenderKeyStore};
+pub use sessions::{SessionStore, SledSessionStore};
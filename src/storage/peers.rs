@@ -0,0 +1,141 @@
+//! This module defines the storage interface and implementation for the peer
+//! address book, which remembers where peers were last reachable.
+//!
+//! The address book seeds Kademlia on startup and is used to redial mailbox
+//! nodes that are no longer connected.
+use crate::crypto::StorageEncryption;
+use anyhow::Result;
+use async_trait::async_trait;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The most addresses remembered for a single peer.
+const MAX_ADDRESSES_PER_PEER: usize = 8;
+
+/// Represents a peer and the addresses it was last reachable at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownPeer {
+    /// The `PeerId` of the peer.
+    pub peer_id: PeerId,
+    /// The known addresses of the peer, most recent first.
+    pub addresses: Vec<Multiaddr>,
+    /// The timestamp of when an address of the peer was last recorded.
+    pub last_seen: i64,
+}
+
+/// Returns the current Unix timestamp in seconds.
+fn current_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// A trait for managing the peer address book.
+#[async_trait]
+pub trait PeerAddressStore: Send + Sync {
+    /// Records addresses at which a peer was reachable.
+    async fn add_addresses(&self, peer_id: &PeerId, addresses: Vec<Multiaddr>) -> Result<()>;
+    /// Retrieves a `KnownPeer` by its `PeerId`.
+    async fn get_peer(&self, peer_id: &PeerId) -> Result<Option<KnownPeer>>;
+    /// Lists all known peers.
+    async fn list_peers(&self) -> Result<Vec<KnownPeer>>;
+}
+
+/// A `PeerAddressStore` implementation using `sled` for storage.
+pub struct SledPeerAddressStore {
+    tree: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledPeerAddressStore {
+    /// Creates a new `SledPeerAddressStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - Optional `StorageEncryption` for encrypting data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying `sled` tree cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let tree = db.open_tree("peer_addresses")?;
+        Ok(Self { tree, encryption })
+    }
+
+    /// Serializes a `KnownPeer` and optionally encrypts it.
+    fn serialize_peer(&self, peer: &KnownPeer) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(peer)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Deserializes a `KnownPeer` and optionally decrypts it.
+    fn deserialize_peer(&self, data: &[u8]) -> Result<KnownPeer> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+#[async_trait]
+impl PeerAddressStore for SledPeerAddressStore {
+    async fn add_addresses(&self, peer_id: &PeerId, addresses: Vec<Multiaddr>) -> Result<()> {
+        if addresses.is_empty() {
+            return Ok(());
+        }
+
+        let mut peer = self.get_peer(peer_id).await?.unwrap_or(KnownPeer {
+            peer_id: *peer_id,
+            addresses: Vec::new(),
+            last_seen: 0,
+        });
+
+        // Newly recorded addresses go first, so the oldest ones are dropped.
+        let mut merged: Vec<Multiaddr> = Vec::new();
+        for address in addresses.into_iter().chain(peer.addresses) {
+            if !merged.contains(&address) {
+                merged.push(address);
+            }
+        }
+        merged.truncate(MAX_ADDRESSES_PER_PEER);
+
+        peer.addresses = merged;
+        peer.last_seen = current_timestamp();
+
+        let key = peer_id.to_bytes();
+        let value = self.serialize_peer(&peer)?;
+        self.tree.insert(key, value)?;
+        Ok(())
+    }
+
+    async fn get_peer(&self, peer_id: &PeerId) -> Result<Option<KnownPeer>> {
+        let key = peer_id.to_bytes();
+        match self.tree.get(key)? {
+            Some(data) => Ok(Some(self.deserialize_peer(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_peers(&self) -> Result<Vec<KnownPeer>> {
+        let mut peers = Vec::new();
+
+        for result in self.tree.iter() {
+            let (_key, value) = result?;
+            peers.push(self.deserialize_peer(&value)?);
+        }
+
+        Ok(peers)
+    }
+}
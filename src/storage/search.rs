@@ -0,0 +1,414 @@
+//! This module implements the full-text search index of the message history.
+//!
+//! Message text is split into lowercase tokens, and each token is stored
+//! under a keyed hash in its own tree: one posting per token and message,
+//! holding the number of occurrences, and one entry per message listing its
+//! token hashes, so that it can be removed again. With storage encryption
+//! enabled the values are encrypted and the tokens cannot be read from the
+//! hashes. The hash key is random and kept, encrypted like other values, in
+//! the history metadata, so it survives re-keying the storage.
+use crate::crypto::StorageEncryption;
+use crate::types::Message;
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The key of the token hash key in the history metadata tree.
+const SEARCH_KEY: &[u8] = b"search_key";
+
+/// Prefixes the postings of a token.
+const POSTING_PREFIX: u8 = b'p';
+
+/// Prefixes the list of token hashes of a message.
+const DOCUMENT_PREFIX: u8 = b'd';
+
+/// The key of the number of indexed messages.
+const DOCUMENT_COUNT_KEY: &[u8] = b"n";
+
+/// The length of a token hash.
+const TOKEN_HASH_LEN: usize = 32;
+
+/// The maximum length of a token, in characters. Longer tokens are cut.
+const MAX_TOKEN_CHARS: usize = 64;
+
+/// Saturates the score of repeated tokens, as in BM25.
+const TERM_SATURATION: f64 = 1.2;
+
+/// A message that matches a search, with the messages around it.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    /// The matching message.
+    pub message: Message,
+    /// The relevance of the message; higher is better.
+    pub score: f64,
+    /// The messages before it in its conversation, in chronological order.
+    pub before: Vec<Message>,
+    /// The messages after it in its conversation, in chronological order.
+    pub after: Vec<Message>,
+}
+
+/// The full-text search index of the message history.
+pub struct SearchIndex {
+    tree: sled::Tree,
+    key: [u8; 32],
+    encryption: Option<StorageEncryption>,
+}
+
+impl SearchIndex {
+    /// Opens the search index, creating its hash key if there is none yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `meta_tree` - The history metadata tree, which holds the hash key.
+    /// * `encryption` - Optional `StorageEncryption` for encrypting the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be opened or the hash key cannot
+    /// be read or stored.
+    pub fn open(
+        db: &sled::Db,
+        meta_tree: &sled::Tree,
+        encryption: Option<StorageEncryption>,
+    ) -> Result<Self> {
+        let tree = db.open_tree("history_search")?;
+
+        let key = match meta_tree.get(SEARCH_KEY)? {
+            Some(stored) => {
+                let key = match encryption {
+                    Some(ref encryption) => encryption.decrypt_value(&stored)?,
+                    None => stored.to_vec(),
+                };
+                <[u8; 32]>::try_from(key.as_slice())
+                    .map_err(|_| anyhow!("Invalid search index key"))?
+            }
+            None => {
+                let mut key = [0u8; 32];
+                getrandom::getrandom(&mut key)?;
+                let stored = match encryption {
+                    Some(ref encryption) => encryption.encrypt_value(&key)?,
+                    None => key.to_vec(),
+                };
+                meta_tree.insert(SEARCH_KEY, stored)?;
+                key
+            }
+        };
+
+        Ok(Self {
+            tree,
+            key,
+            encryption,
+        })
+    }
+
+    /// Indexes the text of a message, replacing its previous entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be written.
+    pub fn index(&self, msg: &Message) -> Result<()> {
+        self.remove(&msg.id)?;
+
+        let mut text = msg.text();
+        if let Some(ref manifest) = msg.attachment {
+            text.push(' ');
+            text.push_str(&manifest.file_name);
+        }
+
+        let mut frequencies: HashMap<[u8; TOKEN_HASH_LEN], u32> = HashMap::new();
+        for token in tokenize(&text) {
+            *frequencies.entry(self.hash_token(&token)).or_default() += 1;
+        }
+        if frequencies.is_empty() {
+            return Ok(());
+        }
+
+        let mut batch = sled::Batch::default();
+        let mut hashes = Vec::with_capacity(frequencies.len() * TOKEN_HASH_LEN);
+        for (hash, frequency) in &frequencies {
+            batch.insert(
+                Self::posting_key(hash, &msg.id),
+                self.seal_value(&frequency.to_be_bytes())?,
+            );
+            hashes.extend_from_slice(hash);
+        }
+        batch.insert(Self::document_key(&msg.id), self.seal_value(&hashes)?);
+        self.tree.apply_batch(batch)?;
+        self.update_document_count(1)?;
+        Ok(())
+    }
+
+    /// Removes a message from the index.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the message was indexed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read or written.
+    pub fn remove(&self, msg_id: &Uuid) -> Result<bool> {
+        let Some(hashes) = self.tree.get(Self::document_key(msg_id))? else {
+            return Ok(false);
+        };
+
+        let hashes = self.open_value(&hashes)?;
+        let mut batch = sled::Batch::default();
+        for hash in hashes.chunks_exact(TOKEN_HASH_LEN) {
+            batch.remove(Self::posting_key(hash, msg_id));
+        }
+        batch.remove(Self::document_key(msg_id));
+        self.tree.apply_batch(batch)?;
+        self.update_document_count(-1)?;
+        Ok(true)
+    }
+
+    /// Finds the messages containing every token of a query.
+    ///
+    /// Messages are ranked by how often they contain the tokens, weighted by
+    /// how rare the tokens are across the history.
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the matching messages with their scores, best first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be read.
+    pub fn search(&self, query: &str) -> Result<Vec<(Uuid, f64)>> {
+        let mut tokens = tokenize(query);
+        tokens.sort();
+        tokens.dedup();
+        if tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let documents = self.document_count()?.max(1) as f64;
+        let mut scores: HashMap<Uuid, (usize, f64)> = HashMap::new();
+        for token in &tokens {
+            let mut prefix = vec![POSTING_PREFIX];
+            prefix.extend_from_slice(&self.hash_token(token));
+
+            let mut postings = Vec::new();
+            for result in self.tree.scan_prefix(&prefix) {
+                let (key, value) = result?;
+                let msg_id = Uuid::from_slice(&key[prefix.len()..])?;
+                let frequency = <[u8; 4]>::try_from(self.open_value(&value)?.as_slice())
+                    .map_err(|_| anyhow!("Invalid search index posting"))?;
+                postings.push((msg_id, u32::from_be_bytes(frequency) as f64));
+            }
+
+            let matching = postings.len() as f64;
+            let rarity = (1.0 + (documents - matching + 0.5) / (matching + 0.5)).ln();
+            for (msg_id, frequency) in postings {
+                let score = scores.entry(msg_id).or_default();
+                score.0 += 1;
+                score.1 +=
+                    rarity * frequency * (TERM_SATURATION + 1.0) / (frequency + TERM_SATURATION);
+            }
+        }
+
+        let mut hits: Vec<(Uuid, f64)> = scores
+            .into_iter()
+            .filter(|(_, (matched, _))| *matched == tokens.len())
+            .map(|(msg_id, (_, score))| (msg_id, score))
+            .collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(hits)
+    }
+
+    /// Removes every entry from the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be cleared.
+    pub fn clear(&self) -> Result<()> {
+        self.tree.clear()?;
+        Ok(())
+    }
+
+    /// Flushes the index to disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index cannot be flushed.
+    pub async fn flush(&self) -> Result<()> {
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+
+    /// Returns the keyed hash of a token.
+    fn hash_token(&self, token: &str) -> [u8; TOKEN_HASH_LEN] {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(token.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Returns the number of indexed messages.
+    fn document_count(&self) -> Result<u64> {
+        Ok(self
+            .tree
+            .get(DOCUMENT_COUNT_KEY)?
+            .and_then(|count| <[u8; 8]>::try_from(count.as_ref()).ok())
+            .map(u64::from_be_bytes)
+            .unwrap_or(0))
+    }
+
+    /// Adds to the number of indexed messages.
+    fn update_document_count(&self, delta: i64) -> Result<()> {
+        self.tree.update_and_fetch(DOCUMENT_COUNT_KEY, |count| {
+            let count = count
+                .and_then(|count| <[u8; 8]>::try_from(count).ok())
+                .map(u64::from_be_bytes)
+                .unwrap_or(0);
+            Some(count.saturating_add_signed(delta).to_be_bytes().to_vec())
+        })?;
+        Ok(())
+    }
+
+    /// Creates the key of the posting of a token hash for a message.
+    fn posting_key(hash: &[u8], msg_id: &Uuid) -> Vec<u8> {
+        let mut key = vec![POSTING_PREFIX];
+        key.extend_from_slice(hash);
+        key.extend_from_slice(msg_id.as_bytes());
+        key
+    }
+
+    /// Creates the key of the token hashes of a message.
+    fn document_key(msg_id: &Uuid) -> Vec<u8> {
+        let mut key = vec![DOCUMENT_PREFIX];
+        key.extend_from_slice(msg_id.as_bytes());
+        key
+    }
+
+    /// Encrypts a value if encryption is enabled.
+    fn seal_value(&self, value: &[u8]) -> Result<Vec<u8>> {
+        match self.encryption {
+            Some(ref encryption) => encryption.encrypt_value(value),
+            None => Ok(value.to_vec()),
+        }
+    }
+
+    /// Decrypts a value if encryption is enabled.
+    fn open_value(&self, value: &[u8]) -> Result<Vec<u8>> {
+        match self.encryption {
+            Some(ref encryption) => encryption.decrypt_value(value),
+            None => Ok(value.to_vec()),
+        }
+    }
+}
+
+/// Splits text into lowercase tokens of letters and digits.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            word.chars()
+                .take(MAX_TOKEN_CHARS)
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DeliveryStatus;
+    use libp2p::PeerId;
+
+    fn message(text: &str) -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            sender: PeerId::random(),
+            recipient: PeerId::random(),
+            timestamp: 0,
+            content: text.as_bytes().to_vec(),
+            nonce: 0,
+            delivery_status: DeliveryStatus::Delivered,
+            signature: Vec::new(),
+            group_id: None,
+            attachment: None,
+            sender_pub_key: Vec::new(),
+        }
+    }
+
+    fn open_index(encryption: Option<StorageEncryption>) -> (sled::Db, SearchIndex) {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let meta_tree = db.open_tree("history_meta").unwrap();
+        let index = SearchIndex::open(&db, &meta_tree, encryption).unwrap();
+        (db, index)
+    }
+
+    #[test]
+    fn ranks_frequent_and_rare_tokens_higher() {
+        let (_db, index) = open_index(None);
+        let once = message("Meet at the station");
+        let twice = message("The station, the STATION!");
+        let common = message("meet at the harbour");
+        let rare = message("meet at the lighthouse");
+        for msg in [&once, &twice, &common, &rare] {
+            index.index(msg).unwrap();
+        }
+
+        let hits = index.search("station").unwrap();
+        let ids: Vec<Uuid> = hits.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [twice.id, once.id]);
+        assert!(hits[0].1 > hits[1].1);
+
+        // Every token must match, and the rarer one weighs more.
+        let hits = index.search("meet lighthouse").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0, rare.id);
+        let meet = index.search("meet").unwrap();
+        assert!(hits[0].1 > meet[0].1);
+
+        assert!(index.search("").unwrap().is_empty());
+        assert!(index.search("ferry").unwrap().is_empty());
+    }
+
+    #[test]
+    fn removal_keeps_the_document_count() {
+        let (_db, index) = open_index(None);
+        let first = message("first message");
+        let second = message("second message");
+        index.index(&first).unwrap();
+        index.index(&second).unwrap();
+        assert_eq!(index.document_count().unwrap(), 2);
+
+        // Indexing a message again replaces its entries.
+        index.index(&first).unwrap();
+        assert_eq!(index.document_count().unwrap(), 2);
+        assert_eq!(index.search("first").unwrap().len(), 1);
+
+        assert!(index.remove(&first.id).unwrap());
+        assert!(!index.remove(&first.id).unwrap());
+        assert_eq!(index.document_count().unwrap(), 1);
+        let hits = index.search("message").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0, second.id);
+
+        // Messages without tokens are not indexed.
+        index.index(&message("!?")).unwrap();
+        assert_eq!(index.document_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn the_hash_key_is_kept_encrypted() {
+        let encryption = StorageEncryption::new("password", &[7; 16]).unwrap();
+        let (db, index) = open_index(Some(encryption.clone()));
+        let msg = message("encrypted search");
+        index.index(&msg).unwrap();
+
+        let meta_tree = db.open_tree("history_meta").unwrap();
+        let stored = meta_tree.get(SEARCH_KEY).unwrap().unwrap();
+        assert_ne!(stored.as_ref(), index.key);
+
+        let reopened = SearchIndex::open(&db, &meta_tree, Some(encryption)).unwrap();
+        assert_eq!(reopened.search("search").unwrap()[0].0, msg.id);
+    }
+}
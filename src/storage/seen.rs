@@ -35,6 +35,17 @@ pub trait SeenTracker {
     /// This function will return an error if the seen status cannot be retrieved.
     async fn is_seen(&self, msg_id: &Uuid) -> Result<bool>;
 
+    /// Forgets that a message has been seen.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg_id` - The `Uuid` of the message to forget.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the seen status cannot be removed.
+    async fn unmark_seen(&self, msg_id: &Uuid) -> Result<()>;
+
     /// Cleans up old seen message records.
     ///
     /// # Arguments
@@ -84,6 +95,13 @@ impl SeenTracker for SledSeenTracker {
         Ok(self.tree.contains_key(key.as_bytes())?)
     }
 
+    async fn unmark_seen(&self, msg_id: &Uuid) -> Result<()> {
+        let key = msg_id.to_string();
+        self.tree.remove(key.as_bytes())?;
+        self.tree.flush_async().await?;
+        Ok(())
+    }
+
     async fn cleanup_old(&self, max_age: Duration) -> Result<()> {
         let cutoff = Utc::now().timestamp_millis() - max_age.as_millis() as i64;
         let mut keys_to_remove = Vec::new();
@@ -106,3 +124,20 @@ impl SeenTracker for SledSeenTracker {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn forgets_unmarked_messages() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let seen = SledSeenTracker::new(db).unwrap();
+        let id = Uuid::new_v4();
+        seen.mark_seen(id).await.unwrap();
+        assert!(seen.is_seen(&id).await.unwrap());
+
+        seen.unmark_seen(&id).await.unwrap();
+        assert!(!seen.is_seen(&id).await.unwrap());
+    }
+}
@@ -0,0 +1,160 @@
+//! This module defines the storage interface and implementation for group sender keys.
+use crate::crypto::sender_key::SenderKeyState;
+use crate::crypto::StorageEncryption;
+use anyhow::Result;
+use libp2p::PeerId;
+use sled::Db;
+use uuid::Uuid;
+
+/// A trait for persisting the sender keys of every member of every group.
+///
+/// Like `SessionStore`, this trait is synchronous, as sender keys are loaded
+/// and saved from within `Identity`.
+pub trait SenderKeyStore: Send + Sync {
+    /// Loads the sender key of a group member.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `sender` - The `PeerId` of the member owning the key.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the `SenderKeyState` if one exists, otherwise `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the key cannot be retrieved.
+    fn load_sender_key(&self, group_id: &Uuid, sender: &PeerId) -> Result<Option<SenderKeyState>>;
+
+    /// Stores the sender key of a group member.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `sender` - The `PeerId` of the member owning the key.
+    /// * `state` - The `SenderKeyState` to store.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the key cannot be stored.
+    fn store_sender_key(
+        &self,
+        group_id: &Uuid,
+        sender: &PeerId,
+        state: &SenderKeyState,
+    ) -> Result<()>;
+
+    /// Removes the sender key of a group member.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    /// * `sender` - The `PeerId` of the member owning the key.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the key cannot be removed.
+    fn remove_sender_key(&self, group_id: &Uuid, sender: &PeerId) -> Result<()>;
+
+    /// Removes the sender keys of all members of a group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group_id` - The ID of the group.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the keys cannot be removed.
+    fn remove_group_keys(&self, group_id: &Uuid) -> Result<()>;
+}
+
+/// A `SenderKeyStore` implementation using `sled` for storage.
+pub struct SledSenderKeyStore {
+    tree: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledSenderKeyStore {
+    /// Creates a new `SledSenderKeyStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - The optional `StorageEncryption` to use for encrypting sender keys.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `sender_keys` tree cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let tree = db.open_tree("sender_keys")?;
+        Ok(Self { tree, encryption })
+    }
+
+    /// Builds the key for a sender key: the group ID followed by the sender's peer ID.
+    fn key(group_id: &Uuid, sender: &PeerId) -> Vec<u8> {
+        let mut key = group_id.as_bytes().to_vec();
+        key.extend_from_slice(&sender.to_bytes());
+        key
+    }
+
+    /// Serializes a `SenderKeyState` and encrypts it if encryption is enabled.
+    fn serialize_state(&self, state: &SenderKeyState) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(state)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Decrypts and deserializes a `SenderKeyState`.
+    fn deserialize_state(&self, data: &[u8]) -> Result<SenderKeyState> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+impl SenderKeyStore for SledSenderKeyStore {
+    fn load_sender_key(&self, group_id: &Uuid, sender: &PeerId) -> Result<Option<SenderKeyState>> {
+        match self.tree.get(Self::key(group_id, sender))? {
+            Some(data) => Ok(Some(self.deserialize_state(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_sender_key(
+        &self,
+        group_id: &Uuid,
+        sender: &PeerId,
+        state: &SenderKeyState,
+    ) -> Result<()> {
+        let value = self.serialize_state(state)?;
+        self.tree.insert(Self::key(group_id, sender), value)?;
+        // As with ratchet sessions, the advanced chain must be on disk before
+        // the ciphertext leaves this node.
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    fn remove_sender_key(&self, group_id: &Uuid, sender: &PeerId) -> Result<()> {
+        self.tree.remove(Self::key(group_id, sender))?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    fn remove_group_keys(&self, group_id: &Uuid) -> Result<()> {
+        for result in self.tree.scan_prefix(group_id.as_bytes()) {
+            let (key, _value) = result?;
+            self.tree.remove(key)?;
+        }
+        self.tree.flush()?;
+        Ok(())
+    }
+}
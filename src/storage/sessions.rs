@@ -0,0 +1,120 @@
+//! This module defines the storage interface and implementation for ratchet sessions.
+use crate::crypto::ratchet::SessionRecord;
+use crate::crypto::StorageEncryption;
+use anyhow::Result;
+use sled::Db;
+
+/// A trait for persisting the ratchet sessions held with each peer.
+///
+/// Unlike the other stores this trait is synchronous, as sessions are loaded
+/// and saved from within `Identity::encrypt_for` and `Identity::decrypt_from`.
+pub trait SessionStore: Send + Sync {
+    /// Loads the session record for a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_public_key` - The E2E public key of the peer.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the `SessionRecord` if one exists, otherwise `None`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the record cannot be retrieved.
+    fn load_session(&self, remote_public_key: &[u8]) -> Result<Option<SessionRecord>>;
+
+    /// Stores the session record for a peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_public_key` - The E2E public key of the peer.
+    /// * `record` - The `SessionRecord` to store.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the record cannot be stored.
+    fn store_session(&self, remote_public_key: &[u8], record: &SessionRecord) -> Result<()>;
+
+    /// Lists the peers we hold session records with.
+    ///
+    /// # Returns
+    ///
+    /// The E2E public keys of the peers.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the records cannot be listed.
+    fn list_sessions(&self) -> Result<Vec<Vec<u8>>>;
+}
+
+/// A `SessionStore` implementation using `sled` for storage.
+pub struct SledSessionStore {
+    tree: sled::Tree,
+    encryption: Option<StorageEncryption>,
+}
+
+impl SledSessionStore {
+    /// Creates a new `SledSessionStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The `sled::Db` instance to use for storage.
+    /// * `encryption` - The optional `StorageEncryption` to use for encrypting session state.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `sessions` tree cannot be opened.
+    pub fn new(db: Db, encryption: Option<StorageEncryption>) -> Result<Self> {
+        let tree = db.open_tree("sessions")?;
+        Ok(Self { tree, encryption })
+    }
+
+    /// Serializes a `SessionRecord` and encrypts it if encryption is enabled.
+    fn serialize_record(&self, record: &SessionRecord) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(record)?;
+
+        if let Some(ref encryption) = self.encryption {
+            encryption.encrypt_value(&serialized)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    /// Decrypts and deserializes a `SessionRecord`.
+    fn deserialize_record(&self, data: &[u8]) -> Result<SessionRecord> {
+        let decrypted = if let Some(ref encryption) = self.encryption {
+            encryption.decrypt_value(data)?
+        } else {
+            data.to_vec()
+        };
+
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+}
+
+impl SessionStore for SledSessionStore {
+    fn load_session(&self, remote_public_key: &[u8]) -> Result<Option<SessionRecord>> {
+        match self.tree.get(remote_public_key)? {
+            Some(data) => Ok(Some(self.deserialize_record(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_session(&self, remote_public_key: &[u8], record: &SessionRecord) -> Result<()> {
+        let value = self.serialize_record(record)?;
+        self.tree.insert(remote_public_key, value)?;
+        // Session state must hit disk before the ciphertext leaves this node,
+        // otherwise a crash could make us reuse message keys.
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    fn list_sessions(&self) -> Result<Vec<Vec<u8>>> {
+        self.tree
+            .iter()
+            .keys()
+            .map(|key| Ok(key?.to_vec()))
+            .collect()
+    }
+}
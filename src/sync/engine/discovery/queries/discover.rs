@@ -51,7 +51,7 @@ impl SyncEngine {
     /// # Arguments
     ///
     /// * `force` - If `true`, a discovery will be performed even if conditions
-    ///             for skipping are met.
+    ///   for skipping are met.
     ///
     /// # Errors
     ///
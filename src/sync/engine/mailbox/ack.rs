@@ -33,8 +33,7 @@ impl SyncEngine {
             return Ok(());
         };
 
-        let recipient_hash =
-            StorageEncryption::derive_recipient_hash(&self.identity.hpke_public_key());
+        let mailbox_keys = self.identity.mailbox_keys();
 
         info!(
             "Acknowledging {} messages to {} mailboxes",
@@ -51,10 +50,19 @@ impl SyncEngine {
         for peer_id in self.get_mailbox_providers().iter() {
             let ack_result = retry_policy
                 .retry_with_jitter(|| async {
-                    network
-                        .mailbox_ack(*peer_id, recipient_hash, msg_ids.clone())
-                        .await
-                        .map_err(|e| anyhow!("ACK failed: {}", e))
+                    // The messages may be stored under any of our recipient hashes.
+                    let mut deleted = 0;
+                    for public_key in &mailbox_keys {
+                        let proof = self
+                            .prove_mailbox_ownership(network, *peer_id, public_key)
+                            .await?;
+                        let recipient_hash = StorageEncryption::derive_recipient_hash(public_key);
+                        deleted += network
+                            .mailbox_ack(*peer_id, recipient_hash, msg_ids.clone(), proof)
+                            .await
+                            .map_err(|e| anyhow!("ACK failed: {}", e))?;
+                    }
+                    Ok(deleted)
                 })
                 .await;
 
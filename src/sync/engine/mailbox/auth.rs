@@ -0,0 +1,49 @@
+//! This module contains logic for proving ownership of our key to mailbox nodes.
+use anyhow::{Context, Result};
+use libp2p::PeerId;
+
+use crate::crypto::StorageEncryption;
+use crate::network::NetworkHandle;
+
+use super::super::SyncEngine;
+
+impl SyncEngine {
+    /// Proves to a mailbox node that we own the key behind a recipient hash.
+    ///
+    /// Mailbox nodes only hand out or delete our messages after we answer a
+    /// fresh challenge, so this is done before every `Fetch` and `Ack`.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The network handle used to reach the mailbox.
+    /// * `peer_id` - The `PeerId` of the mailbox node.
+    /// * `public_key` - Our E2E public key the recipient hash is derived
+    ///   from, one of `Identity::mailbox_keys`.
+    ///
+    /// # Returns
+    ///
+    /// The proof to present in `Fetch` and `Ack` requests.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mailbox refuses to issue a
+    /// challenge or the challenge is malformed.
+    pub(super) async fn prove_mailbox_ownership(
+        &self,
+        network: &NetworkHandle,
+        peer_id: PeerId,
+        public_key: &[u8],
+    ) -> Result<[u8; 32]> {
+        let challenge = network
+            .mailbox_challenge(
+                peer_id,
+                StorageEncryption::derive_recipient_hash(public_key),
+                public_key.to_vec(),
+            )
+            .await
+            .context("Mailbox challenge failed")?;
+
+        self.identity
+            .answer_mailbox_challenge(public_key, &challenge)
+    }
+}
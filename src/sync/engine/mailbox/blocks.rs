@@ -0,0 +1,79 @@
+//! This module contains logic for sending the block list to mailbox nodes.
+//!
+//! Mailboxes refuse to store messages for us from senders on the list, so
+//! blocked peers cannot fill our mailboxes while we are offline.
+use anyhow::Result;
+use libp2p::PeerId;
+use tracing::{debug, info};
+
+use super::super::SyncEngine;
+
+impl SyncEngine {
+    /// Marks the block list as changed, so it is sent to every mailbox again.
+    pub fn mark_blocks_changed(&mut self) {
+        self.blocks_synced.clear();
+    }
+
+    /// Sends the block list to the mailboxes that do not hold the current one.
+    ///
+    /// Mailboxes that cannot be reached, or do not support block lists, are
+    /// tried again on the next sync cycle.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block list cannot be read.
+    pub async fn sync_blocks_to_mailboxes(&mut self) -> Result<()> {
+        let Some(network) = self.network.clone() else {
+            return Ok(());
+        };
+
+        let pending: Vec<PeerId> = self
+            .get_mailbox_providers()
+            .iter()
+            .filter(|peer_id| !self.blocks_synced.contains(peer_id))
+            .copied()
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let blocked: Vec<PeerId> = self
+            .blocks
+            .list_blocked()
+            .await?
+            .into_iter()
+            .map(|entry| entry.peer_id)
+            .collect();
+        let public_key = self.identity.hpke_public_key();
+        let recipient_hash = self.identity.recipient_hash();
+
+        for peer_id in pending {
+            let result = match self
+                .prove_mailbox_ownership(&network, peer_id, &public_key)
+                .await
+            {
+                Ok(proof) => {
+                    network
+                        .mailbox_set_blocked(peer_id, recipient_hash, blocked.clone(), proof)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(count) => {
+                    info!("Mailbox {} now refuses {} blocked senders", peer_id, count);
+                    self.blocks_synced.insert(peer_id);
+                }
+                Err(e) => {
+                    debug!(
+                        "Failed to send the block list to mailbox {}: {}",
+                        peer_id, e
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
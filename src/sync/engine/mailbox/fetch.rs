@@ -73,6 +73,7 @@ impl SyncEngine {
     /// Fetches messages from a single mailbox provider.
     ///
     /// This function attempts to fetch messages from a specified mailbox,
+    /// for our current key and the keys replaced within the grace period,
     /// processes them, and then acknowledges their receipt. It updates the
     /// performance metrics for the mailbox based on the outcome.
     ///
@@ -93,8 +94,8 @@ impl SyncEngine {
             return Ok(vec![]);
         };
 
-        let recipient_hash =
-            StorageEncryption::derive_recipient_hash(&self.identity.hpke_public_key());
+        // Friends may still send to keys we replaced within the grace period.
+        let mailbox_keys = self.identity.mailbox_keys();
 
         debug!("Sync: Fetching messages from mailbox {}", peer_id);
 
@@ -103,10 +104,20 @@ impl SyncEngine {
 
         let fetch_result = retry_policy
             .retry_with_jitter(|| async {
-                network
-                    .mailbox_fetch(peer_id, recipient_hash, 100)
-                    .await
-                    .map_err(|e| anyhow!("Fetch failed: {}", e))
+                let mut messages = Vec::new();
+                for public_key in &mailbox_keys {
+                    let proof = self
+                        .prove_mailbox_ownership(&network, peer_id, public_key)
+                        .await?;
+                    let recipient_hash = StorageEncryption::derive_recipient_hash(public_key);
+                    messages.extend(
+                        network
+                            .mailbox_fetch(peer_id, recipient_hash, 100, proof)
+                            .await
+                            .map_err(|e| anyhow!("Fetch failed: {}", e))?,
+                    );
+                }
+                Ok(messages)
             })
             .await;
 
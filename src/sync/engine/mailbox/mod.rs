@@ -1,8 +1,11 @@
 //! This module contains mailbox-related logic for the synchronization engine.
 //!
-//! It handles fetching messages, acknowledging them, and managing the reliability
-//! of mailbox interactions.
+//! It handles fetching messages, acknowledging them, proving ownership of our
+//! key to mailbox nodes, sending them our block list, and managing the
+//! reliability of mailbox interactions.
 mod ack;
+mod auth;
+mod blocks;
 mod fetch;
 mod processing;
 mod reliability;
@@ -1,19 +1,28 @@
 //! This module contains logic for processing messages fetched from mailboxes.
-use anyhow::Result;
-use tracing::{debug, error, trace};
+use anyhow::{bail, Result};
+use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 use std::ops::Deref;
 
-use crate::cli::UiNotification;
-use crate::types::{ChatRequest, DeliveryConfirmation, DeliveryStatus, EncryptedMessage, Message};
+use crate::cli::{
+    fetch_attachment_from, open_direct_message, open_group_message, resolve_origin, Origin,
+    UiNotification,
+};
+use crate::crypto::signing;
+use crate::types::{
+    ChatRequest, DeliveryConfirmation, DeliveryStatus, EncryptedMessage, Group, Message,
+};
 
 use super::super::SyncEngine;
 
 impl SyncEngine {
     /// Processes a list of encrypted messages fetched from mailboxes.
     ///
-    /// This function iterates through the messages, decrypts them, marks them as seen,
+    /// This function iterates through the messages, drops those from blocked peers,
+    /// authenticates and decrypts them, marks them as seen,
     /// stores them in the history, sends delivery confirmations, and notifies the UI.
+    /// Direct messages from peers that are not friends are stored as message
+    /// requests instead, without a delivery confirmation.
     ///
     /// # Arguments
     ///
@@ -25,7 +34,8 @@ impl SyncEngine {
     ///
     /// # Errors
     ///
-    /// This function will return an error if message decryption, storage, or processing fails.
+    /// This function will return an error if checking the seen status, the
+    /// block list or the friends list fails.
     pub async fn process_mailbox_messages(
         &self,
         messages: Vec<EncryptedMessage>,
@@ -43,20 +53,76 @@ impl SyncEngine {
                 continue;
             }
 
-            // Reconstruct the message from the encrypted version.
-            let message = self
-                .reconstruct_message_from_mailbox(&encrypted_msg)
-                .await?;
+            // Drop messages from blocked peers; the mailbox may have stored
+            // them before it learned of the block.
+            if self.blocks.is_blocked(&encrypted_msg.sender).await? {
+                debug!(
+                    "Dropping mailbox message {} from blocked peer {}",
+                    encrypted_msg.id, encrypted_msg.sender
+                );
+                processed_msg_ids.push(encrypted_msg.id);
+                continue;
+            }
 
-            // Store the message in history.
-            if let Err(e) = self.history.store_message(message.clone()).await {
-                error!(
-                    "Failed to store mailbox message {} in history: {}",
-                    encrypted_msg.id, e
+            // Reject forged messages; they will never verify, so drop them from the mailbox.
+            if let Err(e) = self.authenticate_mailbox_message(&encrypted_msg).await {
+                warn!(
+                    "Rejecting mailbox message {} claiming to be from {}: {}",
+                    encrypted_msg.id, encrypted_msg.sender, e
                 );
+                processed_msg_ids.push(encrypted_msg.id);
                 continue;
             }
 
+            // Direct messages from strangers wait in the message requests.
+            let origin = if encrypted_msg.group_id.is_none() {
+                match self.resolve_mailbox_origin(&encrypted_msg).await? {
+                    Origin::Unknown => {
+                        if self.store_message_request(&encrypted_msg).await {
+                            processed_msg_ids.push(encrypted_msg.id);
+                        }
+                        continue;
+                    }
+                    origin => Some(origin),
+                }
+            } else {
+                None
+            };
+
+            // Reconstruct the message from the encrypted version.
+            let opened = self.open_mailbox_message(&encrypted_msg, origin).await;
+            let (stored, group_update, notification) = match opened {
+                Ok(opened) => opened,
+                Err(e) => {
+                    error!(
+                        "Failed to decrypt mailbox message {} from {}: {}",
+                        encrypted_msg.id, encrypted_msg.sender, e
+                    );
+                    continue;
+                }
+            };
+
+            // Store the message in history.
+            if let Some(ref message) = stored {
+                if let Err(e) = self.history.store_message(message.clone()).await {
+                    error!(
+                        "Failed to store mailbox message {} in history: {}",
+                        encrypted_msg.id, e
+                    );
+                    continue;
+                }
+
+                // The attachment is held by the device that sent the message.
+                if let Some(ref network) = self.network {
+                    fetch_attachment_from(
+                        network,
+                        &self.attachments,
+                        message,
+                        encrypted_msg.sender,
+                    );
+                }
+            }
+
             // Mark the message as seen.
             if let Err(e) = self.seen.mark_seen(encrypted_msg.id).await {
                 error!("Failed to mark message {} as seen: {}", encrypted_msg.id, e);
@@ -80,14 +146,43 @@ impl SyncEngine {
                 });
             }
 
-            // Notify the UI about the new message.
-            if let Err(e) = self.ui_notify_tx.send(UiNotification::NewMessage(message.clone())) {
-                trace!("UI notify channel closed while reporting message: {}", e);
+            // Notify the UI about group changes.
+            if let Some(group) = group_update {
+                if let Err(e) = self
+                    .ui_notify_tx
+                    .send(UiNotification::GroupUpdated(group.clone()))
+                {
+                    trace!("UI notify channel closed while reporting group: {}", e);
+                }
+
+                if let Some(ref web_tx) = self.web_notify_tx {
+                    let _ = web_tx.send(UiNotification::GroupUpdated(group));
+                }
+            }
+
+            if let Some(notification) = notification {
+                if let Err(e) = self.ui_notify_tx.send(notification.clone()) {
+                    trace!("UI notify channel closed while reporting key change: {}", e);
+                }
+
+                if let Some(ref web_tx) = self.web_notify_tx {
+                    let _ = web_tx.send(notification);
+                }
             }
 
-            // Also send to web UI if available.
-            if let Some(ref web_tx) = self.web_notify_tx {
-                let _ = web_tx.send(UiNotification::NewMessage(message));
+            // Notify the UI about the new message.
+            if let Some(message) = stored {
+                if let Err(e) = self
+                    .ui_notify_tx
+                    .send(UiNotification::NewMessage(message.clone()))
+                {
+                    trace!("UI notify channel closed while reporting message: {}", e);
+                }
+
+                // Also send to web UI if available.
+                if let Some(ref web_tx) = self.web_notify_tx {
+                    let _ = web_tx.send(UiNotification::NewMessage(message));
+                }
             }
 
             processed_msg_ids.push(encrypted_msg.id);
@@ -96,6 +191,175 @@ impl SyncEngine {
         Ok(processed_msg_ids)
     }
 
+    /// Checks that a mailbox message really comes from the peer it claims.
+    ///
+    /// The signature must verify against the sender's `PeerId`, and, if the
+    /// sender is a device of a friend or one of our own devices, the attached
+    /// E2E public key must match the one stored for that device. Group
+    /// messages and messages from unknown peers are only checked for a valid
+    /// signature here.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted_msg` - The `EncryptedMessage` to authenticate.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message is forged or the
+    /// sender's key does not match the stored friend key.
+    pub async fn authenticate_mailbox_message(
+        &self,
+        encrypted_msg: &EncryptedMessage,
+    ) -> Result<()> {
+        signing::verify_mailbox_message(encrypted_msg)?;
+
+        // Group members need not be friends; membership is checked when the message is opened.
+        if encrypted_msg.group_id.is_some() {
+            return Ok(());
+        }
+
+        let device = match self.resolve_mailbox_origin(encrypted_msg).await? {
+            Origin::Friend { device, .. } | Origin::OwnDevice(device) => device,
+            Origin::Unknown => return Ok(()),
+        };
+
+        if device.e2e_public_key != encrypted_msg.sender_pub_key {
+            bail!("sender public key does not match the stored device key");
+        }
+
+        Ok(())
+    }
+
+    /// Finds the device a direct mailbox message came from.
+    async fn resolve_mailbox_origin(&self, encrypted_msg: &EncryptedMessage) -> Result<Origin> {
+        resolve_origin(
+            &self.identity,
+            self.friends.as_ref(),
+            self.devices.as_ref(),
+            &encrypted_msg.sender,
+        )
+        .await
+    }
+
+    /// Decrypts a direct mailbox message from a peer that is not a friend and
+    /// stores it as a message request.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted_msg` - The authenticated `EncryptedMessage`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the message was handled and can be acknowledged.
+    async fn store_message_request(&self, encrypted_msg: &EncryptedMessage) -> bool {
+        let message = match self.reconstruct_message_from_mailbox(encrypted_msg).await {
+            Ok(message) => message,
+            Err(e) => {
+                warn!(
+                    "Dropping undecryptable mailbox message {} from unknown peer {}: {}",
+                    encrypted_msg.id, encrypted_msg.sender, e
+                );
+                return true;
+            }
+        };
+
+        match self
+            .requests
+            .add_message(&message, &encrypted_msg.sender_pub_key)
+            .await
+        {
+            Ok(true) => {
+                if let Err(e) = self
+                    .ui_notify_tx
+                    .send(UiNotification::MessageRequestReceived(encrypted_msg.sender))
+                {
+                    trace!("UI notify channel closed while reporting request: {}", e);
+                }
+
+                if let Some(ref web_tx) = self.web_notify_tx {
+                    let _ =
+                        web_tx.send(UiNotification::MessageRequestReceived(encrypted_msg.sender));
+                }
+            }
+            Ok(false) => {
+                debug!(
+                    "Dropping mailbox message {} from {}: the message request is full or has another key",
+                    encrypted_msg.id, encrypted_msg.sender
+                );
+            }
+            Err(e) => {
+                error!(
+                    "Failed to store message request {} from {}: {}",
+                    encrypted_msg.id, encrypted_msg.sender, e
+                );
+                return false;
+            }
+        }
+
+        if let Err(e) = self.seen.mark_seen(encrypted_msg.id).await {
+            error!("Failed to mark message {} as seen: {}", encrypted_msg.id, e);
+        }
+        true
+    }
+
+    /// Opens an authenticated `EncryptedMessage` fetched from a mailbox.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted_msg` - The `EncryptedMessage` to open.
+    /// * `origin` - The device a direct message came from, or `None` for a
+    ///   group message.
+    ///
+    /// # Returns
+    ///
+    /// The decrypted message to store, if any, the group that changed, if
+    /// any, and the notification to show, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the message cannot be decrypted.
+    async fn open_mailbox_message(
+        &self,
+        encrypted_msg: &EncryptedMessage,
+        origin: Option<Origin>,
+    ) -> Result<(Option<Message>, Option<Group>, Option<UiNotification>)> {
+        let copy = Message {
+            id: encrypted_msg.id,
+            sender: encrypted_msg.sender,
+            recipient: self.identity.peer_id,
+            timestamp: encrypted_msg.timestamp,
+            content: encrypted_msg.encrypted_content.clone(),
+            nonce: encrypted_msg.nonce,
+            delivery_status: DeliveryStatus::Delivered,
+            signature: Vec::new(),
+            group_id: encrypted_msg.group_id,
+            attachment: None,
+            sender_pub_key: Vec::new(),
+        };
+
+        let Some(origin) = origin else {
+            let incoming = open_group_message(
+                &self.identity,
+                self.friends.as_ref(),
+                self.groups.as_ref(),
+                copy,
+            )
+            .await?;
+            return Ok((incoming.message, incoming.group, None));
+        };
+
+        let opened = open_direct_message(
+            &self.identity,
+            self.friends.as_ref(),
+            self.devices.as_ref(),
+            self.history.as_ref(),
+            origin,
+            copy,
+        )
+        .await?;
+        Ok((opened.message, None, opened.notification))
+    }
+
     /// Reconstructs a `Message` from an `EncryptedMessage` fetched from a mailbox.
     ///
     /// This involves using the local identity's HPKE context to decrypt the content.
@@ -115,19 +379,25 @@ impl SyncEngine {
         &self,
         encrypted_msg: &EncryptedMessage,
     ) -> Result<Message> {
-        let plaintext_content = self.identity.deref().decrypt_from(
+        let body = self.identity.deref().decrypt_from(
             &encrypted_msg.sender_pub_key,
             &encrypted_msg.encrypted_content,
         )?;
 
-        Ok(Message {
+        let mut message = Message {
             id: encrypted_msg.id,
             sender: encrypted_msg.sender,
             recipient: self.identity.peer_id, // Our peer_id is the recipient
             timestamp: encrypted_msg.timestamp,
-            content: plaintext_content,
+            content: Vec::new(),
             nonce: encrypted_msg.nonce,
             delivery_status: DeliveryStatus::Delivered, // Mark as delivered upon processing
-        })
+            signature: Vec::new(),
+            group_id: None,
+            attachment: None,
+            sender_pub_key: Vec::new(),
+        };
+        message.set_body(body);
+        Ok(message)
     }
 }
@@ -6,7 +6,10 @@
 use crate::cli::UiNotification;
 use crate::crypto::Identity;
 use crate::network::NetworkHandle;
-use crate::storage::{FriendsStore, KnownMailboxesStore, MessageStore, OutboxStore, SeenTracker};
+use crate::storage::{
+    AttachmentStore, BlockListStore, DeviceStore, FriendsStore, GroupsStore, KnownMailboxesStore,
+    MessageRequestsStore, MessageStore, OutboxStore, SeenTracker,
+};
 use crate::sync::backoff::BackoffManager;
 use anyhow::Result;
 use libp2p::{kad, PeerId};
@@ -54,6 +57,18 @@ pub struct SyncEngine {
     pub seen: Arc<dyn SeenTracker + Send + Sync>,
     /// The store for known mailbox providers.
     pub known_mailboxes: Arc<dyn KnownMailboxesStore + Send + Sync>,
+    /// The store for managing groups.
+    pub groups: Arc<dyn GroupsStore + Send + Sync>,
+    /// The store for attachments.
+    pub attachments: Arc<AttachmentStore>,
+    /// The peers the user blocked.
+    pub blocks: Arc<dyn BlockListStore + Send + Sync>,
+    /// The store for messages from peers that are not friends.
+    pub requests: Arc<dyn MessageRequestsStore + Send + Sync>,
+    /// The devices linked to our identity.
+    pub devices: Arc<dyn DeviceStore + Send + Sync>,
+    /// The mailboxes that hold the current block list.
+    pub blocks_synced: HashSet<PeerId>,
     /// The network handle for communicating with the `NetworkLayer`.
     pub network: Option<NetworkHandle>,
     /// Sender for UI notifications.
@@ -75,25 +90,16 @@ pub struct SyncStores {
     pub seen: Arc<dyn SeenTracker + Send + Sync>,
     /// The known mailboxes store.
     pub known_mailboxes: Arc<dyn KnownMailboxesStore + Send + Sync>,
-}
-
-impl SyncStores {
-    /// Creates a new `SyncStores` instance.
-    pub fn new(
-        friends: Arc<dyn FriendsStore + Send + Sync>,
-        outbox: Arc<dyn OutboxStore + Send + Sync>,
-        history: Arc<dyn MessageStore + Send + Sync>,
-        seen: Arc<dyn SeenTracker + Send + Sync>,
-        known_mailboxes: Arc<dyn KnownMailboxesStore + Send + Sync>,
-    ) -> Self {
-        Self {
-            friends,
-            outbox,
-            history,
-            seen,
-            known_mailboxes,
-        }
-    }
+    /// The groups store.
+    pub groups: Arc<dyn GroupsStore + Send + Sync>,
+    /// The attachment store.
+    pub attachments: Arc<AttachmentStore>,
+    /// The block list.
+    pub blocks: Arc<dyn BlockListStore + Send + Sync>,
+    /// The message requests store.
+    pub requests: Arc<dyn MessageRequestsStore + Send + Sync>,
+    /// The linked devices store.
+    pub devices: Arc<dyn DeviceStore + Send + Sync>,
 }
 
 /// Represents the state of a pending Kademlia DHT query.
@@ -141,6 +147,11 @@ impl SyncEngine {
             history,
             seen,
             known_mailboxes,
+            groups,
+            attachments,
+            blocks,
+            requests,
+            devices,
         } = stores;
         let (event_tx, event_rx) = mpsc::unbounded_channel();
         let engine = Self {
@@ -160,6 +171,12 @@ impl SyncEngine {
             history,
             seen,
             known_mailboxes,
+            groups,
+            attachments,
+            blocks,
+            requests,
+            devices,
+            blocks_synced: HashSet::new(),
             network: Some(network),
             ui_notify_tx,
             web_notify_tx,
@@ -185,7 +202,7 @@ impl SyncEngine {
     /// Runs a single synchronization cycle.
     ///
     /// This includes discovering mailboxes, fetching messages, retrying outbox
-    /// messages, and cleaning up old data.
+    /// messages, sending the block list to mailboxes, and cleaning up old data.
     ///
     /// # Errors
     ///
@@ -205,6 +222,10 @@ impl SyncEngine {
             error!("Failed to retry outbox: {}", e);
         }
 
+        if let Err(e) = self.sync_blocks_to_mailboxes().await {
+            error!("Failed to sync the block list to mailboxes: {}", e);
+        }
+
         if let Err(e) = self
             .seen
             .cleanup_old(Duration::from_secs(7 * 24 * 60 * 60))
@@ -1,26 +1,26 @@
 use std::time::Instant;
-use std::ops::Deref;
 
 use anyhow::{anyhow, Result};
 use tracing::{debug, info};
 
+use crate::cli::own_devices;
 use crate::crypto::StorageEncryption;
 use crate::network::NetworkHandle;
-use crate::types::EncryptedMessage;
+use crate::types::{EncryptedMessage, Message};
 
 use super::super::SyncEngine;
 
 impl SyncEngine {
     /// Forwards a pending message to available mailbox providers.
     ///
-    /// This function attempts to encrypt and store a message in at least two
+    /// This function attempts to store a message in at least two
     /// mailbox providers for redundancy. It ranks available mailboxes and updates
     /// their performance metrics.
     ///
     /// # Arguments
     ///
     /// * `network` - The `NetworkHandle` to use for interacting with the network.
-    /// * `message` - The message to encrypt and forward.
+    /// * `message` - The sealed message to forward.
     ///
     /// # Returns
     ///
@@ -29,37 +29,30 @@ impl SyncEngine {
     ///
     /// # Errors
     ///
-    /// This function will return an error if the recipient is not a friend or if
-    /// there are issues with encryption or storage.
+    /// This function will return an error if the recipient is neither a friend
+    /// nor a member of the message's group, or if there are issues with
+    /// encryption or storage.
     pub(super) async fn forward_pending_message(
         &mut self,
         network: &NetworkHandle,
-        message: &crate::types::Message,
+        message: &Message,
     ) -> Result<bool> {
-        let Some(friend) = self.friends.get_friend(&message.recipient).await? else {
-            return Err(anyhow!(
-                "Cannot forward message {}: recipient {} not in friends list.",
-                message.id,
-                message.recipient
-            ));
-        };
-
-        let recipient_hash = StorageEncryption::derive_recipient_hash(&friend.e2e_public_key);
-        // Encrypt the message content.
-        let encrypted_content = self
-            .identity
-            .deref()
-            .encrypt_for(&friend.e2e_public_key, &message.content)?;
+        let recipient_key = self.recipient_public_key(message).await?;
 
-        let encrypted_msg = EncryptedMessage {
+        let recipient_hash = StorageEncryption::derive_recipient_hash(&recipient_key);
+        // Outbox messages are already sealed for the recipient.
+        let mut encrypted_msg = EncryptedMessage {
             id: message.id,
             sender: self.identity.peer_id,
             recipient_hash,
-            encrypted_content,
+            encrypted_content: message.content.clone(),
             timestamp: message.timestamp,
             nonce: message.nonce,
             sender_pub_key: self.identity.hpke_public_key(),
+            signature: Vec::new(),
+            group_id: message.group_id,
         };
+        encrypted_msg.signature = self.identity.sign_mailbox_message(&encrypted_msg)?;
 
         let candidate_mailboxes = self.rank_mailboxes_subset(&self.discovered_mailboxes);
         if candidate_mailboxes.is_empty() {
@@ -135,4 +128,47 @@ impl SyncEngine {
 
         Ok(forwarded_count > 0)
     }
+
+    /// Looks up the E2E public key of the recipient of a pending message.
+    ///
+    /// The recipient may also be a linked device of a friend or one of our
+    /// own devices. Group members are not necessarily our friends, so copies
+    /// of group messages fall back to the key from the group's member list.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the recipient is unknown or
+    /// storage cannot be read.
+    async fn recipient_public_key(&self, message: &Message) -> Result<Vec<u8>> {
+        if let Some(friend) = self.friends.get_friend(&message.recipient).await? {
+            return Ok(friend.e2e_public_key);
+        }
+
+        if message.group_id.is_none() {
+            if let Some((_, device)) = self.friends.find_by_device(&message.recipient).await? {
+                return Ok(device.e2e_public_key);
+            }
+            if let Some(device) = own_devices(&self.identity, self.devices.as_ref())
+                .await?
+                .into_iter()
+                .find(|device| device.peer_id == message.recipient)
+            {
+                return Ok(device.e2e_public_key);
+            }
+        }
+
+        if let Some(group_id) = message.group_id {
+            if let Some(group) = self.groups.get_group(&group_id).await? {
+                if let Some(member) = group.member(&message.recipient) {
+                    return Ok(member.e2e_public_key.clone());
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "Cannot forward message {}: recipient {} is neither a friend nor a known device.",
+            message.id,
+            message.recipient
+        ))
+    }
 }
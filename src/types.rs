@@ -1,4 +1,6 @@
 //! This module defines common data structures and types used throughout the p2p-chat application.
+use crate::crypto::sender_key::{SenderKeyDistribution, SenderKeyMessage};
+use anyhow::Result;
 use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -34,13 +36,146 @@ pub struct Message {
     pub recipient: PeerId,
     /// The timestamp when the message was created (milliseconds since epoch).
     pub timestamp: i64,
-    /// The encrypted content of the message.
+    /// The plaintext body of the message, as kept in the history.
+    ///
+    /// On the wire the body is encrypted and travels in
+    /// `EncryptedMessage::encrypted_content`, see `Message::body`.
+    #[serde(with = "serde_bytes")]
     pub content: Vec<u8>,
     /// A random nonce used for ordering or cryptographic purposes.
     pub nonce: u64,
     /// The current delivery status of the message.
     #[serde(default)]
     pub delivery_status: DeliveryStatus,
+    /// The sender's Ed25519 signature over the message, empty once stored locally.
+    #[serde(default, with = "serde_bytes")]
+    pub signature: Vec<u8>,
+    /// The group the message belongs to, or `None` for a direct conversation.
+    #[serde(default)]
+    pub group_id: Option<Uuid>,
+    /// The file attached to the message, if any.
+    ///
+    /// This is only set on stored messages. On the wire the manifest travels
+    /// inside the encrypted content, see `Message::body`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Box<AttachmentManifest>>,
+    /// The sender's E2E public key, sent along with direct messages.
+    ///
+    /// It lets a recipient that does not have the sender as a friend decrypt
+    /// the message into its message requests. It is not signed; decrypting
+    /// with it proves the sender holds the matching private key. Empty on
+    /// stored and group messages.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "serde_bytes")]
+    pub sender_pub_key: Vec<u8>,
+}
+
+/// Marks a message body that carries an attachment manifest after the text.
+const ATTACHMENT_BODY_MARKER: &[u8] = b"\0p2p-chat/attachment\0";
+
+impl Message {
+    /// Returns the bytes to encrypt for the message: its text, followed by the
+    /// attachment manifest if there is one.
+    ///
+    /// Messages without an attachment are encoded as their plain text, so that
+    /// they stay readable by peers that do not know about attachments.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the manifest cannot be serialized.
+    pub fn body(&self) -> Result<Vec<u8>> {
+        let Some(ref manifest) = self.attachment else {
+            return Ok(self.content.clone());
+        };
+
+        let mut body = ATTACHMENT_BODY_MARKER.to_vec();
+        body.extend_from_slice(&(self.content.len() as u32).to_be_bytes());
+        body.extend_from_slice(&self.content);
+        body.extend_from_slice(&serde_json::to_vec(manifest)?);
+        Ok(body)
+    }
+
+    /// Restores the text and attachment of a message from its decrypted body.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The decrypted body, as produced by `Message::body`.
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.attachment = None;
+
+        let Some(rest) = body.strip_prefix(ATTACHMENT_BODY_MARKER) else {
+            self.content = body;
+            return;
+        };
+
+        let parsed = rest.split_first_chunk::<4>().and_then(|(length, rest)| {
+            let length = u32::from_be_bytes(*length) as usize;
+            if length > rest.len() {
+                return None;
+            }
+            let (text, manifest) = rest.split_at(length);
+            let manifest = serde_json::from_slice(manifest).ok()?;
+            Some((text.to_vec(), manifest))
+        });
+
+        match parsed {
+            Some((text, manifest)) => {
+                self.content = text;
+                self.attachment = Some(Box::new(manifest));
+            }
+            None => self.content = body,
+        }
+    }
+
+    /// Returns the text of a message from the history or a notification.
+    ///
+    /// The history stores messages with their decrypted text, so this is the
+    /// one way the TUI, the web API and the control socket read it.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.content).into_owned()
+    }
+}
+
+/// Describes a file sent as an attachment.
+///
+/// The file is split into chunks that are encrypted with `key` and fetched
+/// from the sender one by one, so that an interrupted transfer can resume
+/// with the chunks that are still missing.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AttachmentManifest {
+    /// The hex-encoded SHA-256 hash of the file, which also identifies the attachment.
+    pub hash: String,
+    /// The name of the file.
+    pub file_name: String,
+    /// The MIME type of the file.
+    pub mime: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The size of each chunk before encryption; the last chunk may be smaller.
+    pub chunk_size: u32,
+    /// The hex-encoded SHA-256 hashes of the encrypted chunks, in order.
+    pub chunks: Vec<String>,
+    /// The key the chunks are encrypted with.
+    pub key: [u8; 32],
+}
+
+impl AttachmentManifest {
+    /// Returns a short, human-readable description of the attachment.
+    pub fn describe(&self) -> String {
+        const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+        let mut size = self.size as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            format!("📎 {} ({} B)", self.file_name, self.size)
+        } else {
+            format!("📎 {} ({:.1} {})", self.file_name, size, UNITS[unit])
+        }
+    }
 }
 
 /// Represents a friend in the application.
@@ -52,6 +187,43 @@ pub struct Friend {
     pub e2e_public_key: Vec<u8>,
     /// An optional nickname for the friend.
     pub nickname: Option<String>,
+    /// Whether the friend's keys were verified by comparing safety numbers.
+    #[serde(default)]
+    pub verified: bool,
+    /// The devices the friend linked to its identity, which receive their
+    /// own copy of each message.
+    #[serde(default)]
+    pub devices: Vec<Device>,
+    /// The timestamp of the device list `devices` came from (milliseconds
+    /// since epoch), or 0 if the friend never sent one.
+    #[serde(default)]
+    pub devices_timestamp: i64,
+}
+
+impl Friend {
+    /// Returns the friend's primary device, whose Peer ID is the friend's.
+    pub fn primary_device(&self) -> Device {
+        Device {
+            peer_id: self.peer_id,
+            e2e_public_key: self.e2e_public_key.clone(),
+        }
+    }
+
+    /// Returns the friend's primary device followed by its linked devices.
+    pub fn all_devices(&self) -> Vec<Device> {
+        std::iter::once(self.primary_device())
+            .chain(self.devices.iter().cloned())
+            .collect()
+    }
+}
+
+/// A device running a client, with its own keypairs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Device {
+    /// The Peer ID of the device.
+    pub peer_id: PeerId,
+    /// The E2E public key of the device.
+    pub e2e_public_key: Vec<u8>,
 }
 
 /// Represents an encrypted message stored in a mailbox.
@@ -64,6 +236,7 @@ pub struct EncryptedMessage {
     /// The cryptographic hash of the recipient's public key, used for mailbox lookup.
     pub recipient_hash: [u8; 32],
     /// The encrypted content of the message.
+    #[serde(with = "serde_bytes")]
     pub encrypted_content: Vec<u8>,
     /// The timestamp when the message was created (milliseconds since epoch).
     pub timestamp: i64,
@@ -71,7 +244,259 @@ pub struct EncryptedMessage {
     #[serde(default)]
     pub nonce: u64,
     /// The sender's E2E public key.
+    #[serde(with = "serde_bytes")]
     pub sender_pub_key: Vec<u8>,
+    /// The sender's Ed25519 signature over all other fields.
+    #[serde(default, with = "serde_bytes")]
+    pub signature: Vec<u8>,
+    /// The group the message belongs to, or `None` for a direct message.
+    #[serde(default)]
+    pub group_id: Option<Uuid>,
+}
+
+/// Represents a group conversation.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Group {
+    /// The unique identifier for the group.
+    pub id: Uuid,
+    /// The name of the group.
+    pub name: String,
+    /// All members of the group, including ourselves.
+    pub members: Vec<GroupMember>,
+    /// The Peer ID of the member who created the group.
+    pub created_by: PeerId,
+    /// The timestamp when the group was created (milliseconds since epoch).
+    pub created_at: i64,
+}
+
+impl Group {
+    /// Returns the member with the given Peer ID, if any.
+    pub fn member(&self, peer_id: &PeerId) -> Option<&GroupMember> {
+        self.members
+            .iter()
+            .find(|member| member.peer_id == *peer_id)
+    }
+
+    /// Returns whether a peer is a member of the group.
+    pub fn is_member(&self, peer_id: &PeerId) -> bool {
+        self.member(peer_id).is_some()
+    }
+}
+
+/// Represents a member of a group.
+///
+/// Members are not necessarily friends of each other, so the member list
+/// carries the E2E public key vouched for by whoever added the member.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GroupMember {
+    /// The Peer ID of the member.
+    pub peer_id: PeerId,
+    /// The E2E public key of the member.
+    pub e2e_public_key: Vec<u8>,
+    /// The name of the member, if known.
+    pub name: Option<String>,
+}
+
+/// Group state sent to a single member over its pairwise session.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GroupControl {
+    /// The group as the sender sees it.
+    pub group: Group,
+    /// The sender's current sender key, if the member does not hold it yet.
+    pub sender_key: Option<SenderKeyDistribution>,
+}
+
+/// The content of a group message copy addressed to one member.
+///
+/// The body is encrypted once with the sender's sender key and shared by all
+/// copies; only the optional control part is encrypted for each member.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GroupEnvelope {
+    /// The ID of the group message, shared by all copies.
+    pub message_id: Uuid,
+    /// A `GroupControl` sealed with the pairwise session, if any.
+    pub control: Option<Vec<u8>>,
+    /// The message text encrypted with the sender key, if any.
+    pub body: Option<SenderKeyMessage>,
+}
+
+/// Marks a message body that carries a key update instead of text.
+const KEY_UPDATE_BODY_MARKER: &[u8] = b"\0p2p-chat/key-update\0";
+
+/// Marks a message body that carries a device list instead of text.
+const DEVICE_LIST_BODY_MARKER: &[u8] = b"\0p2p-chat/device-list\0";
+
+/// Marks a message body that carries a copy of a message sent from another
+/// of our devices.
+const SENT_COPY_BODY_MARKER: &[u8] = b"\0p2p-chat/sent-copy\0";
+
+/// Marks a message body that asks to delete earlier messages.
+const DELETE_REQUEST_BODY_MARKER: &[u8] = b"\0p2p-chat/delete\0";
+
+/// Encodes a value as a message body starting with `marker`.
+fn to_marked_body<T: Serialize>(marker: &[u8], value: &T) -> Result<Vec<u8>> {
+    let mut body = marker.to_vec();
+    body.extend_from_slice(&serde_json::to_vec(value)?);
+    Ok(body)
+}
+
+/// Decodes a value from a message body starting with `marker`.
+fn from_marked_body<T: for<'de> Deserialize<'de>>(marker: &[u8], body: &[u8]) -> Option<T> {
+    serde_json::from_slice(body.strip_prefix(marker)?).ok()
+}
+
+/// Announces that a peer replaced its E2E key.
+///
+/// It is signed with the peer's libp2p identity, which does not change, and
+/// sent to each friend as the body of a message encrypted with the previous
+/// key, so it travels like any other message, directly or via mailboxes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeyUpdate {
+    /// The Peer ID of the peer that rotated its key.
+    pub peer_id: PeerId,
+    /// The E2E public key being replaced.
+    pub previous_key: Vec<u8>,
+    /// The new E2E public key.
+    pub new_key: Vec<u8>,
+    /// The timestamp when the key was rotated (milliseconds since epoch).
+    pub timestamp: i64,
+    /// The peer's Ed25519 signature over all other fields.
+    pub signature: Vec<u8>,
+}
+
+impl KeyUpdate {
+    /// Returns the message body carrying the key update.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the update cannot be serialized.
+    pub fn to_body(&self) -> Result<Vec<u8>> {
+        to_marked_body(KEY_UPDATE_BODY_MARKER, self)
+    }
+
+    /// Parses a key update from a decrypted message body.
+    ///
+    /// # Returns
+    ///
+    /// The key update, or `None` if the body carries a regular message.
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        from_marked_body(KEY_UPDATE_BODY_MARKER, body)
+    }
+}
+
+/// The devices linked to an identity.
+///
+/// The list is signed by the primary device, whose libp2p keypair is the
+/// identity, and sent to friends and to the linked devices whenever it
+/// changes, like a `KeyUpdate`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeviceList {
+    /// The Peer ID of the primary device.
+    pub account: PeerId,
+    /// The linked devices, without the primary device.
+    pub devices: Vec<Device>,
+    /// The timestamp when the list was signed (milliseconds since epoch).
+    pub timestamp: i64,
+    /// The primary device's Ed25519 signature over all other fields.
+    pub signature: Vec<u8>,
+}
+
+impl DeviceList {
+    /// Returns the message body carrying the device list.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the list cannot be serialized.
+    pub fn to_body(&self) -> Result<Vec<u8>> {
+        to_marked_body(DEVICE_LIST_BODY_MARKER, self)
+    }
+
+    /// Parses a device list from a decrypted message body.
+    ///
+    /// # Returns
+    ///
+    /// The device list, or `None` if the body carries something else.
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        from_marked_body(DEVICE_LIST_BODY_MARKER, body)
+    }
+
+    /// Returns whether a device is linked.
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.devices.iter().any(|device| device.peer_id == *peer_id)
+    }
+}
+
+/// A copy of a direct message sent from another of our devices, so that
+/// every device shows the whole conversation.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SentCopy {
+    /// The ID of the message.
+    pub id: Uuid,
+    /// The Peer ID of the friend the message was sent to.
+    pub recipient: PeerId,
+    /// The timestamp of the message (milliseconds since epoch).
+    pub timestamp: i64,
+    /// The nonce of the message.
+    pub nonce: u64,
+    /// The body of the message.
+    pub body: Vec<u8>,
+}
+
+impl SentCopy {
+    /// Returns the message body carrying the copy.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the copy cannot be serialized.
+    pub fn to_body(&self) -> Result<Vec<u8>> {
+        to_marked_body(SENT_COPY_BODY_MARKER, self)
+    }
+
+    /// Parses a sent copy from a decrypted message body.
+    ///
+    /// # Returns
+    ///
+    /// The copy, or `None` if the body carries something else.
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        from_marked_body(SENT_COPY_BODY_MARKER, body)
+    }
+}
+
+/// Asks the recipients of messages to delete them ("delete for everyone").
+///
+/// It is signed by the device that sends it and travels like a `KeyUpdate`.
+/// Recipients only delete the listed messages that were sent by the author
+/// of the request.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeleteRequest {
+    /// The Peer ID of the device that sent the request.
+    pub peer_id: PeerId,
+    /// The IDs of the messages to delete, as stored by the recipient.
+    pub message_ids: Vec<Uuid>,
+    /// The timestamp when the request was signed (milliseconds since epoch).
+    pub timestamp: i64,
+    /// The device's Ed25519 signature over all other fields.
+    pub signature: Vec<u8>,
+}
+
+impl DeleteRequest {
+    /// Returns the message body carrying the delete request.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request cannot be serialized.
+    pub fn to_body(&self) -> Result<Vec<u8>> {
+        to_marked_body(DELETE_REQUEST_BODY_MARKER, self)
+    }
+
+    /// Parses a delete request from a decrypted message body.
+    ///
+    /// # Returns
+    ///
+    /// The request, or `None` if the body carries something else.
+    pub fn from_body(body: &[u8]) -> Option<Self> {
+        from_marked_body(DELETE_REQUEST_BODY_MARKER, body)
+    }
 }
 
 /// Represents a delivery confirmation for a message.
@@ -98,7 +523,7 @@ pub enum ChatRequest {
     /// Request to send a chat message.
     SendMessage {
         /// The message to send.
-        message: Message,
+        message: Box<Message>,
     },
     /// Request to send a delivery confirmation.
     DeliveryConfirmation {
@@ -124,6 +549,83 @@ pub enum ChatResponse {
     },
 }
 
+/// Represents a request in the attachment protocol.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AttachmentRequest {
+    /// The hash of the attachment, as found in its manifest.
+    pub hash: String,
+    /// The index of the requested chunk.
+    pub index: u32,
+}
+
+/// Represents a response in the attachment protocol.
+#[derive(Clone, Debug)]
+pub enum AttachmentResponse {
+    /// The requested chunk, still encrypted with the attachment key.
+    Chunk {
+        /// The encrypted chunk.
+        data: Vec<u8>,
+    },
+    /// The peer does not have the requested chunk.
+    NotFound,
+}
+
+/// A signed contact card, exchanged when two peers become friends.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ContactCard {
+    /// The Peer ID of the card owner.
+    pub peer_id: PeerId,
+    /// The E2E public key of the card owner.
+    pub e2e_public_key: Vec<u8>,
+    /// The display name chosen by the card owner, if any.
+    pub display_name: Option<String>,
+    /// The timestamp when the card was created (milliseconds since epoch).
+    pub timestamp: i64,
+    /// The owner's Ed25519 signature over all other fields.
+    pub signature: Vec<u8>,
+}
+
+/// Represents a request in the contact protocol.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ContactRequest {
+    /// Ask a peer to become friends.
+    Request {
+        /// The requester's contact card.
+        card: ContactCard,
+    },
+    /// Accept a previously received friend request.
+    Accept {
+        /// The accepting peer's contact card.
+        card: ContactCard,
+    },
+    /// Reject a previously received friend request.
+    Reject {
+        /// The rejecting peer's contact card.
+        card: ContactCard,
+    },
+}
+
+impl ContactRequest {
+    /// Returns the contact card carried by the request.
+    pub fn card(&self) -> &ContactCard {
+        match self {
+            ContactRequest::Request { card }
+            | ContactRequest::Accept { card }
+            | ContactRequest::Reject { card } => card,
+        }
+    }
+}
+
+/// Represents a response in the contact protocol.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ContactResponse {
+    /// Acknowledges a contact request.
+    Ack {
+        /// Whether the request was accepted for processing.
+        success: bool,
+    },
+}
+
 /// Represents a request to a mailbox node.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum MailboxRequest {
@@ -132,7 +634,14 @@ pub enum MailboxRequest {
         /// The cryptographic hash of the recipient's public key.
         recipient: [u8; 32],
         /// The encrypted message to store.
-        message: EncryptedMessage,
+        message: Box<EncryptedMessage>,
+    },
+    /// Request for a challenge proving ownership of a recipient's key.
+    Challenge {
+        /// The cryptographic hash of the recipient's public key.
+        recipient: [u8; 32],
+        /// The recipient's public key, which must match the hash.
+        public_key: Vec<u8>,
     },
     /// Request to fetch encrypted messages for a recipient.
     Fetch {
@@ -140,6 +649,8 @@ pub enum MailboxRequest {
         recipient: [u8; 32],
         /// The maximum number of messages to fetch.
         limit: usize,
+        /// The answer to a challenge issued for this recipient.
+        proof: [u8; 32],
     },
     /// Request to acknowledge and delete messages from the mailbox.
     Ack {
@@ -147,9 +658,29 @@ pub enum MailboxRequest {
         recipient: [u8; 32],
         /// The IDs of the messages to acknowledge and delete.
         msg_ids: Vec<Uuid>,
+        /// The answer to a challenge issued for this recipient.
+        proof: [u8; 32],
+    },
+    /// Request to replace the senders whose messages the mailbox refuses for a recipient.
+    SetBlocked {
+        /// The cryptographic hash of the recipient's public key.
+        recipient: [u8; 32],
+        /// The `PeerId`s of the blocked senders.
+        blocked: Vec<PeerId>,
+        /// The answer to a challenge issued for this recipient.
+        proof: [u8; 32],
     },
 }
 
+/// A challenge issued by a mailbox node to prove ownership of a recipient's key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MailboxChallenge {
+    /// A random nonce chosen by the mailbox node.
+    pub nonce: [u8; 32],
+    /// The mailbox node's ephemeral X25519 public key.
+    pub ephemeral_key: [u8; 32],
+}
+
 /// Represents a response from a mailbox node.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum MailboxResponse {
@@ -168,4 +699,13 @@ pub enum MailboxResponse {
         /// The number of messages successfully deleted.
         deleted: usize,
     },
+    /// Response to a `Challenge` request.
+    Challenge(MailboxChallenge),
+    /// Response to a `SetBlocked` request.
+    BlockedSet {
+        /// The number of blocked senders stored.
+        count: usize,
+    },
+    /// The request was refused because ownership of the recipient's key was not proven.
+    Unauthorized,
 }
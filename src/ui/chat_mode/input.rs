@@ -28,30 +28,26 @@ impl ChatMode {
         action_tx: &mpsc::UnboundedSender<UIAction>,
     ) -> Result<()> {
         match key.code {
-            KeyCode::Enter => {
-                if !state.input_buffer.trim().is_empty() {
-                    let input = state.input_buffer.clone();
-                    self.input_history.push(input.clone());
-                    self.history_index = None;
-
-                    if let Err(e) = self.execute_command(&input, action_tx).await {
-                        debug!("Error executing command '{}': {}", input, e);
-                    }
+            KeyCode::Enter if !state.input_buffer.trim().is_empty() => {
+                let input = state.input_buffer.clone();
+                self.input_history.push(input.clone());
+                self.history_index = None;
 
-                    state.input_buffer.clear();
-                    state.cursor_pos = 0;
+                if let Err(e) = self.execute_command(&input, action_tx).await {
+                    debug!("Error executing command '{}': {}", input, e);
                 }
+
+                state.input_buffer.clear();
+                state.cursor_pos = 0;
             }
             KeyCode::Char(c) => {
                 state.safe_insert_char(c);
                 self.history_index = None;
                 self.update_suggestion(state);
             }
-            KeyCode::Backspace => {
-                if state.safe_remove_char_before() {
-                    self.history_index = None;
-                    self.update_suggestion(state);
-                }
+            KeyCode::Backspace if state.safe_remove_char_before() => {
+                self.history_index = None;
+                self.update_suggestion(state);
             }
             KeyCode::Delete => {
                 state.safe_remove_char_at();
@@ -43,33 +43,13 @@ impl ChatMode {
                 DateTime::<Utc>::from_timestamp_millis(message.timestamp).unwrap_or_else(Utc::now);
             let display_timestamp = message_timestamp.with_timezone(&Local);
 
-            let content = if let Some(node) = node {
-                let is_sent = message.sender == node.identity.peer_id;
-                let other_peer = if is_sent {
-                    message.recipient
-                } else {
-                    message.sender
-                };
+            let content = message.text();
 
-                // Decrypt message content if a node is provided
-                match tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current().block_on(node.friends.get_friend(&other_peer))
-                }) {
-                    Ok(Some(friend)) => {
-                        // Attempt to decrypt the message content.
-                        match node
-                            .identity
-                            .decrypt_from(&friend.e2e_public_key, &message.content)
-                        {
-                            Ok(plaintext) => String::from_utf8_lossy(&plaintext).to_string(),
-                            Err(_) => "[Decryption Failed]".to_string(),
-                        }
-                    }
-                    Ok(None) => "[Unknown Peer]".to_string(),
-                    Err(_) => "[Database Error]".to_string(),
-                }
-            } else {
-                "[Encrypted]".to_string()
+            // Show the attached file after the caption, if there is one
+            let content = match (&message.attachment, content.is_empty()) {
+                (Some(manifest), true) => manifest.describe(),
+                (Some(manifest), false) => format!("{} {}", content, manifest.describe()),
+                (None, _) => content,
             };
 
             let (text, color) = if node
@@ -107,6 +87,21 @@ impl ChatMode {
                 (format!("{}: {}", sender_display, content), Color::Cyan)
             };
 
+            // Prefix group messages with the name of their group
+            let text = match (message.group_id, node) {
+                (Some(group_id), Some(node)) => {
+                    let group_name = tokio::task::block_in_place(|| {
+                        tokio::runtime::Handle::current().block_on(node.groups.get_group(&group_id))
+                    })
+                    .ok()
+                    .flatten()
+                    .map(|group| group.name)
+                    .unwrap_or_else(|| "group".to_string());
+                    format!("[{}] {}", group_name, text)
+                }
+                _ => text,
+            };
+
             all_items.push((entry.received_at, display_timestamp, text, color));
         }
 
@@ -18,9 +18,26 @@ impl ChatCompleter {
     pub fn new(friends: Vec<String>) -> Self {
         let commands = vec![
             "send".to_string(),
+            "sendfile".to_string(),
             "history".to_string(),
+            "search".to_string(),
+            "delete".to_string(),
             "friends".to_string(),
             "friend".to_string(),
+            "contact".to_string(),
+            "contacts".to_string(),
+            "accept".to_string(),
+            "reject".to_string(),
+            "requests".to_string(),
+            "block".to_string(),
+            "unblock".to_string(),
+            "blocks".to_string(),
+            "verify".to_string(),
+            "devices".to_string(),
+            "group".to_string(),
+            "groups".to_string(),
+            "gsend".to_string(),
+            "ghistory".to_string(),
             "peers".to_string(),
             "info".to_string(),
             "check".to_string(),
@@ -90,7 +107,7 @@ impl ChatCompleter {
             2 => {
                 // Completing first argument
                 match parts[0] {
-                    "send" | "history" => {
+                    "send" | "history" | "delete" | "block" | "verify" => {
                         // Complete with friend nicknames/IDs
                         let prefix = parts[1].to_lowercase();
                         let mut suggestions = Vec::new();
@@ -112,8 +129,8 @@ impl ChatCompleter {
 
                         suggestions
                     }
-                    "friend" => {
-                        // For 'friend' command, suggest discovered peer IDs that match the prefix
+                    "friend" | "contact" => {
+                        // For 'friend' and 'contact' commands, suggest discovered peer IDs that match the prefix
                         let prefix = parts[1].to_lowercase();
                         let mut suggestions = Vec::new();
 
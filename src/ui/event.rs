@@ -8,7 +8,7 @@ use super::log_entry::LogEntry;
 #[derive(Debug)]
 pub enum UIEvent {
     /// A new message has arrived.
-    NewMessage(Message),
+    NewMessage(Box<Message>),
     /// A batch of new log entries has arrived.
     NewLogBatch(Vec<LogEntry>),
     /// Request to refresh the displayed logs.
@@ -28,26 +28,22 @@ impl LogMode {
         _action_tx: &mpsc::UnboundedSender<UIAction>,
     ) -> Result<()> {
         match key.code {
-            KeyCode::Enter => {
-                if !state.input_buffer.trim().is_empty() {
-                    let input = state.input_buffer.clone();
-                    self.input_history.push(input.clone());
-                    self.history_index = None;
+            KeyCode::Enter if !state.input_buffer.trim().is_empty() => {
+                let input = state.input_buffer.clone();
+                self.input_history.push(input.clone());
+                self.history_index = None;
 
-                    self.execute_log_command(&input, state).await?;
+                self.execute_log_command(&input, state).await?;
 
-                    state.input_buffer.clear();
-                    state.cursor_pos = 0;
-                }
+                state.input_buffer.clear();
+                state.cursor_pos = 0;
             }
             KeyCode::Char(c) => {
                 state.safe_insert_char(c);
                 self.history_index = None;
             }
-            KeyCode::Backspace => {
-                if state.safe_remove_char_before() {
-                    self.history_index = None;
-                }
+            KeyCode::Backspace if state.safe_remove_char_before() => {
+                self.history_index = None;
             }
             KeyCode::Delete => {
                 state.safe_remove_char_at();
@@ -0,0 +1,110 @@
+//! This module contains command handlers for the block list.
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+
+use super::super::context::CommandContext;
+use super::super::resolver::resolve_peer_id;
+
+/// Blocks a peer.
+///
+/// Messages and friend requests from the peer are dropped, and our mailboxes
+/// are told to refuse messages from them.
+///
+/// Usage: `block <peer_id_or_nickname>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn block_peer(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() != 2 {
+        context.emit_chat("Usage: block <peer_id_or_nickname>");
+        return Ok(());
+    }
+
+    let peer_id = match resolve_peer_id(parts[1], context).await {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    match context.node().block_peer(peer_id).await {
+        Ok(true) => context.emit_chat(format!("🚫 Blocked {}", peer_id)),
+        Ok(false) => context.emit_chat(format!("{} is already blocked", peer_id)),
+        Err(e) => context.emit_chat(format!("❌ Failed to block peer: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Unblocks a peer.
+///
+/// Usage: `unblock <peer_id_or_nickname>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn unblock_peer(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() != 2 {
+        context.emit_chat("Usage: unblock <peer_id_or_nickname>");
+        return Ok(());
+    }
+
+    let peer_id = match resolve_peer_id(parts[1], context).await {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    match context.node().unblock_peer(peer_id).await {
+        Ok(true) => context.emit_chat(format!("✅ Unblocked {}", peer_id)),
+        Ok(false) => context.emit_chat(format!("{} is not blocked", peer_id)),
+        Err(e) => context.emit_chat(format!("❌ Failed to unblock peer: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Lists the blocked peers.
+///
+/// # Arguments
+///
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if the block list cannot be read from storage.
+pub async fn list_blocked(context: &CommandContext) -> Result<()> {
+    let blocked = context.node().blocks.list_blocked().await?;
+
+    if blocked.is_empty() {
+        context.emit_chat("No blocked peers.");
+        return Ok(());
+    }
+
+    let mut output = format!("Blocked peers ({}):", blocked.len());
+    for entry in &blocked {
+        let when = Local
+            .timestamp_millis_opt(entry.blocked_at)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        output.push_str(&format!("\n  {} [{}]", entry.peer_id, when));
+    }
+
+    context.emit_chat(output);
+    Ok(())
+}
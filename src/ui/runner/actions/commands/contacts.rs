@@ -0,0 +1,205 @@
+//! This module contains command handlers for friend requests.
+use anyhow::{anyhow, Result};
+use chrono::{Local, TimeZone};
+use libp2p::PeerId;
+use std::str::FromStr;
+
+use crate::storage::{ContactDirection, PendingContact};
+
+use super::super::context::CommandContext;
+
+/// Sends a friend request to a peer.
+///
+/// The peer must be online. Once they accept, both sides store each other as
+/// friends automatically.
+///
+/// Usage: `contact <peer_id>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn send_request(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() != 2 {
+        context.emit_chat("Usage: contact <peer_id>");
+        return Ok(());
+    }
+
+    let peer_id = match PeerId::from_str(parts[1]) {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ Invalid peer ID: {}", e));
+            return Ok(());
+        }
+    };
+
+    match context.node().send_contact_request(peer_id).await {
+        Ok(Some(friend)) => {
+            context.emit_chat(format!(
+                "✅ {} had already asked to be friends. Added {} as a friend",
+                peer_id,
+                friend.nickname.as_deref().unwrap_or("them")
+            ));
+        }
+        Ok(None) => {
+            context.emit_chat(format!("📨 Friend request sent to {}", peer_id));
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to send friend request: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists pending incoming and outgoing friend requests.
+///
+/// # Arguments
+///
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if the pending requests cannot be read from storage.
+pub async fn list_requests(context: &CommandContext) -> Result<()> {
+    let contacts = &context.node().contacts;
+    let incoming = contacts.list_pending(ContactDirection::Incoming).await?;
+    let outgoing = contacts.list_pending(ContactDirection::Outgoing).await?;
+
+    if incoming.is_empty() && outgoing.is_empty() {
+        context.emit_chat("No pending friend requests.");
+        return Ok(());
+    }
+
+    let mut output = format!("Incoming friend requests ({}):", incoming.len());
+    for pending in &incoming {
+        output.push_str(&format!("\n  {}", describe(pending)));
+    }
+    output.push_str(&format!("\nOutgoing friend requests ({}):", outgoing.len()));
+    for pending in &outgoing {
+        output.push_str(&format!("\n  {}", describe(pending)));
+    }
+    output.push_str("\nUse 'accept <peer_id_or_name>' or 'reject <peer_id_or_name>' to answer.");
+
+    context.emit_chat(output);
+    Ok(())
+}
+
+/// Accepts a pending friend request.
+///
+/// Usage: `accept <peer_id_or_name>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn accept_request(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() != 2 {
+        context.emit_chat("Usage: accept <peer_id_or_name>");
+        return Ok(());
+    }
+
+    let peer_id = match resolve_incoming(parts[1], context).await {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    match context.node().accept_contact_request(peer_id).await {
+        Ok(friend) => {
+            context.emit_chat(format!(
+                "✅ Added friend: {} ({})",
+                friend.peer_id,
+                friend.nickname.as_deref().unwrap_or("no nickname")
+            ));
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to accept friend request: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects a pending friend request.
+///
+/// Usage: `reject <peer_id_or_name>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn reject_request(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() != 2 {
+        context.emit_chat("Usage: reject <peer_id_or_name>");
+        return Ok(());
+    }
+
+    let peer_id = match resolve_incoming(parts[1], context).await {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    match context.node().reject_contact_request(peer_id).await {
+        Ok(()) => context.emit_chat(format!("🚫 Rejected friend request from {}", peer_id)),
+        Err(e) => context.emit_chat(format!("❌ Failed to reject friend request: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Resolves the requester of an incoming friend request by Peer ID or display name.
+async fn resolve_incoming(target: &str, context: &CommandContext) -> Result<PeerId> {
+    if let Ok(peer_id) = PeerId::from_str(target) {
+        return Ok(peer_id);
+    }
+
+    context
+        .node()
+        .contacts
+        .list_pending(ContactDirection::Incoming)
+        .await?
+        .into_iter()
+        .find(|pending| {
+            pending
+                .card
+                .as_ref()
+                .and_then(|card| card.display_name.as_deref())
+                == Some(target)
+        })
+        .map(|pending| pending.peer_id)
+        .ok_or_else(|| anyhow!("No pending friend request from '{}'", target))
+}
+
+/// Formats a pending request as a single line.
+fn describe(pending: &PendingContact) -> String {
+    let name = pending
+        .card
+        .as_ref()
+        .and_then(|card| card.display_name.as_deref())
+        .unwrap_or("(no name)");
+    let when = Local
+        .timestamp_millis_opt(pending.created_at)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+
+    format!("{} - {} [{}]", pending.peer_id, name, when)
+}
@@ -0,0 +1,90 @@
+//! This module contains the command handler for deleting messages and conversations.
+use anyhow::Result;
+
+use super::super::context::CommandContext;
+use super::super::resolver::resolve_peer_id;
+
+/// Deletes a message of a conversation, or the whole conversation.
+///
+/// Usage: `delete <peer_id_or_nickname> <n|all> [everyone]`
+///
+/// `n` counts back from the latest message, as listed by `history`, so `1`
+/// is the latest message. With `everyone`, the messages we sent are also
+/// deleted on the friend's devices and our other devices.
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if the message history cannot be read.
+pub async fn handle_delete(parts: &[&str], context: &CommandContext) -> Result<()> {
+    let everyone = match parts.get(3) {
+        None => false,
+        Some(&"everyone") if parts.len() == 4 => true,
+        _ => {
+            context.emit_chat("Usage: delete <peer_id_or_nickname> <n|all> [everyone]");
+            return Ok(());
+        }
+    };
+    if parts.len() < 3 {
+        context.emit_chat("Usage: delete <peer_id_or_nickname> <n|all> [everyone]");
+        return Ok(());
+    }
+
+    let peer_id = match resolve_peer_id(parts[1], context).await {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+    let node = context.node();
+
+    if parts[2] == "all" {
+        match node.delete_conversation(&peer_id, everyone).await {
+            Ok(0) => context.emit_chat(format!("No message history with {}", peer_id)),
+            Ok(count) => context.emit_chat(format!(
+                "🗑️  Deleted {} messages of the conversation with {}",
+                count, peer_id
+            )),
+            Err(e) => context.emit_chat(format!("❌ Failed to delete the conversation: {}", e)),
+        }
+        return Ok(());
+    }
+
+    let position = match parts[2].parse::<usize>() {
+        Ok(position) if position > 0 => position,
+        _ => {
+            context.emit_chat("❌ Give the position of the message from the latest (1), or 'all'");
+            return Ok(());
+        }
+    };
+
+    let messages = node
+        .history
+        .get_history(&node.identity.peer_id, &peer_id, position)
+        .await?;
+    let Some(message) = messages.first().filter(|_| messages.len() == position) else {
+        context.emit_chat(format!(
+            "❌ There are only {} messages with {}",
+            messages.len(),
+            peer_id
+        ));
+        return Ok(());
+    };
+
+    match node.delete_message(&message.id, everyone).await {
+        Ok(true) => context.emit_chat(format!(
+            "🗑️  Deleted message '{}'{}",
+            message.text(),
+            if everyone { " for everyone" } else { "" }
+        )),
+        Ok(false) => context.emit_chat("❌ The message was already deleted"),
+        Err(e) => context.emit_chat(format!("❌ Failed to delete the message: {}", e)),
+    }
+
+    Ok(())
+}
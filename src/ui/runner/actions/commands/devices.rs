@@ -0,0 +1,184 @@
+//! This module contains command handlers for linked devices.
+use anyhow::Result;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use libp2p::PeerId;
+use std::str::FromStr;
+
+use crate::types::Device;
+
+use super::super::context::CommandContext;
+
+/// Handles the `devices` command and its subcommands.
+///
+/// A second device joins the primary device with `devices join`, then the
+/// primary links it with `devices link`, each using the other's Peer ID and
+/// E2E key as shown by `info`.
+///
+/// Usage:
+/// - `devices`
+/// - `devices link <peer_id> <e2e_key>`
+/// - `devices unlink <peer_id>`
+/// - `devices announce`
+/// - `devices join <peer_id> <e2e_key>`
+/// - `devices leave`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if the linked devices cannot be read from storage.
+pub async fn handle_devices(parts: &[&str], context: &CommandContext) -> Result<()> {
+    match parts.get(1).copied() {
+        None => list_devices(context).await,
+        Some("link") if parts.len() == 4 => link_device(parts[2], parts[3], context).await,
+        Some("unlink") if parts.len() == 3 => unlink_device(parts[2], context).await,
+        Some("announce") if parts.len() == 2 => announce_devices(context).await,
+        Some("join") if parts.len() == 4 => join_primary(parts[2], parts[3], context).await,
+        Some("leave") if parts.len() == 2 => leave_primary(context).await,
+        _ => {
+            context.emit_chat(
+                "Usage: devices | devices link <peer_id> <e2e_key> | devices unlink <peer_id> | devices announce | devices join <peer_id> <e2e_key> | devices leave",
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Lists the devices linked to our identity.
+async fn list_devices(context: &CommandContext) -> Result<()> {
+    let linked = context.node().linked_devices().await?;
+
+    let mut output = match linked.primary {
+        Some(ref primary) if linked.pending => format!(
+            "Joined {}; waiting for it to link this device with 'devices link {} {}'",
+            primary.peer_id,
+            context.node().identity.peer_id,
+            BASE64_STANDARD.encode(context.node().identity.hpke_public_key())
+        ),
+        Some(ref primary) => format!("Linked to primary device {}", primary.peer_id),
+        None if linked.devices.is_empty() => {
+            context.emit_chat("No linked devices.");
+            return Ok(());
+        }
+        None => "This is the primary device".to_string(),
+    };
+
+    if !linked.devices.is_empty() {
+        output.push_str(&format!("\nLinked devices ({}):", linked.devices.len()));
+        for device in &linked.devices {
+            let marker = if device.peer_id == context.node().identity.peer_id {
+                " (this device)"
+            } else {
+                ""
+            };
+            output.push_str(&format!("\n  {}{}", device.peer_id, marker));
+        }
+    }
+
+    context.emit_chat(output);
+    Ok(())
+}
+
+/// Links a device that joined us.
+async fn link_device(peer_id: &str, e2e_key: &str, context: &CommandContext) -> Result<()> {
+    let Some(device) = parse_device(peer_id, e2e_key, context) else {
+        return Ok(());
+    };
+
+    let device_id = device.peer_id;
+    match context.node().link_device(device).await {
+        Ok(()) => context.emit_chat(format!(
+            "✅ Linked {}. Friends and devices receive the new device list",
+            device_id
+        )),
+        Err(e) => context.emit_chat(format!("❌ Failed to link {}: {}", device_id, e)),
+    }
+
+    Ok(())
+}
+
+/// Unlinks a device.
+async fn unlink_device(target: &str, context: &CommandContext) -> Result<()> {
+    let peer_id = match PeerId::from_str(target) {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ Invalid peer ID: {}", e));
+            return Ok(());
+        }
+    };
+
+    match context.node().unlink_device(&peer_id).await {
+        Ok(true) => context.emit_chat(format!("✅ Unlinked {}", peer_id)),
+        Ok(false) => context.emit_chat(format!("❌ {} is not a linked device", peer_id)),
+        Err(e) => context.emit_chat(format!("❌ Failed to unlink {}: {}", peer_id, e)),
+    }
+
+    Ok(())
+}
+
+/// Sends the device list to our friends and devices again.
+async fn announce_devices(context: &CommandContext) -> Result<()> {
+    match context.node().announce_devices().await {
+        Ok(count) => context.emit_chat(format!("✅ Sent the device list to {} devices", count)),
+        Err(e) => context.emit_chat(format!("❌ Failed to announce devices: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Joins a primary device.
+async fn join_primary(peer_id: &str, e2e_key: &str, context: &CommandContext) -> Result<()> {
+    let Some(primary) = parse_device(peer_id, e2e_key, context) else {
+        return Ok(());
+    };
+
+    let primary_id = primary.peer_id;
+    match context.node().join_primary(primary).await {
+        Ok(()) => context.emit_chat(format!(
+            "✅ Joined {}. Run 'devices link {} {}' on it to finish linking",
+            primary_id,
+            context.node().identity.peer_id,
+            BASE64_STANDARD.encode(context.node().identity.hpke_public_key())
+        )),
+        Err(e) => context.emit_chat(format!("❌ Failed to join {}: {}", primary_id, e)),
+    }
+
+    Ok(())
+}
+
+/// Leaves the primary device.
+async fn leave_primary(context: &CommandContext) -> Result<()> {
+    match context.node().leave_primary().await {
+        Ok(true) => context.emit_chat("✅ Left the primary device"),
+        Ok(false) => context.emit_chat("❌ This device has not joined a primary device"),
+        Err(e) => context.emit_chat(format!("❌ Failed to leave: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Parses a device from its Peer ID and base64 E2E key, reporting errors in the chat output.
+fn parse_device(peer_id: &str, e2e_key: &str, context: &CommandContext) -> Option<Device> {
+    let peer_id = match PeerId::from_str(peer_id) {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ Invalid peer ID: {}", e));
+            return None;
+        }
+    };
+
+    match BASE64_STANDARD.decode(e2e_key) {
+        Ok(e2e_public_key) => Some(Device {
+            peer_id,
+            e2e_public_key,
+        }),
+        Err(e) => {
+            context.emit_chat(format!("❌ Invalid base64 key: {}", e));
+            None
+        }
+    }
+}
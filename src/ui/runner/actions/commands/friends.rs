@@ -51,9 +51,12 @@ pub async fn add_friend(parts: &[&str], context: &CommandContext) -> Result<()>
         peer_id,
         e2e_public_key,
         nickname: nickname.clone(),
+        verified: false,
+        devices: Vec::new(),
+        devices_timestamp: 0,
     };
 
-    match context.node().friends.add_friend(friend).await {
+    match context.node().save_friend(friend).await {
         Ok(()) => {
             context.emit_chat(format!(
                 "✅ Added friend: {} ({})",
@@ -89,7 +92,11 @@ pub async fn list_friends(context: &CommandContext) -> Result<()> {
                 let mut output = format!("Friends ({}):", friends.len());
                 for friend in friends {
                     let nickname = friend.nickname.as_deref().unwrap_or("(no nickname)");
-                    output.push_str(&format!("\n  {} - {}", friend.peer_id, nickname));
+                    let verified = if friend.verified { " ✅ verified" } else { "" };
+                    output.push_str(&format!(
+                        "\n  {} - {}{}",
+                        friend.peer_id, nickname, verified
+                    ));
                 }
                 context.emit_chat(output);
             }
@@ -0,0 +1,308 @@
+//! This module contains command handlers for group conversations.
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use libp2p::PeerId;
+
+use crate::cli::GroupDelivery;
+use crate::types::Group;
+
+use super::super::context::CommandContext;
+use super::super::resolver::resolve_peer_id;
+
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+/// Handles the `group` command and its subcommands.
+///
+/// Usage:
+/// - `group create <name> <member...>`
+/// - `group add <group> <member...>`
+/// - `group leave <group>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn handle_group(parts: &[&str], context: &CommandContext) -> Result<()> {
+    match parts.get(1).copied() {
+        Some("create") if parts.len() >= 4 => create_group(parts, context).await,
+        Some("add") if parts.len() >= 4 => add_members(parts, context).await,
+        Some("leave") if parts.len() == 3 => leave_group(parts, context).await,
+        _ => {
+            context.emit_chat(
+                "Usage: group create <name> <member...> | group add <group> <member...> | group leave <group>",
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Lists the groups we are a member of.
+///
+/// # Arguments
+///
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if the groups cannot be read from storage.
+pub async fn list_groups(context: &CommandContext) -> Result<()> {
+    let groups = context.node().groups.list_groups().await?;
+
+    if groups.is_empty() {
+        context.emit_chat(
+            "You are not in any groups. Use 'group create <name> <member...>' to start one.",
+        );
+        return Ok(());
+    }
+
+    let mut output = format!("Groups ({}):", groups.len());
+    for group in &groups {
+        output.push_str(&format!(
+            "\n  {} - {} ({} members)",
+            group.id,
+            group.name,
+            group.members.len()
+        ));
+    }
+
+    context.emit_chat(output);
+    Ok(())
+}
+
+/// Sends a message to a group.
+///
+/// Usage: `gsend <group> <message...>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn send_group_message(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() < 3 {
+        context.emit_chat("Usage: gsend <group_id_or_name> <message...>");
+        return Ok(());
+    }
+
+    let group = match context.node().find_group(parts[1]).await {
+        Ok(group) => group,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    let text = parts[2..].join(" ");
+    match context.node().send_group_message(&group.id, text).await {
+        Ok((_, delivery)) => {
+            context.emit_chat(format!(
+                "✅ Message sent to group '{}' ({})",
+                group.name,
+                describe_delivery(&delivery)
+            ));
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to send group message: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Displays the message history of a group.
+///
+/// Usage: `ghistory <group> [message_count]`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if retrieving the message history fails.
+pub async fn show_group_history(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() < 2 || parts.len() > 3 {
+        context.emit_chat("Usage: ghistory <group_id_or_name> [message_count]");
+        return Ok(());
+    }
+
+    let group = match context.node().find_group(parts[1]).await {
+        Ok(group) => group,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    let limit = match parts.get(2) {
+        None => DEFAULT_HISTORY_LIMIT,
+        Some(value) => match value.parse::<usize>() {
+            Ok(count) if (1..=MAX_HISTORY_LIMIT).contains(&count) => count,
+            _ => {
+                context.emit_chat("❌ Message count must be between 1 and 1000");
+                return Ok(());
+            }
+        },
+    };
+
+    let messages = context
+        .node()
+        .history
+        .get_group_history(&group.id, limit)
+        .await?;
+
+    if messages.is_empty() {
+        context.emit_chat(format!("No message history in group '{}'", group.name));
+        return Ok(());
+    }
+
+    let mut output = format!(
+        "Message history in group '{}' (last {} messages):",
+        group.name,
+        messages.len()
+    );
+    for msg in messages {
+        let timestamp = DateTime::<Utc>::from_timestamp_millis(msg.timestamp)
+            .map(|dt| {
+                dt.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "Invalid timestamp".to_string());
+        let sender = if msg.sender == context.node().identity.peer_id {
+            "\x1b[94mYou\x1b[0m".to_string()
+        } else {
+            format!("\x1b[92m{}\x1b[0m", member_label(&group, msg.sender))
+        };
+
+        output.push_str(&format!(
+            "\n  [{}] {}: {}",
+            timestamp,
+            sender,
+            msg.text()
+        ));
+    }
+
+    context.emit_history(output);
+    Ok(())
+}
+
+/// Creates a group with some of our friends.
+async fn create_group(parts: &[&str], context: &CommandContext) -> Result<()> {
+    let Some(members) = resolve_members(&parts[3..], context).await else {
+        return Ok(());
+    };
+
+    match context
+        .node()
+        .create_group(parts[2].to_string(), members)
+        .await
+    {
+        Ok((group, delivery)) => {
+            context.emit_chat(format!(
+                "👥 Created group '{}' ({}) with {} members. Invitations: {}",
+                group.name,
+                group.id,
+                group.members.len(),
+                describe_delivery(&delivery)
+            ));
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to create group: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds some of our friends to a group.
+async fn add_members(parts: &[&str], context: &CommandContext) -> Result<()> {
+    let group = match context.node().find_group(parts[2]).await {
+        Ok(group) => group,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    let Some(members) = resolve_members(&parts[3..], context).await else {
+        return Ok(());
+    };
+
+    match context.node().add_group_members(&group.id, members).await {
+        Ok((group, delivery)) => {
+            context.emit_chat(format!(
+                "👥 Group '{}' now has {} members. Announcements: {}",
+                group.name,
+                group.members.len(),
+                describe_delivery(&delivery)
+            ));
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to add group members: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Leaves a group.
+async fn leave_group(parts: &[&str], context: &CommandContext) -> Result<()> {
+    let group = match context.node().find_group(parts[2]).await {
+        Ok(group) => group,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    match context.node().leave_group(&group.id).await {
+        Ok(_) => context.emit_chat(format!("👋 Left group '{}'", group.name)),
+        Err(e) => context.emit_chat(format!("❌ Failed to leave group: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Resolves member arguments to Peer IDs, reporting the first one that fails.
+async fn resolve_members(targets: &[&str], context: &CommandContext) -> Option<Vec<PeerId>> {
+    let mut members = Vec::with_capacity(targets.len());
+    for target in targets {
+        match resolve_peer_id(target, context).await {
+            Ok(peer_id) => members.push(peer_id),
+            Err(e) => {
+                context.emit_chat(format!("❌ {}", e));
+                return None;
+            }
+        }
+    }
+    Some(members)
+}
+
+/// Returns a member's name, or a shortened Peer ID if it has none.
+fn member_label(group: &Group, peer_id: PeerId) -> String {
+    group
+        .member(&peer_id)
+        .and_then(|member| member.name.clone())
+        .unwrap_or_else(|| {
+            let peer_str = peer_id.to_string();
+            format!("{}...", &peer_str[..8.min(peer_str.len())])
+        })
+}
+
+/// Summarizes how the copies of a group message were delivered.
+fn describe_delivery(delivery: &GroupDelivery) -> String {
+    format!(
+        "{} direct, {} via mailbox, {} queued",
+        delivery.direct, delivery.mailbox, delivery.queued
+    )
+}
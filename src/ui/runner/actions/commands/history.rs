@@ -70,9 +70,12 @@ pub async fn show_history(parts: &[&str], context: &CommandContext) -> Result<()
                     format_direction(&msg, context).await
                 ));
 
-                let content = decrypt_content(&msg, context).await;
                 output.push(' ');
-                output.push_str(&content);
+                output.push_str(&msg.text());
+                if let Some(ref manifest) = msg.attachment {
+                    output.push(' ');
+                    output.push_str(&manifest.describe());
+                }
             }
 
             context.emit_history(output);
@@ -113,7 +116,7 @@ fn parse_limit(raw: Option<&&str>) -> Result<usize, String> {
 /// # Returns
 ///
 /// A formatted string representing the timestamp.
-fn format_timestamp(timestamp_ms: i64) -> String {
+pub(super) fn format_timestamp(timestamp_ms: i64) -> String {
     DateTime::<Utc>::from_timestamp_millis(timestamp_ms)
         .map(|dt| {
             dt.with_timezone(&Local)
@@ -133,7 +136,7 @@ fn format_timestamp(timestamp_ms: i64) -> String {
 /// # Returns
 ///
 /// A formatted string indicating the message direction and peer label.
-async fn format_direction(msg: &Message, context: &CommandContext) -> String {
+pub(super) async fn format_direction(msg: &Message, context: &CommandContext) -> String {
     if msg.sender == context.node().identity.peer_id {
         let label = lookup_peer_label(msg.recipient, context).await;
         format!("\x1b[94mYou -> {}\x1b[0m", label)
@@ -184,43 +187,3 @@ fn short_peer(peer_id: PeerId) -> String {
         peer_str
     }
 }
-
-/// Decrypts the content of a message.
-///
-/// # Arguments
-///
-/// * `msg` - The `Message` whose content to decrypt.
-/// * `context` - The `CommandContext` for accessing identity and friend information.
-///
-/// # Returns
-///
-/// A string containing the decrypted message content, or an error message if decryption fails.
-async fn decrypt_content(msg: &Message, context: &CommandContext) -> String {
-    let other_pubkey = if msg.sender == context.node().identity.peer_id {
-        context
-            .node()
-            .friends
-            .get_friend(&msg.recipient)
-            .await
-            .ok()
-            .flatten()
-            .map(|f| f.e2e_public_key)
-    } else {
-        context
-            .node()
-            .friends
-            .get_friend(&msg.sender)
-            .await
-            .ok()
-            .flatten()
-            .map(|f| f.e2e_public_key)
-    };
-
-    match other_pubkey {
-        Some(pub_key) => match context.node().identity.decrypt_from(&pub_key, &msg.content) {
-            Ok(plaintext) => String::from_utf8_lossy(&plaintext).to_string(),
-            Err(_) => "[Decryption Failed]".to_string(),
-        },
-        None => "[Cannot decrypt - unknown peer]".to_string(),
-    }
-}
@@ -7,7 +7,8 @@ use super::super::context::CommandContext;
 
 /// Displays the user's identity information.
 ///
-/// This includes the Peer ID and the E2E public key.
+/// This includes the Peer ID, the E2E public key, whether the node is
+/// reachable directly or through relays, and what the rate limits refused.
 ///
 /// # Arguments
 ///
@@ -19,6 +20,41 @@ pub async fn show_info(context: &CommandContext) -> Result<()> {
         BASE64_STANDARD.encode(context.node().identity.hpke_public_key())
     );
     context.emit_chat(output);
+
+    match context.node().network.get_reachability().await {
+        Ok(info) => {
+            let mut output = format!("Reachability: {}", info.reachability);
+            if let Some(address) = info.public_address {
+                output.push_str(&format!("\n  Public address: {}", address));
+            }
+            if info.is_relay {
+                output.push_str("\n  Acting as a relay for other peers");
+            }
+            for address in &info.relay_addresses {
+                output.push_str(&format!("\n  Relayed address: {}", address));
+            }
+            output.push_str(&format!(
+                "\n  Hole punching: {} succeeded, {} failed",
+                info.hole_punches_succeeded, info.hole_punches_failed
+            ));
+            context.emit_chat(output);
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to get reachability: {}", e));
+        }
+    }
+
+    if let Ok(counters) = context.node().network.get_limit_counters().await {
+        context.emit_chat(format!(
+            "Limits: {} chat, {} mailbox and {} DHT requests dropped\n  {} connections refused, {} peers blocked ({} now)",
+            counters.chat_requests_limited,
+            counters.mailbox_requests_limited,
+            counters.dht_writes_limited,
+            counters.connections_denied,
+            counters.peers_blocked,
+            counters.currently_blocked
+        ));
+    }
     Ok(())
 }
 
@@ -38,7 +74,41 @@ pub async fn show_check_message(context: &CommandContext) -> Result<()> {
 ///
 /// * `context` - The `CommandContext` for emitting chat messages.
 pub async fn show_help(context: &CommandContext) -> Result<()> {
-    let help_text = "Available commands:\n  friend <peer_id> <e2e_key> [nickname] - Add a friend and optionally assign a nickname\n  friends                     - List all friends\n  send <peer_id_or_nickname> <message>    - Send a message\n  history <peer_id_or_nickname> [count] - Show message history (default: 20, max: 1000)\n  peers                       - Show connected peers\n  info                        - Show your identity\n  check                       - Check for new messages in mailboxes\n  help                        - Show this help\n  exit                        - Exit the application";
+    let help_text = concat!(
+        "Available commands:\n",
+        "  friend <peer_id> <e2e_key> [nickname] - Add a friend and optionally assign a nickname\n",
+        "  friends                     - List all friends\n",
+        "  contact <peer_id>           - Send a friend request\n",
+        "  contacts                    - List pending friend requests\n",
+        "  accept <peer_id_or_name>    - Accept a friend request\n",
+        "  reject <peer_id_or_name>    - Reject a friend request\n",
+        "  requests                    - List messages from peers that are not friends\n",
+        "  requests show|accept|delete <peer_id> - Read, accept or delete a message request\n",
+        "  block <peer_id_or_nickname> - Block a peer\n",
+        "  unblock <peer_id_or_nickname> - Unblock a peer\n",
+        "  blocks                      - List blocked peers\n",
+        "  verify <peer_id_or_nickname> [confirm|reset] - Show the safety number, or mark a friend as verified\n",
+        "  devices                     - List the devices linked to your identity\n",
+        "  devices link|join <peer_id> <e2e_key> - Link a device, or join a primary device\n",
+        "  devices unlink <peer_id>    - Unlink a device\n",
+        "  devices announce|leave      - Resend the device list, or leave the primary device\n",
+        "  send <peer_id_or_nickname> <message>    - Send a message\n",
+        "  sendfile <peer_id_or_nickname> <path> [caption] - Send a file\n",
+        "  history <peer_id_or_nickname> [count] - Show message history (default: 20, max: 1000)\n",
+        "  search <query> [peer_id_or_nickname] - Search the message history\n",
+        "  delete <peer_id_or_nickname> <n|all> [everyone] - Delete the nth latest message, or the conversation\n",
+        "  group create <name> <member...>  - Create a group with some friends\n",
+        "  group add <group> <member...>    - Add friends to a group\n",
+        "  group leave <group>         - Leave a group\n",
+        "  groups                      - List your groups\n",
+        "  gsend <group> <message>     - Send a message to a group\n",
+        "  ghistory <group> [count]    - Show group message history\n",
+        "  peers                       - Show connected peers\n",
+        "  info                        - Show your identity and reachability\n",
+        "  check                       - Check for new messages in mailboxes\n",
+        "  help                        - Show this help\n",
+        "  exit                        - Exit the application"
+    );
     context.emit_chat(help_text);
     Ok(())
 }
@@ -1,11 +1,19 @@
 //! This module contains command dispatching logic for the UI runner.
 //!
 //! It maps command strings to their respective handler functions.
+mod blocks;
+mod contacts;
+mod delete;
+mod devices;
 mod friends;
+mod groups;
 mod history;
 mod info;
 mod peers;
+mod requests;
+mod search;
 mod send;
+mod verify;
 
 use anyhow::Result;
 
@@ -27,9 +35,26 @@ use super::context::CommandContext;
 pub async fn dispatch(parts: &[&str], context: &CommandContext) -> Result<()> {
     match parts[0] {
         "send" => send::handle_send(parts, context).await,
+        "sendfile" => send::handle_sendfile(parts, context).await,
         "friend" => friends::add_friend(parts, context).await,
         "friends" => friends::list_friends(context).await,
+        "contact" => contacts::send_request(parts, context).await,
+        "contacts" => contacts::list_requests(context).await,
+        "accept" => contacts::accept_request(parts, context).await,
+        "reject" => contacts::reject_request(parts, context).await,
+        "block" => blocks::block_peer(parts, context).await,
+        "unblock" => blocks::unblock_peer(parts, context).await,
+        "blocks" => blocks::list_blocked(context).await,
+        "verify" => verify::verify_friend(parts, context).await,
+        "devices" => devices::handle_devices(parts, context).await,
+        "requests" => requests::handle_requests(parts, context).await,
+        "group" => groups::handle_group(parts, context).await,
+        "groups" => groups::list_groups(context).await,
+        "gsend" => groups::send_group_message(parts, context).await,
+        "ghistory" => groups::show_group_history(parts, context).await,
         "history" => history::show_history(parts, context).await,
+        "search" => search::search_history(parts, context).await,
+        "delete" => delete::handle_delete(parts, context).await,
         "peers" => peers::list_peers(context).await,
         "info" => info::show_info(context).await,
         "check" => info::show_check_message(context).await,
@@ -0,0 +1,162 @@
+//! This module contains command handlers for message requests.
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use libp2p::PeerId;
+use std::str::FromStr;
+
+use super::super::context::CommandContext;
+
+/// Handles the `requests` command and its subcommands.
+///
+/// Usage:
+/// - `requests`
+/// - `requests show <peer_id>`
+/// - `requests accept <peer_id> [nickname]`
+/// - `requests delete <peer_id>`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if the message requests cannot be read from storage.
+pub async fn handle_requests(parts: &[&str], context: &CommandContext) -> Result<()> {
+    match parts.get(1).copied() {
+        None => list_requests(context).await,
+        Some("show") if parts.len() == 3 => show_request(parts[2], context).await,
+        Some("accept") if parts.len() == 3 || parts.len() == 4 => {
+            accept_request(parts[2], parts.get(3).copied(), context).await
+        }
+        Some("delete") if parts.len() == 3 => delete_request(parts[2], context).await,
+        _ => {
+            context.emit_chat(
+                "Usage: requests | requests show <peer_id> | requests accept <peer_id> [nickname] | requests delete <peer_id>",
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Lists the message requests, the most recent first.
+async fn list_requests(context: &CommandContext) -> Result<()> {
+    let requests = context.node().requests.list_requests().await?;
+
+    if requests.is_empty() {
+        context.emit_chat("No message requests.");
+        return Ok(());
+    }
+
+    let mut output = format!("Message requests ({}):", requests.len());
+    for request in &requests {
+        output.push_str(&format!(
+            "\n  {} - {} message(s), last [{}]",
+            request.peer_id,
+            request.message_count,
+            format_timestamp(request.last_message_at)
+        ));
+    }
+    output.push_str("\nUse 'requests show|accept|delete <peer_id>' to handle them.");
+
+    context.emit_chat(output);
+    Ok(())
+}
+
+/// Shows the pending messages of a sender.
+async fn show_request(target: &str, context: &CommandContext) -> Result<()> {
+    let Some(peer_id) = parse_peer_id(target, context) else {
+        return Ok(());
+    };
+
+    let messages = context.node().requests.get_messages(&peer_id).await?;
+    if messages.is_empty() {
+        context.emit_chat(format!("❌ No message request from {}", peer_id));
+        return Ok(());
+    }
+
+    let mut output = format!(
+        "Message request from {} ({} messages):",
+        peer_id,
+        messages.len()
+    );
+    for msg in &messages {
+        output.push_str(&format!(
+            "\n  [{}] {}",
+            format_timestamp(msg.timestamp),
+            msg.text()
+        ));
+        if let Some(ref manifest) = msg.attachment {
+            output.push(' ');
+            output.push_str(&manifest.describe());
+        }
+    }
+
+    context.emit_history(output);
+    Ok(())
+}
+
+/// Accepts a message request, adding the sender as a friend.
+async fn accept_request(
+    target: &str,
+    nickname: Option<&str>,
+    context: &CommandContext,
+) -> Result<()> {
+    let Some(peer_id) = parse_peer_id(target, context) else {
+        return Ok(());
+    };
+
+    match context
+        .node()
+        .accept_message_request(peer_id, nickname.map(str::to_string))
+        .await
+    {
+        Ok((friend, moved)) => {
+            context.emit_chat(format!(
+                "✅ Added friend: {} ({}). Moved {} message(s) into the history",
+                friend.peer_id,
+                friend.nickname.as_deref().unwrap_or("no nickname"),
+                moved
+            ));
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to accept message request: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes a message request with all its messages.
+async fn delete_request(target: &str, context: &CommandContext) -> Result<()> {
+    let Some(peer_id) = parse_peer_id(target, context) else {
+        return Ok(());
+    };
+
+    match context.node().delete_message_request(peer_id).await {
+        Ok(count) => context.emit_chat(format!("🗑️ Deleted {} message(s) from {}", count, peer_id)),
+        Err(e) => context.emit_chat(format!("❌ Failed to delete message request: {}", e)),
+    }
+
+    Ok(())
+}
+
+/// Parses a Peer ID argument, reporting invalid ones in the chat output.
+fn parse_peer_id(target: &str, context: &CommandContext) -> Option<PeerId> {
+    match PeerId::from_str(target) {
+        Ok(peer_id) => Some(peer_id),
+        Err(e) => {
+            context.emit_chat(format!("❌ Invalid peer ID: {}", e));
+            None
+        }
+    }
+}
+
+/// Formats a timestamp in milliseconds as local time.
+fn format_timestamp(timestamp_ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(timestamp_ms)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,109 @@
+//! This module contains the command handler for searching the message history.
+use anyhow::Result;
+
+use crate::types::Message;
+
+use super::super::context::CommandContext;
+use super::super::resolver::resolve_peer_id;
+use super::history::{format_direction, format_timestamp};
+
+const SEARCH_LIMIT: usize = 20;
+const SEARCH_CONTEXT: usize = 1;
+
+/// The style of the text of the messages around a match.
+const DIM: &str = "\x1b[2m";
+/// The style of the text of a match.
+const BOLD: &str = "\x1b[1m";
+
+/// Searches the message history for messages containing every word of a query.
+///
+/// Usage: `search <query> [peer_id_or_nickname]`
+///
+/// If the last word names a peer, the search is restricted to messages
+/// exchanged with that peer.
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function returns an error if searching the message history fails.
+pub async fn search_history(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() < 2 {
+        context.emit_chat("Usage: search <query> [peer_id_or_nickname]");
+        return Ok(());
+    }
+
+    let (words, peer_id) = match parts.split_last() {
+        Some((last, words)) if words.len() > 1 => match resolve_peer_id(last, context).await {
+            Ok(peer_id) => (&words[1..], Some(peer_id)),
+            Err(_) => (&parts[1..], None),
+        },
+        _ => (&parts[1..], None),
+    };
+    let query = words.join(" ");
+
+    match context
+        .node()
+        .history
+        .search(&query, peer_id.as_ref(), SEARCH_LIMIT, SEARCH_CONTEXT)
+        .await
+    {
+        Ok(hits) => {
+            if hits.is_empty() {
+                context.emit_chat(format!("No messages found for '{}'", query));
+                return Ok(());
+            }
+
+            let mut output = format!("Search results for '{}' ({} matches):", query, hits.len());
+            for hit in hits {
+                output.push('\n');
+                for msg in &hit.before {
+                    output.push_str(&format_line(msg, "  ", DIM, context).await);
+                }
+                output.push_str(&format_line(&hit.message, "> ", BOLD, context).await);
+                for msg in &hit.after {
+                    output.push_str(&format_line(msg, "  ", DIM, context).await);
+                }
+            }
+
+            context.emit_history(output);
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to search the message history: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats a message of a search result on its own line.
+///
+/// # Arguments
+///
+/// * `msg` - The `Message` to format.
+/// * `marker` - The prefix of the line.
+/// * `style` - The ANSI style of the message text.
+/// * `context` - The `CommandContext` for looking up peer labels.
+///
+/// # Returns
+///
+/// The formatted line, starting with a newline.
+async fn format_line(msg: &Message, marker: &str, style: &str, context: &CommandContext) -> String {
+    let mut line = format!(
+        "\n{}[{}] {} {}{}",
+        marker,
+        format_timestamp(msg.timestamp),
+        format_direction(msg, context).await,
+        style,
+        msg.text()
+    );
+    if let Some(ref manifest) = msg.attachment {
+        line.push(' ');
+        line.push_str(&manifest.describe());
+    }
+    line.push_str("\x1b[0m");
+    line
+}
@@ -1,5 +1,6 @@
 //! This module contains the command handler for sending messages.
 use std::collections::HashSet;
+use std::path::PathBuf;
 
 use anyhow::Result;
 use chrono::Utc;
@@ -9,7 +10,7 @@ use tracing::debug;
 use uuid::Uuid;
 
 use crate::cli::commands::MailboxDeliveryResult;
-use crate::types::{DeliveryStatus, Friend, Message};
+use crate::types::{AttachmentManifest, DeliveryStatus, Friend, Message};
 
 use super::super::context::CommandContext;
 use super::super::resolver::resolve_peer_id;
@@ -38,64 +39,155 @@ pub async fn handle_send(parts: &[&str], context: &CommandContext) -> Result<()>
     let destination = parts[1];
     let message_body = parts[2..].join(" ");
 
-    let recipient_peer_id = match resolve_peer_id(destination, context).await {
-        Ok(id) => id,
-        Err(e) => {
-            context.emit_chat(format!("❌ {}", e));
-            return Ok(());
-        }
+    let Some(friend) = resolve_friend(destination, context).await? else {
+        return Ok(());
     };
 
-    let friend = match context
+    send_to_friend(destination, &friend, message_body, None, context).await
+}
+
+/// Handles the 'sendfile' command, sending a file as an attachment to a friend.
+///
+/// The file is split into encrypted chunks that the friend fetches directly
+/// from us, so the transfer completes once both sides are online.
+///
+/// Usage: `sendfile <peer_id_or_nickname> <path> [caption...]`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and network.
+///
+/// # Errors
+///
+/// This function returns an error if friend lookup, encryption, or message storage fails.
+pub async fn handle_sendfile(parts: &[&str], context: &CommandContext) -> Result<()> {
+    if parts.len() < 3 {
+        context.emit_chat("Usage: sendfile <peer_id_or_nickname> <path> [caption...]");
+        return Ok(());
+    }
+
+    let destination = parts[1];
+    let caption = parts[3..].join(" ");
+
+    let Some(friend) = resolve_friend(destination, context).await? else {
+        return Ok(());
+    };
+
+    let manifest = match context
         .node()
-        .friends
-        .get_friend(&recipient_peer_id)
-        .await?
+        .import_attachment(PathBuf::from(parts[2]))
+        .await
     {
-        Some(f) => f,
-        None => {
-            context.emit_chat("❌ Friend not found. Add them first with 'friend' command.");
+        Ok(manifest) => manifest,
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to read '{}': {}", parts[2], e));
             return Ok(());
         }
     };
 
-    let encrypted_content = match context
-        .node()
-        .identity
-        .encrypt_for(&friend.e2e_public_key, message_body.as_bytes())
-    {
-        Ok(content) => content,
+    context.emit_chat(format!(
+        "📤 Sending {} to {}",
+        manifest.describe(),
+        destination
+    ));
+    send_to_friend(
+        destination,
+        &friend,
+        caption,
+        Some(Box::new(manifest)),
+        context,
+    )
+    .await
+}
+
+/// Resolves a destination to a friend, reporting in the chat output if it is not one.
+async fn resolve_friend(destination: &str, context: &CommandContext) -> Result<Option<Friend>> {
+    let recipient_peer_id = match resolve_peer_id(destination, context).await {
+        Ok(id) => id,
         Err(e) => {
-            context.emit_chat(format!("❌ Encryption failed: {}", e));
-            return Ok(());
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(None);
         }
     };
 
+    let friend = context
+        .node()
+        .friends
+        .get_friend(&recipient_peer_id)
+        .await?;
+    if friend.is_none() {
+        context.emit_chat("❌ Friend not found. Add them first with 'friend' command.");
+    }
+    Ok(friend)
+}
+
+/// Encrypts a message for a friend, stores it, and delivers it.
+///
+/// # Arguments
+///
+/// * `destination` - The display name or PeerId of the recipient.
+/// * `friend` - The `Friend` object of the recipient.
+/// * `text` - The message text.
+/// * `attachment` - The manifest of an attached file, if any.
+/// * `context` - The `CommandContext` for network interaction and chat output.
+///
+/// # Errors
+///
+/// This function returns an error if signing or message storage fails.
+async fn send_to_friend(
+    destination: &str,
+    friend: &Friend,
+    text: String,
+    attachment: Option<Box<AttachmentManifest>>,
+    context: &CommandContext,
+) -> Result<()> {
     let message = Message {
         id: Uuid::new_v4(),
         sender: context.node().identity.peer_id,
-        recipient: recipient_peer_id,
+        recipient: friend.peer_id,
         timestamp: Utc::now().timestamp_millis(),
-        content: encrypted_content,
+        content: text.into_bytes(),
         nonce: random(),
         delivery_status: DeliveryStatus::Sending,
+        signature: Vec::new(),
+        group_id: None,
+        attachment,
+        sender_pub_key: Vec::new(),
     };
 
+    // Seal the content once, so every delivery path carries the same ciphertext.
+    let mut sealed = match message.body().and_then(|body| {
+        context
+            .node()
+            .identity
+            .encrypt_for(&friend.e2e_public_key, &body)
+    }) {
+        Ok(content) => Message {
+            content,
+            attachment: None,
+            sender_pub_key: context.node().identity.hpke_public_key(),
+            ..message.clone()
+        },
+        Err(e) => {
+            context.emit_chat(format!("❌ Encryption failed: {}", e));
+            return Ok(());
+        }
+    };
+    sealed.signature = context.node().identity.sign_message(&sealed)?;
+
     // Store message in history and outbox immediately
-    context
-        .node()
-        .history
-        .store_message(message.clone())
-        .await?;
-    context.node().outbox.add_pending(message.clone()).await?;
+    context.node().history.store_message(message.clone()).await?;
+    context.node().outbox.add_pending(sealed.clone()).await?;
+    context.node().queue_device_copies(friend, &message).await;
 
     // Attempt direct delivery first
-    if attempt_direct_delivery(destination, &message, context).await? {
+    if attempt_direct_delivery(destination, &sealed, context).await? {
         return Ok(());
     }
 
     // If direct delivery fails, attempt mailbox delivery
-    attempt_mailbox_delivery(destination, &message, &friend, context).await
+    attempt_mailbox_delivery(destination, &sealed, friend, context).await
 }
 
 /// Attempts to directly deliver a message to the recipient.
@@ -106,7 +198,7 @@ pub async fn handle_send(parts: &[&str], context: &CommandContext) -> Result<()>
 /// # Arguments
 ///
 /// * `destination` - The display name or PeerId of the recipient.
-/// * `message` - The message to send.
+/// * `message` - The sealed message to send.
 /// * `context` - The `CommandContext` for network interaction and chat output.
 ///
 /// # Returns
@@ -150,7 +242,7 @@ async fn attempt_direct_delivery(
 /// # Arguments
 ///
 /// * `destination` - The display name or PeerId of the recipient.
-/// * `message` - The message to deliver.
+/// * `message` - The sealed message to deliver.
 /// * `friend` - The `Friend` object of the recipient.
 /// * `context` - The `CommandContext` for network interaction and chat output.
 ///
@@ -178,7 +270,7 @@ async fn attempt_mailbox_delivery(
     };
 
     if !providers.is_empty() {
-        return deliver_via_mailboxes(destination, message, friend, context, providers.into_iter())
+        return deliver_via_mailboxes(destination, message, friend, context, providers)
             .await;
     }
 
@@ -204,7 +296,7 @@ async fn attempt_mailbox_delivery(
         message,
         friend,
         context,
-        emergency_set.into_iter(),
+        emergency_set,
     )
     .await
 }
@@ -214,7 +306,7 @@ async fn attempt_mailbox_delivery(
 /// # Arguments
 ///
 /// * `destination` - The display name or PeerId of the recipient.
-/// * `message` - The message to deliver.
+/// * `message` - The sealed message to deliver.
 /// * `friend` - The `Friend` object of the recipient.
 /// * `context` - The `CommandContext` for network interaction and chat output.
 /// * `providers` - An iterator over `PeerId`s of mailbox providers.
@@ -235,7 +327,7 @@ where
     let provider_set: HashSet<PeerId> = providers.into_iter().collect();
     match context
         .node()
-        .forward_to_mailboxes(message, friend, &provider_set)
+        .forward_to_mailboxes(message, &friend.e2e_public_key, &provider_set)
         .await
     {
         Ok(MailboxDeliveryResult::Success(count)) => {
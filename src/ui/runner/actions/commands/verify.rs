@@ -0,0 +1,84 @@
+//! This module contains the command handler for verifying friends' keys.
+use anyhow::Result;
+
+use super::super::context::CommandContext;
+use super::super::resolver::resolve_peer_id;
+
+/// Shows the safety number shared with a friend, or marks the friend as
+/// verified or unverified.
+///
+/// The safety number is compared with the friend over another channel; when
+/// it matches, `confirm` marks the friend as verified.
+///
+/// Usage: `verify <peer_id_or_nickname> [confirm|reset]`
+///
+/// # Arguments
+///
+/// * `parts` - A slice of strings representing the command arguments.
+/// * `context` - The `CommandContext` providing access to the application's state and node.
+///
+/// # Errors
+///
+/// This function does not return errors; failures are reported in the chat output.
+pub async fn verify_friend(parts: &[&str], context: &CommandContext) -> Result<()> {
+    let action = match parts {
+        [_, _] => None,
+        [_, _, action @ ("confirm" | "reset")] => Some(*action),
+        _ => {
+            context.emit_chat("Usage: verify <peer_id_or_nickname> [confirm|reset]");
+            return Ok(());
+        }
+    };
+
+    let peer_id = match resolve_peer_id(parts[1], context).await {
+        Ok(id) => id,
+        Err(e) => {
+            context.emit_chat(format!("❌ {}", e));
+            return Ok(());
+        }
+    };
+
+    let node = context.node();
+    let friend = match node.friends.get_friend(&peer_id).await {
+        Ok(Some(friend)) => friend,
+        Ok(None) => {
+            context.emit_chat(format!("❌ {} is not a friend", peer_id));
+            return Ok(());
+        }
+        Err(e) => {
+            context.emit_chat(format!("❌ Failed to load friend: {}", e));
+            return Ok(());
+        }
+    };
+    let name = friend
+        .nickname
+        .clone()
+        .unwrap_or_else(|| peer_id.to_string());
+
+    match action {
+        None => match node.safety_number(&friend) {
+            Ok(number) => {
+                let status = if friend.verified {
+                    "✅ Verified"
+                } else {
+                    "Not verified"
+                };
+                context.emit_chat(format!(
+                    "🔐 Safety number with {}:\n  {}\n{}. Compare it with {} in person or over another channel, then run 'verify {} confirm'",
+                    name, number, status, name, parts[1]
+                ));
+            }
+            Err(e) => context.emit_chat(format!("❌ Failed to compute the safety number: {}", e)),
+        },
+        Some(action) => {
+            let verified = action == "confirm";
+            match node.set_friend_verified(peer_id, verified).await {
+                Ok(()) if verified => context.emit_chat(format!("✅ Marked {} as verified", name)),
+                Ok(()) => context.emit_chat(format!("Marked {} as not verified", name)),
+                Err(e) => context.emit_chat(format!("❌ Failed to update {}: {}", name, e)),
+            }
+        }
+    }
+
+    Ok(())
+}
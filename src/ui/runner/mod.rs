@@ -4,6 +4,7 @@ use crate::cli::commands::{Node, UiNotification};
 use crate::logging::{LogBuffer, TUILogCollector};
 use anyhow::Result;
 use crossterm::event::{self, Event};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
@@ -22,7 +23,8 @@ use actions::handle_ui_action;
 ///
 /// * `node` - A shared reference to the application's core `Node`.
 /// * `ui_notify_rx` - Receiver for UI notifications from other parts of the application.
-/// * `web_port` - The port on which the web UI is running.
+/// * `web_addr` - The address on which the web UI is running.
+/// * `log_filter` - The `tracing` filter directives, if logging is filtered.
 ///
 /// # Errors
 ///
@@ -30,7 +32,8 @@ use actions::handle_ui_action;
 pub async fn run_tui(
     node: Arc<Node>,
     mut ui_notify_rx: tokio::sync::mpsc::UnboundedReceiver<UiNotification>,
-    web_port: u16,
+    web_addr: SocketAddr,
+    log_filter: Option<String>,
 ) -> Result<()> {
     info!("🚀 Starting P2P Messenger TUI");
 
@@ -38,7 +41,7 @@ pub async fn run_tui(
     let log_buffer = Arc::new(LogBuffer::new(10000));
 
     // Set up TUI log collector.
-    if let Err(e) = TUILogCollector::init_subscriber(log_buffer.clone()) {
+    if let Err(e) = TUILogCollector::init_subscriber(log_buffer.clone(), log_filter.as_deref()) {
         debug!("Failed to initialize TUI log collector: {}", e);
     }
 
@@ -51,8 +54,8 @@ pub async fn run_tui(
 
     // Send web UI notification to chat.
     let _ = ui_event_tx.send(UIEvent::ChatMessage(format!(
-        "🌐 Web UI available at: http://127.0.0.1:{}",
-        web_port
+        "🌐 Web UI available at: http://{}",
+        web_addr
     )));
 
     // Initialize terminal UI.
@@ -126,7 +129,7 @@ pub async fn run_tui(
         while let Some(notification) = ui_notify_rx.recv().await {
             match notification {
                 UiNotification::NewMessage(message) => {
-                    if let Err(e) = ui_event_tx_notifications.send(UIEvent::NewMessage(message)) {
+                    if let Err(e) = ui_event_tx_notifications.send(UIEvent::NewMessage(Box::new(message))) {
                         debug!("Failed to send new message event: {}", e);
                         break;
                     }
@@ -144,6 +147,54 @@ pub async fn run_tui(
                 UiNotification::DeliveryStatusUpdate { .. } => {
                     // Web UI only notification, CLI doesn't need this.
                 }
+                UiNotification::ContactRequestReceived {
+                    peer_id,
+                    display_name,
+                } => {
+                    let _ = ui_event_tx_notifications.send(UIEvent::ChatMessage(format!(
+                        "👋 Friend request from {} ({}). Use 'accept {}' or 'reject {}'",
+                        peer_id,
+                        display_name.as_deref().unwrap_or("no name"),
+                        peer_id,
+                        peer_id
+                    )));
+                }
+                UiNotification::ContactAccepted(peer_id) => {
+                    let _ = ui_event_tx_notifications
+                        .send(UIEvent::ChatMessage(format!("✅ {} is now a friend", peer_id)));
+                }
+                UiNotification::ContactRejected(peer_id) => {
+                    let _ = ui_event_tx_notifications.send(UIEvent::ChatMessage(format!(
+                        "🚫 {} declined your friend request",
+                        peer_id
+                    )));
+                }
+                UiNotification::MessageRequestReceived(peer_id) => {
+                    let _ = ui_event_tx_notifications.send(UIEvent::ChatMessage(format!(
+                        "📨 Message request from {}. Use 'requests show {}' to read it",
+                        peer_id, peer_id
+                    )));
+                }
+                UiNotification::GroupUpdated(group) => {
+                    let _ = ui_event_tx_notifications.send(UIEvent::ChatMessage(format!(
+                        "👥 Group '{}' now has {} members. Use 'gsend {} <message>' to write to it",
+                        group.name,
+                        group.members.len(),
+                        group.name
+                    )));
+                }
+                UiNotification::VerifiedKeyChanged(peer_id) => {
+                    let _ = ui_event_tx_notifications.send(UIEvent::ChatMessage(format!(
+                        "⚠️  WARNING: the key of verified friend {} changed! Someone may be intercepting your messages. Compare safety numbers again with 'verify {}'",
+                        peer_id, peer_id
+                    )));
+                }
+                UiNotification::MessagesDeleted(message_ids) => {
+                    let _ = ui_event_tx_notifications.send(UIEvent::ChatMessage(format!(
+                        "🗑️  {} messages were deleted by their sender",
+                        message_ids.len()
+                    )));
+                }
             }
         }
     });
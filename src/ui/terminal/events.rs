@@ -22,7 +22,7 @@ impl TerminalUI {
     pub(super) async fn handle_event(&mut self, event: UIEvent) -> Result<()> {
         match event {
             UIEvent::NewMessage(msg) => {
-                self.state.add_message(msg);
+                self.state.add_message(*msg);
             }
             UIEvent::ChatMessage(msg) => {
                 self.state.add_chat_message(msg);
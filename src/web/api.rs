@@ -1,9 +1,12 @@
 //! This module defines the HTTP API endpoints for the web user interface.
 use crate::cli::commands::Node;
-use crate::types::{DeliveryStatus, Friend, Message};
+use crate::cli::GroupDelivery;
+use crate::network::{LimitCounters, Reachability, ReachabilityInfo};
+use crate::storage::{ContactDirection, MessageCursor};
+use crate::types::{AttachmentManifest, DeliveryStatus, Friend, Group, Message};
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, StatusCode},
     response::IntoResponse,
     Json,
 };
@@ -34,6 +37,26 @@ pub struct FriendResponse {
     nickname: Option<String>,
     /// Whether the friend is currently online.
     online: bool,
+    /// Whether the friend's keys were verified with the safety number.
+    verified: bool,
+}
+
+/// Response structure for the safety number shared with a friend.
+#[derive(Serialize)]
+pub struct SafetyNumberResponse {
+    /// The friend's Peer ID.
+    peer_id: String,
+    /// The 60-digit safety number in groups of five.
+    safety_number: String,
+    /// Whether the friend's keys were verified.
+    verified: bool,
+}
+
+/// Request structure for marking a friend as verified or unverified.
+#[derive(Deserialize)]
+pub struct VerifyFriendRequest {
+    /// Whether the safety number matched.
+    verified: bool,
 }
 
 /// Request structure for adding a new friend.
@@ -47,6 +70,64 @@ pub struct AddFriendRequest {
     nickname: Option<String>,
 }
 
+/// Response structure for a pending friend request.
+#[derive(Serialize)]
+pub struct ContactRequestResponse {
+    /// The other party's Peer ID.
+    peer_id: String,
+    /// Either "incoming" or "outgoing".
+    direction: String,
+    /// The requester's display name, for incoming requests.
+    display_name: Option<String>,
+    /// The requester's E2E public key, base64 encoded, for incoming requests.
+    e2e_public_key: Option<String>,
+    /// The timestamp when the request was sent or received.
+    created_at: i64,
+}
+
+/// Request structure for sending a friend request.
+#[derive(Deserialize)]
+pub struct SendContactRequest {
+    /// The Peer ID of the peer to befriend.
+    peer_id: String,
+}
+
+/// Response structure for a blocked peer.
+#[derive(Serialize)]
+pub struct BlockedPeerResponse {
+    /// The blocked peer's ID.
+    peer_id: String,
+    /// The timestamp when the peer was blocked.
+    blocked_at: i64,
+}
+
+/// Request structure for blocking a peer.
+#[derive(Deserialize)]
+pub struct BlockPeerRequest {
+    /// The Peer ID of the peer to block.
+    peer_id: String,
+}
+
+/// Response structure for a message request.
+#[derive(Serialize)]
+pub struct MessageRequestResponse {
+    /// The sender's Peer ID.
+    peer_id: String,
+    /// The E2E public key the sender claimed, base64 encoded.
+    e2e_public_key: String,
+    /// The timestamp of the latest pending message.
+    last_message_at: i64,
+    /// The pending messages, in chronological order.
+    messages: Vec<MessageResponse>,
+}
+
+/// Request structure for accepting a message request.
+#[derive(Deserialize)]
+pub struct AcceptMessageRequest {
+    /// An optional nickname for the new friend.
+    nickname: Option<String>,
+}
+
 /// Response structure for a message.
 #[derive(Serialize)]
 pub struct MessageResponse {
@@ -64,6 +145,76 @@ pub struct MessageResponse {
     nonce: u64,
     /// The delivery status of the message.
     delivery_status: String,
+    /// The ID of the group the message belongs to, if any.
+    group_id: Option<String>,
+    /// The file attached to the message, if any.
+    attachment: Option<AttachmentInfoResponse>,
+}
+
+/// Response structure for a page of messages in a conversation.
+#[derive(Serialize)]
+pub struct MessagesPageResponse {
+    /// The messages, in chronological order.
+    messages: Vec<MessageResponse>,
+    /// The cursor to fetch the messages before this page, if it is not empty.
+    before_cursor: Option<String>,
+    /// The cursor to fetch the messages after this page, if it is not empty.
+    after_cursor: Option<String>,
+}
+
+impl From<&Message> for MessageResponse {
+    fn from(msg: &Message) -> Self {
+        Self {
+            id: msg.id.to_string(),
+            sender: msg.sender.to_string(),
+            recipient: msg.recipient.to_string(),
+            content: msg.text(),
+            timestamp: msg.timestamp,
+            nonce: msg.nonce,
+            delivery_status: format!("{:?}", msg.delivery_status),
+            group_id: msg.group_id.map(|id| id.to_string()),
+            attachment: msg.attachment.as_deref().map(AttachmentInfoResponse::from),
+        }
+    }
+}
+
+/// Response structure for a message that matches a search.
+#[derive(Serialize)]
+pub struct SearchHitResponse {
+    /// The matching message.
+    message: MessageResponse,
+    /// The relevance of the message; higher is better.
+    score: f64,
+    /// The Peer ID of the conversation partner, or the group ID for group messages.
+    conversation: String,
+    /// The messages before the match in its conversation.
+    before: Vec<MessageResponse>,
+    /// The messages after the match in its conversation.
+    after: Vec<MessageResponse>,
+}
+
+/// Response structure for the file attached to a message.
+#[derive(Serialize, Clone)]
+pub struct AttachmentInfoResponse {
+    /// The SHA-256 hash of the file, used to download it.
+    hash: String,
+    /// The original name of the file.
+    file_name: String,
+    /// The MIME type of the file.
+    mime: String,
+    /// The size of the file in bytes.
+    size: u64,
+}
+
+impl From<&AttachmentManifest> for AttachmentInfoResponse {
+    fn from(manifest: &AttachmentManifest) -> Self {
+        Self {
+            hash: manifest.hash.clone(),
+            file_name: manifest.file_name.clone(),
+            mime: manifest.mime.clone(),
+            size: manifest.size,
+        }
+    }
 }
 
 /// Request structure for sending a new message.
@@ -76,16 +227,47 @@ pub struct SendMessageRequest {
 /// Query parameters for fetching messages.
 #[derive(Deserialize)]
 pub struct GetMessagesQuery {
-    /// The mode for querying messages (latest, before, or after a specific message).
+    /// The mode for querying messages (latest, before, or after a cursor).
     #[serde(default)]
     mode: MessageQueryMode,
     /// The maximum number of messages to retrieve.
     #[serde(default = "default_limit")]
     limit: usize,
-    /// The ID of the message to fetch messages before (used with `Before` mode).
-    before_id: Option<String>,
-    /// The ID of the message to fetch messages after (used with `After` mode).
-    after_id: Option<String>,
+    /// The cursor to fetch messages before or after (used with `Before` and `After` modes).
+    cursor: Option<String>,
+}
+
+/// Query parameters for searching the message history.
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    /// The words to search for.
+    q: String,
+    /// The Peer ID to restrict the search to, if any.
+    peer: Option<String>,
+    /// The maximum number of matches to retrieve.
+    #[serde(default = "default_search_limit")]
+    limit: usize,
+    /// The number of messages to include before and after each match.
+    #[serde(default = "default_search_context")]
+    context: usize,
+}
+
+/// Default limit for search queries.
+fn default_search_limit() -> usize {
+    20
+}
+
+/// Default number of messages around each search match.
+fn default_search_context() -> usize {
+    2
+}
+
+/// Query parameters for deleting messages and conversations.
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    /// Whether to also delete the messages we sent on the friend's devices.
+    #[serde(default)]
+    everyone: bool,
 }
 
 /// Defines the mode for querying messages.
@@ -95,110 +277,745 @@ enum MessageQueryMode {
     /// Fetch the latest messages.
     #[default]
     Latest,
-    /// Fetch messages before a specific message ID.
+    /// Fetch messages before a cursor.
     Before,
-    /// Fetch messages after a specific message ID.
+    /// Fetch messages after a cursor.
     After,
 }
 
-/// Default limit for message queries.
-fn default_limit() -> usize {
-    50
-}
+/// Decodes the cursor of a message query, which the given mode requires.
+///
+/// Returns the error message for the client if it is missing or invalid.
+fn decode_cursor(cursor: Option<&str>, mode: &str) -> Result<MessageCursor, String> {
+    let cursor = cursor.ok_or_else(|| format!("cursor is required for mode={}", mode))?;
+    MessageCursor::decode(cursor).map_err(|e| format!("Invalid cursor: {}", e))
+}
+
+/// Default limit for message queries.
+fn default_limit() -> usize {
+    50
+}
+
+/// Response structure for a group.
+#[derive(Serialize)]
+pub struct GroupResponse {
+    /// The unique ID of the group.
+    id: String,
+    /// The name of the group.
+    name: String,
+    /// The members of the group, including ourselves.
+    members: Vec<GroupMemberResponse>,
+    /// The Peer ID of the member who created the group.
+    created_by: String,
+    /// The timestamp when the group was created (milliseconds since epoch).
+    created_at: i64,
+}
+
+/// Response structure for a group member.
+#[derive(Serialize)]
+pub struct GroupMemberResponse {
+    /// The member's Peer ID.
+    peer_id: String,
+    /// The member's name, if known.
+    name: Option<String>,
+}
+
+impl From<Group> for GroupResponse {
+    fn from(group: Group) -> Self {
+        Self {
+            id: group.id.to_string(),
+            name: group.name,
+            members: group
+                .members
+                .into_iter()
+                .map(|member| GroupMemberResponse {
+                    peer_id: member.peer_id.to_string(),
+                    name: member.name,
+                })
+                .collect(),
+            created_by: group.created_by.to_string(),
+            created_at: group.created_at,
+        }
+    }
+}
+
+/// Request structure for creating a group.
+#[derive(Deserialize)]
+pub struct CreateGroupRequest {
+    /// The name of the group.
+    name: String,
+    /// The Peer IDs of the friends to invite.
+    members: Vec<String>,
+}
+
+/// Request structure for adding members to a group.
+#[derive(Deserialize)]
+pub struct AddGroupMembersRequest {
+    /// The Peer IDs of the friends to add.
+    members: Vec<String>,
+}
+
+/// Query parameters for fetching group messages.
+#[derive(Deserialize)]
+pub struct GetGroupMessagesQuery {
+    /// The maximum number of messages to retrieve.
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+/// Response structure for how the copies of a group message were delivered.
+#[derive(Serialize)]
+pub struct GroupDeliveryResponse {
+    /// Copies delivered directly to online members.
+    direct: usize,
+    /// Copies stored in mailboxes for offline members.
+    mailbox: usize,
+    /// Copies queued for a later retry.
+    queued: usize,
+}
+
+impl From<GroupDelivery> for GroupDeliveryResponse {
+    fn from(delivery: GroupDelivery) -> Self {
+        Self {
+            direct: delivery.direct,
+            mailbox: delivery.mailbox,
+            queued: delivery.queued,
+        }
+    }
+}
+
+/// Response structure for a conversation summary.
+#[derive(Serialize)]
+pub struct ConversationResponse {
+    /// The Peer ID of the other participant in the conversation.
+    peer_id: String,
+    /// The nickname of the other participant.
+    nickname: Option<String>,
+    /// The last message in the conversation, if any.
+    last_message: Option<MessageResponse>,
+    /// Whether the other participant is currently online.
+    online: bool,
+}
+
+/// Retrieves the user's identity information.
+#[axum::debug_handler]
+pub async fn get_me(State(node): State<Arc<Node>>) -> impl IntoResponse {
+    let response = IdentityResponse {
+        peer_id: node.identity.peer_id.to_string(),
+        hpke_public_key: BASE64_STANDARD.encode(node.identity.hpke_public_key()),
+    };
+    Json(response)
+}
+
+/// Lists all friends of the user.
+#[axum::debug_handler]
+pub async fn list_friends(State(node): State<Arc<Node>>) -> impl IntoResponse {
+    match node.friends.list_friends().await {
+        Ok(friends) => {
+            let online_peers = node
+                .network
+                .get_connected_peers()
+                .await
+                .unwrap_or_default();
+
+            let response: Vec<FriendResponse> = friends
+                .into_iter()
+                .map(|f| FriendResponse {
+                    online: online_peers.contains(&f.peer_id),
+                    peer_id: f.peer_id.to_string(),
+                    e2e_public_key: BASE64_STANDARD.encode(&f.e2e_public_key),
+                    nickname: f.nickname,
+                    verified: f.verified,
+                })
+                .collect();
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to list friends: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Adds a new friend to the user's friend list.
+#[axum::debug_handler]
+pub async fn add_friend(
+    State(node): State<Arc<Node>>,
+    Json(req): Json<AddFriendRequest>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&req.peer_id) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    let e2e_public_key = match BASE64_STANDARD.decode(&req.e2e_public_key) {
+        Ok(key) => key,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid public key: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    let friend = Friend {
+        peer_id,
+        e2e_public_key,
+        nickname: req.nickname,
+        verified: false,
+        devices: Vec::new(),
+        devices_timestamp: 0,
+    };
+
+    match node.save_friend(friend).await {
+        Ok(_) => (StatusCode::CREATED, "Friend added").into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to add friend: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Returns the safety number shared with a friend.
+#[axum::debug_handler]
+pub async fn get_safety_number(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    let friend = match node.friends.get_friend(&peer_id).await {
+        Ok(Some(friend)) => friend,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Not a friend").into_response(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load friend: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.safety_number(&friend) {
+        Ok(safety_number) => Json(SafetyNumberResponse {
+            peer_id: peer_id.to_string(),
+            safety_number,
+            verified: friend.verified,
+        })
+        .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to compute the safety number: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Marks a friend as verified or unverified.
+#[axum::debug_handler]
+pub async fn verify_friend(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+    Json(req): Json<VerifyFriendRequest>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.friends.set_verified(&peer_id, req.verified).await {
+        Ok(true) => (StatusCode::OK, "Friend updated").into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Not a friend").into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to update friend: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Lists pending incoming and outgoing friend requests.
+#[axum::debug_handler]
+pub async fn list_contact_requests(State(node): State<Arc<Node>>) -> impl IntoResponse {
+    let mut response = Vec::new();
+
+    for direction in [ContactDirection::Incoming, ContactDirection::Outgoing] {
+        let pending = match node.contacts.list_pending(direction).await {
+            Ok(pending) => pending,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to list friend requests: {}", e),
+                )
+                    .into_response()
+            }
+        };
+
+        response.extend(pending.into_iter().map(|p| ContactRequestResponse {
+            peer_id: p.peer_id.to_string(),
+            direction: match p.direction {
+                ContactDirection::Incoming => "incoming".to_string(),
+                ContactDirection::Outgoing => "outgoing".to_string(),
+            },
+            display_name: p.card.as_ref().and_then(|c| c.display_name.clone()),
+            e2e_public_key: p.card.as_ref().map(|c| BASE64_STANDARD.encode(&c.e2e_public_key)),
+            created_at: p.created_at,
+        }));
+    }
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Sends a friend request, or accepts one already received from the same peer.
+#[axum::debug_handler]
+pub async fn send_contact_request(
+    State(node): State<Arc<Node>>,
+    Json(req): Json<SendContactRequest>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&req.peer_id) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.send_contact_request(peer_id).await {
+        Ok(Some(_)) => (StatusCode::CREATED, "Friend added").into_response(),
+        Ok(None) => (StatusCode::ACCEPTED, "Friend request sent").into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to send friend request: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Accepts a pending friend request.
+#[axum::debug_handler]
+pub async fn accept_contact_request(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.accept_contact_request(peer_id).await {
+        Ok(_) => (StatusCode::CREATED, "Friend added").into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to accept friend request: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Rejects a pending friend request.
+#[axum::debug_handler]
+pub async fn reject_contact_request(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.reject_contact_request(peer_id).await {
+        Ok(()) => (StatusCode::OK, "Friend request rejected").into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to reject friend request: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Lists the messages from peers that are not friends, grouped by sender.
+#[axum::debug_handler]
+pub async fn list_message_requests(State(node): State<Arc<Node>>) -> impl IntoResponse {
+    let requests = match node.requests.list_requests().await {
+        Ok(requests) => requests,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to list message requests: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    let mut response = Vec::new();
+    for request in requests {
+        let messages = match node.requests.get_messages(&request.peer_id).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to read message request: {}", e),
+                )
+                    .into_response()
+            }
+        };
+
+        response.push(MessageRequestResponse {
+            peer_id: request.peer_id.to_string(),
+            e2e_public_key: BASE64_STANDARD.encode(&request.e2e_public_key),
+            last_message_at: request.last_message_at,
+            messages: messages
+                .iter()
+                .map(|msg| MessageResponse {
+                    id: msg.id.to_string(),
+                    sender: msg.sender.to_string(),
+                    recipient: msg.recipient.to_string(),
+                    content: msg.text(),
+                    timestamp: msg.timestamp,
+                    nonce: msg.nonce,
+                    delivery_status: format!("{:?}", msg.delivery_status),
+                    group_id: None,
+                    attachment: msg.attachment.as_deref().map(AttachmentInfoResponse::from),
+                })
+                .collect(),
+        });
+    }
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Accepts a message request, adding the sender as a friend and moving its
+/// messages into the history.
+#[axum::debug_handler]
+pub async fn accept_message_request(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+    req: Option<Json<AcceptMessageRequest>>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    let nickname = req.and_then(|Json(req)| req.nickname);
+    match node.accept_message_request(peer_id, nickname).await {
+        Ok(_) => (StatusCode::CREATED, "Friend added").into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to accept message request: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Deletes a message request with all its messages.
+#[axum::debug_handler]
+pub async fn delete_message_request(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.delete_message_request(peer_id).await {
+        Ok(_) => (StatusCode::OK, "Message request deleted").into_response(),
+        Err(e) => (
+            StatusCode::NOT_FOUND,
+            format!("Failed to delete message request: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Lists the blocked peers.
+#[axum::debug_handler]
+pub async fn list_blocked_peers(State(node): State<Arc<Node>>) -> impl IntoResponse {
+    match node.blocks.list_blocked().await {
+        Ok(blocked) => {
+            let response: Vec<BlockedPeerResponse> = blocked
+                .into_iter()
+                .map(|entry| BlockedPeerResponse {
+                    peer_id: entry.peer_id.to_string(),
+                    blocked_at: entry.blocked_at,
+                })
+                .collect();
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to list blocked peers: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Blocks a peer.
+#[axum::debug_handler]
+pub async fn block_peer(
+    State(node): State<Arc<Node>>,
+    Json(req): Json<BlockPeerRequest>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&req.peer_id) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.block_peer(peer_id).await {
+        Ok(true) => (StatusCode::CREATED, "Peer blocked").into_response(),
+        Ok(false) => (StatusCode::OK, "Peer already blocked").into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to block peer: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Unblocks a peer.
+#[axum::debug_handler]
+pub async fn unblock_peer(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.unblock_peer(peer_id).await {
+        Ok(true) => (StatusCode::OK, "Peer unblocked").into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Peer is not blocked").into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to unblock peer: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Lists all groups we are a member of.
+#[axum::debug_handler]
+pub async fn list_groups(State(node): State<Arc<Node>>) -> impl IntoResponse {
+    match node.groups.list_groups().await {
+        Ok(groups) => {
+            let response: Vec<GroupResponse> =
+                groups.into_iter().map(GroupResponse::from).collect();
+            Json(response).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to list groups: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Creates a group with some of our friends.
+#[axum::debug_handler]
+pub async fn create_group(
+    State(node): State<Arc<Node>>,
+    Json(req): Json<CreateGroupRequest>,
+) -> impl IntoResponse {
+    let members = match parse_peer_ids(&req.members) {
+        Ok(members) => members,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    match node.create_group(req.name, members).await {
+        Ok((group, _)) => (StatusCode::CREATED, Json(GroupResponse::from(group))).into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to create group: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Adds some of our friends to a group.
+#[axum::debug_handler]
+pub async fn add_group_members(
+    State(node): State<Arc<Node>>,
+    Path(group_id_str): Path<String>,
+    Json(req): Json<AddGroupMembersRequest>,
+) -> impl IntoResponse {
+    let group_id = match Uuid::from_str(&group_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid group ID: {}", e)).into_response()
+        }
+    };
+
+    let members = match parse_peer_ids(&req.members) {
+        Ok(members) => members,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
 
-/// Response structure for a conversation summary.
-#[derive(Serialize)]
-pub struct ConversationResponse {
-    /// The Peer ID of the other participant in the conversation.
-    peer_id: String,
-    /// The nickname of the other participant.
-    nickname: Option<String>,
-    /// The last message in the conversation, if any.
-    last_message: Option<MessageResponse>,
-    /// Whether the other participant is currently online.
-    online: bool,
+    match node.add_group_members(&group_id, members).await {
+        Ok((group, _)) => Json(GroupResponse::from(group)).into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to add group members: {}", e),
+        )
+            .into_response(),
+    }
 }
 
-/// Retrieves the user's identity information.
+/// Leaves a group.
 #[axum::debug_handler]
-pub async fn get_me(State(node): State<Arc<Node>>) -> impl IntoResponse {
-    let response = IdentityResponse {
-        peer_id: node.identity.peer_id.to_string(),
-        hpke_public_key: BASE64_STANDARD.encode(node.identity.hpke_public_key()),
+pub async fn leave_group(
+    State(node): State<Arc<Node>>,
+    Path(group_id_str): Path<String>,
+) -> impl IntoResponse {
+    let group_id = match Uuid::from_str(&group_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid group ID: {}", e)).into_response()
+        }
     };
-    Json(response)
+
+    match node.leave_group(&group_id).await {
+        Ok(_) => (StatusCode::OK, "Left group").into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to leave group: {}", e),
+        )
+            .into_response(),
+    }
 }
 
-/// Lists all friends of the user.
+/// Retrieves the latest messages of a group.
 #[axum::debug_handler]
-pub async fn list_friends(State(node): State<Arc<Node>>) -> impl IntoResponse {
-    match node.friends.list_friends().await {
-        Ok(friends) => {
-            let online_peers = node
-                .network
-                .get_connected_peers()
-                .await
-                .unwrap_or_default();
+pub async fn get_group_messages(
+    State(node): State<Arc<Node>>,
+    Path(group_id_str): Path<String>,
+    Query(query): Query<GetGroupMessagesQuery>,
+) -> impl IntoResponse {
+    let group_id = match Uuid::from_str(&group_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid group ID: {}", e)).into_response()
+        }
+    };
 
-            let response: Vec<FriendResponse> = friends
+    match node.history.get_group_history(&group_id, query.limit).await {
+        Ok(messages) => {
+            let response: Vec<MessageResponse> = messages
                 .into_iter()
-                .map(|f| FriendResponse {
-                    online: online_peers.contains(&f.peer_id),
-                    peer_id: f.peer_id.to_string(),
-                    e2e_public_key: BASE64_STANDARD.encode(&f.e2e_public_key),
-                    nickname: f.nickname,
+                .map(|msg| MessageResponse {
+                    id: msg.id.to_string(),
+                    sender: msg.sender.to_string(),
+                    recipient: msg.recipient.to_string(),
+                    content: msg.text(),
+                    timestamp: msg.timestamp,
+                    nonce: msg.nonce,
+                    delivery_status: format!("{:?}", msg.delivery_status),
+                    group_id: msg.group_id.map(|id| id.to_string()),
+                    attachment: msg.attachment.as_deref().map(AttachmentInfoResponse::from),
                 })
                 .collect();
 
-            (StatusCode::OK, Json(response)).into_response()
+            Json(response).into_response()
         }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to list friends: {}", e),
+            format!("Failed to get group messages: {}", e),
         )
             .into_response(),
     }
 }
 
-/// Adds a new friend to the user's friend list.
+/// Sends a message to a group.
 #[axum::debug_handler]
-pub async fn add_friend(
+pub async fn send_group_message(
     State(node): State<Arc<Node>>,
-    Json(req): Json<AddFriendRequest>,
+    Path(group_id_str): Path<String>,
+    Json(req): Json<SendMessageRequest>,
 ) -> impl IntoResponse {
-    let peer_id = match PeerId::from_str(&req.peer_id) {
+    let group_id = match Uuid::from_str(&group_id_str) {
         Ok(id) => id,
         Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Invalid peer ID: {}", e),
-            )
-                .into_response()
-        }
-    };
-
-    let e2e_public_key = match BASE64_STANDARD.decode(&req.e2e_public_key) {
-        Ok(key) => key,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Invalid public key: {}", e),
-            )
-                .into_response()
+            return (StatusCode::BAD_REQUEST, format!("Invalid group ID: {}", e)).into_response()
         }
     };
 
-    let friend = Friend {
-        peer_id,
-        e2e_public_key,
-        nickname: req.nickname,
-    };
-
-    match node.friends.add_friend(friend).await {
-        Ok(_) => (StatusCode::CREATED, "Friend added").into_response(),
+    match node.send_group_message(&group_id, req.content).await {
+        Ok((message, delivery)) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "id": message.id,
+                "delivery": GroupDeliveryResponse::from(delivery),
+            })),
+        )
+            .into_response(),
         Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to add friend: {}", e),
+            StatusCode::BAD_REQUEST,
+            format!("Failed to send group message: {}", e),
         )
             .into_response(),
     }
@@ -233,20 +1050,17 @@ pub async fn list_conversations(State(node): State<Arc<Node>>) -> impl IntoRespo
             .await
             .unwrap_or_default();
 
-        let mut last_message = None;
-        if let Some(msg) = messages.last() {
-            if let Some(content) = decrypt_message_content(msg, &node).await {
-                last_message = Some(MessageResponse {
-                    id: msg.id.to_string(),
-                    sender: msg.sender.to_string(),
-                    recipient: msg.recipient.to_string(),
-                    content,
-                    timestamp: msg.timestamp,
-                    nonce: msg.nonce,
-                    delivery_status: format!("{:?}", msg.delivery_status),
-                });
-            }
-        }
+        let last_message = messages.last().map(|msg| MessageResponse {
+            id: msg.id.to_string(),
+            sender: msg.sender.to_string(),
+            recipient: msg.recipient.to_string(),
+            content: msg.text(),
+            timestamp: msg.timestamp,
+            nonce: msg.nonce,
+            delivery_status: format!("{:?}", msg.delivery_status),
+            group_id: None,
+            attachment: msg.attachment.as_deref().map(AttachmentInfoResponse::from),
+        });
 
         conversations.push(ConversationResponse {
             peer_id: friend.peer_id.to_string(),
@@ -291,64 +1105,48 @@ pub async fn get_messages(
                 .await
         }
         MessageQueryMode::Before => {
-            let before_id = match &query.before_id {
-                Some(id_str) => match Uuid::from_str(id_str) {
-                    Ok(id) => id,
-                    Err(e) => {
-                        return (
-                            StatusCode::BAD_REQUEST,
-                            format!("Invalid before_id: {}", e),
-                        )
-                            .into_response()
-                    }
-                },
-                None => {
-                    return (StatusCode::BAD_REQUEST, "before_id is required for mode=before")
-                        .into_response()
-                }
+            let before = match decode_cursor(query.cursor.as_deref(), "before") {
+                Ok(cursor) => cursor,
+                Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
             };
             node.history
-                .get_messages_before(&node.identity.peer_id, &peer_id, &before_id, query.limit)
+                .get_messages_before(&node.identity.peer_id, &peer_id, &before, query.limit)
                 .await
         }
         MessageQueryMode::After => {
-            let after_id = match &query.after_id {
-                Some(id_str) => match Uuid::from_str(id_str) {
-                    Ok(id) => id,
-                    Err(e) => {
-                        return (StatusCode::BAD_REQUEST, format!("Invalid after_id: {}", e))
-                            .into_response()
-                    }
-                },
-                None => {
-                    return (StatusCode::BAD_REQUEST, "after_id is required for mode=after")
-                        .into_response()
-                }
+            let after = match decode_cursor(query.cursor.as_deref(), "after") {
+                Ok(cursor) => cursor,
+                Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
             };
             node.history
-                .get_messages_after(&node.identity.peer_id, &peer_id, &after_id, query.limit)
+                .get_messages_after(&node.identity.peer_id, &peer_id, &after, query.limit)
                 .await
         }
     };
 
     match messages_result {
         Ok(messages) => {
-            let mut response = Vec::new();
-            for msg in messages.iter() {
-                if let Some(content) = decrypt_message_content(msg, &node).await {
-                    response.push(MessageResponse {
-                        id: msg.id.to_string(),
-                        sender: msg.sender.to_string(),
-                        recipient: msg.recipient.to_string(),
-                        content,
-                        timestamp: msg.timestamp,
-                        nonce: msg.nonce,
-                        delivery_status: format!("{:?}", msg.delivery_status),
-                    });
-                }
-            }
+            let response: Vec<MessageResponse> = messages
+                .iter()
+                .map(|msg| MessageResponse {
+                    id: msg.id.to_string(),
+                    sender: msg.sender.to_string(),
+                    recipient: msg.recipient.to_string(),
+                    content: msg.text(),
+                    timestamp: msg.timestamp,
+                    nonce: msg.nonce,
+                    delivery_status: format!("{:?}", msg.delivery_status),
+                    group_id: None,
+                    attachment: msg.attachment.as_deref().map(AttachmentInfoResponse::from),
+                })
+                .collect();
 
-            Json(response).into_response()
+            Json(MessagesPageResponse {
+                messages: response,
+                before_cursor: messages.first().map(|msg| MessageCursor::at(msg).encode()),
+                after_cursor: messages.last().map(|msg| MessageCursor::at(msg).encode()),
+            })
+            .into_response()
         }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -358,6 +1156,55 @@ pub async fn get_messages(
     }
 }
 
+/// Searches the message history, returning ranked matches with the messages around them.
+#[axum::debug_handler]
+pub async fn search_messages(
+    State(node): State<Arc<Node>>,
+    Query(query): Query<SearchQuery>,
+) -> impl IntoResponse {
+    let peer_id = match query.peer.as_deref().map(PeerId::from_str).transpose() {
+        Ok(peer_id) => peer_id,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid peer ID: {}", e)).into_response()
+        }
+    };
+
+    match node
+        .history
+        .search(&query.q, peer_id.as_ref(), query.limit, query.context)
+        .await
+    {
+        Ok(hits) => {
+            let response: Vec<SearchHitResponse> = hits
+                .iter()
+                .map(|hit| {
+                    let conversation = match hit.message.group_id {
+                        Some(group_id) => group_id.to_string(),
+                        None if hit.message.sender == node.identity.peer_id => {
+                            hit.message.recipient.to_string()
+                        }
+                        None => hit.message.sender.to_string(),
+                    };
+                    SearchHitResponse {
+                        message: MessageResponse::from(&hit.message),
+                        score: hit.score,
+                        conversation,
+                        before: hit.before.iter().map(MessageResponse::from).collect(),
+                        after: hit.after.iter().map(MessageResponse::from).collect(),
+                    }
+                })
+                .collect();
+
+            Json(response).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to search messages: {}", e),
+        )
+            .into_response(),
+    }
+}
+
 /// Sends a message to a specific peer.
 #[axum::debug_handler]
 pub async fn send_message(
@@ -390,11 +1237,33 @@ pub async fn send_message(
         }
     };
 
-    let encrypted_content = match node
+    let message = Message {
+        id: Uuid::new_v4(),
+        sender: node.identity.peer_id,
+        recipient: peer_id,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        content: req.content.into_bytes(),
+        nonce: rand::random(),
+        delivery_status: DeliveryStatus::Sent,
+        signature: Vec::new(),
+        group_id: None,
+        attachment: None,
+        sender_pub_key: Vec::new(),
+    };
+
+    let sealed = match node
         .identity
-        .encrypt_for(&friend.e2e_public_key, req.content.as_bytes())
-    {
-        Ok(c) => c,
+        .encrypt_for(&friend.e2e_public_key, &message.content)
+        .and_then(|content| {
+            let mut sealed = Message {
+                content,
+                sender_pub_key: node.identity.hpke_public_key(),
+                ..message.clone()
+            };
+            sealed.signature = node.identity.sign_message(&sealed)?;
+            Ok(sealed)
+        }) {
+        Ok(sealed) => sealed,
         Err(e) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -404,15 +1273,7 @@ pub async fn send_message(
         }
     };
 
-    let message = Message {
-        id: Uuid::new_v4(),
-        sender: node.identity.peer_id,
-        recipient: peer_id,
-        timestamp: chrono::Utc::now().timestamp_millis(),
-        content: encrypted_content,
-        nonce: rand::random(),
-        delivery_status: DeliveryStatus::Sent,
-    };
+    let message_id = message.id;
 
     if let Err(e) = node.history.store_message(message.clone()).await {
         return (
@@ -422,24 +1283,24 @@ pub async fn send_message(
             .into_response();
     }
 
-    if let Err(e) = node.outbox.add_pending(message.clone()).await {
+    if let Err(e) = node.outbox.add_pending(sealed.clone()).await {
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Failed to add message to outbox: {}", e),
         )
             .into_response();
     }
+    node.queue_device_copies(&friend, &message).await;
 
     // Try direct send in background (delivery confirmation will update status)
     let network_clone = node.network.clone();
-    let msg_clone = message.clone();
     tokio::spawn(async move {
-        if let Err(e) = network_clone.send_message(peer_id, msg_clone).await {
+        if let Err(e) = network_clone.send_message(peer_id, sealed).await {
             tracing::debug!("Direct send failed, will retry via sync: {}", e);
         }
     });
 
-    (StatusCode::OK, Json(serde_json::json!({ "id": message.id }))).into_response()
+    (StatusCode::OK, Json(serde_json::json!({ "id": message_id }))).into_response()
 }
 
 /// Marks a specific message as read.
@@ -516,6 +1377,67 @@ pub async fn mark_message_read(
     StatusCode::OK.into_response()
 }
 
+/// Deletes a message, locally or for everyone.
+#[axum::debug_handler]
+pub async fn delete_message(
+    State(node): State<Arc<Node>>,
+    Path(msg_id_str): Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> impl IntoResponse {
+    let msg_id = match Uuid::from_str(&msg_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid message ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.delete_message(&msg_id, query.everyone).await {
+        Ok(true) => (StatusCode::OK, "Message deleted").into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Message not found").into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to delete message: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Deletes the whole conversation with a peer, locally or for everyone.
+#[axum::debug_handler]
+pub async fn delete_conversation(
+    State(node): State<Arc<Node>>,
+    Path(peer_id_str): Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> impl IntoResponse {
+    let peer_id = match PeerId::from_str(&peer_id_str) {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid peer ID: {}", e),
+            )
+                .into_response()
+        }
+    };
+
+    match node.delete_conversation(&peer_id, query.everyone).await {
+        Ok(deleted) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "deleted": deleted })),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to delete conversation: {}", e),
+        )
+            .into_response(),
+    }
+}
+
 /// Retrieves a list of currently online peers.
 #[axum::debug_handler]
 pub async fn get_online_peers(State(node): State<Arc<Node>>) -> impl IntoResponse {
@@ -541,6 +1463,74 @@ pub struct SystemStatus {
     known_mailboxes: usize,
     /// The number of messages pending delivery in the outbox.
     pending_messages: usize,
+    /// The NAT traversal state, if the network layer could report it.
+    reachability: Option<ReachabilityResponse>,
+    /// The requests and connections refused by the limits, if the network layer could report them.
+    limits: Option<LimitsResponse>,
+}
+
+/// Response structure for the reachability of the node.
+#[derive(Serialize)]
+pub struct ReachabilityResponse {
+    /// Whether the node is publicly reachable, behind a NAT, or unknown.
+    status: Reachability,
+    /// The address AutoNAT confirmed as publicly reachable, if any.
+    public_address: Option<String>,
+    /// Whether the node serves as a relay for other peers.
+    is_relay: bool,
+    /// The relayed addresses at which the node can be reached.
+    relay_addresses: Vec<String>,
+    /// The number of relayed connections upgraded to direct ones.
+    hole_punches_succeeded: u64,
+    /// The number of failed attempts to upgrade a relayed connection.
+    hole_punches_failed: u64,
+}
+
+impl From<ReachabilityInfo> for ReachabilityResponse {
+    fn from(info: ReachabilityInfo) -> Self {
+        Self {
+            status: info.reachability,
+            public_address: info.public_address.map(|address| address.to_string()),
+            is_relay: info.is_relay,
+            relay_addresses: info
+                .relay_addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect(),
+            hole_punches_succeeded: info.hole_punches_succeeded,
+            hole_punches_failed: info.hole_punches_failed,
+        }
+    }
+}
+
+/// Response structure for the counters of the rate and connection limits.
+#[derive(Serialize)]
+pub struct LimitsResponse {
+    /// The chat requests dropped for exceeding the rate limit.
+    chat_requests_limited: u64,
+    /// The mailbox requests dropped for exceeding the rate limit.
+    mailbox_requests_limited: u64,
+    /// The DHT writes dropped for exceeding the rate limit.
+    dht_writes_limited: u64,
+    /// The inbound connections refused by a connection limit or block.
+    connections_denied: u64,
+    /// The peers blocked for repeatedly exceeding the limits.
+    peers_blocked: u64,
+    /// The peers that are blocked at the moment.
+    currently_blocked: usize,
+}
+
+impl From<LimitCounters> for LimitsResponse {
+    fn from(counters: LimitCounters) -> Self {
+        Self {
+            chat_requests_limited: counters.chat_requests_limited,
+            mailbox_requests_limited: counters.mailbox_requests_limited,
+            dht_writes_limited: counters.dht_writes_limited,
+            connections_denied: counters.connections_denied,
+            peers_blocked: counters.peers_blocked,
+            currently_blocked: counters.currently_blocked,
+        }
+    }
 }
 
 /// Retrieves the current system status.
@@ -560,31 +1550,101 @@ pub async fn get_system_status(State(node): State<Arc<Node>>) -> impl IntoRespon
 
     let pending_messages = node.outbox.count_pending().await.unwrap_or(0);
 
+    let reachability = node
+        .network
+        .get_reachability()
+        .await
+        .ok()
+        .map(ReachabilityResponse::from);
+
+    let limits = node
+        .network
+        .get_limit_counters()
+        .await
+        .ok()
+        .map(LimitsResponse::from);
+
     Json(SystemStatus {
         connected_peers,
         known_mailboxes,
         pending_messages,
+        reachability,
+        limits,
     })
     .into_response()
 }
 
-/// Helper function to decrypt message content for web API responses.
-async fn decrypt_message_content(msg: &Message, node: &Node) -> Option<String> {
-    // Determine which peer's public key to use for decryption.
-    let other_peer = if msg.sender == node.identity.peer_id {
-        &msg.recipient
-    } else {
-        &msg.sender
-    };
+/// Parses a list of Peer IDs from a request body.
+fn parse_peer_ids(raw: &[String]) -> Result<Vec<PeerId>, String> {
+    raw.iter()
+        .map(|id| PeerId::from_str(id).map_err(|e| format!("Invalid peer ID '{}': {}", id, e)))
+        .collect()
+}
 
-    // Get the friend's public key.
-    let friend = node.friends.get_friend(other_peer).await.ok()??;
+/// Handler for downloading an attachment.
+///
+/// Returns the decrypted file with its MIME type, or `409 Conflict` if it is
+/// still being fetched from the sender.
+///
+/// # Arguments
+///
+/// * `State(node)` - The application state, containing the `Node`.
+/// * `Path(hash)` - The SHA-256 hash of the attachment.
+///
+/// # Returns
+///
+/// The contents of the file.
+#[axum::debug_handler]
+pub async fn get_attachment(
+    State(node): State<Arc<Node>>,
+    Path(hash): Path<String>,
+) -> impl IntoResponse {
+    match node.attachments.is_complete(&hash) {
+        Ok(true) => {}
+        Ok(false) => match node.attachments.get_manifest(&hash) {
+            Ok(Some(_)) => {
+                return (StatusCode::CONFLICT, "Attachment is still being downloaded")
+                    .into_response()
+            }
+            _ => return (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        },
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid attachment hash: {}", e),
+            )
+                .into_response()
+        }
+    }
 
-    // Decrypt using the friend's public key.
-    let plaintext = node
-        .identity
-        .decrypt_from(&friend.e2e_public_key, &msg.content)
-        .ok()?;
+    let store = node.attachments.clone();
+    let result = tokio::task::spawn_blocking(move || store.read_file(&hash)).await;
 
-    String::from_utf8(plaintext).ok()
+    match result {
+        Ok(Ok(Some((manifest, data)))) => {
+            let disposition = format!(
+                "attachment; filename=\"{}\"",
+                manifest.file_name.replace(['"', '\\'], "_")
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, manifest.mime),
+                    (header::CONTENT_DISPOSITION, disposition),
+                ],
+                data,
+            )
+                .into_response()
+        }
+        Ok(Ok(None)) => (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Ok(Err(e)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read attachment: {}", e),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read attachment: {}", e),
+        )
+            .into_response(),
+    }
 }
@@ -14,6 +14,7 @@ use axum::{
     Router,
 };
 use rust_embed::RustEmbed;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::{broadcast, mpsc};
 use tower_http::cors::CorsLayer;
@@ -34,7 +35,7 @@ struct Assets;
 /// # Arguments
 ///
 /// * `node` - A shared reference to the application's core `Node`.
-/// * `port` - The port to bind the web server to.
+/// * `addr` - The socket address to bind the web server to.
 /// * `ui_notify_rx` - Receiver for UI notifications from the core application.
 ///
 /// # Errors
@@ -42,7 +43,7 @@ struct Assets;
 /// Returns an error if the server fails to bind or run.
 pub async fn start_server(
     node: Arc<Node>,
-    port: u16,
+    addr: SocketAddr,
     mut ui_notify_rx: mpsc::UnboundedReceiver<UiNotification>,
 ) -> Result<()> {
     let (broadcast_tx, _) = broadcast::channel::<WebSocketMessage>(100);
@@ -52,42 +53,23 @@ pub async fn start_server(
     });
 
     // Spawn task to forward UI notifications to broadcast channel.
-    let node_clone = node.clone();
     tokio::spawn(async move {
         while let Some(notification) = ui_notify_rx.recv().await {
             match notification {
                 UiNotification::NewMessage(msg) => {
-                    // Decrypt message content before broadcasting.
-                    let other_peer = if msg.sender == node_clone.identity.peer_id {
-                        &msg.recipient
-                    } else {
-                        &msg.sender
-                    };
-
-                    let content = match node_clone.friends.get_friend(other_peer).await {
-                        Ok(Some(friend)) => {
-                            match node_clone
-                                .identity
-                                .decrypt_from(&friend.e2e_public_key, &msg.content)
-                            {
-                                Ok(plaintext) => match String::from_utf8(plaintext) {
-                                    Ok(s) => s,
-                                    Err(_) => continue, // Skip non-UTF8 messages
-                                },
-                                Err(_) => continue, // Skip decryption failures
-                            }
-                        }
-                        _ => continue, // Skip if friend not found
-                    };
-
                     let ws_msg = WebSocketMessage::NewMessage {
                         id: msg.id.to_string(),
                         sender: msg.sender.to_string(),
                         recipient: msg.recipient.to_string(),
-                        content,
+                        content: msg.text(),
                         timestamp: msg.timestamp,
                         nonce: msg.nonce,
                         delivery_status: format!("{:?}", msg.delivery_status),
+                        group_id: msg.group_id.map(|id| id.to_string()),
+                        attachment: msg
+                            .attachment
+                            .as_deref()
+                            .map(api::AttachmentInfoResponse::from),
                     };
                     let _ = broadcast_tx.send(ws_msg);
                 }
@@ -110,6 +92,58 @@ pub async fn start_server(
                     };
                     let _ = broadcast_tx.send(ws_msg);
                 }
+                UiNotification::ContactRequestReceived {
+                    peer_id,
+                    display_name,
+                } => {
+                    let ws_msg = WebSocketMessage::ContactRequest {
+                        peer_id: peer_id.to_string(),
+                        display_name,
+                    };
+                    let _ = broadcast_tx.send(ws_msg);
+                }
+                UiNotification::ContactAccepted(peer_id) => {
+                    let ws_msg = WebSocketMessage::ContactAccepted {
+                        peer_id: peer_id.to_string(),
+                    };
+                    let _ = broadcast_tx.send(ws_msg);
+                }
+                UiNotification::ContactRejected(peer_id) => {
+                    let ws_msg = WebSocketMessage::ContactRejected {
+                        peer_id: peer_id.to_string(),
+                    };
+                    let _ = broadcast_tx.send(ws_msg);
+                }
+                UiNotification::MessageRequestReceived(peer_id) => {
+                    let ws_msg = WebSocketMessage::MessageRequest {
+                        peer_id: peer_id.to_string(),
+                    };
+                    let _ = broadcast_tx.send(ws_msg);
+                }
+                UiNotification::GroupUpdated(group) => {
+                    let ws_msg = WebSocketMessage::GroupUpdated {
+                        group_id: group.id.to_string(),
+                        name: group.name,
+                        members: group
+                            .members
+                            .iter()
+                            .map(|member| member.peer_id.to_string())
+                            .collect(),
+                    };
+                    let _ = broadcast_tx.send(ws_msg);
+                }
+                UiNotification::VerifiedKeyChanged(peer_id) => {
+                    let ws_msg = WebSocketMessage::VerifiedKeyChanged {
+                        peer_id: peer_id.to_string(),
+                    };
+                    let _ = broadcast_tx.send(ws_msg);
+                }
+                UiNotification::MessagesDeleted(message_ids) => {
+                    let ws_msg = WebSocketMessage::MessagesDeleted {
+                        message_ids: message_ids.iter().map(|id| id.to_string()).collect(),
+                    };
+                    let _ = broadcast_tx.send(ws_msg);
+                }
             }
         }
     });
@@ -117,10 +151,43 @@ pub async fn start_server(
     let api_router = Router::new()
         .route("/api/me", get(api::get_me))
         .route("/api/friends", get(api::list_friends).post(api::add_friend))
+        .route("/api/friends/:peer_id/safety-number", get(api::get_safety_number))
+        .route("/api/friends/:peer_id/verify", axum::routing::post(api::verify_friend))
+        .route(
+            "/api/contacts/requests",
+            get(api::list_contact_requests).post(api::send_contact_request),
+        )
+        .route(
+            "/api/contacts/requests/:peer_id/accept",
+            axum::routing::post(api::accept_contact_request),
+        )
+        .route(
+            "/api/contacts/requests/:peer_id/reject",
+            axum::routing::post(api::reject_contact_request),
+        )
+        .route("/api/requests", get(api::list_message_requests))
+        .route(
+            "/api/requests/:peer_id/accept",
+            axum::routing::post(api::accept_message_request),
+        )
+        .route("/api/requests/:peer_id", axum::routing::delete(api::delete_message_request))
+        .route("/api/blocks", get(api::list_blocked_peers).post(api::block_peer))
+        .route("/api/blocks/:peer_id", axum::routing::delete(api::unblock_peer))
+        .route("/api/groups", get(api::list_groups).post(api::create_group))
+        .route("/api/groups/:group_id/members", axum::routing::post(api::add_group_members))
+        .route("/api/groups/:group_id/leave", axum::routing::post(api::leave_group))
+        .route(
+            "/api/groups/:group_id/messages",
+            get(api::get_group_messages).post(api::send_group_message),
+        )
         .route("/api/conversations", get(api::list_conversations))
+        .route("/api/conversations/:peer_id", axum::routing::delete(api::delete_conversation))
         .route("/api/conversations/:peer_id/messages", get(api::get_messages))
         .route("/api/conversations/:peer_id/messages", axum::routing::post(api::send_message))
+        .route("/api/messages/:msg_id", axum::routing::delete(api::delete_message))
         .route("/api/messages/:msg_id/read", axum::routing::post(api::mark_message_read))
+        .route("/api/search", get(api::search_messages))
+        .route("/api/attachments/:hash", get(api::get_attachment))
         .route("/api/peers/online", get(api::get_online_peers))
         .route("/api/system/status", get(api::get_system_status))
         .with_state(node);
@@ -135,8 +202,7 @@ pub async fn start_server(
         .fallback(static_handler)
         .layer(CorsLayer::permissive());
 
-    let addr = format!("127.0.0.1:{}", port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
 
     info!("Web server listening on http://{}", addr);
 
@@ -1,4 +1,5 @@
 //! This module handles WebSocket connections for the web UI.
+use super::api::AttachmentInfoResponse;
 use axum::{
     extract::{ws::WebSocket, State, WebSocketUpgrade},
     response::Response,
@@ -22,6 +23,8 @@ pub enum WebSocketMessage {
         timestamp: i64,
         nonce: u64,
         delivery_status: String,
+        group_id: Option<String>,
+        attachment: Option<AttachmentInfoResponse>,
     },
     /// A peer has connected to the network.
     PeerConnected {
@@ -36,6 +39,37 @@ pub enum WebSocketMessage {
         message_id: String,
         new_status: String,
     },
+    /// A friend request has been received.
+    ContactRequest {
+        peer_id: String,
+        display_name: Option<String>,
+    },
+    /// A friend request has been accepted and the peer is now a friend.
+    ContactAccepted {
+        peer_id: String,
+    },
+    /// A friend request we sent has been rejected.
+    ContactRejected {
+        peer_id: String,
+    },
+    /// A peer that is not a friend sent us a message.
+    MessageRequest {
+        peer_id: String,
+    },
+    /// We joined a group, or the members of a group changed.
+    GroupUpdated {
+        group_id: String,
+        name: String,
+        members: Vec<String>,
+    },
+    /// The key of a verified friend changed, so it is no longer verified.
+    VerifiedKeyChanged {
+        peer_id: String,
+    },
+    /// Messages were deleted by their sender.
+    MessagesDeleted {
+        message_ids: Vec<String>,
+    },
 }
 
 /// The state shared across WebSocket connections.
@@ -4,7 +4,7 @@
  * It includes functions for fetching identity, managing friends, conversations, messages,
  * and system status, all using standard Fetch API.
  */
-import type { Identity, Friend, Conversation, Message } from './types'
+import type { Identity, Friend, Conversation, Message, MessagesPage } from './types'
 
 /**
  * @constant {string} API_BASE - The base URL for the API endpoints.
@@ -58,6 +58,46 @@ export async function addFriend(
   if (!response.ok) throw new Error('Failed to add friend')
 }
 
+/**
+ * @interface SafetyNumber
+ * @property {string} peer_id - The Peer ID of the friend.
+ * @property {string} safety_number - The 60-digit safety number in groups of five.
+ * @property {boolean} verified - Whether the friend's keys were verified.
+ */
+export interface SafetyNumber {
+  peer_id: string
+  safety_number: string
+  verified: boolean
+}
+
+/**
+ * Fetches the safety number shared with a friend, to compare out of band.
+ * @param {string} peerId - The Peer ID of the friend.
+ * @returns {Promise<SafetyNumber>} A promise that resolves to the safety number.
+ * @throws {Error} If the API call fails.
+ */
+export async function getSafetyNumber(peerId: string): Promise<SafetyNumber> {
+  const response = await fetch(`${API_BASE}/friends/${peerId}/safety-number`)
+  if (!response.ok) throw new Error('Failed to fetch safety number')
+  return response.json()
+}
+
+/**
+ * Marks a friend as verified or unverified.
+ * @param {string} peerId - The Peer ID of the friend.
+ * @param {boolean} verified - Whether the safety number matched.
+ * @returns {Promise<void>} A promise that resolves when the friend is updated.
+ * @throws {Error} If the API call fails.
+ */
+export async function setFriendVerified(peerId: string, verified: boolean): Promise<void> {
+  const response = await fetch(`${API_BASE}/friends/${peerId}/verify`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ verified })
+  })
+  if (!response.ok) throw new Error('Failed to update friend')
+}
+
 /**
  * Fetches a list of all conversations from the API.
  * @returns {Promise<Conversation[]>} A promise that resolves to an array of Conversation objects.
@@ -70,28 +110,26 @@ export async function listConversations(): Promise<Conversation[]> {
 }
 
 /**
- * Fetches messages for a specific peer.
+ * Fetches a page of messages for a specific peer.
  * @param {string} peerId - The Peer ID of the conversation partner.
- * @param {'latest' | 'before' | 'after'} [mode='latest'] - The mode of fetching messages: 'latest', 'before' a reference message, or 'after' a reference message.
+ * @param {'latest' | 'before' | 'after'} [mode='latest'] - The mode of fetching messages: 'latest', 'before' a cursor, or 'after' a cursor.
  * @param {number} [limit=50] - The maximum number of messages to fetch.
- * @param {string} [referenceId] - The ID of the reference message when mode is 'before' or 'after'.
- * @returns {Promise<Message[]>} A promise that resolves to an array of Message objects.
+ * @param {string} [cursor] - The cursor from a previous page when mode is 'before' or 'after'.
+ * @returns {Promise<MessagesPage>} A promise that resolves to a page of messages with the cursors around it.
  * @throws {Error} If the API call fails.
  */
 export async function getMessages(
   peerId: string,
   mode: 'latest' | 'before' | 'after' = 'latest',
   limit: number = 50,
-  referenceId?: string
-): Promise<Message[]> {
+  cursor?: string
+): Promise<MessagesPage> {
   const params = new URLSearchParams()
   params.set('mode', mode)
   params.set('limit', limit.toString())
 
-  if (mode === 'before' && referenceId) {
-    params.set('before_id', referenceId)
-  } else if (mode === 'after' && referenceId) {
-    params.set('after_id', referenceId)
+  if (mode !== 'latest' && cursor) {
+    params.set('cursor', cursor)
   }
 
   const response = await fetch(`${API_BASE}/conversations/${peerId}/messages?${params}`)
@@ -132,6 +170,36 @@ export async function markMessageRead(messageId: string): Promise<void> {
   if (!response.ok) throw new Error('Failed to mark message as read')
 }
 
+/**
+ * Deletes a message.
+ * @param {string} messageId - The ID of the message to delete.
+ * @param {boolean} [everyone=false] - Whether to also delete a message we sent on the recipient's devices.
+ * @returns {Promise<void>} A promise that resolves when the message is deleted.
+ * @throws {Error} If the API call fails.
+ */
+export async function deleteMessage(messageId: string, everyone = false): Promise<void> {
+  const response = await fetch(`${API_BASE}/messages/${messageId}?everyone=${everyone}`, {
+    method: 'DELETE'
+  })
+  if (!response.ok) throw new Error('Failed to delete message')
+}
+
+/**
+ * Deletes the whole conversation with a peer.
+ * @param {string} peerId - The ID of the peer.
+ * @param {boolean} [everyone=false] - Whether to also delete the messages we sent on the peer's devices.
+ * @returns {Promise<number>} A promise that resolves to the number of deleted messages.
+ * @throws {Error} If the API call fails.
+ */
+export async function deleteConversation(peerId: string, everyone = false): Promise<number> {
+  const response = await fetch(`${API_BASE}/conversations/${peerId}?everyone=${everyone}`, {
+    method: 'DELETE'
+  })
+  if (!response.ok) throw new Error('Failed to delete conversation')
+  const result: { deleted: number } = await response.json()
+  return result.deleted
+}
+
 /**
  * Fetches a list of currently online peers.
  * @returns {Promise<string[]>} A promise that resolves to an array of Peer IDs of online peers.
@@ -143,16 +211,159 @@ export async function getOnlinePeers(): Promise<string[]> {
   return response.json()
 }
 
+/**
+ * @interface MessageRequest
+ * @property {string} peer_id - The Peer ID of the sender, who is not a friend.
+ * @property {string} e2e_public_key - The E2E public key the sender claimed, base64 encoded.
+ * @property {number} last_message_at - The timestamp of the latest pending message.
+ * @property {Message[]} messages - The pending messages, in chronological order.
+ */
+export interface MessageRequest {
+  peer_id: string
+  e2e_public_key: string
+  last_message_at: number
+  messages: Message[]
+}
+
+/**
+ * Fetches the messages from peers that are not friends, grouped by sender.
+ * @returns {Promise<MessageRequest[]>} A promise that resolves to an array of MessageRequest objects.
+ * @throws {Error} If the API call fails.
+ */
+export async function listMessageRequests(): Promise<MessageRequest[]> {
+  const response = await fetch(`${API_BASE}/requests`)
+  if (!response.ok) throw new Error('Failed to fetch message requests')
+  return response.json()
+}
+
+/**
+ * Accepts a message request, adding the sender as a friend.
+ * @param {string} peerId - The Peer ID of the sender.
+ * @param {string} [nickname] - An optional nickname for the new friend.
+ * @returns {Promise<void>} A promise that resolves when the request is accepted.
+ * @throws {Error} If the API call fails.
+ */
+export async function acceptMessageRequest(peerId: string, nickname?: string): Promise<void> {
+  const response = await fetch(`${API_BASE}/requests/${peerId}/accept`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ nickname })
+  })
+  if (!response.ok) throw new Error('Failed to accept message request')
+}
+
+/**
+ * Deletes a message request with all its messages.
+ * @param {string} peerId - The Peer ID of the sender.
+ * @returns {Promise<void>} A promise that resolves when the request is deleted.
+ * @throws {Error} If the API call fails.
+ */
+export async function deleteMessageRequest(peerId: string): Promise<void> {
+  const response = await fetch(`${API_BASE}/requests/${peerId}`, {
+    method: 'DELETE'
+  })
+  if (!response.ok) throw new Error('Failed to delete message request')
+}
+
+/**
+ * @interface BlockedPeer
+ * @property {string} peer_id - The Peer ID of the blocked peer.
+ * @property {number} blocked_at - The timestamp when the peer was blocked.
+ */
+export interface BlockedPeer {
+  peer_id: string
+  blocked_at: number
+}
+
+/**
+ * Fetches the list of blocked peers.
+ * @returns {Promise<BlockedPeer[]>} A promise that resolves to an array of BlockedPeer objects.
+ * @throws {Error} If the API call fails.
+ */
+export async function listBlockedPeers(): Promise<BlockedPeer[]> {
+  const response = await fetch(`${API_BASE}/blocks`)
+  if (!response.ok) throw new Error('Failed to fetch blocked peers')
+  return response.json()
+}
+
+/**
+ * Blocks a peer.
+ * @param {string} peerId - The Peer ID of the peer to block.
+ * @returns {Promise<void>} A promise that resolves when the peer is blocked.
+ * @throws {Error} If the API call fails.
+ */
+export async function blockPeer(peerId: string): Promise<void> {
+  const response = await fetch(`${API_BASE}/blocks`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ peer_id: peerId })
+  })
+  if (!response.ok) throw new Error('Failed to block peer')
+}
+
+/**
+ * Unblocks a peer.
+ * @param {string} peerId - The Peer ID of the peer to unblock.
+ * @returns {Promise<void>} A promise that resolves when the peer is unblocked.
+ * @throws {Error} If the API call fails.
+ */
+export async function unblockPeer(peerId: string): Promise<void> {
+  const response = await fetch(`${API_BASE}/blocks/${peerId}`, {
+    method: 'DELETE'
+  })
+  if (!response.ok) throw new Error('Failed to unblock peer')
+}
+
+/**
+ * @interface Reachability
+ * @property {'unknown' | 'public' | 'private'} status - Whether the node is publicly reachable.
+ * @property {string | null} public_address - The address confirmed as publicly reachable, if any.
+ * @property {boolean} is_relay - Whether the node relays connections for other peers.
+ * @property {string[]} relay_addresses - The relayed addresses at which the node can be reached.
+ * @property {number} hole_punches_succeeded - The number of relayed connections upgraded to direct ones.
+ * @property {number} hole_punches_failed - The number of failed hole punching attempts.
+ */
+export interface Reachability {
+  status: 'unknown' | 'public' | 'private'
+  public_address: string | null
+  is_relay: boolean
+  relay_addresses: string[]
+  hole_punches_succeeded: number
+  hole_punches_failed: number
+}
+
+/**
+ * @interface Limits
+ * @property {number} chat_requests_limited - The chat requests dropped for exceeding the rate limit.
+ * @property {number} mailbox_requests_limited - The mailbox requests dropped for exceeding the rate limit.
+ * @property {number} dht_writes_limited - The DHT writes dropped for exceeding the rate limit.
+ * @property {number} connections_denied - The inbound connections refused by a limit or block.
+ * @property {number} peers_blocked - The peers blocked for repeatedly exceeding the limits.
+ * @property {number} currently_blocked - The peers that are blocked at the moment.
+ */
+export interface Limits {
+  chat_requests_limited: number
+  mailbox_requests_limited: number
+  dht_writes_limited: number
+  connections_denied: number
+  peers_blocked: number
+  currently_blocked: number
+}
+
 /**
  * @interface SystemStatus
  * @property {number} connected_peers - The number of currently connected peers.
  * @property {number} known_mailboxes - The number of known mailboxes.
  * @property {number} pending_messages - The number of pending messages.
+ * @property {Reachability | null} reachability - The NAT traversal state of the node.
+ * @property {Limits | null} limits - The requests and connections refused by the limits.
  */
 export interface SystemStatus {
   connected_peers: number
   known_mailboxes: number
   pending_messages: number
+  reachability: Reachability | null
+  limits: Limits | null
 }
 
 /**
@@ -22,12 +22,14 @@ export interface Identity {
  * @property {string} e2e_public_key - The end-to-end encryption public key of the friend.
  * @property {string | null} nickname - The display name of the friend, or null if not set.
  * @property {boolean} online - Indicates if the friend is currently online.
+ * @property {boolean} verified - Indicates if the friend's keys were verified with the safety number.
  */
 export interface Friend {
   peer_id: string
   e2e_public_key: string
   nickname: string | null
   online: boolean
+  verified: boolean
 }
 
 /**
@@ -57,6 +59,19 @@ export interface Message {
   delivery_status: DeliveryStatus
 }
 
+/**
+ * Represents a page of messages in a conversation.
+ * @interface MessagesPage
+ * @property {Message[]} messages - The messages, in chronological order.
+ * @property {string | null} before_cursor - The opaque cursor to fetch the messages before this page, or null if the page is empty.
+ * @property {string | null} after_cursor - The opaque cursor to fetch the messages after this page, or null if the page is empty.
+ */
+export interface MessagesPage {
+  messages: Message[]
+  before_cursor: string | null
+  after_cursor: string | null
+}
+
 /**
  * Represents a conversation with a peer.
  * @interface Conversation
@@ -93,6 +108,15 @@ export interface Conversation {
  * @property {'delivery_status_update'} type - Indicates an update to a message's delivery status.
  * @property {string} message_id - The ID of the message whose status is being updated.
  * @property {DeliveryStatus} new_status - The new delivery status of the message.
+ *
+ * @property {'message_request'} type - Indicates a peer that is not a friend sent a message.
+ * @property {string} peer_id - The peer ID of the sender.
+ *
+ * @property {'verified_key_changed'} type - Indicates the key of a verified friend changed.
+ * @property {string} peer_id - The peer ID of the friend.
+ *
+ * @property {'messages_deleted'} type - Indicates messages were deleted by their sender.
+ * @property {string[]} message_ids - The IDs of the deleted messages.
  */
 export type WebSocketMessage =
   | {
@@ -118,3 +142,15 @@ export type WebSocketMessage =
       message_id: string
       new_status: DeliveryStatus
     }
+  | {
+      type: 'message_request'
+      peer_id: string
+    }
+  | {
+      type: 'verified_key_changed'
+      peer_id: string
+    }
+  | {
+      type: 'messages_deleted'
+      message_ids: string[]
+    }
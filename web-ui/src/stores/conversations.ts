@@ -18,6 +18,7 @@ import { useIdentityStore } from './identity'
  * @property {string[]} sortedIds - An array of message IDs, sorted by timestamp and nonce, representing the display order.
  * @property {string | null} oldestLoadedId - The ID of the oldest message currently loaded for this peer.
  * @property {string | null} newestLoadedId - The ID of the newest message currently loaded for this peer.
+ * @property {string | null} olderCursor - The cursor to load the messages before the oldest loaded page.
  * @property {boolean} hasMoreOlder - Indicates if there are more older messages to load for this conversation.
  * @property {boolean} isLoadingOlder - Indicates if older messages are currently being loaded.
  */
@@ -26,6 +27,7 @@ interface MessageStore {
   sortedIds: string[]
   oldestLoadedId: string | null
   newestLoadedId: string | null
+  olderCursor: string | null
   hasMoreOlder: boolean
   isLoadingOlder: boolean
 }
@@ -139,6 +141,7 @@ export const useConversationsStore = defineStore('conversations', () => {
         sortedIds: [],
         oldestLoadedId: null,
         newestLoadedId: null,
+        olderCursor: null,
         hasMoreOlder: true,
         isLoadingOlder: false,
       })
@@ -237,13 +240,15 @@ export const useConversationsStore = defineStore('conversations', () => {
     const store = getOrCreateMessageStore(peerId)
 
     try {
-      const msgs = await getMessages(peerId, 'latest', 50)
+      const page = await getMessages(peerId, 'latest', 50)
+      const msgs = page.messages
 
       // Clear existing messages for this peer and reload
       store.messagesById.clear()
       store.sortedIds = []
       store.oldestLoadedId = null
       store.newestLoadedId = null
+      store.olderCursor = page.before_cursor
       store.hasMoreOlder = msgs.length === 50
 
       // Insert all messages
@@ -282,7 +287,7 @@ export const useConversationsStore = defineStore('conversations', () => {
   async function loadOlderMessages(peerId: string) {
     const store = getOrCreateMessageStore(peerId)
 
-    if (!store.hasMoreOlder || store.isLoadingOlder || !store.oldestLoadedId) {
+    if (!store.hasMoreOlder || store.isLoadingOlder || !store.olderCursor) {
       return
     }
 
@@ -290,13 +295,16 @@ export const useConversationsStore = defineStore('conversations', () => {
     error.value = null
 
     try {
-      const msgs = await getMessages(peerId, 'before', 50, store.oldestLoadedId)
+      const page = await getMessages(peerId, 'before', 50, store.olderCursor)
+      const msgs = page.messages
 
       if (msgs.length === 0) {
         store.hasMoreOlder = false
         return
       }
 
+      store.olderCursor = page.before_cursor
+
       // Check if we got fewer messages than requested
       if (msgs.length < 50) {
         store.hasMoreOlder = false
@@ -438,6 +446,33 @@ export const useConversationsStore = defineStore('conversations', () => {
     // No need to queue, just ignore
   }
 
+  /**
+   * Removes deleted messages from all conversations.
+   * @param {string[]} messageIds - The IDs of the deleted messages.
+   */
+  function removeMessages(messageIds: string[]) {
+    const deleted = new Set(messageIds)
+
+    for (const store of messages.value.values()) {
+      if (!store.sortedIds.some(id => deleted.has(id))) continue
+
+      for (const id of messageIds) {
+        store.messagesById.delete(id)
+      }
+      store.sortedIds = store.sortedIds.filter(id => !deleted.has(id))
+      store.oldestLoadedId = store.sortedIds[0] ?? null
+      store.newestLoadedId = store.sortedIds[store.sortedIds.length - 1] ?? null
+    }
+
+    for (const conv of conversations.value) {
+      if (conv.last_message && deleted.has(conv.last_message.id)) {
+        const store = messages.value.get(conv.peer_id)
+        const newestId = store?.newestLoadedId
+        conv.last_message = newestId ? store!.messagesById.get(newestId) ?? null : null
+      }
+    }
+  }
+
   return {
     conversations,
     messages,
@@ -457,5 +492,6 @@ export const useConversationsStore = defineStore('conversations', () => {
     updatePeerOnlineStatus,
     updateConversationLastMessage,
     updateMessageDeliveryStatus,
+    removeMessages,
   }
 })